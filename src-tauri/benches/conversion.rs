@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use csv2sav_app_lib::bench::{self, Shape};
+
+const ROWS: usize = 20_000;
+
+fn shapes() -> [(&'static str, Shape); 4] {
+    [
+        ("narrow", Shape::narrow(ROWS)),
+        ("wide", Shape::wide(ROWS / 10)),
+        ("numeric_heavy", Shape::numeric_heavy(ROWS)),
+        ("string_heavy", Shape::string_heavy(ROWS)),
+    ]
+}
+
+fn fixture(name: &str, shape: &Shape) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("csv2sav_bench_{name}.csv"));
+    bench::generate_csv(&path, shape).expect("failed to generate fixture");
+    path
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let out_dir = std::env::temp_dir();
+
+    let mut infer = c.benchmark_group("infer");
+    for (name, shape) in shapes() {
+        let input = fixture(name, &shape);
+        infer.throughput(Throughput::Bytes(std::fs::metadata(&input).unwrap().len()));
+        infer.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| bench::infer(input).unwrap())
+        });
+    }
+    infer.finish();
+
+    let mut convert = c.benchmark_group("convert");
+    convert.sample_size(10);
+    for (name, shape) in shapes() {
+        let input = fixture(name, &shape);
+        let output = out_dir.join(format!("csv2sav_bench_{name}.zsav"));
+        convert.throughput(Throughput::Elements(shape.rows as u64));
+        convert.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| bench::convert(input, &output).unwrap())
+        });
+    }
+    convert.finish();
+
+    let mut write = c.benchmark_group("write_zsav");
+    write.sample_size(10);
+    for (name, shape) in shapes() {
+        let output = out_dir.join(format!("csv2sav_bench_{name}_direct.zsav"));
+        write.throughput(Throughput::Elements(shape.rows as u64));
+        write.bench_with_input(BenchmarkId::from_parameter(name), &shape, |b, shape| {
+            b.iter(|| bench::write_zsav(&output, shape).unwrap())
+        });
+    }
+    write.finish();
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
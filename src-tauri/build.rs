@@ -34,6 +34,7 @@ fn main() {
         .warnings(false)
         .include("vendor/readstat/src")
         .include("vendor/readstat/src/spss")
+        .include("csrc/xport")
         .include("vendor/zlib"); // zlib.h for readstat_sav_write.c
 
     if is_windows {
@@ -60,6 +61,7 @@ fn main() {
         "vendor/readstat/src/spss/readstat_sav.c",
         "vendor/readstat/src/spss/readstat_sav_compress.c",
         "vendor/readstat/src/spss/readstat_sav_parse.c",
+        "vendor/readstat/src/spss/readstat_sav_parse_mr_name.c",
         "vendor/readstat/src/spss/readstat_sav_parse_timestamp.c",
         "vendor/readstat/src/spss/readstat_sav_read.c",
         "vendor/readstat/src/spss/readstat_sav_write.c",
@@ -70,7 +72,16 @@ fn main() {
         "vendor/readstat/src/spss/readstat_zsav_write.c",
     ];
 
-    for src in core_sources.iter().chain(spss_sources.iter()) {
+    // SAS Transport writer written for this app against ReadStat's writer interface;
+    // not part of the vendored ReadStat sources.
+    let xport_sources = [
+        "csrc/xport/xport_ibm.c",
+        "csrc/xport/xport_layout.c",
+        "csrc/xport/xport_names.c",
+        "csrc/xport/xport_write.c",
+    ];
+
+    for src in core_sources.iter().chain(&spss_sources).chain(&xport_sources) {
         build.file(src);
     }
 
@@ -0,0 +1,51 @@
+/* xport_ibm.c - IEEE 754 doubles to IBM System/370 floating point.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+#include <stdint.h>
+#include <string.h>
+#include <math.h>
+
+#include "xport_ibm.h"
+
+/* An IBM double is a sign bit, a base-16 exponent biased by 64 and a 56-bit
+ * fraction whose first hex digit is non-zero. Every finite IEEE double in range
+ * converts exactly: the 53-bit mantissa is shifted by the remainder of the binary
+ * exponent modulo 4, and the quotient becomes the hex exponent. */
+int xport_double_to_ibm(double value, unsigned char *ibm) {
+    uint64_t bits, fraction, ibm_bits;
+    int exponent, hex_exponent;
+    int i;
+
+    memset(ibm, 0, 8);
+
+    if (isnan(value) || isinf(value) || fabs(value) >= XPORT_IBM_DOUBLE_LIMIT)
+        return -1;
+
+    memcpy(&bits, &value, sizeof(double));
+
+    exponent = (int)((bits >> 52) & 0x7FF);
+    if (exponent == 0) // zero or subnormal, far below the smallest IBM double
+        return 0;
+
+    fraction = (bits & 0x000FFFFFFFFFFFFFULL) | 0x0010000000000000ULL;
+    exponent -= 1023;
+
+    hex_exponent = (exponent >= 0 ? exponent / 4 : -((-exponent + 3) / 4)) + 1;
+    fraction <<= exponent - 4 * (hex_exponent - 1);
+
+    if (hex_exponent + 64 < 0) // underflow
+        return 0;
+
+    ibm_bits = (bits & 0x8000000000000000ULL)
+        | ((uint64_t)(hex_exponent + 64) << 56)
+        | fraction;
+
+    for (i=0; i<8; i++) {
+        ibm[i] = (ibm_bits >> (56 - 8 * i)) & 0xFF;
+    }
+    return 0;
+}
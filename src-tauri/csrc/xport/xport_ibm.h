@@ -0,0 +1,13 @@
+/* xport_ibm.h - IEEE 754 doubles to IBM System/370 floating point.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+// 16^63, the smallest magnitude too large for an IBM double
+#define XPORT_IBM_DOUBLE_LIMIT 7.2370055773322621e+75
+
+// Writes the eight big-endian bytes of `value` as an IBM double. Returns 0, or -1
+// for infinities, NaNs and magnitudes the IBM format cannot hold.
+int xport_double_to_ibm(double value, unsigned char *ibm);
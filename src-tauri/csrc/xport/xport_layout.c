@@ -0,0 +1,83 @@
+/* xport_layout.c - SAS Transport record layout and byte order.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "readstat.h"
+#include "readstat_bits.h"
+#include "xport_layout.h"
+
+void xport_namestr_bswap(xport_namestr_t *namestr) {
+    if (!machine_is_little_endian())
+        return;
+
+    namestr->ntype = byteswap2(namestr->ntype);
+    namestr->nhfun = byteswap2(namestr->nhfun);
+    namestr->nlng = byteswap2(namestr->nlng);
+    namestr->nvar0 = byteswap2(namestr->nvar0);
+
+    namestr->nfl = byteswap2(namestr->nfl);
+    namestr->nfd = byteswap2(namestr->nfd);
+    namestr->nfj = byteswap2(namestr->nfj);
+
+    namestr->nifl = byteswap2(namestr->nifl);
+    namestr->nifd = byteswap2(namestr->nifd);
+    namestr->npos = byteswap4(namestr->npos);
+
+    namestr->labeln = byteswap2(namestr->labeln);
+}
+
+static int xport_parse_digits(const char *data, size_t len) {
+    int value = 0;
+    size_t i;
+    for (i=0; i<len; i++) {
+        value = 10 * value + (data[i] - '0');
+    }
+    return value;
+}
+
+/* A format such as "BEST12.", "$20.", "DATE9." or "F8.2": a name, which may
+ * contain digits but does not end in one, then a width and decimals. */
+readstat_error_t xport_parse_format(const char *data, size_t len, xport_format_t *fmt) {
+    size_t name_len = len, width_len = 0, decimals_len = 0;
+    const char *dot = memchr(data, '.', len);
+
+    memset(fmt, 0, sizeof(xport_format_t));
+
+    if (dot) {
+        name_len = dot - data;
+        decimals_len = len - name_len - 1;
+        if (decimals_len > 4)
+            return READSTAT_ERROR_BAD_FORMAT_STRING;
+        size_t i;
+        for (i=0; i<decimals_len; i++) {
+            if (dot[1+i] < '0' || dot[1+i] > '9')
+                return READSTAT_ERROR_BAD_FORMAT_STRING;
+        }
+        fmt->decimals = xport_parse_digits(dot + 1, decimals_len);
+    }
+
+    while (width_len < name_len && data[name_len-width_len-1] >= '0' &&
+            data[name_len-width_len-1] <= '9') {
+        width_len++;
+    }
+    if (width_len > 5)
+        return READSTAT_ERROR_BAD_FORMAT_STRING;
+
+    name_len -= width_len;
+    fmt->width = xport_parse_digits(data + name_len, width_len);
+
+    if (name_len >= sizeof(fmt->name))
+        return READSTAT_ERROR_BAD_FORMAT_STRING;
+
+    memcpy(fmt->name, data, name_len);
+    fmt->name[name_len] = '\0';
+
+    return READSTAT_OK;
+}
@@ -0,0 +1,66 @@
+/* xport_layout.h - SAS Transport record layout.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+#define XPORT_LINE_LEN          80
+#define XPORT_NAMESTR_LEN       140
+
+#define XPORT_DEFAULT_VERSION   8
+#define XPORT_DEFAULT_TABLE_NAME "DATASET"
+
+#define XPORT_MAX_NAME_LEN_V5   8
+#define XPORT_MAX_NAME_LEN_V8   32
+#define XPORT_MAX_LABEL_LEN_V5  40
+#define XPORT_MAX_LABEL_LEN_V8  256
+#define XPORT_MAX_STRING_LEN_V5 200
+#define XPORT_MAX_STRING_LEN_V8 32767
+#define XPORT_MAX_VARIABLES     99999
+
+#define XPORT_NTYPE_NUMERIC     1
+#define XPORT_NTYPE_CHARACTER   2
+
+// "HEADER RECORD*******<name> HEADER RECORD!!!!!!!" and six five-digit numbers
+typedef struct xport_header_record_s {
+    char    name[9];
+    int     num1;
+    int     num2;
+    int     num3;
+    int     num4;
+    int     num5;
+    int     num6;
+} xport_header_record_t;
+
+// One variable's description; the long name and label length are used in V8 only.
+// All numbers are big-endian in the file.
+typedef struct xport_namestr_s {
+    uint16_t    ntype;
+    uint16_t    nhfun;
+    uint16_t    nlng;
+    uint16_t    nvar0;
+    char        nname[8];
+    char        nlabel[40];
+    char        nform[8];
+    uint16_t    nfl;
+    uint16_t    nfd;
+    uint16_t    nfj;
+    char        nfill[2];
+    char        niform[8];
+    uint16_t    nifl;
+    uint16_t    nifd;
+    uint32_t    npos;
+    char        longname[32];
+    uint16_t    labeln;
+    char        rest[18];
+} xport_namestr_t;
+
+typedef struct xport_format_s {
+    char    name[33];
+    int     width;
+    int     decimals;
+} xport_format_t;
+
+void xport_namestr_bswap(xport_namestr_t *namestr);
+readstat_error_t xport_parse_format(const char *data, size_t len, xport_format_t *fmt);
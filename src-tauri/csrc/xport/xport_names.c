@@ -0,0 +1,41 @@
+/* xport_names.c - SAS variable name rules.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+#include <stdlib.h>
+#include <string.h>
+
+#include "readstat.h"
+#include "xport_names.h"
+
+static int xport_name_char_is_valid(char c, int first) {
+    if (c == '_')
+        return 1;
+    if ((c >= 'A' && c <= 'Z') || (c >= 'a' && c <= 'z'))
+        return 1;
+    return !first && c >= '0' && c <= '9';
+}
+
+readstat_error_t xport_validate_name(const char *name, size_t max_len) {
+    size_t len = strlen(name);
+    size_t i;
+
+    if (len == 0)
+        return READSTAT_ERROR_NAME_IS_ZERO_LENGTH;
+
+    if (len > max_len)
+        return READSTAT_ERROR_NAME_IS_TOO_LONG;
+
+    if (!xport_name_char_is_valid(name[0], 1))
+        return READSTAT_ERROR_NAME_BEGINS_WITH_ILLEGAL_CHARACTER;
+
+    for (i=1; i<len; i++) {
+        if (!xport_name_char_is_valid(name[i], 0))
+            return READSTAT_ERROR_NAME_CONTAINS_ILLEGAL_CHARACTER;
+    }
+
+    return READSTAT_OK;
+}
@@ -0,0 +1,8 @@
+/* xport_names.h - SAS variable name rules.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+readstat_error_t xport_validate_name(const char *name, size_t max_len);
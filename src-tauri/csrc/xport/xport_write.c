@@ -0,0 +1,406 @@
+/* xport_write.c - SAS Transport V5 and V8 writer behind readstat_begin_writing_xport.
+ * Labels longer than the version allows are truncated.
+ *
+ * Provenance: written for csv2sav, not derived from ReadStat's own sas/ sources.
+ * It builds against the vendored ReadStat writer interface in
+ * vendor/readstat/src and follows the SAS Transport layout of SAS technical
+ * note TS-140. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <stdint.h>
+#include <math.h>
+#include <time.h>
+
+#include "readstat.h"
+#include "readstat_writer.h"
+
+#include "xport_names.h"
+#include "xport_layout.h"
+#include "xport_ibm.h"
+
+static const char *xport_months[] = {
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN",
+    "JUL", "AUG", "SEP", "OCT", "NOV", "DEC" };
+
+static size_t xport_max_name_len(readstat_writer_t *writer) {
+    return writer->version == 5 ? XPORT_MAX_NAME_LEN_V5 : XPORT_MAX_NAME_LEN_V8;
+}
+
+static const char *xport_table_name(readstat_writer_t *writer) {
+    return writer->table_name[0] ? writer->table_name : XPORT_DEFAULT_TABLE_NAME;
+}
+
+static void copy_text(void *buf, size_t buf_len, const char *str) {
+    size_t str_len = strlen(str);
+    memset(buf, ' ', buf_len);
+    memcpy(buf, str, str_len < buf_len ? str_len : buf_len);
+}
+
+static readstat_error_t xport_write_record(readstat_writer_t *writer, const char *record) {
+    return readstat_write_bytes(writer, record, XPORT_LINE_LEN);
+}
+
+static readstat_error_t xport_write_header_record(readstat_writer_t *writer,
+        xport_header_record_t *xrecord) {
+    char record[XPORT_LINE_LEN+1];
+    snprintf(record, sizeof(record),
+            "HEADER RECORD*******%-8sHEADER RECORD!!!!!!!%05d%05d%05d%05d%05d%05d  ",
+            xrecord->name,
+            xrecord->num1, xrecord->num2, xrecord->num3,
+            xrecord->num4, xrecord->num5, xrecord->num6);
+    return xport_write_record(writer, record);
+}
+
+static readstat_error_t xport_write_named_header_record(readstat_writer_t *writer,
+        const char *v5_name, const char *v8_name) {
+    xport_header_record_t xrecord = { .name = "" };
+    snprintf(xrecord.name, sizeof(xrecord.name), "%s", writer->version == 5 ? v5_name : v8_name);
+    return xport_write_header_record(writer, &xrecord);
+}
+
+static void xport_format_timestamp(char *buf, size_t len, time_t timestamp) {
+    struct tm *ts = localtime(&timestamp);
+    if (ts == NULL) {
+        snprintf(buf, len, "%-16s", "");
+        return;
+    }
+    snprintf(buf, len, "%02d%3.3s%02d:%02d:%02d:%02d",
+            ts->tm_mday, xport_months[ts->tm_mon], ts->tm_year % 100,
+            ts->tm_hour, ts->tm_min, ts->tm_sec);
+}
+
+static readstat_error_t xport_write_library_header(readstat_writer_t *writer) {
+    readstat_error_t retval = READSTAT_OK;
+    char record[XPORT_LINE_LEN+1];
+    char timestamp[17];
+
+    xport_format_timestamp(timestamp, sizeof(timestamp), writer->timestamp);
+
+    if ((retval = xport_write_named_header_record(writer, "LIBRARY", "LIBV8")) != READSTAT_OK)
+        goto cleanup;
+
+    snprintf(record, sizeof(record), "%-8s%-8s%-8s%-8s%-8.8s%24s%16s",
+            "SAS", "SAS", "SASLIB", "9.4", READSTAT_PRODUCT_NAME, "", timestamp);
+    if ((retval = xport_write_record(writer, record)) != READSTAT_OK)
+        goto cleanup;
+
+    snprintf(record, sizeof(record), "%16s%64s", timestamp, "");
+    if ((retval = xport_write_record(writer, record)) != READSTAT_OK)
+        goto cleanup;
+
+cleanup:
+    return retval;
+}
+
+static readstat_error_t xport_write_member_header(readstat_writer_t *writer) {
+    readstat_error_t retval = READSTAT_OK;
+    char record[XPORT_LINE_LEN+1];
+    char timestamp[17];
+    xport_header_record_t member = { .num4 = 160, .num6 = XPORT_NAMESTR_LEN };
+
+    xport_format_timestamp(timestamp, sizeof(timestamp), writer->timestamp);
+
+    snprintf(member.name, sizeof(member.name), "%s", writer->version == 5 ? "MEMBER" : "MEMBV8");
+    if ((retval = xport_write_header_record(writer, &member)) != READSTAT_OK)
+        goto cleanup;
+
+    if ((retval = xport_write_named_header_record(writer, "DSCRPTR", "DSCPTV8")) != READSTAT_OK)
+        goto cleanup;
+
+    if (writer->version == 5) {
+        snprintf(record, sizeof(record), "%-8s%-8.8s%-8s%-8s%-8.8s%24s%16s",
+                "SAS", xport_table_name(writer), "SASDATA", "9.4",
+                READSTAT_PRODUCT_NAME, "", timestamp);
+    } else {
+        snprintf(record, sizeof(record), "%-8s%-32.32s%-8s%-8s%-8.8s%16s",
+                "SAS", xport_table_name(writer), "SASDATA", "9.4",
+                READSTAT_PRODUCT_NAME, timestamp);
+    }
+    if ((retval = xport_write_record(writer, record)) != READSTAT_OK)
+        goto cleanup;
+
+    snprintf(record, sizeof(record), "%16s%16s%-40.40s%-8s",
+            timestamp, "", writer->file_label, "");
+    if ((retval = xport_write_record(writer, record)) != READSTAT_OK)
+        goto cleanup;
+
+cleanup:
+    return retval;
+}
+
+static readstat_error_t xport_write_namestr(readstat_writer_t *writer,
+        readstat_variable_t *r_variable) {
+    readstat_error_t retval = READSTAT_OK;
+    xport_namestr_t namestr;
+    xport_format_t format;
+
+    memset(&namestr, 0, sizeof(xport_namestr_t));
+
+    if (r_variable->type == READSTAT_TYPE_STRING) {
+        namestr.ntype = XPORT_NTYPE_CHARACTER;
+    } else {
+        namestr.ntype = XPORT_NTYPE_NUMERIC;
+        namestr.nfj = 1;
+    }
+    namestr.nlng = r_variable->storage_width;
+    namestr.nvar0 = r_variable->index + 1;
+    namestr.npos = r_variable->offset;
+
+    copy_text(namestr.nname, sizeof(namestr.nname), r_variable->name);
+    copy_text(namestr.nlabel, sizeof(namestr.nlabel), r_variable->label);
+    copy_text(namestr.niform, sizeof(namestr.niform), "");
+
+    retval = xport_parse_format(r_variable->format, strlen(r_variable->format), &format);
+    if (retval != READSTAT_OK)
+        goto cleanup;
+
+    copy_text(namestr.nform, sizeof(namestr.nform), format.name);
+    namestr.nfl = format.width;
+    namestr.nfd = format.decimals;
+
+    if (writer->version == 8) {
+        size_t label_len = strlen(r_variable->label);
+        if (label_len > XPORT_MAX_LABEL_LEN_V8)
+            label_len = XPORT_MAX_LABEL_LEN_V8;
+        copy_text(namestr.longname, sizeof(namestr.longname), r_variable->name);
+        namestr.labeln = label_len;
+    }
+
+    xport_namestr_bswap(&namestr);
+
+    retval = readstat_write_bytes(writer, &namestr, XPORT_NAMESTR_LEN);
+
+cleanup:
+    return retval;
+}
+
+static readstat_error_t xport_write_variables(readstat_writer_t *writer) {
+    readstat_error_t retval = READSTAT_OK;
+    xport_header_record_t namestr_header = { .num2 = writer->variables_count };
+    int i;
+
+    snprintf(namestr_header.name, sizeof(namestr_header.name), "%s",
+            writer->version == 5 ? "NAMESTR" : "NAMSTV8");
+    if ((retval = xport_write_header_record(writer, &namestr_header)) != READSTAT_OK)
+        goto cleanup;
+
+    for (i=0; i<writer->variables_count; i++) {
+        retval = xport_write_namestr(writer, readstat_get_variable(writer, i));
+        if (retval != READSTAT_OK)
+            goto cleanup;
+    }
+
+    retval = readstat_write_line_padding(writer, ' ', XPORT_LINE_LEN, "");
+
+cleanup:
+    return retval;
+}
+
+/* V8 keeps labels longer than the namestr's 40 bytes in their own records:
+ * the variable number, name length and label length, then the name and label. */
+static readstat_error_t xport_write_long_labels(readstat_writer_t *writer) {
+    readstat_error_t retval = READSTAT_OK;
+    xport_header_record_t label_header = { .name = "LABELV8" };
+    int i;
+
+    for (i=0; i<writer->variables_count; i++) {
+        if (strlen(readstat_get_variable(writer, i)->label) > XPORT_MAX_LABEL_LEN_V5)
+            label_header.num1++;
+    }
+    if (writer->version == 5 || label_header.num1 == 0)
+        goto cleanup;
+
+    if ((retval = xport_write_header_record(writer, &label_header)) != READSTAT_OK)
+        goto cleanup;
+
+    for (i=0; i<writer->variables_count; i++) {
+        readstat_variable_t *r_variable = readstat_get_variable(writer, i);
+        size_t name_len = strlen(r_variable->name);
+        size_t label_len = strlen(r_variable->label);
+        if (label_len <= XPORT_MAX_LABEL_LEN_V5)
+            continue;
+        if (label_len > XPORT_MAX_LABEL_LEN_V8)
+            label_len = XPORT_MAX_LABEL_LEN_V8;
+
+        uint16_t lengths[3] = { r_variable->index + 1, name_len, label_len };
+        unsigned char bytes[6];
+        int k;
+        for (k=0; k<3; k++) {
+            bytes[2*k] = lengths[k] >> 8;
+            bytes[2*k+1] = lengths[k] & 0xFF;
+        }
+        if ((retval = readstat_write_bytes(writer, bytes, sizeof(bytes))) != READSTAT_OK)
+            goto cleanup;
+        if ((retval = readstat_write_bytes(writer, r_variable->name, name_len)) != READSTAT_OK)
+            goto cleanup;
+        if ((retval = readstat_write_bytes(writer, r_variable->label, label_len)) != READSTAT_OK)
+            goto cleanup;
+    }
+
+    retval = readstat_write_line_padding(writer, ' ', XPORT_LINE_LEN, "");
+
+cleanup:
+    return retval;
+}
+
+static readstat_error_t xport_begin_data(void *writer_ctx) {
+    readstat_writer_t *writer = (readstat_writer_t *)writer_ctx;
+    readstat_error_t retval = READSTAT_OK;
+
+    if ((retval = xport_write_library_header(writer)) != READSTAT_OK)
+        goto cleanup;
+
+    if ((retval = xport_write_member_header(writer)) != READSTAT_OK)
+        goto cleanup;
+
+    if ((retval = xport_write_variables(writer)) != READSTAT_OK)
+        goto cleanup;
+
+    if ((retval = xport_write_long_labels(writer)) != READSTAT_OK)
+        goto cleanup;
+
+    retval = xport_write_named_header_record(writer, "OBS", "OBSV8");
+
+cleanup:
+    return retval;
+}
+
+static readstat_error_t xport_end_data(void *writer_ctx) {
+    readstat_writer_t *writer = (readstat_writer_t *)writer_ctx;
+    return readstat_write_line_padding(writer, ' ', XPORT_LINE_LEN, "");
+}
+
+static size_t xport_variable_width(readstat_type_t type, size_t user_width) {
+    if (type == READSTAT_TYPE_STRING)
+        return user_width ? user_width : 1;
+
+    if (user_width >= 3 && user_width <= 8)
+        return user_width;
+
+    return 8;
+}
+
+static readstat_error_t xport_metadata_ok(void *writer_ctx) {
+    readstat_writer_t *writer = (readstat_writer_t *)writer_ctx;
+    readstat_error_t retval = READSTAT_OK;
+    size_t max_string_len = writer->version == 5 ?
+        XPORT_MAX_STRING_LEN_V5 : XPORT_MAX_STRING_LEN_V8;
+    int i;
+
+    if (writer->version != 5 && writer->version != 8)
+        return READSTAT_ERROR_UNSUPPORTED_FILE_FORMAT_VERSION;
+
+    if (writer->variables_count > XPORT_MAX_VARIABLES)
+        return READSTAT_ERROR_TOO_MANY_COLUMNS;
+
+    if (writer->table_name[0]) {
+        retval = xport_validate_name(writer->table_name, xport_max_name_len(writer));
+        if (retval != READSTAT_OK)
+            return retval;
+    }
+
+    for (i=0; i<writer->variables_count; i++) {
+        readstat_variable_t *r_variable = readstat_get_variable(writer, i);
+        retval = xport_validate_name(r_variable->name, xport_max_name_len(writer));
+        if (retval != READSTAT_OK)
+            return retval;
+
+        if (r_variable->type == READSTAT_TYPE_STRING) {
+            if (r_variable->user_width > max_string_len)
+                return READSTAT_ERROR_STRING_VALUE_IS_TOO_LONG;
+        } else if (r_variable->user_width != 0 &&
+                (r_variable->user_width < 3 || r_variable->user_width > 8)) {
+            return READSTAT_ERROR_NUMERIC_VALUE_IS_OUT_OF_RANGE;
+        }
+    }
+
+    return READSTAT_OK;
+}
+
+static readstat_error_t xport_write_double(void *row, const readstat_variable_t *var, double value) {
+    unsigned char ibm[8];
+
+    if (isnan(value)) {
+        memset(row, '\0', var->storage_width);
+        ((char *)row)[0] = '.';
+        return READSTAT_OK;
+    }
+
+    if (xport_double_to_ibm(value, ibm) != 0)
+        return READSTAT_ERROR_NUMERIC_VALUE_IS_OUT_OF_RANGE;
+
+    memcpy(row, ibm, var->storage_width);
+    return READSTAT_OK;
+}
+
+static readstat_error_t xport_write_int8(void *row, const readstat_variable_t *var, int8_t value) {
+    return xport_write_double(row, var, value);
+}
+
+static readstat_error_t xport_write_int16(void *row, const readstat_variable_t *var, int16_t value) {
+    return xport_write_double(row, var, value);
+}
+
+static readstat_error_t xport_write_int32(void *row, const readstat_variable_t *var, int32_t value) {
+    return xport_write_double(row, var, value);
+}
+
+static readstat_error_t xport_write_float(void *row, const readstat_variable_t *var, float value) {
+    return xport_write_double(row, var, value);
+}
+
+static readstat_error_t xport_write_string(void *row, const readstat_variable_t *var, const char *value) {
+    memset(row, ' ', var->storage_width);
+    if (value != NULL && value[0] != '\0') {
+        size_t value_len = strlen(value);
+        if (value_len > var->storage_width)
+            return READSTAT_ERROR_STRING_VALUE_IS_TOO_LONG;
+
+        memcpy(row, value, value_len);
+    }
+    return READSTAT_OK;
+}
+
+static readstat_error_t xport_write_missing_string(void *row, const readstat_variable_t *var) {
+    memset(row, ' ', var->storage_width);
+    return READSTAT_OK;
+}
+
+static readstat_error_t xport_write_missing_tagged(void *row, const readstat_variable_t *var, char tag) {
+    if (tag != '_' && (tag < 'A' || tag > 'Z'))
+        return READSTAT_ERROR_TAGGED_VALUE_IS_OUT_OF_RANGE;
+
+    memset(row, '\0', var->storage_width);
+    ((char *)row)[0] = tag;
+    return READSTAT_OK;
+}
+
+static readstat_error_t xport_write_missing_number(void *row, const readstat_variable_t *var) {
+    memset(row, '\0', var->storage_width);
+    ((char *)row)[0] = '.';
+    return READSTAT_OK;
+}
+
+readstat_error_t readstat_begin_writing_xport(readstat_writer_t *writer, void *user_ctx, long row_count) {
+
+    if (writer->version == 0)
+        writer->version = XPORT_DEFAULT_VERSION;
+
+    writer->callbacks.metadata_ok = &xport_metadata_ok;
+    writer->callbacks.variable_width = &xport_variable_width;
+    writer->callbacks.write_int8 = &xport_write_int8;
+    writer->callbacks.write_int16 = &xport_write_int16;
+    writer->callbacks.write_int32 = &xport_write_int32;
+    writer->callbacks.write_float = &xport_write_float;
+    writer->callbacks.write_double = &xport_write_double;
+    writer->callbacks.write_string = &xport_write_string;
+    writer->callbacks.write_missing_string = &xport_write_missing_string;
+    writer->callbacks.write_missing_number = &xport_write_missing_number;
+    writer->callbacks.write_missing_tagged = &xport_write_missing_tagged;
+    writer->callbacks.begin_data = &xport_begin_data;
+    writer->callbacks.end_data = &xport_end_data;
+
+    return readstat_begin_writing_file(writer, user_ctx, row_count);
+}
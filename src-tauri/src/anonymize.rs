@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::options::Anonymize;
+
+/// Trailing characters a mask leaves readable, e.g. the last digits of a phone number.
+const MASK_KEEP: usize = 4;
+/// Shorter values are masked completely, since their tail would give too much away.
+const MASK_MIN_CHARS: usize = 8;
+/// Width of a hex-encoded SHA-256 digest.
+pub const HASH_WIDTH: usize = 64;
+
+impl Anonymize {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Anonymize::Drop => "drop",
+            Anonymize::Hash => "hash",
+            Anonymize::Mask => "mask",
+            Anonymize::Year => "year",
+        }
+    }
+}
+
+/// Hex SHA-256 of the salt and the value. The same value and salt always give the
+/// same hash, so hashed columns still link records across files.
+pub fn hash(value: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Replaces every character but the last few with `*`.
+pub fn mask(value: &str) -> String {
+    let chars = value.chars().count();
+    let keep = if chars >= MASK_MIN_CHARS { MASK_KEEP } else { 0 };
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i + keep < chars { '*' } else { c })
+        .collect()
+}
+
+/// First standalone four-digit number in `text`, e.g. the year of `2024-03-01`.
+pub fn year_in_text(text: &str) -> Option<f64> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .find(|token| token.len() == 4)
+        .and_then(|token| token.parse().ok())
+}
+
+/// Optional record of what each anonymized value became, written as
+/// `<output>.anonymization.csv` with columns column, method, original_value and
+/// replacement. It holds the personal data itself and must be kept apart from the SAV.
+pub struct AnonymizationMap {
+    writer: Option<(PathBuf, csv::Writer<BufWriter<File>>)>,
+    /// Column and original value pairs already written.
+    seen: HashSet<(usize, String)>,
+}
+
+impl AnonymizationMap {
+    pub fn disabled() -> Self {
+        Self {
+            writer: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn create(output: &Path) -> Result<Self, String> {
+        let path = map_path(output);
+        let file = File::create(&path)
+            .map_err(|e| format!("Failed to create anonymization map: {e}"))?;
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer
+            .write_record(["column", "method", "original_value", "replacement"])
+            .map_err(|e| format!("Failed to write anonymization map: {e}"))?;
+        Ok(Self {
+            writer: Some((path, writer)),
+            seen: HashSet::new(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Writes a mapping the first time the column meets the original value.
+    pub fn record(
+        &mut self,
+        index: usize,
+        column: &str,
+        method: Anonymize,
+        original: &str,
+        replacement: &str,
+    ) -> Result<(), String> {
+        let Some((_, writer)) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if !self.seen.insert((index, original.to_string())) {
+            return Ok(());
+        }
+        writer
+            .write_record([column, method.as_str(), original, replacement])
+            .map_err(|e| format!("Failed to write anonymization map: {e}"))
+    }
+
+    /// Flushes the file and returns a summary line; an empty map is removed.
+    pub fn finish(self) -> Result<Option<String>, String> {
+        let Some((path, mut writer)) = self.writer else {
+            return Ok(None);
+        };
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write anonymization map: {e}"))?;
+        drop(writer);
+        if self.seen.is_empty() {
+            let _ = std::fs::remove_file(&path);
+            return Ok(None);
+        }
+        Ok(Some(format!(
+            "Anonymization map of {} value(s) written to {}; store it apart from the data",
+            self.seen.len(),
+            path.display()
+        )))
+    }
+}
+
+pub fn map_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".anonymization.csv");
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_mask_and_year() {
+        assert_eq!(hash("alice@example.com", "pepper"), hash("alice@example.com", "pepper"));
+        assert_ne!(hash("alice@example.com", "pepper"), hash("alice@example.com", "salt"));
+        assert_eq!(hash("x", "").len(), HASH_WIDTH);
+
+        assert_eq!(mask("0612345678"), "******5678");
+        assert_eq!(mask("Bob"), "***");
+        assert_eq!(mask("张三李四王五赵六"), "****王五赵六");
+
+        assert_eq!(year_in_text("2024-03-01"), Some(2024.0));
+        assert_eq!(year_in_text("01/03/1987 10:00"), Some(1987.0));
+        assert_eq!(year_in_text("March"), None);
+    }
+}
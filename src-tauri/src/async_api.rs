@@ -0,0 +1,128 @@
+//! Async entry points for Tokio-based services: conversion runs on the blocking
+//! pool, progress and warnings arrive as a stream of events, and cancelling or
+//! dropping the handle stops the conversion. Only compiled with the `async` feature.
+
+use std::path::PathBuf;
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::converter::{self, ConvertOutcome};
+use crate::options::ConvertOptions;
+use crate::schema;
+
+/// Something a running conversion reports before it finishes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Progress { rows: usize, bytes_read: u64, total_bytes: u64 },
+    Warning(String),
+}
+
+/// Cancels on drop, so abandoning a conversion frees its blocking thread.
+#[derive(Debug)]
+struct CancelOnDrop(CancelToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// A conversion running on Tokio's blocking pool.
+pub struct Conversion {
+    events: UnboundedReceiver<Event>,
+    task: JoinHandle<Result<ConvertOutcome, TaskError>>,
+    guard: CancelOnDrop,
+}
+
+impl Conversion {
+    /// Next progress or warning event; None once the conversion has stopped.
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.guard.0.clone()
+    }
+
+    pub fn cancel(&self) {
+        self.guard.0.cancel();
+    }
+
+    /// Waits for the result. A cancelled conversion fails with [`TaskError::Cancelled`].
+    pub async fn finish(self) -> Result<ConvertOutcome, TaskError> {
+        let Conversion { task, guard, .. } = self;
+        let result = task.await.map_err(|e| format!("Conversion task failed: {e}"))?;
+        drop(guard);
+        result
+    }
+}
+
+/// Infers the schema of `input` and converts it to ZSAV at `output`, like the app's
+/// convert command. `cancel` may carry a deadline; the conversion also stops when
+/// the returned handle is dropped. Must be called from within a Tokio runtime.
+pub fn convert(input: PathBuf, output: PathBuf, options: ConvertOptions, cancel: CancelToken) -> Conversion {
+    let (tx, events) = mpsc::unbounded_channel();
+    let token = cancel.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        let cancel = &token;
+        let csv_schema = schema::infer_schema(&input, &options, cancel)?;
+        for warning in &csv_schema.warnings {
+            let _ = tx.send(Event::Warning(warning.clone()));
+        }
+        let mut outcome = converter::convert_csv_to_zsav(
+            &input,
+            &output,
+            &csv_schema,
+            &options,
+            cancel,
+            &|rows, bytes_read, total_bytes| {
+                let _ = tx.send(Event::Progress { rows, bytes_read, total_bytes });
+            },
+            &|warning| {
+                let _ = tx.send(Event::Warning(warning.to_string()));
+            },
+        )?;
+        let mut warnings = csv_schema.warnings;
+        warnings.append(&mut outcome.warnings);
+        outcome.warnings = warnings;
+        Ok(outcome)
+    });
+    Conversion { events, task, guard: CancelOnDrop(cancel) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_async_convert_streams_events_and_cancels() {
+        let dir = std::env::temp_dir().join("csv2sav_async_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let mut csv = "id,name\n".to_string();
+        for i in 0..120_000 {
+            csv.push_str(&format!("{i},name{i}\n"));
+        }
+        std::fs::write(&input, csv).unwrap();
+        let options = ConvertOptions { sample_rows: 10, cache_records_max_bytes: 0, ..ConvertOptions::default() };
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let mut conversion = convert(input.clone(), dir.join("out.zsav"), options.clone(), CancelToken::new());
+            let mut progressed = false;
+            while let Some(event) = conversion.next_event().await {
+                progressed |= matches!(event, Event::Progress { .. });
+            }
+            assert!(progressed);
+            assert_eq!(conversion.finish().await.unwrap().rows, 120_000);
+
+            let conversion = convert(input.clone(), dir.join("cancelled.zsav"), options, CancelToken::new());
+            conversion.cancel();
+            assert!(conversion.finish().await.err().is_some_and(|e| e.is_cancelled()));
+        });
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
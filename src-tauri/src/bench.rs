@@ -0,0 +1,184 @@
+//! Synthetic CSV fixtures and entry points for the Criterion benches in `benches/`.
+//! Only compiled with the `bench` feature; the rest of the crate stays private.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::cancel::CancelToken;
+use crate::converter;
+use crate::options::ConvertOptions;
+use crate::readstat_writer::{ColDef, ColType, Compression, FileMeta, Value, Writer};
+use crate::schema;
+
+/// Layout of a generated CSV.
+#[derive(Debug, Clone, Copy)]
+pub struct Shape {
+    pub rows: usize,
+    pub numeric_cols: usize,
+    pub string_cols: usize,
+    /// Maximum length of a generated string value; lengths vary between 1 and this.
+    pub string_len: usize,
+}
+
+impl Shape {
+    pub fn narrow(rows: usize) -> Self {
+        Self { rows, numeric_cols: 3, string_cols: 2, string_len: 12 }
+    }
+
+    pub fn wide(rows: usize) -> Self {
+        Self { rows, numeric_cols: 250, string_cols: 50, string_len: 12 }
+    }
+
+    pub fn numeric_heavy(rows: usize) -> Self {
+        Self { rows, numeric_cols: 40, string_cols: 0, string_len: 0 }
+    }
+
+    pub fn string_heavy(rows: usize) -> Self {
+        Self { rows, numeric_cols: 2, string_cols: 38, string_len: 40 }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.numeric_cols + self.string_cols
+    }
+}
+
+/// Small deterministic generator, so every run benchmarks the same bytes.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+/// Writes a CSV of the given shape: numeric columns first (integers and two-decimal
+/// values, with occasional blanks), then string columns. Some strings contain commas
+/// so the quoting path is exercised too.
+pub fn generate_csv(path: &Path, shape: &Shape) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let headers: Vec<String> = (0..shape.numeric_cols)
+        .map(|i| format!("num_{i}"))
+        .chain((0..shape.string_cols).map(|i| format!("str_{i}")))
+        .collect();
+    writeln!(out, "{}", headers.join(","))?;
+
+    let mut rng = Lcg(0x5EED);
+    let mut text = String::new();
+    for row in 0..shape.rows {
+        for col in 0..shape.numeric_cols {
+            if col > 0 {
+                out.write_all(b",")?;
+            }
+            match rng.next() % 20 {
+                0 => {}
+                n if n % 2 == 0 => write!(out, "{}", rng.next() % 100_000)?,
+                _ => write!(out, "{:.2}", (rng.next() % 1_000_000) as f64 / 100.0)?,
+            }
+        }
+        for col in 0..shape.string_cols {
+            if col > 0 || shape.numeric_cols > 0 {
+                out.write_all(b",")?;
+            }
+            let len = 1 + rng.next() as usize % shape.string_len.max(1);
+            text.clear();
+            text.extend((0..len).map(|_| (b'a' + (rng.next() % 26) as u8) as char));
+            if (row + col) % 7 == 0 {
+                write!(out, "\"{text}, {row}\"")?;
+            } else {
+                out.write_all(text.as_bytes())?;
+            }
+        }
+        out.write_all(b"\n")?;
+    }
+    out.flush()
+}
+
+/// Runs schema inference with default options.
+pub fn infer(path: &Path) -> Result<(), String> {
+    schema::infer_schema(path, &ConvertOptions::default(), &CancelToken::new())?;
+    Ok(())
+}
+
+/// Infers and converts `input` to `output` with default options; returns the row count.
+pub fn convert(input: &Path, output: &Path) -> Result<usize, String> {
+    let options = ConvertOptions::default();
+    let cancel = CancelToken::new();
+    let csv_schema = schema::infer_schema(input, &options, &cancel)?;
+    let outcome = converter::convert_csv_to_zsav(
+        input,
+        output,
+        &csv_schema,
+        &options,
+        &cancel,
+        &|_, _, _| {},
+        &|_| {},
+    )?;
+    Ok(outcome.rows)
+}
+
+/// Writes a ZSAV of the given shape straight through the writer, without any CSV
+/// parsing, to measure ReadStat, compression and output I/O on their own.
+pub fn write_zsav(output: &Path, shape: &Shape) -> Result<(), String> {
+    let cols: Vec<ColDef> = (0..shape.columns())
+        .map(|i| ColDef {
+            name: format!("V{}", i + 1),
+            label: String::new(),
+            col_type: if i < shape.numeric_cols {
+                ColType::Numeric { width: 8, decimals: 2 }
+            } else {
+                ColType::String(shape.string_len.max(1))
+            },
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        })
+        .collect();
+    let file = File::create(output).map_err(|e| format!("Failed to create output: {e}"))?;
+    let mut writer = Writer::new(file, &cols, &FileMeta::default(), shape.rows, Compression::Zlib)?;
+
+    let text = "x".repeat(shape.string_len.max(1));
+    let mut rng = Lcg(0x5EED);
+    for _ in 0..shape.rows {
+        writer.begin_row()?;
+        for i in 0..shape.numeric_cols {
+            writer.insert(i, Value::Number(Some((rng.next() % 100_000) as f64)))?;
+        }
+        for i in shape.numeric_cols..shape.columns() {
+            let len = 1 + rng.next() as usize % text.len();
+            writer.insert(i, Value::Str(&text[..len]))?;
+        }
+        writer.end_row()?;
+    }
+    writer.finish().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_csv_converts() {
+        let dir = std::env::temp_dir();
+        let input = dir.join("csv2sav_bench_fixture_test.csv");
+        let output = dir.join("csv2sav_bench_fixture_test.zsav");
+        let shape = Shape { rows: 50, numeric_cols: 3, string_cols: 2, string_len: 8 };
+        generate_csv(&input, &shape).unwrap();
+
+        let mut reader = csv::Reader::from_path(&input).unwrap();
+        assert_eq!(reader.headers().unwrap().len(), shape.columns());
+        assert_eq!(reader.records().count(), shape.rows);
+        assert_eq!(convert(&input, &output).unwrap(), shape.rows);
+
+        std::fs::remove_file(&input).ok();
+        std::fs::remove_file(&output).ok();
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Reason given when a token's deadline passes.
+const DEADLINE_REASON: &str = "time limit reached";
+
+/// Shared cancellation state for long-running work. Clones observe the same state;
+/// an optional deadline cancels the work once it passes.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    reason: Mutex<Option<String>>,
+    deadline: Mutex<Option<Instant>>,
+    /// Token whose cancellation also cancels this one.
+    parent: Option<CancelToken>,
+}
+
+/// The work stopped because its token was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cancelled {
+    pub reason: Option<String>,
+    /// Stopped by the token's deadline rather than by a call to cancel.
+    pub timed_out: bool,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "Cancelled: {reason}"),
+            None => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that cancels itself once `timeout` has passed.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            inner: Arc::new(Inner { deadline: Mutex::new(Some(deadline)), ..Inner::default() }),
+        }
+    }
+
+    /// A token cancelled along with this one but with a time limit of its own, so one
+    /// job of a batch can time out without stopping the others.
+    pub fn child(&self) -> Self {
+        Self { inner: Arc::new(Inner { parent: Some(self.clone()), ..Inner::default() }) }
+    }
+
+    /// Starts, or with None removes, a time limit counted from now.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        *self.inner.deadline.lock().unwrap() = timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancels with a reason that is passed on in the [`Cancelled`] error.
+    pub fn cancel_with(&self, reason: impl Into<String>) {
+        *self.inner.reason.lock().unwrap() = Some(reason.into());
+        self.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Relaxed)
+            || self.inner.deadline.lock().unwrap().is_some_and(|deadline| Instant::now() >= deadline)
+            || self.inner.parent.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Err once cancelled; call it at the points where work may stop.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if !self.is_cancelled() {
+            return Ok(());
+        }
+        if let Some(parent) = self.inner.parent.as_ref().filter(|p| p.is_cancelled()) {
+            return parent.check();
+        }
+        let reason = self.inner.reason.lock().unwrap().clone();
+        let timed_out = !self.inner.cancelled.load(Ordering::Relaxed);
+        Err(Cancelled {
+            reason: reason.or_else(|| timed_out.then(|| DEADLINE_REASON.to_string())),
+            timed_out,
+        })
+    }
+}
+
+/// Tokens of the running jobs of every batch, keyed by batch and job index, so one
+/// job can be cancelled without stopping the rest of its batch.
+#[derive(Debug, Default)]
+pub struct JobTokens {
+    /// The id the next batch gets; batches are numbered from 1.
+    next_batch: AtomicU64,
+    jobs: Mutex<HashMap<(u64, usize), CancelToken>>,
+}
+
+impl JobTokens {
+    /// Numbers a new batch.
+    pub fn begin_batch(&self) -> u64 {
+        self.next_batch.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// A child of `batch_token` for `job`, kept until [`JobTokens::finish`].
+    pub fn start(&self, batch: u64, job: usize, batch_token: &CancelToken) -> CancelToken {
+        let token = batch_token.child();
+        self.jobs.lock().unwrap().insert((batch, job), token.clone());
+        token
+    }
+
+    pub fn finish(&self, batch: u64, job: usize) {
+        self.jobs.lock().unwrap().remove(&(batch, job));
+    }
+
+    /// Cancels `job` of `batch`, or of the latest batch when none is given. Returns
+    /// false when that job is not running.
+    pub fn cancel(&self, batch: Option<u64>, job: usize) -> bool {
+        let batch = batch.unwrap_or_else(|| self.next_batch.load(Ordering::Relaxed));
+        match self.jobs.lock().unwrap().get(&(batch, job)) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Error of cancellable work: cancellation, kept apart from failures so callers
+/// need not match on message text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskError {
+    Cancelled(Cancelled),
+    Failed(String),
+}
+
+impl TaskError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, TaskError::Cancelled(_))
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, TaskError::Cancelled(cancelled) if cancelled.timed_out)
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Cancelled(cancelled) => cancelled.fmt(f),
+            TaskError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<Cancelled> for TaskError {
+    fn from(cancelled: Cancelled) -> Self {
+        TaskError::Cancelled(cancelled)
+    }
+}
+
+impl From<String> for TaskError {
+    fn from(message: String) -> Self {
+        TaskError::Failed(message)
+    }
+}
+
+impl From<&str> for TaskError {
+    fn from(message: &str) -> Self {
+        TaskError::Failed(message.to_string())
+    }
+}
+
+impl From<TaskError> for String {
+    fn from(error: TaskError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert_eq!(token.check(), Ok(()));
+        clone.cancel_with("user request");
+        assert_eq!(
+            token.check(),
+            Err(Cancelled { reason: Some("user request".to_string()), timed_out: false })
+        );
+        assert_eq!(TaskError::from(token.check().unwrap_err()).to_string(), "Cancelled: user request");
+        let token = CancelToken::new();
+
+        let expired = CancelToken::with_deadline(Instant::now());
+        assert_eq!(expired.check().unwrap_err().reason.as_deref(), Some(DEADLINE_REASON));
+        assert!(TaskError::from(expired.check().unwrap_err()).is_timeout());
+        assert!(!CancelToken::with_timeout(Duration::from_secs(60)).is_cancelled());
+
+        token.set_timeout(Some(Duration::ZERO));
+        assert!(token.check().unwrap_err().timed_out);
+        token.set_timeout(None);
+        assert_eq!(token.check(), Ok(()));
+
+        let child = token.child();
+        child.set_timeout(Some(Duration::ZERO));
+        assert!(child.check().unwrap_err().timed_out);
+        assert_eq!(token.check(), Ok(()));
+        let sibling = token.child();
+        token.cancel_with("stop all");
+        assert_eq!(sibling.check().unwrap_err().reason.as_deref(), Some("stop all"));
+        assert!(!child.check().unwrap_err().timed_out);
+    }
+
+    #[test]
+    fn test_cancel_one_job() {
+        let jobs = JobTokens::default();
+        let token = CancelToken::new();
+        let batch = jobs.begin_batch();
+        let (first, second) = (jobs.start(batch, 0, &token), jobs.start(batch, 1, &token));
+
+        assert!(jobs.cancel(Some(batch), 0));
+        assert!(first.is_cancelled());
+        assert!(!second.is_cancelled());
+        assert!(!token.is_cancelled());
+
+        let later = jobs.begin_batch();
+        let other = jobs.start(later, 1, &token);
+        assert!(jobs.cancel(None, 1));
+        assert!(other.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        jobs.finish(batch, 1);
+        assert!(!jobs.cancel(Some(batch), 1));
+    }
+}
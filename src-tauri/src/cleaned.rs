@@ -0,0 +1,124 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::dates::{self, DateKind};
+use crate::readstat_writer::{ColDef, ColType, Value};
+
+/// Optional copy of the data exactly as it went into the SAV file, written as
+/// `<output>.cleaned.csv`: variable names as the header, values after trimming,
+/// decoding, missing-value handling and truncation, dates as ISO 8601 text and
+/// missing values empty.
+pub struct CleanedCsv {
+    writer: Option<(PathBuf, csv::Writer<BufWriter<File>>)>,
+    dates: Vec<Option<DateKind>>,
+    fields: Vec<String>,
+}
+
+impl CleanedCsv {
+    pub fn disabled() -> Self {
+        Self {
+            writer: None,
+            dates: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn create(output: &Path, cols: &[ColDef]) -> Result<Self, String> {
+        let path = cleaned_path(output);
+        let file = File::create(&path).map_err(|e| format!("Failed to create cleaned CSV: {e}"))?;
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer
+            .write_record(cols.iter().map(|c| c.name.as_str()))
+            .map_err(|e| format!("Failed to write cleaned CSV: {e}"))?;
+        let dates = cols
+            .iter()
+            .map(|c| match c.col_type {
+                ColType::Date(format) => dates::date_kind(format),
+                _ => None,
+            })
+            .collect();
+        Ok(Self {
+            writer: Some((path, writer)),
+            dates,
+            fields: vec![String::new(); cols.len()],
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Writes one row; `values` holds every variable in order.
+    pub fn write_row<'v>(&mut self, values: impl Iterator<Item = Value<'v>>) -> Result<(), String> {
+        let Some((_, writer)) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        for ((field, value), date) in self.fields.iter_mut().zip(values).zip(&self.dates) {
+            field.clear();
+            match (value, date) {
+                (Value::Number(None), _) => {}
+                (Value::Number(Some(n)), Some(kind)) => {
+                    field.push_str(&dates::format_spss(n, *kind));
+                }
+                (Value::Number(Some(n)), None) => {
+                    let _ = write!(field, "{n}");
+                }
+                (Value::Str(s), _) => field.push_str(s),
+            }
+        }
+        writer
+            .write_record(&self.fields)
+            .map_err(|e| format!("Failed to write cleaned CSV: {e}"))
+    }
+
+    /// Flushes the file. Returns a line saying where it was written.
+    pub fn finish(self) -> Result<Option<String>, String> {
+        let Some((path, mut writer)) = self.writer else {
+            return Ok(None);
+        };
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write cleaned CSV: {e}"))?;
+        Ok(Some(format!("Cleaned CSV written to {}", path.display())))
+    }
+}
+
+pub fn cleaned_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".cleaned.csv");
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::options::ConvertOptions;
+
+    #[test]
+    fn test_cleaned_csv_matches_written_values() {
+        let dir = std::env::temp_dir().join("csv2sav_cleaned_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let csv = "id,name,joined,score\n1, ann ,1 Mar 2024,NA\n2,bob,,2.50\n";
+        std::fs::write(&input, csv).unwrap();
+        let options = ConvertOptions {
+            write_cleaned_csv: true,
+            ..ConvertOptions::default()
+        };
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome = crate::converter::convert_csv_to_zsav(
+            &input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {},
+        )
+        .unwrap();
+        assert!(outcome.warnings.iter().any(|w| w.starts_with("Cleaned CSV written to")));
+
+        let cleaned = std::fs::read_to_string(cleaned_path(&output)).unwrap();
+        assert_eq!(cleaned, "V1,V2,V3,V4\n1,ann,2024-03-01,\n2,bob,,2.5\n");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
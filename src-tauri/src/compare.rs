@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8};
+use serde::Serialize;
+
+use crate::options::CompareOptions;
+use crate::readstat_sys::*;
+use crate::readstat_writer::check;
+
+/// Dictionary entry of one variable, as far as a comparison looks at it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub label: String,
+    pub is_string: bool,
+    pub format: String,
+    /// Storage width in bytes of a string variable; 8 for numbers.
+    pub width: usize,
+    pub measure: &'static str,
+    /// Data View column width in characters.
+    pub display_width: usize,
+    pub missing: Vec<String>,
+    /// Sorted by value.
+    pub value_labels: Vec<(String, String)>,
+}
+
+/// A SAV file's dictionary and first rows.
+#[derive(Debug, Default)]
+pub struct SavContents {
+    pub rows: usize,
+    /// Name of the variable that weights the cases.
+    pub weight: Option<String>,
+    pub variables: Vec<Variable>,
+    pub data: Vec<Vec<String>>,
+}
+
+/// One dictionary attribute that differs between the files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariableChange {
+    pub variable: String,
+    /// `label`, `type`, `format`, `width`, `measure`, `display_width`, `missing` or
+    /// `value_labels`.
+    pub attribute: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CellDifference {
+    /// 1-based row number.
+    pub row: usize,
+    pub variable: String,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SavComparison {
+    /// No dictionary or sampled data differences.
+    pub identical: bool,
+    pub left_rows: usize,
+    pub right_rows: usize,
+    pub only_left: Vec<String>,
+    pub only_right: Vec<String>,
+    /// The shared variables appear in a different order.
+    pub reordered: bool,
+    pub left_weight: Option<String>,
+    pub right_weight: Option<String>,
+    pub changes: Vec<VariableChange>,
+    pub rows_compared: usize,
+    /// Differing cells among the compared rows, listed up to the options' limit.
+    pub differing_cells: usize,
+    pub differences: Vec<CellDifference>,
+}
+
+/// Compares the dictionaries of two SAV or ZSAV files, and the values of their first
+/// rows, so a re-conversion can be checked against an earlier output.
+pub fn compare_sav(left: &Path, right: &Path, options: &CompareOptions) -> Result<SavComparison, String> {
+    let left = read(left, options.sample_rows)?;
+    let right = read(right, options.sample_rows)?;
+
+    let position = |contents: &SavContents| -> HashMap<String, usize> {
+        contents
+            .variables
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.name.clone(), i))
+            .collect()
+    };
+    let (left_at, right_at) = (position(&left), position(&right));
+    let only = |from: &SavContents, other: &HashMap<String, usize>| -> Vec<String> {
+        from.variables
+            .iter()
+            .filter(|v| !other.contains_key(&v.name))
+            .map(|v| v.name.clone())
+            .collect()
+    };
+    let only_left = only(&left, &right_at);
+    let only_right = only(&right, &left_at);
+    // Shared variables as (left index, right index), in left order.
+    let shared: Vec<(usize, usize)> = left
+        .variables
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| Some((i, *right_at.get(&v.name)?)))
+        .collect();
+    let reordered = shared.windows(2).any(|w| w[0].1 > w[1].1);
+
+    let mut changes = Vec::new();
+    for &(l, r) in &shared {
+        changes.extend(variable_changes(&left.variables[l], &right.variables[r]));
+    }
+
+    let rows_compared = left.data.len().min(right.data.len());
+    let mut differing_cells = 0;
+    let mut differences = Vec::new();
+    for (row, (left_row, right_row)) in left.data.iter().zip(&right.data).enumerate() {
+        for &(l, r) in &shared {
+            if left_row[l] == right_row[r] {
+                continue;
+            }
+            differing_cells += 1;
+            if differences.len() < options.max_differences {
+                differences.push(CellDifference {
+                    row: row + 1,
+                    variable: left.variables[l].name.clone(),
+                    left: left_row[l].clone(),
+                    right: right_row[r].clone(),
+                });
+            }
+        }
+    }
+
+    let identical = only_left.is_empty()
+        && only_right.is_empty()
+        && !reordered
+        && left.weight == right.weight
+        && changes.is_empty()
+        && differing_cells == 0
+        && left.rows == right.rows;
+    Ok(SavComparison {
+        identical,
+        left_rows: left.rows,
+        right_rows: right.rows,
+        only_left,
+        only_right,
+        reordered,
+        left_weight: left.weight,
+        right_weight: right.weight,
+        changes,
+        rows_compared,
+        differing_cells,
+        differences,
+    })
+}
+
+fn variable_changes(left: &Variable, right: &Variable) -> Vec<VariableChange> {
+    let type_name = |v: &Variable| if v.is_string { "string" } else { "numeric" }.to_string();
+    let joined = |values: &[String]| values.join("; ");
+    let labels = |v: &Variable| {
+        v.value_labels
+            .iter()
+            .map(|(value, label)| format!("{value}={label}"))
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+    let attributes: [(&'static str, String, String); 8] = [
+        ("label", left.label.clone(), right.label.clone()),
+        ("type", type_name(left), type_name(right)),
+        ("format", left.format.clone(), right.format.clone()),
+        ("width", left.width.to_string(), right.width.to_string()),
+        ("measure", left.measure.to_string(), right.measure.to_string()),
+        ("display_width", left.display_width.to_string(), right.display_width.to_string()),
+        ("missing", joined(&left.missing), joined(&right.missing)),
+        ("value_labels", labels(left), labels(right)),
+    ];
+    attributes
+        .into_iter()
+        .filter(|(_, l, r)| l != r)
+        .map(|(attribute, left_value, right_value)| VariableChange {
+            variable: left.name.clone(),
+            attribute,
+            left: left_value,
+            right: right_value,
+        })
+        .collect()
+}
+
+/// State shared with the ReadStat callbacks.
+struct ReadCtx {
+    encoding: &'static Encoding,
+    contents: SavContents,
+    /// Value label set name of each variable.
+    label_set_names: Vec<Option<String>>,
+    label_sets: HashMap<String, Vec<(String, String)>>,
+    row: Vec<String>,
+    parse_error: Option<String>,
+}
+
+unsafe fn bytes<'a>(ptr: *const c_char) -> &'a [u8] {
+    if ptr.is_null() {
+        &[]
+    } else {
+        CStr::from_ptr(ptr).to_bytes()
+    }
+}
+
+unsafe fn read_ctx<'a>(ctx: *mut c_void) -> &'a mut ReadCtx {
+    &mut *(ctx as *mut ReadCtx)
+}
+
+impl ReadCtx {
+    fn decode(&self, raw: &[u8]) -> String {
+        self.encoding.decode_without_bom_handling(raw).0.into_owned()
+    }
+
+    /// Text of a value; system-missing is blank.
+    unsafe fn text(&self, value: readstat_value_t) -> String {
+        if readstat_value_is_system_missing(value) != 0 {
+            String::new()
+        } else if readstat_value_type(value) == readstat_type_t::READSTAT_TYPE_STRING {
+            self.decode(bytes(readstat_string_value(value)))
+        } else {
+            readstat_double_value(value).to_string()
+        }
+    }
+}
+
+unsafe extern "C" fn handle_metadata(metadata: *mut readstat_metadata_t, ctx: *mut c_void) -> c_int {
+    let ctx = read_ctx(ctx);
+    ctx.contents.rows = readstat_get_row_count(metadata).max(0) as usize;
+    ctx.encoding = Encoding::for_label(bytes(readstat_get_file_encoding(metadata))).unwrap_or(UTF_8);
+    READSTAT_HANDLER_OK
+}
+
+unsafe extern "C" fn handle_variable(
+    _index: c_int,
+    variable: *mut readstat_variable_t,
+    val_labels: *const c_char,
+    ctx: *mut c_void,
+) -> c_int {
+    let ctx = read_ctx(ctx);
+    let missing = (0..readstat_variable_get_missing_ranges_count(variable))
+        .map(|i| {
+            let lo = ctx.text(readstat_variable_get_missing_range_lo(variable, i));
+            let hi = ctx.text(readstat_variable_get_missing_range_hi(variable, i));
+            if lo == hi { lo } else { format!("{lo} thru {hi}") }
+        })
+        .collect();
+    let measure = match readstat_variable_get_measure(variable) {
+        readstat_measure_t::READSTAT_MEASURE_NOMINAL => "nominal",
+        readstat_measure_t::READSTAT_MEASURE_ORDINAL => "ordinal",
+        readstat_measure_t::READSTAT_MEASURE_SCALE => "scale",
+        readstat_measure_t::READSTAT_MEASURE_UNKNOWN => "unknown",
+    };
+    let variable = Variable {
+        name: ctx.decode(bytes(readstat_variable_get_name(variable))),
+        label: ctx.decode(bytes(readstat_variable_get_label(variable))),
+        is_string: readstat_variable_get_type(variable) == readstat_type_t::READSTAT_TYPE_STRING,
+        format: String::from_utf8_lossy(bytes(readstat_variable_get_format(variable))).into_owned(),
+        width: readstat_variable_get_storage_width(variable),
+        measure,
+        display_width: readstat_variable_get_display_width(variable).max(0) as usize,
+        missing,
+        value_labels: Vec::new(),
+    };
+    ctx.contents.variables.push(variable);
+    ctx.label_set_names
+        .push((!val_labels.is_null()).then(|| String::from_utf8_lossy(bytes(val_labels)).into_owned()));
+    READSTAT_HANDLER_OK
+}
+
+unsafe extern "C" fn handle_value_label(
+    val_labels: *const c_char,
+    value: readstat_value_t,
+    label: *const c_char,
+    ctx: *mut c_void,
+) -> c_int {
+    let ctx = read_ctx(ctx);
+    let entry = (ctx.text(value), ctx.decode(bytes(label)));
+    let name = String::from_utf8_lossy(bytes(val_labels)).into_owned();
+    ctx.label_sets.entry(name).or_default().push(entry);
+    READSTAT_HANDLER_OK
+}
+
+unsafe extern "C" fn handle_value(
+    _obs_index: c_int,
+    variable: *mut readstat_variable_t,
+    value: readstat_value_t,
+    ctx: *mut c_void,
+) -> c_int {
+    let ctx = read_ctx(ctx);
+    let text = ctx.text(value);
+    ctx.row.push(text);
+    if readstat_variable_get_index(variable) as usize + 1 == ctx.contents.variables.len() {
+        let row = std::mem::take(&mut ctx.row);
+        ctx.contents.data.push(row);
+    }
+    READSTAT_HANDLER_OK
+}
+
+unsafe extern "C" fn handle_fweight(variable: *mut readstat_variable_t, ctx: *mut c_void) -> c_int {
+    let ctx = read_ctx(ctx);
+    ctx.contents.weight = Some(ctx.decode(bytes(readstat_variable_get_name(variable))));
+    READSTAT_HANDLER_OK
+}
+
+unsafe extern "C" fn handle_error(message: *const c_char, ctx: *mut c_void) {
+    read_ctx(ctx).parse_error = Some(String::from_utf8_lossy(bytes(message)).trim_end().to_string());
+}
+
+/// A SAV file held in memory, read through ReadStat's I/O handlers.
+struct MemoryFile<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+unsafe fn memory_file<'a>(io_ctx: *mut c_void) -> &'a mut MemoryFile<'a> {
+    &mut *(io_ctx as *mut MemoryFile)
+}
+
+unsafe extern "C" fn memory_open(_path: *const c_char, _io_ctx: *mut c_void) -> c_int {
+    0
+}
+
+unsafe extern "C" fn memory_close(_io_ctx: *mut c_void) -> c_int {
+    0
+}
+
+unsafe extern "C" fn memory_seek(
+    offset: readstat_off_t,
+    whence: readstat_io_flags_t,
+    io_ctx: *mut c_void,
+) -> readstat_off_t {
+    let file = memory_file(io_ctx);
+    let base = match whence {
+        readstat_io_flags_t::READSTAT_SEEK_SET => 0,
+        readstat_io_flags_t::READSTAT_SEEK_CUR => file.pos as readstat_off_t,
+        readstat_io_flags_t::READSTAT_SEEK_END => file.data.len() as readstat_off_t,
+    };
+    let pos = base + offset;
+    if pos < 0 || pos as usize > file.data.len() {
+        return -1;
+    }
+    file.pos = pos as usize;
+    pos
+}
+
+unsafe extern "C" fn memory_read(buf: *mut c_void, nbyte: usize, io_ctx: *mut c_void) -> isize {
+    let file = memory_file(io_ctx);
+    let n = nbyte.min(file.data.len() - file.pos);
+    std::ptr::copy_nonoverlapping(file.data[file.pos..].as_ptr(), buf as *mut u8, n);
+    file.pos += n;
+    n as isize
+}
+
+unsafe extern "C" fn memory_update(
+    _file_size: std::os::raw::c_long,
+    _progress_handler: readstat_progress_handler,
+    _user_ctx: *mut c_void,
+    _io_ctx: *mut c_void,
+) -> readstat_error_t {
+    readstat_error_t::READSTAT_OK
+}
+
+/// Reads the dictionary and the first `sample_rows` rows of a SAV or ZSAV file.
+pub fn read(path: &Path, sample_rows: usize) -> Result<SavContents, String> {
+    let c_path = path
+        .to_str()
+        .and_then(|p| CString::new(p).ok())
+        .ok_or("Input path is not valid UTF-8")?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    parse(&c_path, None, sample_rows, &name)
+}
+
+/// Like [`read`], for a SAV or ZSAV file held in memory.
+pub fn read_bytes(data: &[u8], sample_rows: usize) -> Result<SavContents, String> {
+    let mut file = MemoryFile { data, pos: 0 };
+    parse(c"", Some(&mut file), sample_rows, "data")
+}
+
+fn parse(
+    path: &CStr,
+    memory: Option<&mut MemoryFile>,
+    sample_rows: usize,
+    name: &str,
+) -> Result<SavContents, String> {
+    let mut ctx = ReadCtx {
+        encoding: UTF_8,
+        contents: SavContents::default(),
+        label_set_names: Vec::new(),
+        label_sets: HashMap::new(),
+        row: Vec::new(),
+        parse_error: None,
+    };
+    let status = unsafe {
+        let parser = readstat_parser_init();
+        if parser.is_null() {
+            return Err("Failed to init ReadStat parser".to_string());
+        }
+        readstat_set_handler_character_encoding(parser, std::ptr::null());
+        readstat_set_metadata_handler(parser, Some(handle_metadata));
+        readstat_set_variable_handler(parser, Some(handle_variable));
+        readstat_set_value_label_handler(parser, Some(handle_value_label));
+        readstat_set_fweight_handler(parser, Some(handle_fweight));
+        readstat_set_error_handler(parser, Some(handle_error));
+        if let Some(file) = memory {
+            readstat_set_open_handler(parser, Some(memory_open));
+            readstat_set_close_handler(parser, Some(memory_close));
+            readstat_set_seek_handler(parser, Some(memory_seek));
+            readstat_set_read_handler(parser, Some(memory_read));
+            readstat_set_update_handler(parser, Some(memory_update));
+            readstat_set_io_ctx(parser, file as *mut MemoryFile as *mut c_void);
+        }
+        // Without a value handler ReadStat skips the data entirely.
+        if sample_rows > 0 {
+            readstat_set_value_handler(parser, Some(handle_value));
+            readstat_set_row_limit(parser, sample_rows as std::os::raw::c_long);
+        }
+        let status = readstat_parse_sav(parser, path.as_ptr(), &mut ctx as *mut ReadCtx as *mut c_void);
+        readstat_parser_free(parser);
+        status
+    };
+    check(status).map_err(|e| match &ctx.parse_error {
+        Some(detail) => format!("Failed to read {name}: {e} ({detail})"),
+        None => format!("Failed to read {name}: {e}"),
+    })?;
+
+    for (variable, set) in ctx.contents.variables.iter_mut().zip(&ctx.label_set_names) {
+        if let Some(labels) = set.as_ref().and_then(|s| ctx.label_sets.get(s)) {
+            variable.value_labels = labels.clone();
+            variable.value_labels.sort();
+        }
+    }
+    Ok(ctx.contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+
+    use crate::options::ConvertOptions;
+
+    fn convert(dir: &Path, name: &str, csv: &str, options: &ConvertOptions) -> std::path::PathBuf {
+        let input = dir.join(format!("{name}.csv"));
+        let output = dir.join(format!("{name}.zsav"));
+        std::fs::write(&input, csv).unwrap();
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, options, &cancel).unwrap();
+        crate::converter::convert_csv_to_zsav(&input, &output, &schema, options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn test_compare_sav() {
+        let dir = std::env::temp_dir().join("csv2sav_compare_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = ConvertOptions::default();
+        let a = convert(&dir, "a", "id,name,score\n1,ann,2.5\n2,bob,3\n", &options);
+        let b = convert(&dir, "b", "id,name,score\n1,ann,2.5\n2,bob,3\n", &options);
+        let c = convert(&dir, "c", "id,name,total\n1,ann,2.5\n2,bobby,3\n", &options);
+
+        let same = compare_sav(&a, &b, &CompareOptions::default()).unwrap();
+        assert!(same.identical, "{same:?}");
+        assert_eq!((same.left_rows, same.rows_compared), (2, 2));
+
+        let diff = compare_sav(&a, &c, &CompareOptions::default()).unwrap();
+        assert!(!diff.identical);
+        assert!(diff.only_left.is_empty() && !diff.reordered);
+        let attributes: Vec<(&str, &str)> =
+            diff.changes.iter().map(|c| (c.variable.as_str(), c.attribute)).collect();
+        assert_eq!(attributes, [("V3", "label")]);
+        assert_eq!(diff.differing_cells, 1);
+        assert_eq!(
+            diff.differences,
+            vec![CellDifference {
+                row: 2,
+                variable: "V2".to_string(),
+                left: "bob".to_string(),
+                right: "bobby".to_string(),
+            }]
+        );
+
+        let dictionary_only = CompareOptions { sample_rows: 0, ..Default::default() };
+        assert_eq!(compare_sav(&a, &c, &dictionary_only).unwrap().rows_compared, 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,18 +1,59 @@
-use std::cell::Cell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use std::io::{BufReader, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
 
-use crate::readstat_writer::{ColDef, ColType, Value, Writer};
-use crate::schema::{self, ColType as SchemaColType, CsvSchema};
+use csv::ByteRecord;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::anonymize::{self, AnonymizationMap};
+use crate::cancel::{CancelToken, TaskError};
+use crate::cleaned::{self, CleanedCsv};
+use crate::dates::{self, MonthNames};
+use crate::dictionary::{self, DataDictionary, MAX_MISSING_VALUES};
+use crate::googleforms;
+use crate::input;
+use crate::issues::{self, Action, IssueList, IssueLog};
+use crate::labels::{self, MAX_LABEL_BYTES, MAX_VALUE_LABEL_BYTES};
+use crate::options::{
+    Anonymize, ConvertOptions, LabelOverflow, NulBytes, OutOfRange, WhitespaceOnly,
+};
+use crate::output::SharedBuffer;
+use crate::pairs::{LabelPair, PairTracker};
+use crate::provenance::{self, Provenance};
+use crate::qualtrics;
+use crate::readstat_writer::{
+    self, ColDef, ColType, FileMeta, LabelValue, Measure, MrSet, Value, Writer,
+};
+use crate::reshape::Reshaper;
+use crate::retry::{self, RetryReader};
+use crate::schema::{self, CachedRecords, ColType as SchemaColType, CsvSchema};
+use crate::script::RowScript;
+use crate::surveymonkey;
 
 const CSV_BUF_SIZE: usize = 512 * 1024;
 const PROGRESS_INTERVAL: usize = 10_000;
 const CANCEL_CHECK_INTERVAL: usize = 1_000;
+/// Records parsed in parallel at a time before being written in order.
+const BATCH_ROWS: usize = 4_096;
+/// Cells per batch; very wide files get fewer rows per batch so memory stays flat.
+const BATCH_CELLS: usize = 1 << 20;
+/// Offending values kept per truncated column, each shortened to EXAMPLE_CHARS.
+const TRUNCATION_EXAMPLES: usize = 3;
+const EXAMPLE_CHARS: usize = 80;
+/// F40 is the widest SPSS numeric format; below this even negative integers fit it.
+const MAX_MAGNITUDE: f64 = 1e39;
 
-fn truncate_utf8(s: &str, max_bytes: usize) -> &str {
+/// Cuts `s` to at most `max_bytes` without splitting a UTF-8 character.
+pub fn truncate_utf8(s: &str, max_bytes: usize) -> &str {
     if s.len() <= max_bytes {
         return s;
     }
@@ -47,156 +88,3109 @@ impl<R: Read> Read for CountingReader<R> {
     }
 }
 
-fn make_col_defs(schema: &CsvSchema) -> Vec<ColDef> {
+/// SAV variable name for the column at `index`; the CSV header is kept as the label.
+fn var_name(index: usize) -> String {
+    format!("V{}", index + 1)
+}
+
+/// SAV variable names for every column: the data dictionary's where it has one,
+/// otherwise the question id of a Qualtrics export or the `Q3_1` style name of a
+/// SurveyMonkey column when it makes a valid, unused name, otherwise `V1`, `V2`, …
+pub fn variable_names(
+    schema: &CsvSchema,
+    options: &ConvertOptions,
+    dictionary: Option<&DataDictionary>,
+) -> Vec<String> {
+    let mut taken = HashSet::new();
     schema
         .headers
         .iter()
-        .zip(&schema.col_types)
         .enumerate()
-        .map(|(i, (header, col_type))| {
-            let name = format!("V{}", i + 1);
-            let sav_type = match col_type {
-                SchemaColType::Numeric => ColType::Numeric,
-                SchemaColType::String(w) => ColType::String(*w),
-            };
-            ColDef {
-                name,
-                label: header.clone(),
-                col_type: sav_type,
+        .map(|(i, header)| {
+            let chosen = options.columns.get(header).and_then(|c| c.name.clone());
+            if let Some(name) = chosen.or_else(|| dictionary?.get(header)?.name.clone()) {
+                return name;
             }
+            let survey_name = schema
+                .surveymonkey
+                .as_ref()
+                .and_then(|s| s.names[i].clone());
+            schema
+                .qualtrics
+                .as_ref()
+                .and_then(|_| dictionary::sanitize_name(header))
+                .or(survey_name)
+                .filter(|name| taken.insert(name.to_uppercase()))
+                .unwrap_or_else(|| var_name(i))
         })
         .collect()
 }
 
-/// Converts CSV to ZSAV using two passes:
-/// 1. Count rows via CSV parser (handles quoted multi-line fields).
-/// 2. Stream rows into ZSAV writer with exact row count.
-pub fn convert_csv_to_zsav(
-    input: &Path,
+/// Builds the SAV dictionary, applying a data dictionary's names, labels, value
+/// labels, missing codes and measure levels where it has an entry. Labels too long
+/// for SPSS are cut at a character boundary and, depending on the overflow policy,
+/// kept in full as document lines; each cut is reported in `warnings`.
+fn make_col_defs(
+    schema: &CsvSchema,
+    options: &ConvertOptions,
+    dictionary: Option<&DataDictionary>,
+    warnings: &mut Vec<String>,
+) -> Result<(Vec<ColDef>, FileMeta), String> {
+    let mut meta = FileMeta::default();
+    let mut cols = Vec::with_capacity(schema.headers.len());
+    let mut names = HashSet::new();
+    let variable_names = variable_names(schema, options, dictionary);
+    for (i, ((header, col_type), name)) in schema
+        .headers
+        .iter()
+        .zip(&schema.col_types)
+        .zip(variable_names)
+        .enumerate()
+    {
+        let spec = dictionary.and_then(|d| d.get(header));
+        let column = options.columns.get(header);
+        if column.is_some_and(|c| c.name.is_some()) {
+            dictionary::validate_name(&name).map_err(|e| format!("Column '{header}': {e}"))?;
+        }
+        // SPSS variable names are case-insensitive.
+        if !names.insert(name.to_uppercase()) {
+            return Err(format!("Variable name '{name}' is used by more than one column"));
+        }
+        let sav_type = match col_type {
+            SchemaColType::Numeric { width, decimals } => ColType::Numeric {
+                width: *width,
+                decimals: *decimals,
+            },
+            SchemaColType::String(w) => ColType::String(*w),
+            SchemaColType::Date | SchemaColType::NumericDate { .. } => {
+                ColType::Date(dates::DATE_FORMAT)
+            }
+            SchemaColType::Period(format) => ColType::Date(dates::period_format_spec(*format)),
+            SchemaColType::Checkbox
+            | SchemaColType::Dummy { .. }
+            | SchemaColType::Selected { .. } => ColType::Numeric { width: 1, decimals: 0 },
+            SchemaColType::Timestamp { .. } => ColType::Date(dates::DATETIME_FORMAT),
+        };
+        // Anonymized columns get the type of their replacement and none of the codes
+        // a dictionary or survey layout attaches to the original values.
+        let anonymized = options.anonymize(header);
+        let (sav_type, spec_codes) = match (anonymized, col_type) {
+            (None, _) => (sav_type, spec),
+            (Some(Anonymize::Year), _) => (ColType::Numeric { width: 4, decimals: 0 }, None),
+            (Some(Anonymize::Mask), SchemaColType::String(w)) => (ColType::String(*w), None),
+            (Some(_), _) => (ColType::String(anonymize::HASH_WIDTH), None),
+        };
+        let is_string = matches!(sav_type, ColType::String(_));
+
+        let question = schema
+            .qualtrics
+            .as_ref()
+            .map(|q| &q.labels)
+            .or(schema.surveymonkey.as_ref().map(|s| &s.labels))
+            .and_then(|labels| labels.get(i))
+            .filter(|text| !text.is_empty());
+        let full_label = column
+            .and_then(|c| c.label.as_ref())
+            .or(spec.and_then(|s| s.label.as_ref()))
+            .or(question)
+            .map_or(header.as_str(), String::as_str);
+        let policy = options.label_overflow;
+        let label = labels::fit(full_label, MAX_LABEL_BYTES, policy);
+        if label.len() < full_label.len() {
+            if policy == LabelOverflow::Document {
+                meta.notes.extend(labels::document_lines(&name, full_label));
+            }
+            warnings.push(format!(
+                "Column '{name}': label longer than {MAX_LABEL_BYTES} bytes {}",
+                labels::overflow_note(policy)
+            ));
+        }
+
+        let mut missing_strings = match (is_string, options.whitespace_only) {
+            (true, WhitespaceOnly::Missing) => vec![String::new()],
+            _ => Vec::new(),
+        };
+        let mut missing_numbers = Vec::new();
+        let mut value_labels: Vec<(LabelValue, String)> = Vec::new();
+        let code = |text: &str, source: &str| -> Result<LabelValue, String> {
+            if is_string {
+                return Ok(LabelValue::Str(text.to_string()));
+            }
+            text.trim()
+                .parse()
+                .map(LabelValue::Number)
+                .map_err(|_| format!("{source}: '{text}' is not a number (column '{header}')"))
+        };
+        let coded = column.filter(|_| anonymized.is_none());
+        let label_sources = spec_codes
+            .map(|s| ("Data dictionary", &s.value_labels))
+            .into_iter()
+            .chain(coded.map(|c| ("Value labels", &c.value_labels)));
+        let missing_sources = spec_codes
+            .map(|s| ("Data dictionary", &s.missing))
+            .into_iter()
+            .chain(coded.map(|c| ("Missing values", &c.missing)));
+        let mut missing_range = None;
+        for (source, texts) in missing_sources {
+            for text in texts {
+                if let Some((lo, hi)) = split_range(text) {
+                    let (LabelValue::Number(lo), LabelValue::Number(hi)) =
+                        (code(lo, source)?, code(hi, source)?)
+                    else {
+                        return Err(format!(
+                            "{source}: missing range '{text}' needs a numeric column (column '{header}')"
+                        ));
+                    };
+                    if missing_range.replace((lo.min(hi), lo.max(hi))).is_some() {
+                        return Err(format!(
+                            "Column '{header}': SPSS allows one missing range per variable"
+                        ));
+                    }
+                    continue;
+                }
+                match code(text, source)? {
+                    LabelValue::Number(n) if !missing_numbers.contains(&n) => {
+                        missing_numbers.push(n)
+                    }
+                    LabelValue::Str(s) if !missing_strings.contains(&s) => missing_strings.push(s),
+                    _ => {}
+                }
+            }
+        }
+        let declared = missing_strings.len()
+            + missing_numbers.len()
+            + if missing_range.is_some() { 2 } else { 0 };
+        if declared > MAX_MISSING_VALUES {
+            return Err(format!(
+                "Column '{header}': at most {MAX_MISSING_VALUES} missing values are allowed, including the blank one; a range counts as two"
+            ));
+        }
+        for (source, labels) in label_sources {
+            for (value, full_text) in labels {
+                let text = labels::fit(full_text, MAX_VALUE_LABEL_BYTES, policy);
+                if text.len() < full_text.len() {
+                    if policy == LabelOverflow::Document {
+                        let var = format!("{name} value {value}");
+                        meta.notes.extend(labels::document_lines(&var, full_text));
+                    }
+                    warnings.push(format!(
+                        "Column '{name}': value label for '{value}' longer than {MAX_VALUE_LABEL_BYTES} bytes {}",
+                        labels::overflow_note(policy)
+                    ));
+                }
+                let value = code(value, source)?;
+                value_labels.retain(|(v, _)| *v != value);
+                value_labels.push((value, text.into_owned()));
+            }
+        }
+        let option = match (col_type, &schema.surveymonkey) {
+            _ if anonymized.is_some() => None,
+            (SchemaColType::Checkbox, Some(survey)) => Some(survey.option(i)),
+            (SchemaColType::Dummy { option, .. }, _) => Some(option.as_str()),
+            (SchemaColType::Selected { option }, _) => Some(option.as_str()),
+            _ => None,
+        };
+        if let Some(option) = option {
+            if !value_labels.iter().any(|(v, _)| matches!(v, LabelValue::Number(n) if *n == 1.0)) {
+                let option = labels::fit(option, MAX_VALUE_LABEL_BYTES, policy);
+                value_labels.push((LabelValue::Number(1.0), option.into_owned()));
+            }
+        }
+        if schema.qualtrics.is_some() && anonymized.is_none() && !matches!(sav_type, ColType::Date(_)) {
+            if is_string {
+                let code = qualtrics::SEEN_UNANSWERED.to_string();
+                if declared < MAX_MISSING_VALUES && !missing_strings.contains(&code) {
+                    missing_strings.push(code);
+                }
+            } else if declared < MAX_MISSING_VALUES
+                && !missing_numbers.contains(&qualtrics::SEEN_UNANSWERED)
+            {
+                missing_numbers.push(qualtrics::SEEN_UNANSWERED);
+            }
+        }
+
+        cols.push(ColDef {
+            name,
+            label: label.to_string(),
+            col_type: sav_type,
+            missing_strings,
+            missing_numbers,
+            missing_range,
+            value_labels,
+            measure: spec.and_then(|s| s.measure),
+            alignment: column.and_then(|c| c.alignment),
+            display_width: column.and_then(|c| c.display_width),
+        });
+    }
+    if let Some(version) = options.xport {
+        let names: Vec<String> = cols.iter().map(|c| c.name.clone()).collect();
+        let sas_names = readstat_writer::sas_names(&names, version);
+        for ((col, header), name) in cols.iter_mut().zip(&schema.headers).zip(sas_names) {
+            if col.name != name {
+                warnings.push(format!(
+                    "Column '{header}': variable name '{}' is not valid in XPT {version:?}; written as '{name}'",
+                    col.name
+                ));
+                col.name = name;
+            }
+        }
+    }
+    if let Some(survey) = &schema.surveymonkey {
+        let names: Vec<String> = cols.iter().map(|c| c.name.clone()).collect();
+        meta.notes.extend(surveymonkey::mrsets_syntax(&survey.groups, &names));
+    }
+    Ok((cols, meta))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TruncationReport {
+    pub column: String,
+    /// Declared width the values were cut to, in bytes.
+    pub width: usize,
+    pub count: usize,
+    pub examples: Vec<String>,
+}
+
+impl TruncationReport {
+    fn message(&self) -> String {
+        format!(
+            "Column '{}': {} value(s) truncated to {} bytes",
+            self.column, self.count, self.width
+        )
+    }
+}
+
+/// Whether SPSS can store and display `n` sensibly.
+fn is_representable(n: f64) -> bool {
+    n == 0.0 || (n.is_normal() && n.abs() < MAX_MAGNITUDE)
+}
+
+/// Applies the out-of-range policy to an unrepresentable value.
+fn fix_out_of_range(n: f64, policy: OutOfRange) -> Option<f64> {
+    match policy {
+        OutOfRange::Keep => Some(n),
+        OutOfRange::SetMissing => None,
+        OutOfRange::Clamp if n.is_nan() => None,
+        OutOfRange::Clamp if n.is_subnormal() => Some(0.0),
+        OutOfRange::Clamp => Some(n.clamp(-MAX_MAGNITUDE, MAX_MAGNITUDE)),
+    }
+}
+
+fn out_of_range_message(column: &str, count: usize, policy: OutOfRange) -> String {
+    let outcome = match policy {
+        OutOfRange::Keep => "kept as is",
+        OutOfRange::Clamp => "clamped",
+        OutOfRange::SetMissing => "set to missing",
+    };
+    format!(
+        "Column '{column}': {count} value(s) outside the range SPSS can display (infinite, NaN, subnormal or |x| ≥ {MAX_MAGNITUDE:e}) {outcome}"
+    )
+}
+
+fn example_value(field: &str) -> String {
+    match field.char_indices().nth(EXAMPLE_CHARS) {
+        Some((end, _)) => format!("{}…", &field[..end]),
+        None => field.to_string(),
+    }
+}
+
+/// One written SAV file. Wide CSVs split by column range produce several, and each
+/// subset output is one more.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputPart {
+    pub path: PathBuf,
+    /// 1-based CSV columns of the file's first and last variable: an inclusive range
+    /// of columns unless the file is a subset output.
+    pub first_column: usize,
+    pub last_column: usize,
+    /// Hex-encoded SHA-256 of the file.
+    pub sha256: String,
+}
+
+/// Schema column of the case weight or filter (`role`), which must be a numeric
+/// column kept as is.
+fn special_column(
+    schema: &CsvSchema,
+    options: &ConvertOptions,
+    role: &str,
+    header: &str,
+) -> Result<usize, String> {
+    let i = schema
+        .headers
+        .iter()
+        .position(|h| h == header)
+        .ok_or_else(|| format!("{role} column '{header}' not found"))?;
+    if options.anonymize(header).is_some() {
+        return Err(format!("{role} column '{header}' cannot be anonymized"));
+    }
+    if !matches!(schema.col_types[i], SchemaColType::Numeric { .. }) {
+        return Err(format!("{role} column '{header}' must be numeric"));
+    }
+    Ok(i)
+}
+
+/// Document lines holding the syntax that turns the filter on.
+fn filter_syntax(name: &str) -> Vec<String> {
+    vec!["Filter (SPSS syntax):".to_string(), format!("FILTER BY {name}.")]
+}
+
+/// Multiple response sets over the written variables: the checkbox groups of a
+/// SurveyMonkey export and, when registering them is on, the checkbox column families
+/// found by inference. Dropped and anonymized columns are left out of their sets.
+fn mr_sets(schema: &CsvSchema, options: &ConvertOptions, kept: &[usize]) -> Vec<MrSet> {
+    let vars = |columns: &[usize]| -> Vec<usize> {
+        columns
+            .iter()
+            .filter(|&&i| options.anonymize(&schema.headers[i]).is_none())
+            .filter_map(|&i| kept.iter().position(|&k| k == i))
+            .collect()
+    };
+    let mut sets = Vec::new();
+    for group in schema.surveymonkey.iter().flat_map(|s| &s.groups) {
+        sets.push(MrSet {
+            name: group.name.clone(),
+            label: group.label.clone(),
+            counted_value: Some("1".to_string()),
+            vars: vars(&group.columns),
+        });
+    }
+    let families = schema.response_sets.iter().filter(|_| options.register_response_sets);
+    for (n, set) in families.enumerate() {
+        let name = dictionary::sanitize_name(&set.stem).unwrap_or_else(|| format!("SET{}", n + 1));
+        let mut name = format!("${}", truncate_utf8(&name, dictionary::MAX_NAME_BYTES - 1));
+        if sets.iter().any(|s: &MrSet| s.name.eq_ignore_ascii_case(&name)) {
+            name = format!("$SET{}", n + 1);
+        }
+        sets.push(MrSet {
+            name,
+            label: set.stem.clone(),
+            counted_value: set.counted_value().map(str::to_string),
+            vars: vars(&set.columns),
+        });
+    }
+    // SPSS needs at least two variables in a set.
+    sets.retain(|set| set.vars.len() >= 2);
+    sets
+}
+
+/// Variable sets from the options, as indices of written variables. Columns that
+/// are missing or dropped are left out with a warning.
+fn variable_sets(
+    schema: &CsvSchema,
+    options: &ConvertOptions,
+    kept: &[usize],
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(String, Vec<usize>)>, String> {
+    let mut sets = Vec::new();
+    for (name, headers) in &options.variable_sets {
+        if name.trim().is_empty() || name.contains(['=', '\n']) {
+            return Err(format!("Invalid variable set name '{name}'"));
+        }
+        let mut vars = Vec::new();
+        for header in headers {
+            let Some(i) = schema.headers.iter().position(|h| h == header) else {
+                warnings.push(format!("Variable set '{name}': column '{header}' not found"));
+                continue;
+            };
+            match kept.iter().position(|&k| k == i) {
+                Some(var) if !vars.contains(&var) => vars.push(var),
+                Some(_) => {}
+                None => warnings.push(format!("Variable set '{name}': column '{header}' is dropped")),
+            }
+        }
+        if vars.is_empty() {
+            warnings.push(format!("Variable set '{name}' has no columns; left out"));
+        } else {
+            sets.push((name.clone(), vars));
+        }
+    }
+    Ok(sets)
+}
+
+/// Path and written variables of each subset output, in the order of their headers.
+fn subset_outputs(
+    schema: &CsvSchema,
+    options: &ConvertOptions,
+    kept: &[usize],
     output: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(PathBuf, Vec<usize>)>, String> {
+    let mut subsets = Vec::new();
+    for (name, headers) in &options.subset_outputs {
+        let path = output.parent().unwrap_or(Path::new("")).join(name);
+        if name.trim().is_empty() || path == output {
+            return Err(format!("Invalid subset output name '{name}'"));
+        }
+        let mut vars = Vec::new();
+        for header in headers {
+            let Some(i) = schema.headers.iter().position(|h| h == header) else {
+                warnings.push(format!("Subset output '{name}': column '{header}' not found"));
+                continue;
+            };
+            match kept.iter().position(|&k| k == i) {
+                Some(var) if !vars.contains(&var) => vars.push(var),
+                Some(_) => {}
+                None => {
+                    warnings.push(format!("Subset output '{name}': column '{header}' is dropped"))
+                }
+            }
+        }
+        if vars.len() > options.max_columns {
+            return Err(format!(
+                "Subset output '{name}' has more than {} columns",
+                options.max_columns
+            ));
+        }
+        if vars.is_empty() {
+            warnings.push(format!("Subset output '{name}' has no columns; not written"));
+        } else {
+            subsets.push((path, vars));
+        }
+    }
+    Ok(subsets)
+}
+
+/// Label pairs to merge, checked against every row when inference only saw a sample.
+/// Pairs the full data contradicts are left alone, with a warning.
+fn merged_label_pairs(
+    input: Input<'_, '_>,
     csv_schema: &CsvSchema,
-    cancelled: &AtomicBool,
-    on_progress: &dyn Fn(usize, u64, u64),
-) -> Result<usize, String> {
-    let total_rows = schema::count_rows(input, cancelled)?;
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<LabelPair>, TaskError> {
+    if !options.merge_label_columns || csv_schema.label_pairs.iter().all(|p| p.complete) {
+        return Ok(csv_schema.label_pairs.clone());
+    }
+    let source = open_records(input, csv_schema, options)?;
+    let mut read = source.read;
+    if let Some(reshaper) = &csv_schema.reshape {
+        read = reshaped(read, Rc::new(RefCell::new(reshaper.clone())));
+    }
+    let script = options.row_script.as_deref().map(RowScript::load).transpose()?;
+    let headers = &csv_schema.headers[..csv_schema.fields()];
+    let mut tracker = PairTracker::for_pairs(&csv_schema.label_pairs);
+    let mut record = ByteRecord::new();
+    let mut row = 0usize;
+    while read(&mut record).map_err(|e| format!("CSV read error at row {}: {e}", row + 1))? {
+        row += 1;
+        if row.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+            cancel.check()?;
+        }
+        if let Some(script) = &script {
+            script.apply(headers, &mut record, row)?;
+        }
+        tracker.observe(|i| record.get(i).and_then(|field| std::str::from_utf8(field).ok()));
+    }
+    let pairs = tracker.finish(true);
+    for pair in &csv_schema.label_pairs {
+        if !pairs.iter().any(|p| p.code == pair.code && p.name == pair.name) {
+            warnings.push(format!(
+                "Columns '{}' and '{}' do not pair up over the whole file; both kept",
+                csv_schema.headers[pair.code], csv_schema.headers[pair.name]
+            ));
+        }
+    }
+    Ok(pairs)
+}
 
-    if cancelled.load(Ordering::Relaxed) {
-        return Err("Cancelled".to_string());
+fn column_ranges(col_count: usize, max_columns: usize, split: bool) -> Vec<Range<usize>> {
+    if !split || col_count <= max_columns {
+        return vec![Range { start: 0, end: col_count }];
     }
+    let step = max_columns.max(1);
+    (0..col_count)
+        .step_by(step)
+        .map(|start| start..(start + step).min(col_count))
+        .collect()
+}
 
-    let col_defs = make_col_defs(csv_schema);
-    let out_file =
-        File::create(output).map_err(|e| format!("Failed to create ZSAV file: {e}"))?;
-    let mut writer = Writer::new_zsav(out_file, &col_defs, total_rows)
-        .map_err(|e| format!("Failed to init writer: {e}"))?;
+/// `data.zsav` → `data_part2.zsav`.
+fn part_path(output: &Path, n: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}_part{n}");
+    if let Some(ext) = output.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    output.with_file_name(name)
+}
 
-    let csv_file =
-        File::open(input).map_err(|e| format!("Failed to open CSV for conversion: {e}"))?;
-    let (counting, bytes_counter) = CountingReader::new(csv_file);
-    let csv_buf = BufReader::with_capacity(CSV_BUF_SIZE, counting);
-    let mut reader = csv::Reader::from_reader(csv_buf);
+pub struct ConvertOutcome {
+    pub rows: usize,
+    /// The written files; exactly one unless the columns were split.
+    pub parts: Vec<OutputPart>,
+    pub warnings: Vec<String>,
+    pub truncations: Vec<TruncationReport>,
+    /// What was altered, when only checking.
+    pub issues: IssueList,
+}
 
-    let col_types = &csv_schema.col_types;
-    let col_count = col_types.len();
-    let mut row_count = 0usize;
-    let mut string_buf: Vec<String> = vec![String::new(); col_count];
+/// Rows per batch for a file `col_count` columns wide.
+fn batch_rows(col_count: usize) -> usize {
+    (BATCH_CELLS / col_count.max(1)).clamp(1, BATCH_ROWS)
+}
 
-    for result in reader.records() {
-        let record =
-            result.map_err(|e| format!("CSV read error at row {}: {e}", row_count + 1))?;
-        row_count += 1;
+/// A converted cell. Text is a byte range into its record rather than a borrow, so
+/// one flat arena indexed by row and column is reused across batches.
+#[derive(Debug, Clone, Copy)]
+enum CellValue {
+    Number(Option<f64>),
+    Text { start: usize, len: usize },
+    /// Text that is not in the record, such as an anonymized value; an index into
+    /// the row's owned strings.
+    Owned(usize),
+}
 
-        if row_count % CANCEL_CHECK_INTERVAL == 0 && cancelled.load(Ordering::Relaxed) {
-            drop(writer);
-            let _ = std::fs::remove_file(output);
-            return Err("Cancelled".to_string());
+impl CellValue {
+    fn text(record: &ByteRecord, s: &str) -> Self {
+        if s.is_empty() {
+            return CellValue::Text { start: 0, len: 0 };
         }
+        // Text values are always subslices of the record's field buffer.
+        let start = s.as_ptr() as usize - record.as_slice().as_ptr() as usize;
+        CellValue::Text { start, len: s.len() }
+    }
 
-        for i in 0..col_count {
-            let field = record.get(i).unwrap_or("").trim();
-            string_buf[i].clear();
-            match &col_types[i] {
-                SchemaColType::String(max_width) => {
-                    string_buf[i].push_str(truncate_utf8(field, *max_width));
-                }
-                _ => {
-                    string_buf[i].push_str(field);
-                }
+    fn resolve<'r>(self, record: &'r ByteRecord, owned: &'r [String]) -> Value<'r> {
+        match self {
+            CellValue::Number(n) => Value::Number(n),
+            CellValue::Owned(i) => Value::Str(&owned[i]),
+            CellValue::Text { start, len } => {
+                let bytes = &record.as_slice()[start..start + len];
+                Value::Str(std::str::from_utf8(bytes).unwrap_or_default())
             }
         }
+    }
+}
 
-        let row_values: Vec<Value<'_>> = col_types
-            .iter()
-            .enumerate()
-            .map(|(i, col_type)| {
-                let field = string_buf[i].as_str();
-                match col_type {
-                    SchemaColType::Numeric => {
-                        if field.is_empty() {
-                            Value::Number(None)
-                        } else {
-                            match field.parse::<f64>() {
-                                Ok(n) => Value::Number(Some(n)),
-                                Err(_) => Value::Number(None),
-                            }
+/// Something the parallel parse noticed about a cell, applied in row order afterwards.
+enum CellEvent<'a> {
+    /// Cut to the column width; holds the full trimmed text.
+    Truncated(&'a str),
+    /// Outside the SPSS range; holds the text and what the policy did to it.
+    OutOfRange(&'a str, Option<Action>),
+    /// Could not be parsed and was written as missing.
+    SetMissing(Cow<'a, str>),
+    /// Text holding NUL characters, written as the NUL policy says.
+    Nul(&'a str),
+}
+
+/// What converting a row produced besides the cell values.
+#[derive(Default)]
+struct RowOutput<'r> {
+    events: Vec<(usize, CellEvent<'r>)>,
+    /// Strings referenced by [`CellValue::Owned`].
+    owned: Vec<String>,
+}
+
+/// An output file being written: its path, the columns it holds and its writer.
+type OutputWriter = (PathBuf, Vec<usize>, Writer);
+
+/// Records and converted cells of one batch, handed to the [`RowWriter`] thread and
+/// back again for reuse.
+#[derive(Default)]
+struct Batch {
+    records: Vec<ByteRecord>,
+    cells: Vec<CellValue>,
+    /// Strings of each row referenced by [`CellValue::Owned`].
+    owned: Vec<Vec<String>>,
+    /// Rows to write, from the start of `records`.
+    rows: usize,
+    /// Rows converted before this batch, for error messages.
+    first_row: usize,
+}
+
+/// Batches being filled, queued and written at once; the most that are allocated.
+const BATCHES_IN_FLIGHT: usize = 3;
+
+/// Feeds converted batches to the writers on a thread of its own, so ReadStat's
+/// compression of one batch overlaps reading and converting the next.
+struct RowWriter {
+    batches: Option<SyncSender<Batch>>,
+    written: Receiver<Batch>,
+    allocated: usize,
+    thread: Option<JoinHandle<Result<Vec<OutputWriter>, String>>>,
+}
+
+impl RowWriter {
+    /// `slots` holds the writers and variable indices of every column.
+    fn spawn(mut writers: Vec<OutputWriter>, slots: Vec<Vec<(usize, usize)>>) -> Self {
+        let (batches, queued) = mpsc::sync_channel::<Batch>(1);
+        let (done, written) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let stride = slots.len().max(1);
+            for batch in queued {
+                for (k, record) in batch.records[..batch.rows].iter().enumerate() {
+                    let write_error =
+                        |e: String| format!("Failed to write row {}: {e}", batch.first_row + k + 1);
+                    let row = &batch.cells[k * stride..(k + 1) * stride];
+                    for (_, _, writer) in writers.iter_mut() {
+                        writer.begin_row().map_err(write_error)?;
+                    }
+                    for (&cell, slot) in row.iter().zip(&slots) {
+                        if slot.is_empty() {
+                            continue;
                         }
+                        let value = cell.resolve(record, &batch.owned[k]);
+                        for &(w, index) in slot {
+                            writers[w].2.insert(index, value).map_err(write_error)?;
+                        }
+                    }
+                    for (_, _, writer) in writers.iter_mut() {
+                        writer.end_row().map_err(write_error)?;
                     }
-                    SchemaColType::String(_) => Value::Str(field),
                 }
-            })
-            .collect();
+                let _ = done.send(batch);
+            }
+            Ok(writers)
+        });
+        Self { batches: Some(batches), written, allocated: 0, thread: Some(thread) }
+    }
 
-        writer
-            .write_row(&row_values)
-            .map_err(|e| format!("Failed to write row {}: {e}", row_count))?;
+    /// An empty batch to fill: one already written, or a new one while fewer than
+    /// [`BATCHES_IN_FLIGHT`] exist.
+    fn next_batch(&mut self) -> Batch {
+        if let Ok(batch) = self.written.try_recv() {
+            return batch;
+        }
+        if self.allocated < BATCHES_IN_FLIGHT {
+            self.allocated += 1;
+            return Batch::default();
+        }
+        // A failed thread sends nothing back; `write` then reports its error.
+        self.written.recv().unwrap_or_default()
+    }
 
-        if row_count % PROGRESS_INTERVAL == 0 {
-            on_progress(row_count, bytes_counter.get(), csv_schema.file_size);
+    fn write(&mut self, batch: Batch) -> Result<(), String> {
+        if let Some(batches) = &self.batches {
+            if batches.send(batch).is_ok() {
+                return Ok(());
+            }
         }
+        // The thread only stops before `finish` on an error.
+        Err(self.finish().err().unwrap_or_default())
     }
 
-    writer
-        .finish()
-        .map_err(|e| format!("Failed to finalize ZSAV file: {e}"))?;
+    /// Waits for the queued batches to be written and returns the writers.
+    fn finish(&mut self) -> Result<Vec<OutputWriter>, String> {
+        self.batches = None;
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| "Writer thread panicked".to_string())?,
+            None => Err("Writer thread already finished".to_string()),
+        }
+    }
+}
 
-    Ok(row_count)
+impl Drop for RowWriter {
+    /// Closes the files before an error return removes them.
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::Path;
-    use std::sync::atomic::AtomicBool;
+/// Files a conversion has created, removed again unless it completes. Declared before
+/// the writers, so they are closed by the time it is dropped.
+#[derive(Default)]
+struct CreatedFiles {
+    paths: Vec<PathBuf>,
+    kept: bool,
+}
 
-    #[test]
-    fn test_zsav_magic_bytes() {
-        let input = Path::new("../testFiles/pc.csv");
-        if !input.exists() {
-            return;
+impl CreatedFiles {
+    fn add(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for CreatedFiles {
+    /// Leaves no partial output behind after an error or cancellation.
+    fn drop(&mut self) {
+        if !self.kept {
+            for path in &self.paths {
+                let _ = std::fs::remove_file(path);
+            }
         }
-        let output = std::env::temp_dir().join("csv2sav_test_output.zsav");
-        let cancelled = AtomicBool::new(false);
+    }
+}
 
-        let schema = crate::schema::infer_schema(input, 10_000, &cancelled).unwrap();
-        convert_csv_to_zsav(input, &output, &schema, &cancelled, &|_, _, _| {}).unwrap();
+/// Read-only state the worker threads need to turn fields into values.
+struct CellContext<'a> {
+    col_types: &'a [SchemaColType],
+    options: &'a ConvertOptions,
+    months: &'a MonthNames,
+    /// Anonymization of each column, if any.
+    anonymize: &'a [Option<Anonymize>],
+}
 
-        let data = std::fs::read(&output).unwrap();
-        let magic = &data[..4];
-        assert_eq!(magic, b"$FL3", "ZSAV file must start with $FL3, got {:?}", std::str::from_utf8(magic));
+impl CellContext<'_> {
+    /// Fills `row` with the record's values. Text columns must already be valid UTF-8.
+    fn convert_row<'r>(&self, record: &'r ByteRecord, row: &mut [CellValue]) -> RowOutput<'r> {
+        let mut out = RowOutput::default();
+        for (i, (col_type, slot)) in self.col_types.iter().zip(row.iter_mut()).enumerate() {
+            let field = match col_type {
+                SchemaColType::Dummy { source, .. } => *source,
+                _ => i,
+            };
+            let bytes = record.get(field).unwrap_or(b"");
+            // Anonymized values never raise events: those would carry the original
+            // text into the issues file.
+            if let Some(method) = self.anonymize[i] {
+                *slot = self.anonymize_cell(method, col_type, bytes, &mut out.owned);
+                continue;
+            }
+            // A SAV string ends at its first NUL, so those are scrubbed before writing.
+            if let (SchemaColType::String(width), true) = (col_type, bytes.contains(&0)) {
+                let text = std::str::from_utf8(bytes).unwrap_or_default().trim();
+                let scrubbed = match self.options.nul_bytes {
+                    NulBytes::Replace => text.replace('\0', "\u{FFFD}"),
+                    NulBytes::Strip | NulBytes::Error => text.replace('\0', ""),
+                };
+                out.owned.push(truncate_utf8(&scrubbed, *width).to_string());
+                *slot = CellValue::Owned(out.owned.len() - 1);
+                out.events.push((i, CellEvent::Nul(text)));
+                continue;
+            }
+            let (value, event) = self.convert_cell(col_type, bytes);
+            *slot = match value {
+                Value::Number(n) => CellValue::Number(n),
+                Value::Str(s) => CellValue::text(record, s),
+            };
+            if let Some(event) = event {
+                out.events.push((i, event));
+            }
+        }
+        out
+    }
 
-        std::fs::remove_file(&output).ok();
+    fn anonymize_cell(
+        &self,
+        method: Anonymize,
+        col_type: &SchemaColType,
+        bytes: &[u8],
+        owned: &mut Vec<String>,
+    ) -> CellValue {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim();
+        let replacement = match method {
+            Anonymize::Drop => return CellValue::Number(None),
+            Anonymize::Year => {
+                let date = match col_type {
+                    SchemaColType::Date => self.months.parse(text),
+                    SchemaColType::Period(format) => self.months.parse_period(text, *format),
+                    SchemaColType::Timestamp { day_first }
+                    | SchemaColType::NumericDate { day_first } => {
+                        dates::parse_timestamp(text, *day_first)
+                    }
+                    _ => None,
+                };
+                let year = date.map(dates::year_of).or_else(|| anonymize::year_in_text(text));
+                return CellValue::Number(year);
+            }
+            _ if text.is_empty() => return CellValue::Text { start: 0, len: 0 },
+            Anonymize::Hash => anonymize::hash(text, &self.options.anonymize_salt),
+            Anonymize::Mask => {
+                let width = match col_type {
+                    SchemaColType::String(w) => *w,
+                    _ => anonymize::HASH_WIDTH,
+                };
+                truncate_utf8(&anonymize::mask(text), width).to_string()
+            }
+        };
+        owned.push(replacement);
+        CellValue::Owned(owned.len() - 1)
     }
 
-    #[test]
-    fn generate_zsav_for_validation() {
-        let input = Path::new("../testFiles/pc.csv");
-        if !input.exists() {
-            return;
+    fn convert_cell<'r>(
+        &self,
+        col_type: &SchemaColType,
+        bytes: &'r [u8],
+    ) -> (Value<'r>, Option<CellEvent<'r>>) {
+        let options = self.options;
+        if let SchemaColType::Numeric { .. } = col_type {
+            return match std::str::from_utf8(bytes).map(str::trim) {
+                Ok("") => (Value::Number(None), None),
+                Ok(field) => match options.parse_number(field) {
+                    Some(n) if is_representable(n) => (Value::Number(Some(n)), None),
+                    Some(n) => {
+                        let fixed = fix_out_of_range(n, options.out_of_range);
+                        let action = match fixed {
+                            None => Some(Action::SetMissing),
+                            Some(v) if v.to_bits() != n.to_bits() => Some(Action::Clamped),
+                            Some(_) => None,
+                        };
+                        (Value::Number(fixed), Some(CellEvent::OutOfRange(field, action)))
+                    }
+                    None if options.is_missing_marker(field) => (Value::Number(None), None),
+                    None => (
+                        Value::Number(None),
+                        Some(CellEvent::SetMissing(Cow::Borrowed(field))),
+                    ),
+                },
+                Err(_) => {
+                    let field = match String::from_utf8_lossy(bytes) {
+                        Cow::Owned(s) => Cow::Owned(s.trim().to_string()),
+                        Cow::Borrowed(s) => Cow::Borrowed(s.trim()),
+                    };
+                    (Value::Number(None), Some(CellEvent::SetMissing(field)))
+                }
+            };
         }
-        let output = std::path::PathBuf::from("/tmp/validate_output.zsav");
-        let cancelled = AtomicBool::new(false);
-        let schema = crate::schema::infer_schema(input, 10_000, &cancelled).unwrap();
-        convert_csv_to_zsav(input, &output, &schema, &cancelled, &|_, _, _| {}).unwrap();
-        println!("Generated ZSAV at /tmp/validate_output.zsav");
+
+        // Validated (or repaired) by input::repair_fields before conversion.
+        let raw = std::str::from_utf8(bytes).unwrap_or_default();
+        let field = raw.trim();
+        match col_type {
+            SchemaColType::String(max_width) => {
+                let field = if field.is_empty() && options.whitespace_only == WhitespaceOnly::Blank
+                {
+                    raw
+                } else {
+                    field
+                };
+                let kept = truncate_utf8(field, *max_width);
+                let event = (kept.len() < field.len()).then_some(CellEvent::Truncated(field));
+                (Value::Str(kept), event)
+            }
+            SchemaColType::Checkbox => (Value::Number((!field.is_empty()).then_some(1.0)), None),
+            SchemaColType::Selected { .. } => {
+                let ticked = f64::from(u8::from(!field.is_empty()));
+                (Value::Number((!options.is_missing_marker(field)).then_some(ticked)), None)
+            }
+            SchemaColType::Dummy { option, .. } => {
+                let selected = googleforms::is_selected(field, option);
+                (Value::Number((!field.is_empty()).then_some(selected as u8 as f64)), None)
+            }
+            _ if field.is_empty() => (Value::Number(None), None),
+            _ => {
+                let date = match col_type {
+                    SchemaColType::Period(format) => self.months.parse_period(field, *format),
+                    SchemaColType::Timestamp { day_first }
+                    | SchemaColType::NumericDate { day_first } => {
+                        dates::parse_timestamp(field, *day_first)
+                    }
+                    _ => self.months.parse(field),
+                };
+                let event = (date.is_none() && !options.is_missing_marker(field))
+                    .then_some(CellEvent::SetMissing(Cow::Borrowed(field)));
+                (Value::Number(date), event)
+            }
+        }
+    }
+}
+
+/// Reads the next record into the given one, reusing its buffers; false at the end.
+type ReadRecord<'a> = Box<dyn FnMut(&mut ByteRecord) -> csv::Result<bool> + 'a>;
+
+/// Data records to convert, with what the conversion needs to know about where they came from.
+struct RecordSource<'a> {
+    read: ReadRecord<'a>,
+    /// Bytes skipped before the first record (a BOM and preamble lines).
+    skipped: u64,
+    /// Reads that succeeded only after a retry.
+    recovered: Rc<Cell<usize>>,
+    /// Input bytes consumed so far, for progress.
+    bytes_read: Rc<Cell<u64>>,
+}
+
+/// Uses the records cached by inference when available, otherwise parses the input.
+fn open_records<'a>(
+    input: Input<'a, '_>,
+    csv_schema: &'a CsvSchema,
+    options: &ConvertOptions,
+) -> Result<RecordSource<'a>, String> {
+    if let Some(cached) = &csv_schema.records {
+        let bytes_read = Rc::new(Cell::new(0u64));
+        let counter = bytes_read.clone();
+        let mut records = cached.records.iter();
+        let read = move |into: &mut ByteRecord| {
+            let Some(record) = records.next() else {
+                return Ok(false);
+            };
+            counter.set(cached.skipped + record.position().map_or(0, |p| p.byte()));
+            into.clear();
+            for field in record {
+                into.push_field(field);
+            }
+            into.set_position(record.position().cloned());
+            Ok(true)
+        };
+        return Ok(RecordSource {
+            read: Box::new(read),
+            skipped: cached.skipped,
+            recovered: Rc::new(Cell::new(0)),
+            bytes_read,
+        });
+    }
+
+    let csv_file: Box<dyn Read + 'a> = match input {
+        Input::File(path) => Box::new(
+            File::open(path).map_err(|e| format!("Failed to open CSV for conversion: {e}"))?,
+        ),
+        Input::Stream(stream) => stream.take().ok_or(
+            "A streamed input can be read only once; these options need a file to read it again",
+        )?,
+    };
+    let (csv_file, recovered) = RetryReader::new(csv_file, options.retry_policy());
+    let (counting, bytes_counter) = CountingReader::new(csv_file);
+    let decoded = input::Transcoder::new(counting, options.encoding);
+    let mut csv_buf = BufReader::with_capacity(CSV_BUF_SIZE, decoded);
+    let has_bom =
+        input::skip_utf8_bom(&mut csv_buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let preamble = input::skip_preamble(&mut csv_buf, options)?;
+    let bom = if has_bom { input::UTF8_BOM.len() as u64 } else { 0 };
+    let skipped = bom + preamble.bytes;
+    let mut reader = preamble.csv_reader(options)?.from_reader(csv_buf);
+    input::seek_header(&mut reader, options.header_row)?;
+    let mut header_row = ByteRecord::new();
+    for _ in 0..csv_schema.skip_rows() {
+        reader
+            .read_byte_record(&mut header_row)
+            .map_err(|e| format!("Failed to read CSV headers: {e}"))?;
+    }
+    Ok(RecordSource {
+        read: Box::new(move |record| reader.read_byte_record(record)),
+        skipped,
+        recovered,
+        bytes_read: bytes_counter,
+    })
+}
+
+/// Passes records through the reshaper, so everything downstream sees reshaped rows.
+fn reshaped<'a>(mut read: ReadRecord<'a>, reshaper: Rc<RefCell<Reshaper>>) -> ReadRecord<'a> {
+    let mut pending = VecDeque::new();
+    let mut source = ByteRecord::new();
+    let mut done = false;
+    Box::new(move |into| loop {
+        if let Some(row) = pending.pop_front() {
+            *into = row;
+            return Ok(true);
+        }
+        if done {
+            return Ok(false);
+        }
+        if read(&mut source)? {
+            reshaper.borrow_mut().push(&source, &mut pending);
+        } else {
+            reshaper.borrow_mut().finish(&mut pending);
+            done = true;
+        }
+    })
+}
+
+/// Rows after reshaping, counted by running the reshaper over the whole input.
+fn count_reshaped_rows(
+    input: Input<'_, '_>,
+    csv_schema: &CsvSchema,
+    reshaper: &Reshaper,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<usize, TaskError> {
+    let source = open_records(input, csv_schema, options)?;
+    let mut read = reshaped(source.read, Rc::new(RefCell::new(reshaper.clone())));
+    let mut record = ByteRecord::new();
+    let mut count = 0usize;
+    while read(&mut record).map_err(|e| format!("CSV read error at row {}: {e}", count + 1))? {
+        count += 1;
+        if count.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+            cancel.check()?;
+        }
+    }
+    Ok(count)
+}
+
+/// Converts CSV to ZSAV in a single pass: the row count goes into the header once
+/// all rows are written. SAV output without compression into zlib blocks first counts
+/// rows via the CSV parser (handles quoted multi-line fields), unless inference
+/// already read the whole file.
+pub fn convert_csv_to_zsav(
+    input: &Path,
+    output: &Path,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+    on_progress: &dyn Fn(usize, u64, u64),
+    on_warning: &dyn Fn(&str),
+) -> Result<ConvertOutcome, TaskError> {
+    let input = Input::File(input);
+    convert(input, output, Target::Files, csv_schema, options, cancel, on_progress, on_warning)
+}
+
+/// Converts CSV from any reader to ZSAV on any writer, without touching the
+/// filesystem. Both are streamed: only the sample inference reads is held in memory,
+/// and the output is written in one go once the row count is known, so neither needs
+/// to be seekable. Options that read the input again, split output and the files
+/// written next to an output path (issues, dictionary export, provenance, cleaned
+/// CSV) are not available; the outcome's part has an empty path.
+pub fn convert_csv_stream(
+    input: impl Read,
+    output: impl Write + Send + 'static,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<ConvertOutcome, TaskError> {
+    let sidecars = options.write_issues_file
+        || options.export_dictionary.is_some()
+        || options.write_provenance
+        || options.write_cleaned_csv;
+    if sidecars {
+        return Err("Files written next to the output need an output path".into());
+    }
+    if let Some(timeout) = options.timeout() {
+        cancel.set_timeout(Some(timeout));
+    }
+    let mut input = input::Replay::new(input);
+    // Counting every row would keep the whole input for the replay.
+    let sampling = ConvertOptions { count_all_rows: false, ..options.clone() };
+    let none = Path::new("");
+    let csv_schema = schema::infer_schema_from(&mut input, 0, none, false, &sampling, cancel)?;
+    if csv_schema.row_count.is_none() && !options.streams_rows() {
+        return Err("A stream longer than the sample needs ZSAV or XPT output".into());
+    }
+    let source: RefCell<Option<Box<dyn Read>>> = RefCell::new(Some(Box::new(input.replay())));
+    let sink: RefCell<Option<Box<dyn Write + Send>>> = RefCell::new(Some(Box::new(output)));
+    let (input, target) = (Input::Stream(&source), Target::Stream(&sink));
+    convert(input, none, target, &csv_schema, options, cancel, &|_, _, _| {}, &|_| {})
+}
+
+/// `90 thru 99` split into its ends, as the exported dictionary and SPSS syntax
+/// write a missing range.
+fn split_range(text: &str) -> Option<(&str, &str)> {
+    let at = text.to_ascii_lowercase().find(" thru ")?;
+    Some((&text[..at], &text[at + " thru ".len()..]))
+}
+
+/// SplitMix64; enough to draw rows evenly, not for anything secret.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next()) * u128::from(n)) >> 64) as u64
+    }
+}
+
+fn clock_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Draws `size` records uniformly from the whole file in one streaming pass
+/// (reservoir sampling), keeping them in file order. Returns a schema holding just
+/// those records, and the number of records read.
+fn sample_records(
+    input: Input<'_, '_>,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    size: usize,
+    seed: u64,
+    cancel: &CancelToken,
+) -> Result<(CsvSchema, usize), TaskError> {
+    if size == 0 {
+        return Err("random_sample must be at least 1".into());
+    }
+    let mut source = open_records(input, csv_schema, options)?;
+    let mut rng = SplitMix64(seed);
+    let mut reservoir: Vec<(usize, ByteRecord)> = Vec::with_capacity(size.min(BATCH_ROWS));
+    let mut record = ByteRecord::new();
+    let mut seen = 0;
+    while (source.read)(&mut record)
+        .map_err(|e| format!("CSV read error at row {}: {e}", seen + 1))?
+    {
+        if reservoir.len() < size {
+            reservoir.push((seen, record.clone()));
+        } else {
+            let slot = rng.below(seen as u64 + 1) as usize;
+            if slot < size {
+                reservoir[slot] = (seen, record.clone());
+            }
+        }
+        seen += 1;
+        if seen.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+            cancel.check()?;
+        }
+    }
+    reservoir.sort_unstable_by_key(|(row, _)| *row);
+    let records: Vec<ByteRecord> = reservoir.into_iter().map(|(_, r)| r).collect();
+    let sample = CsvSchema {
+        // A reshape changes the row count; conversion counts the reshaped sample itself.
+        row_count: csv_schema.reshape.is_none().then_some(records.len()),
+        records: Some(Arc::new(CachedRecords { skipped: source.skipped, records })),
+        ..csv_schema.clone()
+    };
+    Ok((sample, seen))
+}
+
+/// Converts the first `rows` data rows into an in-memory ZSAV with the schema of the
+/// whole file, so they come out as the full conversion would write them. Nothing is
+/// written next to the input and the columns are never split.
+pub fn convert_csv_head(
+    input: &Path,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    rows: usize,
+    cancel: &CancelToken,
+) -> Result<(Vec<u8>, ConvertOutcome), TaskError> {
+    let mut source = open_records(Input::File(input), csv_schema, options)?;
+    let mut records = Vec::with_capacity(rows);
+    let mut record = ByteRecord::new();
+    while records.len() < rows
+        && (source.read)(&mut record)
+            .map_err(|e| format!("CSV read error at row {}: {e}", records.len() + 1))?
+    {
+        records.push(record.clone());
+    }
+    let head = CsvSchema {
+        // A reshape changes the row count; conversion counts the reshaped head itself.
+        row_count: csv_schema.reshape.is_none().then_some(records.len()),
+        records: Some(Arc::new(CachedRecords { skipped: source.skipped, records })),
+        ..csv_schema.clone()
+    };
+    let options = ConvertOptions {
+        write_issues_file: false,
+        export_dictionary: None,
+        write_provenance: false,
+        write_cleaned_csv: false,
+        split_columns: false,
+        ..options.clone()
+    };
+    let buffer = SharedBuffer::default();
+    let target = Target::Memory(&buffer);
+    let none = Path::new("");
+    let input = Input::File(input);
+    let outcome = convert(input, none, target, &head, &options, cancel, &|_, _, _| {}, &|_| {})?;
+    Ok((buffer.take(), outcome))
+}
+
+/// Where [`convert`] reads the CSV.
+#[derive(Clone, Copy)]
+enum Input<'a, 's> {
+    File(&'a Path),
+    /// A stream that can be read only once, taken by the first read.
+    Stream(&'a RefCell<Option<Box<dyn Read + 's>>>),
+}
+
+impl Input<'_, '_> {
+    /// The file, for messages and provenance; empty for a stream.
+    fn path(&self) -> &Path {
+        match self {
+            Input::File(path) => path,
+            Input::Stream(_) => Path::new(""),
+        }
+    }
+}
+
+/// Where [`convert`] writes.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    /// Files at the output path.
+    Files,
+    Memory(&'a SharedBuffer),
+    /// The single file goes to this writer, taken when it is created.
+    Stream(&'a RefCell<Option<Box<dyn Write + Send>>>),
+    /// Nothing: the first `limit` altered cells and rows are kept in the outcome.
+    Check { limit: usize },
+}
+
+/// Cells and rows a conversion with these options would alter, found by converting
+/// without writing anything.
+pub fn find_problems(
+    input: &Path,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    limit: usize,
+    cancel: &CancelToken,
+) -> Result<ConvertOutcome, TaskError> {
+    let options = ConvertOptions {
+        write_issues_file: false,
+        export_dictionary: None,
+        write_provenance: false,
+        write_cleaned_csv: false,
+        keep_partial_output: false,
+        ..options.clone()
+    };
+    let target = Target::Check { limit };
+    let (input, none) = (Input::File(input), Path::new(""));
+    convert(input, none, target, csv_schema, &options, cancel, &|_, _, _| {}, &|_| {})
+}
+
+/// Conversion into files at `output`, into memory or nowhere, as `target` says.
+#[allow(clippy::too_many_arguments)]
+fn convert(
+    input: Input<'_, '_>,
+    output: &Path,
+    target: Target<'_>,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+    on_progress: &dyn Fn(usize, u64, u64),
+    on_warning: &dyn Fn(&str),
+) -> Result<ConvertOutcome, TaskError> {
+    if let Some(size) = options.random_sample {
+        let seed = options.random_seed.unwrap_or_else(clock_seed);
+        let (sample, total) = sample_records(input, csv_schema, options, size, seed, cancel)?;
+        let note = format!(
+            "Converted a random sample of {} of {total} rows (random_seed {seed})",
+            sample.records.as_ref().map_or(0, |r| r.records.len())
+        );
+        on_warning(&note);
+        let options = ConvertOptions { random_sample: None, ..options.clone() };
+        let mut outcome =
+            convert(input, output, target, &sample, &options, cancel, on_progress, on_warning)?;
+        outcome.warnings.insert(0, note);
+        return Ok(outcome);
+    }
+    if let Some(version) = options.xport {
+        // Declared string widths can exceed what the version holds; longer values are
+        // then truncated like any value wider than its column.
+        let max = version.max_string_width();
+        let too_wide = |t: &SchemaColType| matches!(t, SchemaColType::String(w) if *w > max);
+        if csv_schema.col_types.iter().any(too_wide) {
+            let mut narrowed = csv_schema.clone();
+            for col_type in &mut narrowed.col_types {
+                if let SchemaColType::String(w) = col_type {
+                    *w = (*w).min(max);
+                }
+            }
+            let schema = &narrowed;
+            return convert(input, output, target, schema, options, cancel, on_progress, on_warning);
+        }
+    }
+
+    let total_rows = match (csv_schema.row_count, &csv_schema.reshape) {
+        // Only written files announce their row count.
+        _ if matches!(target, Target::Check { .. }) => 0,
+        (Some(rows), _) => rows,
+        // ZSAV corrects it before the header is written and XPT records none, so the
+        // CSV is read only once.
+        _ if options.streams_rows() => readstat_writer::UNKNOWN_ROW_COUNT,
+        (None, Some(reshaper)) => count_reshaped_rows(input, csv_schema, reshaper, options, cancel)?,
+        (None, None) => schema::count_rows(input.path(), options, cancel)?
+            .saturating_sub(csv_schema.skip_rows()),
+    };
+
+    cancel.check()?;
+
+    let mut warnings: Vec<String> = Vec::new();
+    let mut dictionary = options
+        .dictionary
+        .as_deref()
+        .map(dictionary::load)
+        .transpose()?;
+    if let Some(dictionary) = &dictionary {
+        warnings.extend(dictionary.unmatched(&csv_schema.headers));
+    }
+    if let Some(path) = &options.value_label_file {
+        let codebook = dictionary::load_value_labels(path)?;
+        let dictionary = dictionary.get_or_insert_with(DataDictionary::default);
+        warnings.extend(dictionary.add_value_labels(codebook, &csv_schema.headers));
+    }
+    let (mut col_defs, meta) = make_col_defs(csv_schema, options, dictionary.as_ref(), &mut warnings)?;
+    let label_pairs = merged_label_pairs(input, csv_schema, options, cancel, &mut warnings)?;
+    for pair in &label_pairs {
+        let def = &mut col_defs[pair.code];
+        let is_string = matches!(csv_schema.col_types[pair.code], SchemaColType::String(_));
+        for (code, name) in &pair.labels {
+            let value = if is_string {
+                LabelValue::Str(code.clone())
+            } else {
+                // Codes that are not numbers are missing markers.
+                match code.parse() {
+                    Ok(n) => LabelValue::Number(n),
+                    Err(_) => continue,
+                }
+            };
+            // Labels from the data dictionary or codebook take precedence.
+            if !def.value_labels.iter().any(|(v, _)| *v == value) {
+                let name = labels::fit(name, MAX_VALUE_LABEL_BYTES, options.label_overflow);
+                def.value_labels.push((value, name.into_owned()));
+            }
+        }
+        def.measure.get_or_insert(Measure::Nominal);
+        warnings.push(format!(
+            "Column '{}' dropped; its names are now value labels of '{}'",
+            csv_schema.headers[pair.name], csv_schema.headers[pair.code]
+        ));
+    }
+
+    let anonymize: Vec<Option<Anonymize>> = csv_schema
+        .headers
+        .iter()
+        .map(|header| options.anonymize(header))
+        .collect();
+    if anonymize.contains(&Some(Anonymize::Hash)) && options.anonymize_salt.is_empty() {
+        return Err("Hashing columns requires a salt".into());
+    }
+    let anonymized: Vec<(usize, Anonymize)> = anonymize
+        .iter()
+        .enumerate()
+        .filter_map(|(i, a)| a.map(|a| (i, a)))
+        .collect();
+    if !anonymized.is_empty() {
+        let columns: Vec<String> = anonymized
+            .iter()
+            .map(|&(i, a)| format!("'{}' ({})", csv_schema.headers[i], a.as_str()))
+            .collect();
+        warnings.push(format!("Anonymized column(s): {}", columns.join(", ")));
+    }
+    // Schema column of every written variable; dropped columns are left out.
+    let dropped: Vec<bool> = (0..col_defs.len())
+        .map(|i| anonymize[i] == Some(Anonymize::Drop) || label_pairs.iter().any(|p| p.name == i))
+        .collect();
+    let kept: Vec<usize> = (0..col_defs.len()).filter(|&i| !dropped[i]).collect();
+    if kept.is_empty() {
+        return Err("Every column is dropped; nothing to convert".into());
+    }
+    let mut col_defs: Vec<ColDef> = col_defs
+        .into_iter()
+        .zip(&dropped)
+        .filter(|(_, dropped)| !**dropped)
+        .map(|(def, _)| def)
+        .collect();
+    let weight = options
+        .weight
+        .as_deref()
+        .map(|header| special_column(csv_schema, options, "Weight", header))
+        .transpose()?;
+    let weight_var = weight.and_then(|w| kept.iter().position(|&k| k == w));
+    let filter = options
+        .filter
+        .as_deref()
+        .map(|header| special_column(csv_schema, options, "Filter", header))
+        .transpose()?;
+    let filter_var = filter.and_then(|f| kept.iter().position(|&k| k == f));
+    if let Some(f) = filter_var {
+        let def = &mut col_defs[f];
+        if def.value_labels.is_empty() {
+            def.value_labels = vec![
+                (LabelValue::Number(0.0), "Not Selected".to_string()),
+                (LabelValue::Number(1.0), "Selected".to_string()),
+            ];
+        }
+        def.measure = Some(Measure::Nominal);
+    }
+    let variable_sets = variable_sets(csv_schema, options, &kept, &mut warnings)?;
+    let mr_sets = mr_sets(csv_schema, options, &kept);
+    let ranges = column_ranges(col_defs.len(), options.max_columns, options.split_columns);
+    let split_parts = ranges.len();
+    let memory = matches!(target, Target::Memory(_) | Target::Stream(_));
+    if memory && split_parts > 1 {
+        return Err("Splitting the output into several files needs an output path".into());
+    }
+    let subsets = subset_outputs(csv_schema, options, &kept, output, &mut warnings)?;
+    if memory && !subsets.is_empty() {
+        return Err("Subset outputs need an output path".into());
+    }
+    // Path and written variables of every file, by their index in `col_defs`.
+    let files: Vec<(PathBuf, Vec<usize>)> = ranges
+        .into_iter()
+        .enumerate()
+        .map(|(n, range)| {
+            let path = if n == 0 && range.len() == col_defs.len() {
+                output.to_path_buf()
+            } else {
+                part_path(output, n + 1)
+            };
+            (path, range.collect())
+        })
+        .chain(subsets)
+        .filter(|_| !matches!(target, Target::Check { .. }))
+        .collect();
+    let mut created = CreatedFiles::default();
+    let mut writers = Vec::with_capacity(files.len());
+    for (path, vars) in files {
+        let index = |var: &usize| vars.iter().position(|v| v == var);
+        let mut meta = FileMeta {
+            weight: weight_var.as_ref().and_then(index),
+            // Each file holds the members of a set that are among its variables.
+            variable_sets: variable_sets
+                .iter()
+                .map(|(name, members)| (name.clone(), members.iter().filter_map(index).collect()))
+                .filter(|(_, members): &(String, Vec<usize>)| !members.is_empty())
+                .collect(),
+            mr_sets: mr_sets
+                .iter()
+                .map(|set| MrSet {
+                    vars: set.vars.iter().filter_map(index).collect(),
+                    ..set.clone()
+                })
+                .filter(|set| set.vars.len() >= 2)
+                .collect(),
+            ..meta.clone()
+        };
+        if let Some(f) = filter_var.filter(|f| vars.contains(f)) {
+            meta.notes.extend(filter_syntax(&col_defs[f].name));
+        }
+        let out_file: Box<dyn Write + Send> = match target {
+            Target::Memory(buffer) => Box::new(buffer.clone()),
+            Target::Stream(stream) => {
+                stream.take().ok_or("Only one file can be written to a stream")?
+            }
+            _ => {
+                let file =
+                    File::create(&path).map_err(|e| format!("Failed to create ZSAV file: {e}"))?;
+                created.add(path.clone());
+                Box::new(file)
+            }
+        };
+        let defs: Vec<ColDef> = vars.iter().map(|&v| col_defs[v].clone()).collect();
+        let writer = match options.xport {
+            Some(version) => Writer::xport(out_file, &defs, &meta, version),
+            None => Writer::new(out_file, &defs, &meta, total_rows, options.compression),
+        }
+        .map_err(|e| format!("Failed to init writer: {e}"))?;
+        writers.push((path, vars, writer));
+    }
+    if split_parts > 1 {
+        warnings.push(format!(
+            "{} columns exceed the limit of {} per file; output split into {} files",
+            col_defs.len(),
+            options.max_columns,
+            split_parts
+        ));
+        if let Some(w) = weight {
+            warnings.push(format!(
+                "Only the file holding column '{}' is weighted by it",
+                csv_schema.headers[w]
+            ));
+        }
+        if let Some(f) = filter {
+            warnings.push(format!(
+                "Only the file holding column '{}' has the filter syntax",
+                csv_schema.headers[f]
+            ));
+        }
+        if !variable_sets.is_empty() {
+            warnings.push("Variable sets are split with the columns; each file holds its own members".to_string());
+        }
+    }
+
+    let RecordSource {
+        mut read,
+        skipped,
+        recovered,
+        bytes_read: bytes_counter,
+    } = open_records(input, csv_schema, options)?;
+    let reshaper = csv_schema.reshape.clone().map(|r| Rc::new(RefCell::new(r)));
+    if let Some(reshaper) = &reshaper {
+        read = reshaped(read, reshaper.clone());
+    }
+
+    let mut issues = if let Target::Check { limit } = target {
+        IssueLog::in_memory(limit)
+    } else if options.write_issues_file {
+        created.add(issues::issues_path(output));
+        IssueLog::create(output)?
+    } else {
+        IssueLog::disabled()
+    };
+    let mut cleaned = if options.write_cleaned_csv {
+        created.add(cleaned::cleaned_path(output));
+        CleanedCsv::create(output, &col_defs)?
+    } else {
+        CleanedCsv::disabled()
+    };
+    let mut map = if options.write_issues_file
+        && anonymized.iter().any(|&(_, a)| a != Anonymize::Drop)
+    {
+        created.add(anonymize::map_path(output));
+        AnonymizationMap::create(output)?
+    } else {
+        AnonymizationMap::disabled()
+    };
+
+    let months = MonthNames::new(&options.month_names)?;
+    let script = options.row_script.as_deref().map(RowScript::load).transpose()?;
+    let headers = &csv_schema.headers;
+    let col_types = &csv_schema.col_types;
+    let col_count = col_types.len();
+    let field_count = csv_schema.fields();
+    let mut row_count = 0usize;
+    let mut truncations: Vec<Option<TruncationReport>> = vec![None; col_count];
+    let mut replaced_cells = 0usize;
+    let mut nul_cells = 0usize;
+    let mut unweighted = 0usize;
+    let mut out_of_range = vec![0usize; col_count];
+    // Values that did not parse for their column's type, and a few examples.
+    let mut set_missing: Vec<(usize, Vec<String>)> = vec![(0, Vec::new()); col_count];
+    // Writers and variable indices of every column, for split and subset outputs;
+    // empty when dropped.
+    let mut slots: Vec<Vec<(usize, usize)>> = vec![Vec::new(); col_count];
+    for (w, (_, vars, _)) in writers.iter().enumerate() {
+        for (index, &var) in vars.iter().enumerate() {
+            slots[kept[var]].push((w, index));
+        }
+    }
+    // Only text columns need valid UTF-8; numeric fields are parsed straight from the bytes.
+    let text_cols: Vec<usize> = col_types
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !matches!(t, SchemaColType::Numeric { .. }))
+        .map(|(i, _)| i)
+        .collect();
+
+    let ctx = CellContext {
+        col_types,
+        options,
+        months: &months,
+        anonymize: &anonymize,
+    };
+    // Records and converted cells are reused from batch to batch, so memory depends
+    // on the batch's cell count rather than growing with every row.
+    let batch_rows = batch_rows(col_count);
+    let mut row_writer = RowWriter::spawn(writers, slots);
+    // Output in memory has nowhere to be kept.
+    let keep_partial = options.keep_partial_output && matches!(target, Target::Files);
+    let mut stopped = false;
+
+    loop {
+        let mut buffers = row_writer.next_batch();
+        let (pool, cells) = (&mut buffers.records, &mut buffers.cells);
+        let mut filled = 0;
+        while filled < batch_rows {
+            if filled == pool.len() {
+                pool.push(ByteRecord::new());
+            }
+            let more = read(&mut pool[filled])
+                .map_err(|e| format!("CSV read error at row {}: {e}", row_count + filled + 1))?;
+            if !more {
+                break;
+            }
+            filled += 1;
+        }
+        if filled == 0 {
+            break;
+        }
+        let batch = &mut pool[..filled];
+
+        // Parse the batch on the worker pool; everything order-dependent (issue log,
+        // reports, writing) happens below on this thread, row by row.
+        let repairs: Vec<Result<Vec<usize>, usize>> = batch
+            .par_iter_mut()
+            .map(|record| input::repair_fields(record, &text_cols, options.invalid_utf8))
+            .collect();
+        if let Some(script) = &script {
+            let first_row = row_count + 1;
+            batch
+                .par_iter_mut()
+                .enumerate()
+                .try_for_each(|(k, record)| script.apply(&headers[..field_count], record, first_row + k))?;
+        }
+        cells.clear();
+        cells.resize(filled * col_count.max(1), CellValue::Number(None));
+        let batch = &*batch;
+        let first_row = row_count;
+        let mut owned = std::mem::take(&mut buffers.owned);
+        owned.clear();
+        let outputs: Vec<RowOutput<'_>> = cells
+            .par_chunks_mut(col_count.max(1))
+            .zip(batch.par_iter())
+            .map(|(row, record)| ctx.convert_row(record, row))
+            .collect();
+
+        for (((record, repair), row), out) in batch
+            .iter()
+            .zip(repairs)
+            .zip(cells.chunks(col_count.max(1)))
+            .zip(outputs)
+        {
+            if row_count > 0 && row_count.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+                if let Err(cancelled) = cancel.check() {
+                    if keep_partial {
+                        stopped = true;
+                        break;
+                    }
+                    return Err(cancelled.into());
+                }
+            }
+
+            row_count += 1;
+            let replaced = repair.map_err(|i| {
+                let start = skipped + record.position().map_or(0, |p| p.byte());
+                let location = format!("row {row_count}, column '{}'", headers[i]);
+                input::invalid_utf8_error(input.path(), start, &location)
+            })?;
+            replaced_cells += replaced.len();
+            for i in replaced {
+                let text = match anonymize.get(i) {
+                    Some(Some(_)) => Cow::Borrowed(""),
+                    _ => String::from_utf8_lossy(&record[i]),
+                };
+                issues.record(row_count, &headers[i], &text, Action::ReplacedInvalidUtf8)?;
+            }
+
+            if issues.is_enabled() && record.len() != field_count {
+                let action = if record.len() < field_count {
+                    Action::PaddedMissingFields
+                } else {
+                    Action::DroppedExtraFields
+                };
+                let fields = format!("{} fields", record.len());
+                issues.record(row_count, "", &fields, action)?;
+            }
+
+            for (i, event) in out.events {
+                let header = &headers[i];
+                match event {
+                    CellEvent::Truncated(field) => {
+                        issues.record(row_count, header, field, Action::Truncated)?;
+                        let SchemaColType::String(max_width) = col_types[i] else {
+                            continue;
+                        };
+                        let report = truncations[i].get_or_insert_with(|| {
+                            on_warning(&format!(
+                                "Column '{header}': values longer than {max_width} bytes are being truncated"
+                            ));
+                            TruncationReport {
+                                column: header.clone(),
+                                width: max_width,
+                                count: 0,
+                                examples: Vec::new(),
+                            }
+                        });
+                        report.count += 1;
+                        if report.examples.len() < TRUNCATION_EXAMPLES {
+                            report.examples.push(example_value(field));
+                        }
+                    }
+                    CellEvent::OutOfRange(field, action) => {
+                        out_of_range[i] += 1;
+                        if let Some(action) = action {
+                            issues.record(row_count, header, field, action)?;
+                        }
+                    }
+                    CellEvent::Nul(field) => {
+                        if options.nul_bytes == NulBytes::Error {
+                            let message = format!("NUL character in row {row_count}, column '{header}'");
+                            return Err(message.into());
+                        }
+                        nul_cells += 1;
+                        issues.record(row_count, header, field, Action::ScrubbedNul)?;
+                    }
+                    CellEvent::SetMissing(field) => {
+                        let (count, examples) = &mut set_missing[i];
+                        *count += 1;
+                        if examples.len() < TRUNCATION_EXAMPLES {
+                            examples.push(example_value(&field));
+                        }
+                        issues.record(row_count, header, &field, Action::SetMissing)?;
+                    }
+                }
+            }
+
+            if let Some(w) = weight {
+                match row[w] {
+                    CellValue::Number(Some(n)) if n <= 0.0 => {
+                        return Err(format!(
+                            "Weight column '{}' must be positive; row {row_count} has {n}",
+                            headers[w]
+                        ).into());
+                    }
+                    CellValue::Number(None) => unweighted += 1,
+                    _ => {}
+                }
+            }
+            if let Some(f) = filter {
+                if let CellValue::Number(Some(n)) = row[f] {
+                    if n != 0.0 && n != 1.0 {
+                        return Err(format!(
+                            "Filter column '{}' must hold 0 or 1; row {row_count} has {n}",
+                            headers[f]
+                        ).into());
+                    }
+                }
+            }
+
+            if cleaned.is_enabled() {
+                cleaned.write_row(kept.iter().map(|&i| row[i].resolve(record, &out.owned)))?;
+            }
+
+            if map.is_enabled() {
+                for &(i, method) in &anonymized {
+                    let field = match &col_types[i] {
+                        SchemaColType::Dummy { source, .. } => *source,
+                        _ => i,
+                    };
+                    let original = String::from_utf8_lossy(record.get(field).unwrap_or(b""));
+                    if method == Anonymize::Drop || original.trim().is_empty() {
+                        continue;
+                    }
+                    let replacement = match row[i].resolve(record, &out.owned) {
+                        Value::Number(n) => n.map(|n| n.to_string()).unwrap_or_default(),
+                        Value::Str(s) => s.to_string(),
+                    };
+                    map.record(i, &headers[i], method, original.trim(), &replacement)?;
+                }
+            }
+
+            owned.push(out.owned);
+            if row_count.is_multiple_of(PROGRESS_INTERVAL) {
+                on_progress(row_count, bytes_counter.get(), csv_schema.file_size);
+            }
+        }
+        buffers.owned = owned;
+        buffers.rows = row_count - first_row;
+        buffers.first_row = first_row;
+        row_writer.write(buffers)?;
+        if stopped {
+            break;
+        }
+    }
+    let writers = row_writer.finish()?;
+
+    let mut parts = Vec::with_capacity(writers.len());
+    for (path, vars, writer) in writers {
+        let sha256 = if row_count < total_rows && !options.streams_rows() {
+            writer
+                .finish_early()
+                .and_then(|_| readstat_writer::correct_case_count(&path, total_rows, row_count))
+        } else {
+            writer.finish()
+        }
+        .map_err(|e| format!("Failed to finalize ZSAV file: {e}"))?;
+        parts.push(OutputPart {
+            path,
+            first_column: kept[vars[0]] + 1,
+            last_column: kept[vars[vars.len() - 1]] + 1,
+            sha256,
+        });
+    }
+
+    let variables: Vec<dictionary::ExportedVariable> =
+        if options.export_dictionary.is_some() || options.write_provenance {
+            col_defs
+                .iter()
+                .zip(&kept)
+                .map(|(def, &i)| dictionary::ExportedVariable::new(&headers[i], def))
+                .collect()
+        } else {
+            Vec::new()
+        };
+    if let Some(format) = options.export_dictionary {
+        let path = dictionary::export_path(output, format);
+        dictionary::export(&path, format, &variables)?;
+        warnings.push(format!("Data dictionary written to {}", path.display()));
+    }
+
+    let truncations: Vec<TruncationReport> = truncations.into_iter().flatten().collect();
+    if stopped {
+        let of = match total_rows {
+            readstat_writer::UNKNOWN_ROW_COUNT => String::new(),
+            total => format!(" of {total}"),
+        };
+        warnings.push(format!(
+            "Cancelled after {row_count}{of} rows; the rows converted so far were kept"
+        ));
+    }
+    warnings.extend(retry::recovered_warning(recovered.get()));
+    if let Some(reshaper) = &reshaper {
+        warnings.extend(reshaper.borrow().warnings());
+    }
+    if let (Some(w), true) = (weight, unweighted > 0) {
+        warnings.push(format!(
+            "Weight column '{}': {unweighted} case(s) have no weight and are left out of weighted analyses",
+            headers[w]
+        ));
+    }
+    if replaced_cells > 0 {
+        warnings.push(format!(
+            "Replaced invalid UTF-8 with U+FFFD in {replaced_cells} cell(s)"
+        ));
+    }
+    if nul_cells > 0 {
+        let done = match options.nul_bytes {
+            NulBytes::Replace => "Replaced NUL characters with U+FFFD",
+            _ => "Removed NUL characters",
+        };
+        warnings.push(format!("{done} in {nul_cells} cell(s)"));
+    }
+    for (header, &count) in headers.iter().zip(&out_of_range) {
+        if count > 0 {
+            warnings.push(out_of_range_message(header, count, options.out_of_range));
+        }
+    }
+    for (header, (count, examples)) in headers.iter().zip(&set_missing) {
+        if *count > 0 {
+            warnings.push(format!(
+                "Column '{header}': {count} value(s) not valid for its type set to missing, e.g. '{}'",
+                examples.join("', '")
+            ));
+        }
+    }
+    warnings.extend(truncations.iter().map(TruncationReport::message));
+    let issue_list = issues.take();
+    warnings.extend(issues.finish()?);
+    warnings.extend(cleaned.finish()?);
+    warnings.extend(map.finish()?);
+    if options.write_provenance {
+        // A kept partial output is described even though the token is cancelled.
+        let never = CancelToken::new();
+        let cancel = if stopped { &never } else { cancel };
+        let all_warnings: Vec<String> =
+            csv_schema.warnings.iter().chain(&warnings).cloned().collect();
+        let path = provenance::sidecar_path(output);
+        let input = input.path();
+        Provenance::new(input, options, row_count, &parts, &variables, &all_warnings, cancel)?
+            .write(&path)?;
+        warnings.push(format!("Provenance written to {}", path.display()));
+    }
+    for warning in &warnings {
+        on_warning(warning);
+    }
+    created.keep();
+
+    Ok(ConvertOutcome {
+        rows: row_count,
+        parts,
+        warnings,
+        truncations,
+        issues: issue_list,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readstat_writer::Compression;
+    use std::path::Path;
+
+    #[test]
+    fn test_zsav_magic_bytes() {
+        let input = Path::new("../testFiles/pc.csv");
+        if !input.exists() {
+            return;
+        }
+        let output = std::env::temp_dir().join("csv2sav_test_output.zsav");
+        let cancel = CancelToken::new();
+        let options = ConvertOptions::default();
+
+        let schema = crate::schema::infer_schema(input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+
+        let data = std::fs::read(&output).unwrap();
+        let magic = &data[..4];
+        assert_eq!(magic, b"$FL3", "ZSAV file must start with $FL3, got {:?}", std::str::from_utf8(magic));
+
+        std::fs::remove_file(&output).ok();
+    }
+
+    #[test]
+    fn test_plain_sav_compression() {
+        let dir = std::env::temp_dir().join("csv2sav_plain_sav_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.sav");
+        std::fs::write(&input, "id,name\n1,Ann\n2,Bob\n").unwrap();
+        let cancel = CancelToken::new();
+        for compression in [Compression::Rows, Compression::None] {
+            let options = ConvertOptions { compression, ..ConvertOptions::default() };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+            assert_eq!(&std::fs::read(&output).unwrap()[..4], b"$FL2");
+            let contents = crate::compare::read(&output, 10).unwrap();
+            assert_eq!(contents.rows, 2);
+            assert_eq!(contents.data[1], ["2", "Bob"]);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_xport_output() {
+        let dir = std::env::temp_dir().join("csv2sav_xport_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.xpt");
+        std::fs::write(&input, "id,name,visit\n1,Ann,2024-03-01\n2,Bob,2024-03-02\n").unwrap();
+        let cancel = CancelToken::new();
+        // Compression does not apply, so rows are neither counted first nor patched.
+        for compression in [Compression::Zlib, Compression::None] {
+            let options = ConvertOptions {
+                xport: Some(readstat_writer::XportVersion::V5),
+                compression,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            let outcome = convert_csv_to_zsav(
+                &input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {},
+            )
+            .unwrap();
+            assert_eq!(outcome.rows, 2);
+            let data = std::fs::read(&output).unwrap();
+            assert!(data.starts_with(b"HEADER RECORD*******LIBRARY HEADER RECORD"));
+            assert_eq!(data.len() % 80, 0);
+            let obs = data.windows(23).position(|w| w == b"HEADER RECORD*******OBS").unwrap();
+            // id, name at the 200 bytes V5 allows, and the visit as days since 1960
+            // (23436 = 0x5B8C).
+            let row = &data[obs + 80..];
+            assert_eq!(row[..8], [0x41, 0x10, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(&row[8..12], b"Ann ");
+            assert_eq!(row[208..212], [0x44, 0x5B, 0x8C, 0]);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_xport_v5_names() {
+        let dir = std::env::temp_dir().join("csv2sav_xport_names_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.xpt");
+        std::fs::write(
+            &input,
+            concat!(
+                "StartDate,ResponseId,Duration (in seconds),Duration (in minutes)\n",
+                "Start Date,Response ID,Duration (in seconds),Duration (in minutes)\n",
+                "\"{\"\"ImportId\"\":\"\"startDate\"\"}\",\"{\"\"ImportId\"\":\"\"_recordId\"\"}\",",
+                "\"{\"\"ImportId\"\":\"\"duration\"\"}\",\"{\"\"ImportId\"\":\"\"QID1\"\"}\"\n",
+                "2024-03-01,R_1,120,2\n",
+            ),
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            xport: Some(readstat_writer::XportVersion::V5),
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        assert!(schema.qualtrics.is_some());
+        let outcome = convert_csv_to_zsav(
+            &input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {},
+        )
+        .unwrap();
+        assert_eq!(outcome.rows, 1);
+        for (old, new) in [
+            ("StartDate", "StartDat"),
+            ("ResponseId", "Response"),
+            ("Duration_in_seconds", "Duration"),
+            ("Duration_in_minutes", "Duratio2"),
+        ] {
+            let warning =
+                format!("variable name '{old}' is not valid in XPT V5; written as '{new}'");
+            assert!(outcome.warnings.iter().any(|w| w.ends_with(&warning)), "{warning}");
+        }
+        let data = std::fs::read(&output).unwrap();
+        let contains = |name: &[u8]| data.windows(name.len()).any(|w| w == name);
+        assert!(contains(b"StartDat") && contains(b"Duratio2"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn generate_zsav_for_validation() {
+        let input = Path::new("../testFiles/pc.csv");
+        if !input.exists() {
+            return;
+        }
+        let output = std::path::PathBuf::from("/tmp/validate_output.zsav");
+        let cancel = CancelToken::new();
+        let options = ConvertOptions::default();
+        let schema = crate::schema::infer_schema(input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        println!("Generated ZSAV at /tmp/validate_output.zsav");
+    }
+
+    #[test]
+    fn test_issues_file_lists_altered_cells() {
+        let dir = std::env::temp_dir().join("csv2sav_issues_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,score\n1,2.5\n2,oops\n3\n4,NA\n").unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            sample_rows: 1,
+            write_issues_file: true,
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+        assert_eq!(outcome.rows, 4);
+        assert!(outcome.warnings.iter().any(|w| {
+            w == "Column 'score': 1 value(s) not valid for its type set to missing, e.g. 'oops'"
+        }));
+
+        let issues = std::fs::read_to_string(crate::issues::issues_path(&output)).unwrap();
+        assert_eq!(
+            issues,
+            "row,column,original_value,action\n2,score,oops,set_missing\n3,,1 fields,padded_missing_fields\n"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_find_problems_lists_without_writing() {
+        let dir = std::env::temp_dir().join("csv2sav_find_problems_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "id,score\n1,2.5\n2,oops\n3\n4,NA\n").unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            sample_rows: 1,
+            write_issues_file: true,
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome = find_problems(&input, &schema, &options, 1, &cancel).unwrap();
+        assert_eq!((outcome.rows, outcome.issues.total), (4, 2));
+        let issue = &outcome.issues.issues[..];
+        assert_eq!(issue.len(), 1);
+        assert_eq!((issue[0].row, issue[0].column.as_str()), (2, "score"));
+        assert_eq!(issue[0].original_value, "oops");
+        assert_eq!(issue[0].action, Action::SetMissing);
+        assert!(outcome.parts.is_empty());
+        let written: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(written.len(), 1);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_random_sample() {
+        let dir = std::env::temp_dir().join("csv2sav_random_sample_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let rows: String = (1..=100).map(|i| format!("{i},{}\n", i * 2)).collect();
+        std::fs::write(&input, format!("id,double\n{rows}")).unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            random_sample: Some(10),
+            random_seed: Some(7),
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let ids = |seed| {
+            let (sample, total) =
+                sample_records(Input::File(&input), &schema, &options, 10, seed, &cancel).unwrap();
+            assert_eq!(total, 100);
+            let records = &sample.records.as_ref().unwrap().records;
+            assert_eq!(sample.row_count, Some(records.len()));
+            records
+                .iter()
+                .map(|r| std::str::from_utf8(&r[0]).unwrap().parse::<usize>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let drawn = ids(7);
+        assert_eq!(drawn.len(), 10);
+        assert!(drawn.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(drawn, ids(7));
+        assert_ne!(drawn, (1..=10).collect::<Vec<_>>());
+
+        let outcome =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+        assert_eq!(outcome.rows, 10);
+        assert!(outcome.warnings[0].contains("10 of 100 rows (random_seed 7)"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_header_row() {
+        let dir = std::env::temp_dir().join("csv2sav_header_row_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "Quarterly report,,\nid,name,score\n1,a,3.5\n2,b,4\n").unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            header_row: 1,
+            cache_records_max_bytes: 0,
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        assert_eq!(schema.headers, vec!["id", "name", "score"]);
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let contents = crate::compare::read(&output, 2).unwrap();
+        assert_eq!(contents.rows, 2);
+        assert_eq!(contents.data[1], vec!["2", "b", "4"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_legacy_input_encoding() {
+        let dir = std::env::temp_dir().join("csv2sav_legacy_encoding_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let (gbk, _, _) = encoding_rs::GBK.encode("城市,人口\n北京,2189\n上海,2487\n");
+        std::fs::write(&input, &gbk).unwrap();
+
+        let cancel = CancelToken::new();
+        for encoding in [crate::options::InputEncoding::Gbk, crate::options::InputEncoding::Auto] {
+            let options = ConvertOptions {
+                encoding,
+                cache_records_max_bytes: 0,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.headers, vec!["城市", "人口"]);
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+            let contents = crate::compare::read(&output, 2).unwrap();
+            assert_eq!(contents.data, vec![vec!["北京", "2189"], vec!["上海", "2487"]]);
+        }
+
+        let options = ConvertOptions::default();
+        let Err(err) = crate::schema::infer_schema(&input, &options, &cancel) else {
+            panic!("GBK input read as UTF-8");
+        };
+        assert!(err.to_string().starts_with("Invalid UTF-8"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_utf16_input() {
+        let dir = std::env::temp_dir().join("csv2sav_utf16_input_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let text = "\u{FEFF}city,pop\nZürich,421\n東京,13960\n";
+        let cancel = CancelToken::new();
+        let options = ConvertOptions { cache_records_max_bytes: 0, ..ConvertOptions::default() };
+        let encodings: [fn(u16) -> [u8; 2]; 2] = [u16::to_le_bytes, u16::to_be_bytes];
+        for to_bytes in encodings {
+            let utf16: Vec<u8> = text.encode_utf16().flat_map(to_bytes).collect();
+            std::fs::write(&input, &utf16).unwrap();
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.headers, vec!["city", "pop"]);
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+            let contents = crate::compare::read(&output, 2).unwrap();
+            assert_eq!(contents.data, vec![vec!["Zürich", "421"], vec!["東京", "13960"]]);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_nul_policy() {
+        let dir = std::env::temp_dir().join("csv2sav_nul_policy_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,note\n1,a\0b\n2,ok\n").unwrap();
+        let cancel = CancelToken::new();
+        let convert = |nul_bytes| {
+            let options = ConvertOptions { nul_bytes, ..ConvertOptions::default() };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .map(|outcome| outcome.warnings)
+        };
+
+        let warnings = convert(NulBytes::Replace).unwrap();
+        assert!(warnings.contains(&"Replaced NUL characters with U+FFFD in 1 cell(s)".to_string()));
+        assert_eq!(crate::compare::read(&output, 1).unwrap().data[0][1], "a\u{FFFD}b");
+        convert(NulBytes::Strip).unwrap();
+        assert_eq!(crate::compare::read(&output, 1).unwrap().data[0][1], "ab");
+        let err = convert(NulBytes::Error).unwrap_err().to_string();
+        assert_eq!(err, "NUL character in row 1, column 'note'");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_data_dictionary_applied() {
+        let dir = std::env::temp_dir().join("csv2sav_dictionary_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let dictionary = dir.join("dict.json");
+        std::fs::write(&input, "Sex,Age\n1,30\n2,-99\n").unwrap();
+        std::fs::write(
+            &dictionary,
+            r#"{"variables": [
+                {"column": "Sex", "name": "sex", "measure": "nominal", "value_labels": {"1": "Male", "2": "Female"}},
+                {"column": "Age", "name": "age", "missing": ["-99"]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            dictionary: Some(dictionary.clone()),
+            export_dictionary: Some(crate::options::DictionaryFormat::Csv),
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+
+        // The exported dictionary describes the written variables and reads back.
+        let written = dir.join("out.zsav.dictionary.csv");
+        let text = std::fs::read_to_string(&written).unwrap();
+        assert!(text.starts_with("column,name,label,type,format,width,measure,missing,value_labels\n"));
+        assert!(text.contains("Sex,sex,Sex,numeric,F1.0,,nominal,,1=Male;2=Female\n"), "{text}");
+        let reloaded = dictionary::load(&written).unwrap();
+        assert_eq!(reloaded.get("Age").unwrap().missing, vec!["-99"]);
+        assert_eq!(reloaded.get("Age").unwrap().measure, Some(crate::readstat_writer::Measure::Scale));
+
+        let exported = dir.join("out.csv");
+        let export_options = crate::options::ExportOptions {
+            values: crate::options::ExportValues::Labeled,
+            ..Default::default()
+        };
+        crate::exporter::export_sav_to_csv(&output, &exported, &export_options, &cancel, &|_, _| {})
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&exported).unwrap(),
+            "sex,age\nMale,30\nFemale,\n"
+        );
+
+        std::fs::write(
+            &dictionary,
+            r#"{"variables": [{"column": "Sex", "name": "age"}, {"column": "Age", "name": "AGE"}]}"#,
+        )
+        .unwrap();
+        let Err(err) = convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+        else {
+            panic!("duplicate dictionary names should be rejected");
+        };
+        assert!(err.to_string().contains("used by more than one column"), "{err}");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_qualtrics_export() {
+        let dir = std::env::temp_dir().join("csv2sav_qualtrics_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(
+            &input,
+            concat!(
+                "StartDate,ResponseId,Duration (in seconds),Q1\n",
+                "Start Date,Response ID,Duration (in seconds),How satisfied are you?\n",
+                "\"{\"\"ImportId\"\":\"\"startDate\"\"}\",\"{\"\"ImportId\"\":\"\"_recordId\"\"}\",",
+                "\"{\"\"ImportId\"\":\"\"duration\"\"}\",\"{\"\"ImportId\"\":\"\"QID1\"\"}\"\n",
+                "2024-03-01,R_1,120,4\n",
+                "2024-03-02,R_2,95,-99\n",
+            ),
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        for cache_records_max_bytes in [0, u64::MAX] {
+            let options = ConvertOptions {
+                cache_records_max_bytes,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.skip_rows(), 2);
+            assert!(matches!(schema.col_types[3], SchemaColType::Numeric { .. }));
+
+            let mut warnings = Vec::new();
+            let (cols, _) = make_col_defs(&schema, &options, None, &mut warnings).unwrap();
+            let names: Vec<&str> = cols.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["StartDate", "ResponseId", "Duration_in_seconds", "Q1"]);
+            assert_eq!(cols[3].label, "How satisfied are you?");
+            assert_eq!(cols[3].missing_numbers, vec![-99.0]);
+
+            let outcome = convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+            assert_eq!(outcome.rows, 2);
+
+            let exported = dir.join("out.csv");
+            crate::exporter::export_sav_to_csv(&output, &exported, &Default::default(), &cancel, &|_, _| {})
+                .unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&exported).unwrap(),
+                "StartDate,ResponseId,Duration_in_seconds,Q1\n2024-03-01,R_1,120,4\n2024-03-02,R_2,95,\n"
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_surveymonkey_export() {
+        let dir = std::env::temp_dir().join("csv2sav_surveymonkey_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(
+            &input,
+            concat!(
+                "Respondent ID,Which fruits do you like?,,,How old are you?\n",
+                ",Apple,Banana,Other (please specify),Response\n",
+                "101,Apple,,,34\n",
+                "102,Apple,Banana,Kiwi,27\n",
+                "103,,,,\n",
+            ),
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        for cache_records_max_bytes in [0, u64::MAX] {
+            let options = ConvertOptions {
+                cache_records_max_bytes,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.skip_rows(), 1);
+            assert!(matches!(schema.col_types[1], SchemaColType::Checkbox));
+            assert!(matches!(schema.col_types[3], SchemaColType::String(_)));
+
+            let mut warnings = Vec::new();
+            let (cols, meta) = make_col_defs(&schema, &options, None, &mut warnings).unwrap();
+            let names: Vec<&str> = cols.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["Respondent_ID", "Q1_1", "Q1_2", "Q1_3", "Q2"]);
+            assert_eq!(cols[2].label, "Which fruits do you like? - Banana");
+            assert_eq!(cols[4].label, "How old are you?");
+            assert!(meta.notes.join(" ").contains("NAME=$Q1"));
+
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+            let exported = dir.join("out.csv");
+            let export_options = crate::options::ExportOptions {
+                values: crate::options::ExportValues::Labeled,
+                ..Default::default()
+            };
+            crate::exporter::export_sav_to_csv(&output, &exported, &export_options, &cancel, &|_, _| {})
+                .unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&exported).unwrap(),
+                "Respondent_ID,Q1_1,Q1_2,Q1_3,Q2\n101,Apple,,,34\n102,Apple,Banana,Kiwi,27\n103,,,,\n"
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_google_forms_export() {
+        let dir = std::env::temp_dir().join("csv2sav_google_forms_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(
+            &input,
+            concat!(
+                "Timestamp,Fruits,Age\n",
+                "2024/03/01 10:15:30 AM GMT+1,Apple;Banana,34\n",
+                "2024/03/02 1:05:00 PM GMT+1,Kiwi,27\n",
+                "2024/03/03 9:00:00 AM GMT+1,,41\n",
+            ),
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            split_multi_select: true,
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        assert!(matches!(schema.col_types[0], SchemaColType::Timestamp { day_first: false }));
+        assert_eq!(schema.fields(), 3);
+        assert_eq!(&schema.headers[3..], ["Fruits [Apple]", "Fruits [Banana]", "Fruits [Kiwi]"]);
+
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let exported = dir.join("out.csv");
+        crate::exporter::export_sav_to_csv(&output, &exported, &Default::default(), &cancel, &|_, _| {})
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&exported).unwrap(),
+            concat!(
+                "V1,V2,V3,V4,V5,V6\n",
+                "2024-03-01 10:15:30,Apple;Banana,34,1,1,0\n",
+                "2024-03-02 13:05:00,Kiwi,27,0,0,1\n",
+                "2024-03-03 09:00:00,,41,,,\n",
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_anonymized_columns() {
+        let dir = std::env::temp_dir().join("csv2sav_anonymize_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(
+            &input,
+            "id,email,phone,born,ssn\n1,a@example.com,0612345678,01-Mar-1987,123-45-6789\n2,,0687654321,15-Jun-1990,987-65-4321\n",
+        )
+        .unwrap();
+
+        let column = |anonymize| crate::options::ColumnOptions {
+            anonymize: Some(anonymize),
+            ..Default::default()
+        };
+        let mut options = ConvertOptions {
+            write_issues_file: true,
+            ..ConvertOptions::default()
+        };
+        options.columns.insert("email".to_string(), column(Anonymize::Hash));
+        options.columns.insert("phone".to_string(), column(Anonymize::Mask));
+        options.columns.insert("born".to_string(), column(Anonymize::Year));
+        options.columns.insert("ssn".to_string(), column(Anonymize::Drop));
+
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let convert = |options: &ConvertOptions| {
+            convert_csv_to_zsav(&input, &output, &schema, options, &cancel, &|_, _, _| {}, &|_| {})
+        };
+        let Err(err) = convert(&options) else {
+            panic!("hashing without a salt should fail");
+        };
+        assert!(err.to_string().contains("salt"), "{err}");
+
+        options.anonymize_salt = "pepper".to_string();
+        let outcome = convert(&options).unwrap();
+        assert_eq!((outcome.parts[0].first_column, outcome.parts[0].last_column), (1, 4));
+
+        let exported = dir.join("out.csv");
+        crate::exporter::export_sav_to_csv(&output, &exported, &Default::default(), &cancel, &|_, _| {})
+            .unwrap();
+        let hash = anonymize::hash("a@example.com", "pepper");
+        assert_eq!(
+            std::fs::read_to_string(&exported).unwrap(),
+            format!("V1,V2,V3,V4\n1,{hash},******5678,1987\n2,,******4321,1990\n")
+        );
+
+        let map = std::fs::read_to_string(anonymize::map_path(&output)).unwrap();
+        assert!(map.contains(&format!("email,hash,a@example.com,{hash}")));
+        assert!(map.contains("born,year,15-Jun-1990,1990"));
+        assert!(!map.contains("123-45-6789"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_row_script_applied() {
+        let dir = std::env::temp_dir().join("csv2sav_script_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let script = dir.join("clean.rhai");
+        std::fs::write(&input, "name,age,label\nann,999,\nbob,41,\n").unwrap();
+        std::fs::write(
+            &script,
+            "if row.age == \"999\" { row.age = (); }\nrow.label = `${row.name}-${row_number}`;\n",
+        )
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            row_script: Some(script),
+            ..ConvertOptions::default()
+        };
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        // The blank label column is typed from the derived text.
+        assert!(matches!(schema.col_types[2], SchemaColType::String(_)));
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+
+        let exported = dir.join("out.csv");
+        crate::exporter::export_sav_to_csv(&output, &exported, &Default::default(), &cancel, &|_, _| {})
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&exported).unwrap(),
+            "V1,V2,V3\nann,,ann-1\nbob,41,bob-2\n"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reshaped_conversion() {
+        use crate::options::{Reshape, ToLong, ToWide};
+
+        let dir = std::env::temp_dir().join("csv2sav_reshape_convert_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let exported = dir.join("out.csv");
+        let cancel = CancelToken::new();
+        let convert = |options: &ConvertOptions| {
+            let schema = crate::schema::infer_schema(&input, options, &cancel).unwrap();
+            let outcome =
+                convert_csv_to_zsav(&input, &output, &schema, options, &cancel, &|_, _, _| {}, &|_| {})
+                    .unwrap();
+            crate::exporter::export_sav_to_csv(&output, &exported, &Default::default(), &cancel, &|_, _| {})
+                .unwrap();
+            (outcome.rows, std::fs::read_to_string(&exported).unwrap())
+        };
+
+        std::fs::write(&input, "id,Q1_1,Q1_2\n1,4,5\n2,3,\n").unwrap();
+        let options = ConvertOptions {
+            reshape: Some(Reshape::ToLong(ToLong {
+                stubs: vec!["Q1".to_string()],
+                levels: vec!["1".to_string(), "2".to_string()],
+                ..Default::default()
+            })),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(convert(&options), (4, "V1,V2,V3\n1,1,4\n1,2,5\n2,1,3\n2,2,\n".to_string()));
+
+        // Without cached records or a full sample, the wide rows are counted in a pass.
+        std::fs::write(&input, "id,wave,score\n1,1,4\n1,2,5\n2,1,3\n").unwrap();
+        let options = ConvertOptions {
+            sample_rows: 1,
+            cache_records_max_bytes: 0,
+            reshape: Some(Reshape::ToWide(ToWide {
+                id: "id".to_string(),
+                index: "wave".to_string(),
+                levels: vec!["1".to_string(), "2".to_string()],
+                variables: vec!["score".to_string()],
+                ..Default::default()
+            })),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(convert(&options), (2, "V1,V2,V3\n1,4,5\n2,3,\n".to_string()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_column_ranges_and_part_paths() {
+        let whole = vec![Range { start: 0, end: 5 }];
+        assert_eq!(column_ranges(5, 2, false), whole);
+        assert_eq!(column_ranges(5, 5, true), whole);
+        assert_eq!(column_ranges(5, 2, true), vec![0..2, 2..4, 4..5]);
+        assert_eq!(
+            part_path(Path::new("/tmp/data.zsav"), 2),
+            Path::new("/tmp/data_part2.zsav")
+        );
+    }
+
+    #[test]
+    fn test_batch_rows_scale_with_width() {
+        assert_eq!(batch_rows(0), BATCH_ROWS);
+        assert_eq!(batch_rows(10), BATCH_ROWS);
+        assert_eq!(batch_rows(20_000), BATCH_CELLS / 20_000);
+        assert_eq!(batch_rows(BATCH_CELLS * 2), 1);
+        assert!(batch_rows(20_000) * 20_000 <= BATCH_CELLS);
+    }
+
+    #[test]
+    fn test_out_of_range_policy() {
+        assert!(is_representable(-12.5));
+        assert!(!is_representable(f64::INFINITY));
+        assert!(!is_representable(1e300));
+        assert!(!is_representable(f64::MIN_POSITIVE / 2.0));
+
+        assert_eq!(fix_out_of_range(1e300, OutOfRange::Clamp), Some(MAX_MAGNITUDE));
+        assert_eq!(fix_out_of_range(f64::NEG_INFINITY, OutOfRange::Clamp), Some(-MAX_MAGNITUDE));
+        assert_eq!(fix_out_of_range(f64::MIN_POSITIVE / 2.0, OutOfRange::Clamp), Some(0.0));
+        assert_eq!(fix_out_of_range(f64::NAN, OutOfRange::Clamp), None);
+        assert_eq!(fix_out_of_range(1e300, OutOfRange::SetMissing), None);
+        assert_eq!(fix_out_of_range(1e300, OutOfRange::Keep), Some(1e300));
+    }
+
+    #[test]
+    fn test_column_alignment_and_display_width() {
+        let dir = std::env::temp_dir().join("csv2sav_alignment_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,name\n1,ann\n").unwrap();
+        let column = crate::options::ColumnOptions {
+            alignment: Some(crate::readstat_writer::Alignment::Center),
+            display_width: Some(20),
+            ..Default::default()
+        };
+        let options = ConvertOptions {
+            columns: [("name".to_string(), column)].into_iter().collect(),
+            ..ConvertOptions::default()
+        };
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let variables = crate::compare::read(&output, 0).unwrap().variables;
+        assert_eq!((variables[0].display_width, variables[1].display_width), (8, 20));
+        // ReadStat does not read alignment back, so find the variable display record
+        // (type 7, subtype 11): measure, width and alignment per variable.
+        let bytes = std::fs::read(&output).unwrap();
+        let header: Vec<u8> = [7i32, 11, 4].iter().flat_map(|n| n.to_le_bytes()).collect();
+        let at = bytes.windows(header.len()).position(|w| w == header).unwrap();
+        // Skip the header and the value count.
+        let start = at + header.len() + 4;
+        let values: Vec<i32> = bytes[start..start + 24]
+            .chunks(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [3, 8, 1, 1, 20, 2]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_weight_column() {
+        let dir = std::env::temp_dir().join("csv2sav_weight_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let cancel = CancelToken::new();
+        let convert = |csv: &str, weight: &str| {
+            std::fs::write(&input, csv).unwrap();
+            let options = ConvertOptions {
+                weight: Some(weight.to_string()),
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .map(|outcome| outcome.warnings)
+        };
+
+        let warnings = convert("id,name,w\n1,ann,2\n2,bob,\n3,cy,1.5\n", "w").unwrap();
+        assert!(warnings.iter().any(|w| w.contains("1 case(s) have no weight")));
+        let contents = crate::compare::read(&output, 0).unwrap();
+        assert_eq!(contents.weight.as_deref(), Some("V3"));
+
+        assert!(convert("id,name,w\n1,ann,2\n2,bob,0\n", "w").unwrap_err().to_string().contains("row 2 has 0"));
+        assert!(!output.exists(), "partial output left behind");
+        assert!(convert("id,name,w\n1,ann,2\n", "name").unwrap_err().to_string().contains("must be numeric"));
+        assert!(convert("id,name,w\n1,ann,2\n", "x").unwrap_err().to_string().contains("not found"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_filter_column() {
+        let dir = std::env::temp_dir().join("csv2sav_filter_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let cancel = CancelToken::new();
+        let convert = |csv: &str| {
+            std::fs::write(&input, csv).unwrap();
+            let options = ConvertOptions {
+                filter: Some("keep".to_string()),
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .map(|outcome| outcome.rows)
+        };
+
+        assert_eq!(convert("id,keep\n1,1\n2,0\n3,\n"), Ok(3));
+        let contents = crate::compare::read(&output, 0).unwrap();
+        let filter = &contents.variables[1];
+        assert_eq!(filter.measure, "nominal");
+        assert_eq!(
+            filter.value_labels,
+            [("0".to_string(), "Not Selected".to_string()), ("1".to_string(), "Selected".to_string())]
+        );
+        assert_eq!(filter_syntax("V2")[1], "FILTER BY V2.");
+
+        assert!(convert("id,keep\n1,1\n2,2\n").unwrap_err().to_string().contains("row 2 has 2"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_merge_label_columns() {
+        let dir = std::env::temp_dir().join("csv2sav_label_pairs_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let cancel = CancelToken::new();
+        let convert = |csv: &str| {
+            std::fs::write(&input, csv).unwrap();
+            let options = ConvertOptions {
+                merge_label_columns: true,
+                sample_rows: 2,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.label_pairs.len(), 1);
+            let outcome =
+                convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {}).unwrap();
+            (outcome.warnings, crate::compare::read(&output, 0).unwrap().variables)
+        };
+
+        let (warnings, variables) = convert("region_code,region_name,x\n1,North,a\n2,South,b\n3,East,c\n1,North,d\n");
+        assert!(warnings.contains(&"Column 'region_name' dropped; its names are now value labels of 'region_code'".to_string()));
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables[0].measure, "nominal");
+        assert_eq!(
+            variables[0].value_labels,
+            [("1", "North"), ("2", "South"), ("3", "East")].map(|(v, l)| (v.to_string(), l.to_string()))
+        );
+
+        // A contradiction past the sample keeps both columns.
+        let (warnings, variables) = convert("region_code,region_name\n1,North\n2,South\n1,East\n");
+        assert!(warnings.iter().any(|w| w.contains("do not pair up")));
+        assert_eq!(variables.len(), 2);
+        assert!(variables[0].value_labels.is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_column_value_labels() {
+        let dir = std::env::temp_dir().join("csv2sav_column_value_labels_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "sex,grade\n1,A\n2,B\n").unwrap();
+        let cancel = CancelToken::new();
+        let labels = |pairs: &[(&str, &str)]| crate::options::ColumnOptions {
+            value_labels: pairs.iter().map(|(v, l)| (v.to_string(), l.to_string())).collect(),
+            ..Default::default()
+        };
+        let mut options = ConvertOptions::default();
+        options.columns.insert("sex".into(), labels(&[("1", "Male"), ("2", "Female")]));
+        options.columns.insert("grade".into(), labels(&[("A", "Top")]));
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let variables = crate::compare::read(&output, 0).unwrap().variables;
+        let pairs = |p: &[(&str, &str)]| {
+            p.iter().map(|(v, l)| (v.to_string(), l.to_string())).collect::<Vec<_>>()
+        };
+        assert_eq!(variables[0].value_labels, pairs(&[("1", "Male"), ("2", "Female")]));
+        assert_eq!(variables[1].value_labels, pairs(&[("A", "Top")]));
+
+        options.columns.insert("sex".into(), labels(&[("M", "Male")]));
+        let Err(err) =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+        else {
+            panic!("'M' is not a numeric code");
+        };
+        assert!(err.to_string().contains("Value labels: 'M' is not a number (column 'sex')"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_column_schema_overrides() {
+        use crate::options::{ColumnOptions, ForcedType};
+        let dir = std::env::temp_dir().join("csv2sav_schema_override_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,score\n007,1.5\n012,n/a\n").unwrap();
+        let cancel = CancelToken::new();
+        let mut options = ConvertOptions::default();
+        let id = ColumnOptions {
+            col_type: Some(ForcedType::String),
+            width: Some(5),
+            name: Some("resp_id".into()),
+            label: Some("Respondent".into()),
+            ..Default::default()
+        };
+        options.columns.insert("id".into(), id);
+        let score = ColumnOptions { width: Some(10), ..Default::default() };
+        options.columns.insert("score".into(), score);
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let contents = crate::compare::read(&output, 10).unwrap();
+        let v = &contents.variables;
+        assert_eq!((v[0].name.as_str(), v[0].label.as_str()), ("resp_id", "Respondent"));
+        assert_eq!(v[0].format, "A5");
+        assert_eq!(v[1].format, "F10.1");
+        assert_eq!(contents.data[0][0], "007");
+
+        options.columns.get_mut("id").unwrap().name = Some("1id".into());
+        let Err(err) =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+        else {
+            panic!("'1id' is not a valid name");
+        };
+        assert!(err.to_string().starts_with("Column 'id': "), "{err}");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_column_missing_values() {
+        let dir = std::env::temp_dir().join("csv2sav_column_missing_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "age,city\n34,Oslo\n-99,NA\n95,Rome\n").unwrap();
+        let cancel = CancelToken::new();
+        let convert = |age: &[&str], city: &[&str]| {
+            let mut options = ConvertOptions::default();
+            for (header, missing) in [("age", age), ("city", city)] {
+                let column = crate::options::ColumnOptions {
+                    missing: missing.iter().map(|m| m.to_string()).collect(),
+                    ..Default::default()
+                };
+                options.columns.insert(header.into(), column);
+            }
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .map(|_| crate::compare::read(&output, 0).unwrap().variables)
+                .map_err(|e| e.to_string())
+        };
+
+        let variables = convert(&["-99", "90 THRU 99"], &["NA"]).unwrap();
+        assert_eq!(variables[0].missing, ["90 thru 99", "-99"]);
+        assert_eq!(variables[1].missing, ["NA"]);
+
+        let err = convert(&["-99", "-98", "90 thru 99"], &[]).unwrap_err();
+        assert!(err.contains("a range counts as two"), "{err}");
+        let err = convert(&[], &["A thru M"]).unwrap_err();
+        assert!(err.contains("needs a numeric column (column 'city')"), "{err}");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_register_response_sets() {
+        let dir = std::env::temp_dir().join("csv2sav_response_sets_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let csv = "id,Q5_1,Q5_2,Q5_3\n1,Apples,,Plums\n2,,Pears,\n3,Apples,,\n";
+        std::fs::write(&input, csv).unwrap();
+        let cancel = CancelToken::new();
+        let mut options = ConvertOptions::default();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let offered = |w: &String| w.starts_with("Columns 'Q5_1' to 'Q5_3' look like");
+        assert!(schema.warnings.iter().any(offered));
+
+        options.register_response_sets = true;
+        options.collapse_selected = true;
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let SchemaColType::Selected { option } = &schema.col_types[2] else {
+            panic!("Q5_2 not collapsed");
+        };
+        assert_eq!(option, "Pears");
+        convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+            .unwrap();
+        let data = std::fs::read(&output).unwrap();
+        let record = b"$Q5=D1 1 2 Q5 v2 v3 v4\n";
+        assert!(data.windows(record.len()).any(|w| w == record));
+        let variables = crate::compare::read(&output, 0).unwrap().variables;
+        assert_eq!(variables[2].value_labels, vec![("1".to_string(), "Pears".to_string())]);
+
+        let exported = dir.join("out.csv");
+        let export_options = Default::default();
+        let on_progress = |_, _| {};
+        let export = crate::exporter::export_sav_to_csv;
+        export(&output, &exported, &export_options, &cancel, &on_progress).unwrap();
+        let text = std::fs::read_to_string(&exported).unwrap();
+        assert!(text.ends_with("1,1,0,1\n2,0,1,0\n3,1,0,0\n"), "{text}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_variable_sets() {
+        let dir = std::env::temp_dir().join("csv2sav_variable_sets_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,age,sex,score\n1,30,f,2\n").unwrap();
+        let cancel = CancelToken::new();
+        let mut options = ConvertOptions::default();
+        options.variable_sets.insert("Demographics".to_string(), vec!["sex".to_string(), "age".to_string()]);
+        options.variable_sets.insert("Other".to_string(), vec!["missing".to_string()]);
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {}).unwrap();
+        assert!(outcome.warnings.contains(&"Variable set 'Other': column 'missing' not found".to_string()));
+        assert!(outcome.warnings.contains(&"Variable set 'Other' has no columns; left out".to_string()));
+
+        // Subtype 5 record: header, then the set lines.
+        let bytes = std::fs::read(&output).unwrap();
+        let text = b"Demographics= V3 V2\n";
+        let record: Vec<u8> = [7i32, 5, 1, text.len() as i32]
+            .iter()
+            .flat_map(|n| n.to_ne_bytes())
+            .chain(text.iter().copied())
+            .collect();
+        assert!(bytes.windows(record.len()).any(|w| w == record));
+        assert_eq!(crate::compare::read(&output, 0).unwrap().variables.len(), 4);
+
+        options.variable_sets.insert("a=b".to_string(), vec!["id".to_string()]);
+        assert!(variable_sets(&schema, &options, &[0, 1, 2, 3], &mut Vec::new()).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_keep_partial_output_on_cancel() {
+        let dir = std::env::temp_dir().join("csv2sav_partial_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let mut csv = "id,name\n".to_string();
+        for i in 0..25_000 {
+            csv.push_str(&format!("{i},row {i}\n"));
+        }
+        std::fs::write(&input, csv).unwrap();
+        let options = ConvertOptions { keep_partial_output: true, ..ConvertOptions::default() };
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let on_progress = |rows: usize, _: u64, _: u64| {
+            if rows == PROGRESS_INTERVAL {
+                cancel.cancel();
+            }
+        };
+        let outcome =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &on_progress, &|_| {})
+                .unwrap();
+        assert_eq!(outcome.rows, PROGRESS_INTERVAL);
+        let cancelled = "Cancelled after 10000 of 25000 rows";
+        assert!(outcome.warnings.iter().any(|w| w.starts_with(cancelled)));
+        let zsav = std::fs::read(&output).unwrap();
+        use sha2::Digest;
+        assert_eq!(outcome.parts[0].sha256, format!("{:x}", sha2::Sha256::digest(&zsav)));
+
+        let exported = dir.join("out.csv");
+        let never = CancelToken::new();
+        let export_options = Default::default();
+        let export =
+            crate::exporter::export_sav_to_csv(&output, &exported, &export_options, &never, &|_, _| {})
+                .unwrap();
+        assert_eq!(export.rows, outcome.rows);
+        let text = std::fs::read_to_string(&exported).unwrap();
+        assert!(text.trim_end().ends_with("9999,row 9999"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_uncounted_conversion() {
+        let dir = std::env::temp_dir().join("csv2sav_uncounted_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let rows: String = (1..=2500).map(|i| format!("{i},row {i}\n")).collect();
+        std::fs::write(&input, format!("id,name\n{rows}")).unwrap();
+
+        let cancel = CancelToken::new();
+        for compression in [Compression::Zlib, Compression::Rows, Compression::None] {
+            let options = ConvertOptions {
+                compression,
+                sample_rows: 100,
+                cache_records_max_bytes: 0,
+                ..ConvertOptions::default()
+            };
+            let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+            assert_eq!(schema.row_count, None);
+            let outcome = convert_csv_to_zsav(
+                &input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {},
+            )
+            .unwrap();
+            assert_eq!(outcome.rows, 2500);
+            use sha2::Digest;
+            let sav = std::fs::read(&output).unwrap();
+            assert_eq!(outcome.parts[0].sha256, format!("{:x}", sha2::Sha256::digest(&sav)));
+            assert_eq!(i32::from_ne_bytes(sav[80..84].try_into().unwrap()), 2500);
+
+            let exported = dir.join("out.csv");
+            let export = crate::exporter::export_sav_to_csv(
+                &output, &exported, &Default::default(), &cancel, &|_, _| {},
+            )
+            .unwrap();
+            assert_eq!(export.rows, 2500);
+            let text = std::fs::read_to_string(&exported).unwrap();
+            assert!(text.trim_end().ends_with("2500,row 2500"), "{compression:?}");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_subset_outputs() {
+        let dir = std::env::temp_dir().join("csv2sav_subset_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        std::fs::write(&input, "id,name,score\n1,ann,2.5\n2,bob,3\n").unwrap();
+        let cancel = CancelToken::new();
+        let mut options = ConvertOptions::default();
+        let subsets = [
+            ("identifiable.zsav", vec!["id", "name"]),
+            ("analysis.zsav", vec!["score", "id", "missing"]),
+            ("empty.zsav", vec!["missing"]),
+        ];
+        for (name, headers) in subsets {
+            let headers = headers.into_iter().map(str::to_string).collect();
+            options.subset_outputs.insert(name.to_string(), headers);
+        }
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome =
+            convert_csv_to_zsav(&input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {})
+                .unwrap();
+        let paths: Vec<&Path> = outcome.parts.iter().map(|p| p.path.as_path()).collect();
+        let expected = [output.clone(), dir.join("analysis.zsav"), dir.join("identifiable.zsav")];
+        assert_eq!(paths, expected);
+        assert_eq!((outcome.parts[1].first_column, outcome.parts[1].last_column), (3, 1));
+        let warned = |w: &str| outcome.warnings.iter().any(|x| x == w);
+        assert!(warned("Subset output 'analysis.zsav': column 'missing' not found"));
+        assert!(warned("Subset output 'empty.zsav' has no columns; not written"));
+        assert!(!dir.join("empty.zsav").exists());
+
+        let exported = dir.join("analysis.csv");
+        let export_options = Default::default();
+        let on_progress = |_, _| {};
+        crate::exporter::export_sav_to_csv(paths[1], &exported, &export_options, &cancel, &on_progress)
+            .unwrap();
+        let text = std::fs::read_to_string(&exported).unwrap();
+        assert!(text.ends_with("2.5,1\n3,2\n"), "{text}");
+        assert_eq!(crate::compare::read(paths[2], 0).unwrap().variables.len(), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_convert_csv_stream() {
+        use sha2::Digest;
+
+        let cancel = CancelToken::new();
+        let sink = SharedBuffer::default();
+        let csv = "id,name\n1,ann\n2,bob\n";
+        let options = ConvertOptions::default();
+        let outcome = convert_csv_stream(csv.as_bytes(), sink.clone(), &options, &cancel).unwrap();
+        assert_eq!(outcome.rows, 2);
+        let zsav = sink.take();
+        assert_eq!(&zsav[..4], b"$FL3");
+        assert_eq!(outcome.parts[0].sha256, format!("{:x}", sha2::Sha256::digest(&zsav)));
+
+        let options = ConvertOptions { write_issues_file: true, ..ConvertOptions::default() };
+        let unused = SharedBuffer::default();
+        assert!(convert_csv_stream(csv.as_bytes(), unused, &options, &cancel).is_err());
+
+        // Longer than the sample: the rest is read straight from the stream.
+        let rows: String = (1..=5000).map(|i| format!("{i},row {i}\n")).collect();
+        let csv = format!("id,name\n{rows}");
+        let options = ConvertOptions { sample_rows: 100, ..ConvertOptions::default() };
+        let outcome = convert_csv_stream(csv.as_bytes(), sink.clone(), &options, &cancel).unwrap();
+        assert_eq!(outcome.rows, 5000);
+        let path = std::env::temp_dir().join("csv2sav_stream_convert_test.zsav");
+        std::fs::write(&path, sink.take()).unwrap();
+        let contents = crate::compare::read(&path, 5000).unwrap();
+        assert_eq!(contents.rows, 5000);
+        assert_eq!(contents.data[4999], vec!["5000", "row 5000"]);
+        std::fs::remove_file(&path).ok();
+
+        let options = ConvertOptions { compression: Compression::Rows, ..options };
+        let unused = SharedBuffer::default();
+        assert!(convert_csv_stream(csv.as_bytes(), unused, &options, &cancel).is_err());
     }
 }
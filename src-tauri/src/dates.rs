@@ -0,0 +1,545 @@
+use crate::options::PeriodFormat;
+
+/// SPSS stores dates as seconds since the start of the Gregorian calendar (1582-10-14).
+const SECONDS_PER_DAY: f64 = 86_400.0;
+const SPSS_EPOCH: (i64, u32, u32) = (1582, 10, 14);
+/// SAS counts dates in days and date-times in seconds, both from 1960-01-01.
+const SAS_EPOCH: (i64, u32, u32) = (1960, 1, 1);
+/// Display format for inferred date columns, e.g. `01-MAR-2024`.
+pub const DATE_FORMAT: &str = "DATE11";
+/// Display format for timestamps, e.g. `01-MAR-2024 10:15:30`.
+pub const DATETIME_FORMAT: &str = "DATETIME20";
+
+const ENGLISH_MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september",
+    "october", "november", "december",
+];
+
+/// Recognizes month names in dates such as "01-Mar-2024", "1 March 2024" or
+/// "March 1, 2024". English names and three-letter abbreviations are built in;
+/// further languages come from [`ConvertOptions::month_names`](crate::options::ConvertOptions).
+#[derive(Debug, Clone)]
+pub struct MonthNames {
+    /// Lowercased name and its month number (1-12).
+    names: Vec<(String, u32)>,
+}
+
+impl MonthNames {
+    /// `extra` holds sets of twelve names each, January first.
+    pub fn new(extra: &[Vec<String>]) -> Result<Self, String> {
+        let mut names = Vec::new();
+        for (month, name) in (1..).zip(ENGLISH_MONTHS) {
+            names.push((name.to_string(), month));
+            names.push((name[..3].to_string(), month));
+        }
+        names.push(("sept".to_string(), 9));
+        for (i, set) in extra.iter().enumerate() {
+            if set.len() != 12 {
+                return Err(format!(
+                    "Month name set {} has {} names; expected 12",
+                    i + 1,
+                    set.len()
+                ));
+            }
+            for (month, name) in (1..).zip(set) {
+                names.push((name.trim().trim_end_matches('.').to_lowercase(), month));
+            }
+        }
+        Ok(Self { names })
+    }
+
+    fn month(&self, token: &str) -> Option<u32> {
+        let token = token.to_lowercase();
+        self.names
+            .iter()
+            .find(|(name, _)| *name == token)
+            .map(|&(_, month)| month)
+    }
+
+    /// Parses a day-month-year or month-day-year date with a named month into an
+    /// SPSS date value.
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        let mut tokens = text
+            .split([' ', '-', '/', '.', ','])
+            .filter(|t| !t.is_empty());
+        let (first, second, third) = (tokens.next()?, tokens.next()?, tokens.next()?);
+        if tokens.next().is_some() {
+            return None;
+        }
+        let (day, month) = match (self.month(first), self.month(second)) {
+            (None, Some(month)) => (first, month),
+            (Some(month), None) => (second, month),
+            _ => return None,
+        };
+        if third.len() != 4 || day.len() > 2 {
+            return None;
+        }
+        let day: u32 = day.parse().ok()?;
+        let year: i64 = third.parse().ok()?;
+        if day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(spss_date(year, month, day))
+    }
+}
+
+/// Tracks which day/month order every sampled date or timestamp parses in. A Google
+/// Forms CSV download writes `2024/03/01 10:15:30 AM GMT+1`; a copy saved from Sheets,
+/// like most exports, uses the locale's order, e.g. `3/1/2024` or `01/03/2024 10:15`.
+#[derive(Debug, Clone)]
+pub struct TimestampColumn {
+    month_first: bool,
+    day_first: bool,
+    seen: bool,
+    /// Some value had a time of day.
+    time: bool,
+}
+
+impl Default for TimestampColumn {
+    fn default() -> Self {
+        Self { month_first: true, day_first: true, seen: false, time: false }
+    }
+}
+
+impl TimestampColumn {
+    pub fn observe(&mut self, value: &str) {
+        let value = value.trim();
+        if value.is_empty() || !(self.month_first || self.day_first) {
+            return;
+        }
+        self.seen = true;
+        self.time |= value.contains(':');
+        self.month_first &= parse_timestamp(value, false).is_some();
+        self.day_first &= parse_timestamp(value, true).is_some();
+    }
+
+    /// Whether to read the column day first, or None when some value is not a
+    /// timestamp in either order. Month first wins when both work.
+    pub fn day_first(&self) -> Option<bool> {
+        match (self.seen, self.month_first, self.day_first) {
+            (true, true, _) => Some(false),
+            (true, false, true) => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn has_time(&self) -> bool {
+        self.time
+    }
+}
+
+/// Parses a timestamp such as `2024/03/01 10:15:30 AM GMT+1`, `3/1/2024 10:15:30`,
+/// `2024-03-01T10:15:30Z` or a bare date into an SPSS datetime value. The wall-clock
+/// time is kept and any time zone or offset dropped, since SPSS has none.
+/// `day_first` decides `01/03/2024`.
+pub fn parse_timestamp(text: &str, day_first: bool) -> Option<f64> {
+    let mut parts = text.split_whitespace();
+    let first = parts.next()?;
+    let (date, joined_time) = match first.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (first, None),
+    };
+    let mut fields = date.split(['/', '-', '.']);
+    let (a, b, c) = (fields.next()?, fields.next()?, fields.next()?);
+    if fields.next().is_some() {
+        return None;
+    }
+    let (year, month, day) = if a.len() == 4 {
+        (a, b, c)
+    } else if c.len() == 4 && day_first {
+        (c, b, a)
+    } else if c.len() == 4 {
+        (c, a, b)
+    } else {
+        return None;
+    };
+    let (year, month, day): (i64, u32, u32) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    if let Some(time) = joined_time.or_else(|| parts.next()) {
+        let time = time.strip_suffix(['Z', 'z']).unwrap_or(time);
+        let time = time.split_once(['+', '-']).map_or(time, |(time, _offset)| time);
+        let mut hms = time.split(':');
+        let hour: u32 = hms.next()?.parse().ok()?;
+        let minute: u32 = hms.next()?.parse().ok()?;
+        let second: f64 = hms.next().map_or(Some(0.0), |s| s.parse().ok())?;
+        if hms.next().is_some() || minute > 59 || !(0.0..60.0).contains(&second) {
+            return None;
+        }
+        let hour = match parts.next().map(str::to_ascii_uppercase).as_deref() {
+            Some("AM") if (1..=12).contains(&hour) => hour % 12,
+            Some("PM") if (1..=12).contains(&hour) => hour % 12 + 12,
+            Some("AM" | "PM") => return None,
+            Some(zone) if zone.starts_with("GMT") || zone.starts_with("UTC") => hour,
+            Some(_) => return None,
+            None => hour,
+        };
+        if hour > 23 {
+            return None;
+        }
+        seconds = f64::from(hour * 3600 + minute * 60) + second;
+    }
+    match parts.next() {
+        Some(zone) if zone.starts_with("GMT") || zone.starts_with("UTC") => {}
+        Some(_) => return None,
+        None => {}
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(spss_date(year, month, day) + seconds)
+}
+
+/// SPSS display format of a period column.
+pub fn period_format_spec(format: PeriodFormat) -> &'static str {
+    match format {
+        PeriodFormat::Qyr => "QYR8",
+        PeriodFormat::Moyr => "MOYR8",
+        PeriodFormat::Wkyr => "WKYR10",
+    }
+}
+
+/// Splits "2024Q1", "Q1 2024", "Mar 2024" or "5 WK 2024" into runs of letters and
+/// digits, dropping separators.
+fn period_tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut is_digit = false;
+    for (i, c) in text.char_indices() {
+        let kind = if c.is_ascii_digit() {
+            Some(true)
+        } else if c.is_alphabetic() {
+            Some(false)
+        } else {
+            None
+        };
+        if let Some(s) = start {
+            if kind != Some(is_digit) {
+                tokens.push(&text[s..i]);
+                start = None;
+            }
+        }
+        if let (None, Some(digit)) = (start, kind) {
+            start = Some(i);
+            is_digit = digit;
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&text[s..]);
+    }
+    tokens
+}
+
+impl MonthNames {
+    /// Parses a quarter, month or week of a year into the SPSS date value of the
+    /// period's first day. SPSS counts week 1 from January 1.
+    pub fn parse_period(&self, text: &str, format: PeriodFormat) -> Option<f64> {
+        let tokens = period_tokens(text);
+        let mut year = None;
+        let mut number = None;
+        let mut word = None;
+        for token in &tokens {
+            let slot = if token.len() == 4 && token.bytes().all(|b| b.is_ascii_digit()) {
+                &mut year
+            } else if token.bytes().all(|b| b.is_ascii_digit()) {
+                &mut number
+            } else {
+                &mut word
+            };
+            if slot.replace(*token).is_some() {
+                return None;
+            }
+        }
+        let year: i64 = year?.parse().ok()?;
+        let number: Option<u32> = match number {
+            Some(n) if n.len() <= 2 => Some(n.parse().ok()?),
+            Some(_) => return None,
+            None => None,
+        };
+        let word = word.map(str::to_ascii_uppercase);
+
+        match format {
+            PeriodFormat::Qyr => match (word.as_deref(), number) {
+                (Some("Q"), Some(q @ 1..=4)) => Some(spss_date(year, 3 * (q - 1) + 1, 1)),
+                _ => None,
+            },
+            PeriodFormat::Moyr => {
+                let month = match (word, number) {
+                    (Some(_), Some(_)) => return None,
+                    (Some(word), None) => self.month(&word)?,
+                    (None, Some(m @ 1..=12)) => m,
+                    _ => return None,
+                };
+                Some(spss_date(year, month, 1))
+            }
+            PeriodFormat::Wkyr => match (word.as_deref(), number) {
+                (Some("W" | "WK"), Some(w @ 1..=53)) => {
+                    Some(spss_date(year, 1, 1) + (w - 1) as f64 * 7.0 * SECONDS_PER_DAY)
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// SPSS date value (seconds since 1582-10-14) of a calendar date.
+pub fn spss_date(year: i64, month: u32, day: u32) -> f64 {
+    let (ey, em, ed) = SPSS_EPOCH;
+    (days_from_civil(year, month, day) - days_from_civil(ey, em, ed)) as f64 * SECONDS_PER_DAY
+}
+
+/// Calendar year of an SPSS date value.
+pub fn year_of(value: f64) -> f64 {
+    let days = (value / SECONDS_PER_DAY).floor() as i64;
+    let (ey, em, ed) = SPSS_EPOCH;
+    civil_from_days(days + days_from_civil(ey, em, ed)).0 as f64
+}
+
+/// What an SPSS display format shows of a date value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateKind {
+    Date,
+    DateTime,
+    /// A duration such as `TIME8`; may exceed 24 hours.
+    Time,
+}
+
+/// Kind of date an SPSS format such as `DATE11` or `DATETIME20` displays, or None
+/// for non-date formats.
+pub fn date_kind(format: &str) -> Option<DateKind> {
+    let name = format.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match name.to_ascii_uppercase().as_str() {
+        "DATE" | "ADATE" | "EDATE" | "SDATE" | "JDATE" | "QYR" | "MOYR" | "WKYR" => {
+            Some(DateKind::Date)
+        }
+        "DATETIME" | "YMDHMS" => Some(DateKind::DateTime),
+        "TIME" | "DTIME" | "MTIME" => Some(DateKind::Time),
+        _ => None,
+    }
+}
+
+/// An SPSS date value in SAS's units for `kind`; durations are seconds in both.
+pub fn sas_value(value: f64, kind: DateKind) -> f64 {
+    let (y, m, d) = SAS_EPOCH;
+    match kind {
+        DateKind::Date => (value - spss_date(y, m, d)) / SECONDS_PER_DAY,
+        DateKind::DateTime => value - spss_date(y, m, d),
+        DateKind::Time => value,
+    }
+}
+
+/// SAS format showing what the SPSS date format `format` shows, at its width:
+/// `DATE11` stays, `ADATE10` becomes `MMDDYY10`. Formats SAS has no match for fall
+/// back to `DATE9`, `DATETIME20` or `TIME8` by kind.
+pub fn sas_format(format: &str) -> String {
+    let name = format.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let width = &format[name.len()..];
+    let sas = match name.to_ascii_uppercase().as_str() {
+        "DATE" | "DATETIME" | "TIME" => return format.to_ascii_uppercase(),
+        "ADATE" => "MMDDYY",
+        "EDATE" => "DDMMYY",
+        "SDATE" => "YYMMDD",
+        _ => match date_kind(format) {
+            Some(DateKind::DateTime) => return "DATETIME20".to_string(),
+            Some(DateKind::Time) => return "TIME8".to_string(),
+            _ => return "DATE9".to_string(),
+        },
+    };
+    format!("{sas}{width}")
+}
+
+/// Formats an SPSS date value as ISO 8601 (`2024-03-01`, `2024-03-01 13:45:00`),
+/// or a duration as `13:45:00`. Seconds are rounded to whole numbers.
+pub fn format_spss(value: f64, kind: DateKind) -> String {
+    let total = value.round() as i64;
+    let (days, secs) = (total.div_euclid(86_400), total.rem_euclid(86_400));
+    let time = |secs: i64| format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    match kind {
+        DateKind::Time if total < 0 => format!("-{}", time(-total)),
+        DateKind::Time => time(total),
+        _ => {
+            let (ey, em, ed) = SPSS_EPOCH;
+            let (y, m, d) = civil_from_days(days + days_from_civil(ey, em, ed));
+            match kind {
+                DateKind::DateTime => format!("{y:04}-{m:02}-{d:02} {}", time(secs)),
+                _ => format!("{y:04}-{m:02}-{d:02}"),
+            }
+        }
+    }
+}
+
+/// A date value as SPSS displays it in a format such as `DATE11` (`01-MAR-2024`),
+/// `DATETIME20`, `QYR8` (`1 Q 2024`), `MOYR8` or `WKYR10`; None for other formats.
+pub fn format_display(value: f64, format: &str) -> Option<String> {
+    let name = format.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let total = value.round() as i64;
+    let (days, secs) = (total.div_euclid(86_400), total.rem_euclid(86_400));
+    let (ey, em, ed) = SPSS_EPOCH;
+    let days = days + days_from_civil(ey, em, ed);
+    let (y, m, d) = civil_from_days(days);
+    let month = ENGLISH_MONTHS[m as usize - 1][..3].to_ascii_uppercase();
+    let time = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    let text = match name.to_ascii_uppercase().as_str() {
+        "DATE" => format!("{d:02}-{month}-{y:04}"),
+        "ADATE" => format!("{m:02}/{d:02}/{y:04}"),
+        "EDATE" => format!("{d:02}.{m:02}.{y:04}"),
+        "SDATE" => format!("{y:04}/{m:02}/{d:02}"),
+        "DATETIME" => format!("{d:02}-{month}-{y:04} {time}"),
+        "QYR" => format!("{} Q {y:04}", (m - 1) / 3 + 1),
+        "MOYR" => format!("{month} {y:04}"),
+        "WKYR" => format!("{} WK {y:04}", (days - days_from_civil(y, 1, 1)) / 7 + 1),
+        "TIME" => format_spss(value, DateKind::Time),
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_month_name_dates() {
+        let months = MonthNames::new(&[]).unwrap();
+        let expected = spss_date(2024, 3, 1);
+        assert_eq!(months.parse("01-Mar-2024"), Some(expected));
+        assert_eq!(months.parse("1 March 2024"), Some(expected));
+        assert_eq!(months.parse("March 1, 2024"), Some(expected));
+        assert_eq!(months.parse("30-Feb-2024"), None);
+        assert_eq!(months.parse("2024-03-01"), None);
+        // 1970-01-01 is 141428 days after the SPSS epoch.
+        assert_eq!(spss_date(1970, 1, 1), 141_428.0 * SECONDS_PER_DAY);
+
+        let french: Vec<String> = [
+            "janvier", "février", "mars", "avril", "mai", "juin", "juillet", "août", "septembre",
+            "octobre", "novembre", "décembre",
+        ]
+        .map(String::from)
+        .to_vec();
+        let months = MonthNames::new(&[french]).unwrap();
+        assert_eq!(months.parse("1 Mars 2024"), Some(expected));
+        assert!(MonthNames::new(&[vec!["jan".to_string()]]).is_err());
+    }
+
+    #[test]
+    fn test_parse_periods() {
+        let months = MonthNames::new(&[]).unwrap();
+        let q2 = spss_date(2024, 4, 1);
+        assert_eq!(months.parse_period("2024Q2", PeriodFormat::Qyr), Some(q2));
+        assert_eq!(months.parse_period("Q2 2024", PeriodFormat::Qyr), Some(q2));
+        assert_eq!(months.parse_period("2 Q 2024", PeriodFormat::Qyr), Some(q2));
+        assert_eq!(months.parse_period("2024Q5", PeriodFormat::Qyr), None);
+
+        let march = spss_date(2024, 3, 1);
+        assert_eq!(months.parse_period("Mar 2024", PeriodFormat::Moyr), Some(march));
+        assert_eq!(months.parse_period("2024-03", PeriodFormat::Moyr), Some(march));
+        assert_eq!(months.parse_period("Foo 2024", PeriodFormat::Moyr), None);
+
+        let week5 = spss_date(2024, 1, 29);
+        assert_eq!(months.parse_period("2024W05", PeriodFormat::Wkyr), Some(week5));
+        assert_eq!(months.parse_period("5 WK 2024", PeriodFormat::Wkyr), Some(week5));
+    }
+
+    #[test]
+    fn test_parse_timestamps() {
+        let morning = spss_date(2024, 3, 1) + 10.0 * 3600.0 + 15.0 * 60.0 + 30.0;
+        assert_eq!(parse_timestamp("2024/03/01 10:15:30 AM GMT+1", false), Some(morning));
+        assert_eq!(parse_timestamp("2024/03/01 10:15:30 PM GMT+1", false), Some(morning + 12.0 * 3600.0));
+        assert_eq!(parse_timestamp("3/1/2024 10:15:30", false), Some(morning));
+        assert_eq!(parse_timestamp("01/03/2024 10:15:30", true), Some(morning));
+        assert_eq!(parse_timestamp("2024-03-01", false), Some(spss_date(2024, 3, 1)));
+        assert_eq!(parse_timestamp("12/1/2024 12:00:00 AM", false), Some(spss_date(2024, 12, 1)));
+        assert_eq!(parse_timestamp("25/1/2024 10:15:30", false), None);
+        assert_eq!(parse_timestamp("2024/03/01 25:00", false), None);
+        assert_eq!(parse_timestamp("2024/03/01 10:15 tomorrow", false), None);
+        assert_eq!(parse_timestamp("2024-03-01T10:15:30Z", false), Some(morning));
+        assert_eq!(parse_timestamp("2024-03-01T10:15:30.000+01:00", true), Some(morning));
+        assert_eq!(parse_timestamp("2024-03-01T", false), None);
+
+        let mut column = TimestampColumn::default();
+        column.observe("3/1/2024");
+        assert_eq!((column.day_first(), column.has_time()), (Some(false), false));
+        column.observe("25/1/2024 10:15:30");
+        assert_eq!((column.day_first(), column.has_time()), (Some(true), true));
+        column.observe("not a date");
+        assert_eq!(column.day_first(), None);
+    }
+
+    #[test]
+    fn test_format_spss_dates() {
+        let value = spss_date(2024, 3, 1);
+        assert_eq!(format_spss(value, DateKind::Date), "2024-03-01");
+        assert_eq!(format_spss(value + 49_500.0, DateKind::DateTime), "2024-03-01 13:45:00");
+        assert_eq!(format_spss(0.0, DateKind::Date), "1582-10-14");
+        assert_eq!(year_of(value + 49_500.0), 2024.0);
+        assert_eq!(format_spss(49_500.0, DateKind::Time), "13:45:00");
+        assert_eq!(format_spss(-90.0, DateKind::Time), "-00:01:30");
+
+        assert_eq!(date_kind("DATE11"), Some(DateKind::Date));
+        assert_eq!(date_kind("DATETIME20"), Some(DateKind::DateTime));
+        assert_eq!(date_kind("TIME8.2"), Some(DateKind::Time));
+        assert_eq!(date_kind("F8.2"), None);
+
+        assert_eq!(format_display(value, DATE_FORMAT).as_deref(), Some("01-MAR-2024"));
+        assert_eq!(format_display(value + 49_500.0, DATETIME_FORMAT).as_deref(), Some("01-MAR-2024 13:45:00"));
+        assert_eq!(format_display(spss_date(2024, 4, 1), "QYR8").as_deref(), Some("2 Q 2024"));
+        assert_eq!(format_display(value, "MOYR8").as_deref(), Some("MAR 2024"));
+        assert_eq!(format_display(spss_date(2024, 1, 29), "WKYR10").as_deref(), Some("5 WK 2024"));
+        assert_eq!(format_display(value, "F8.2"), None);
+    }
+
+    #[test]
+    fn test_sas_dates() {
+        assert_eq!(sas_value(spss_date(1960, 1, 1), DateKind::Date), 0.0);
+        assert_eq!(sas_value(spss_date(2024, 3, 1), DateKind::Date), 23_436.0);
+        assert_eq!(sas_value(spss_date(1959, 12, 31) + 60.0, DateKind::DateTime), -86_340.0);
+        assert_eq!(sas_value(49_500.0, DateKind::Time), 49_500.0);
+
+        assert_eq!(sas_format("DATE11"), "DATE11");
+        assert_eq!(sas_format("ADATE10"), "MMDDYY10");
+        assert_eq!(sas_format("SDATE10"), "YYMMDD10");
+        assert_eq!(sas_format("QYR8"), "DATE9");
+        assert_eq!(sas_format("YMDHMS19"), "DATETIME20");
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use url::Url;
+
+use crate::launch;
+
+pub const SCHEME: &str = "csv2sav";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertRequest {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Parses `csv2sav://convert?input=…[&format=zsav]`.
+/// The result is always written next to the input with the format's extension: a link
+/// can come from any web page, so it may not choose which file gets written, nor
+/// overwrite one. An existing output gets a numbered sibling instead.
+pub fn parse(url: &Url) -> Result<ConvertRequest, String> {
+    if url.scheme() != SCHEME {
+        return Err(format!("Unsupported URL scheme: {}", url.scheme()));
+    }
+    let action = url.host_str().unwrap_or_default();
+    if action != "convert" {
+        return Err(format!("Unsupported deep link action: {action}"));
+    }
+
+    let mut input = None;
+    let mut format = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "input" => input = Some(PathBuf::from(value.as_ref())),
+            "output" => return Err("Deep links cannot choose the output path".to_string()),
+            "format" => format = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    let input = input.ok_or("Deep link is missing the input parameter")?;
+    if !launch::is_csv_file(&input) {
+        return Err(format!("Input is not a CSV file: {}", input.display()));
+    }
+
+    let format = format.unwrap_or_else(|| "zsav".to_string());
+    if format != "zsav" {
+        return Err(format!("Unsupported output format: {format}"));
+    }
+
+    let output = unused_path(&input.with_extension(&format));
+
+    Ok(ConvertRequest { input, output })
+}
+
+/// `path`, or the first of `name (2).ext`, `name (3).ext`, … that does not exist.
+fn unused_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|ext| format!(".{}", ext.to_string_lossy()));
+    let mut candidate = path.to_path_buf();
+    for n in 2.. {
+        if !candidate.exists() {
+            break;
+        }
+        candidate = path.with_file_name(format!("{stem} ({n}){}", ext.as_deref().unwrap_or("")));
+    }
+    candidate
+}
+
+/// Deep link requests waiting for the user to confirm them in the window.
+#[derive(Default)]
+pub struct PendingRequests {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, ConvertRequest>>,
+}
+
+impl PendingRequests {
+    /// Holds `request` and returns the id the window answers it with.
+    pub fn add(&self, request: ConvertRequest) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests.lock().unwrap().insert(id, request);
+        id
+    }
+
+    /// Removes the request, so each one is answered at most once.
+    pub fn take(&self, id: u64) -> Option<ConvertRequest> {
+        self.requests.lock().unwrap().remove(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_convert_link() {
+        let input = std::env::temp_dir().join("csv2sav_deeplink_test.csv");
+        std::fs::write(&input, "a\n1\n").unwrap();
+        let input_str = input.to_str().unwrap();
+
+        let mut url = Url::parse("csv2sav://convert").unwrap();
+        url.query_pairs_mut()
+            .append_pair("input", input_str)
+            .append_pair("format", "zsav");
+        let req = parse(&url).unwrap();
+        assert_eq!(req.input, input);
+        assert_eq!(req.output, input.with_extension("zsav"));
+
+        let taken = input.with_extension("zsav");
+        std::fs::write(&taken, "").unwrap();
+        let req = parse(&url).unwrap();
+        assert_eq!(req.output, input.with_file_name("csv2sav_deeplink_test (2).zsav"));
+        std::fs::remove_file(&taken).ok();
+
+        let pending = PendingRequests::default();
+        let id = pending.add(req.clone());
+        assert_eq!(pending.take(id), Some(req));
+        assert_eq!(pending.take(id), None);
+
+        let mut foreign = url.clone();
+        foreign.query_pairs_mut().append_pair("output", "/home/user/.bashrc");
+        assert!(parse(&foreign).is_err());
+
+        url.query_pairs_mut().append_pair("format", "xlsx");
+        assert!(parse(&url).is_err());
+        assert!(parse(&Url::parse("csv2sav://delete?input=x").unwrap()).is_err());
+
+        std::fs::remove_file(&input).ok();
+    }
+}
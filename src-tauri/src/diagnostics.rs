@@ -0,0 +1,322 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::input;
+use crate::options::ConvertOptions;
+use crate::settings::Settings;
+use crate::zip::ZipWriter;
+
+const LOG_FILE: &str = "activity.log";
+const OLD_LOG_FILE: &str = "activity.old.log";
+const REPORT_FILE: &str = "last_report.json";
+/// Past this size the log is moved to OLD_LOG_FILE and started over.
+const MAX_LOG_BYTES: u64 = 1 << 20;
+/// Data rows of a CSV sampled into a bundle.
+pub const SAMPLE_ROWS: usize = 20;
+/// Warnings quote the cell values they are about after this, up to the end.
+const EXAMPLES_MARKER: &str = ", e.g. ";
+
+/// What the bundle says about the installation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Versions {
+    pub app: String,
+    pub tauri: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// When the bundle was made, in seconds since the Unix epoch.
+    pub created: u64,
+}
+
+impl Versions {
+    pub fn new(app: String, tauri: &str) -> Self {
+        Self {
+            app,
+            tauri: tauri.to_string(),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            created: unix_time(),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Activity log and last conversion report, kept in the app's log directory for a
+/// diagnostic bundle. Best-effort: diagnostics that cannot be written never fail a
+/// conversion.
+pub struct Diagnostics {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Diagnostics {
+    pub fn open(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends a timestamped line to the activity log, with any quoted cell values
+    /// masked.
+    pub fn record(&self, level: &str, message: &str) {
+        let _guard = self.lock.lock().unwrap();
+        let _ = fs::create_dir_all(&self.dir);
+        let path = self.dir.join(LOG_FILE);
+        if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+            let _ = fs::rename(&path, self.dir.join(OLD_LOG_FILE));
+        }
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+            let message = redact_values(message).replace('\n', " ");
+            let _ = writeln!(file, "{} {level} {message}", unix_time());
+        }
+    }
+
+    /// Keeps `report` as the last one, with the cell values it quotes masked: every
+    /// string under an `examples` key and the examples ending any other string.
+    pub fn save_report(&self, report: &impl Serialize) {
+        let Ok(mut report) = serde_json::to_value(report) else {
+            return;
+        };
+        redact_report(&mut report, false);
+        let _guard = self.lock.lock().unwrap();
+        if let Ok(data) = serde_json::to_vec_pretty(&report) {
+            let _ = fs::create_dir_all(&self.dir);
+            let _ = fs::write(self.dir.join(REPORT_FILE), data);
+        }
+    }
+
+    /// Writes a zip for support to `dest`: the versions, the settings without secrets,
+    /// the activity log, the last conversion report and, when `sample` is given, an
+    /// anonymized copy of that CSV's first rows.
+    pub fn write_bundle(
+        &self,
+        dest: &Path,
+        versions: &Versions,
+        settings: &Settings,
+        sample: Option<&Path>,
+    ) -> Result<(), String> {
+        let file = File::create(dest).map_err(|e| format!("Failed to create diagnostic bundle: {e}"))?;
+        let result = self.fill_bundle(ZipWriter::new(BufWriter::new(file)), versions, settings, sample);
+        if result.is_err() {
+            let _ = fs::remove_file(dest);
+        }
+        result
+    }
+
+    fn fill_bundle(
+        &self,
+        mut zip: ZipWriter<impl Write>,
+        versions: &Versions,
+        settings: &Settings,
+        sample: Option<&Path>,
+    ) -> Result<(), String> {
+        let write_error = |e: io::Error| format!("Failed to write diagnostic bundle: {e}");
+        let settings = Settings {
+            webhook_url: settings.webhook_url.as_deref().map(redact_url),
+            temp_dir: settings.temp_dir.clone(),
+        };
+        for (name, value) in [
+            ("versions.json", serde_json::to_vec_pretty(versions)),
+            ("settings.json", serde_json::to_vec_pretty(&settings)),
+        ] {
+            let data = value.map_err(|e| format!("Failed to serialize {name}: {e}"))?;
+            zip.add(name, &data).map_err(write_error)?;
+        }
+        {
+            let _guard = self.lock.lock().unwrap();
+            for name in [OLD_LOG_FILE, LOG_FILE, REPORT_FILE] {
+                if let Ok(data) = fs::read(self.dir.join(name)) {
+                    zip.add(name, &data).map_err(write_error)?;
+                }
+            }
+        }
+        if let Some(path) = sample {
+            let data = anonymized_sample(path, SAMPLE_ROWS)?;
+            zip.add("sample.csv", &data).map_err(write_error)?;
+        }
+        zip.finish().map_err(write_error)?;
+        Ok(())
+    }
+}
+
+/// Scheme and host of a URL; paths and queries of webhooks often hold tokens.
+fn redact_url(raw: &str) -> String {
+    match url::Url::parse(raw) {
+        Ok(url) => format!("{}://{}/…", url.scheme(), url.host_str().unwrap_or_default()),
+        Err(_) => "(invalid URL)".to_string(),
+    }
+}
+
+/// Letters replaced by `x` or `X` and digits by `9`, so the shape of a value
+/// survives but not the value.
+pub fn mask(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            c if c.is_numeric() => '9',
+            c if c.is_uppercase() => 'X',
+            c if c.is_alphabetic() => 'x',
+            c => c,
+        })
+        .collect()
+}
+
+/// `message` with the cell values it quotes after "e.g." masked.
+pub fn redact_values(message: &str) -> String {
+    match message.find(EXAMPLES_MARKER) {
+        Some(at) => {
+            let (head, examples) = message.split_at(at + EXAMPLES_MARKER.len());
+            format!("{head}{}", mask(examples))
+        }
+        None => message.to_string(),
+    }
+}
+
+fn redact_report(value: &mut serde_json::Value, examples: bool) {
+    match value {
+        serde_json::Value::String(text) if examples => *text = mask(text),
+        serde_json::Value::String(text) => *text = redact_values(text),
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(|item| redact_report(item, examples));
+        }
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields {
+                redact_report(field, examples || key == "examples");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The header and first `rows` records of a CSV with every letter replaced by `x`
+/// or `X` and every digit by `9`, so layout, quoting, lengths and invalid UTF-8
+/// (as U+FFFD) survive but the values do not. Missing markers such as `NA` are kept.
+pub fn anonymized_sample(path: &Path, rows: usize) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open CSV: {e}"))?;
+    let mut buf = BufReader::new(file);
+    input::skip_utf8_bom(&mut buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(buf);
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+    let write_error = |e: csv::Error| format!("Failed to write sample: {e}");
+    let options = ConvertOptions::default();
+    for (i, record) in reader.byte_records().take(rows + 1).enumerate() {
+        let record = record.map_err(|e| format!("CSV read error at row {i}: {e}"))?;
+        if i == 0 {
+            writer.write_record(&record).map_err(write_error)?;
+            continue;
+        }
+        let fields = record.iter().map(|field| {
+            let text = String::from_utf8_lossy(field);
+            if options.is_missing_marker(&text) {
+                return text.into_owned();
+            }
+            mask(&text)
+        });
+        writer.write_record(fields.collect::<Vec<String>>()).map_err(write_error)?;
+    }
+    writer.into_inner().map_err(|e| format!("Failed to write sample: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_with_anonymized_sample() {
+        let dir = std::env::temp_dir().join("csv2sav_diagnostics_test");
+        let _ = fs::remove_dir_all(&dir);
+        let diagnostics = Diagnostics::open(dir.join("logs"));
+        diagnostics.record("error", "a.csv: failed\nbadly");
+        diagnostics.save_report(&serde_json::json!({ "success": false }));
+
+        let csv = dir.join("a.csv");
+        fs::write(&csv, "name,phone,note\nAnn Lee,555-0101,\"NA\"\nÉva,+36 1 234,\"two\nlines\"\nrest,0,0\n").unwrap();
+        assert_eq!(
+            String::from_utf8(anonymized_sample(&csv, 2).unwrap()).unwrap(),
+            "name,phone,note\nXxx Xxx,999-9999,NA\nXxx,+99 9 999,\"xxx\nxxxxx\"\n"
+        );
+
+        let settings = Settings {
+            webhook_url: Some("https://hooks.example.com/T000/secret".to_string()),
+            ..Settings::default()
+        };
+        let dest = dir.join("bundle.zip");
+        let versions = Versions::new("1.0.0".to_string(), "2.0.0");
+        diagnostics.write_bundle(&dest, &versions, &settings, Some(&csv)).unwrap();
+        let data = fs::read(&dest).unwrap();
+        let contains = |needle: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+        for name in ["versions.json", "settings.json", LOG_FILE, REPORT_FILE, "sample.csv"] {
+            assert!(contains(name.as_bytes()), "{name}");
+        }
+        assert!(!contains(OLD_LOG_FILE.as_bytes()));
+        assert_eq!(redact_url("https://hooks.example.com/T000/secret"), "https://hooks.example.com/…");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Name and inflated contents of every entry of a zip from [`ZipWriter`].
+    fn entries(data: &[u8]) -> Vec<(String, String)> {
+        use std::io::Read;
+
+        let mut entries = Vec::new();
+        let mut at = 0;
+        while data[at..at + 4] == 0x0403_4b50u32.to_le_bytes() {
+            let size = u32::from_le_bytes(data[at + 18..at + 22].try_into().unwrap()) as usize;
+            let name_len = u16::from_le_bytes([data[at + 26], data[at + 27]]) as usize;
+            let name = String::from_utf8(data[at + 30..at + 30 + name_len].to_vec()).unwrap();
+            let start = at + 30 + name_len;
+            let mut text = String::new();
+            flate2::read::DeflateDecoder::new(&data[start..start + size])
+                .read_to_string(&mut text)
+                .unwrap();
+            entries.push((name, text));
+            at = start + size;
+        }
+        entries
+    }
+
+    #[test]
+    fn test_bundle_masks_cell_values() {
+        let dir = std::env::temp_dir().join("csv2sav_diagnostics_values_test");
+        let _ = fs::remove_dir_all(&dir);
+        let diagnostics = Diagnostics::open(dir.clone());
+        let warning =
+            "Column 'name': 1 value(s) not valid for its type set to missing, e.g. 'Ann Lee', '42'";
+        diagnostics.record("warning", &format!("a.csv: {warning}"));
+        diagnostics.save_report(&serde_json::json!({
+            "input_path": "a.csv",
+            "warnings": [warning],
+            "truncations": [{ "column": "note", "width": 8, "examples": ["Secret note…"] }],
+        }));
+
+        let mut data = Vec::new();
+        let versions = Versions::new("1.0.0".to_string(), "2.0.0");
+        let settings = Settings::default();
+        diagnostics.fill_bundle(ZipWriter::new(&mut data), &versions, &settings, None).unwrap();
+        let entries = entries(&data);
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert!(names.contains(&LOG_FILE) && names.contains(&REPORT_FILE), "{names:?}");
+        for (name, text) in &entries {
+            for value in ["Ann Lee", "42", "Secret"] {
+                assert!(!text.contains(value), "{name} holds '{value}'");
+            }
+        }
+        let report = &entries.iter().find(|(name, _)| name == REPORT_FILE).unwrap().1;
+        assert!(report.contains("e.g. 'Xxx Xxx', '99'"), "{report}");
+        assert!(report.contains("Xxxxxx xxxx…") && report.contains("a.csv"), "{report}");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::options::DictionaryFormat;
+use crate::readstat_writer::{ColDef, ColType, LabelValue, Measure};
+
+/// SPSS allows at most three discrete user-missing values per variable.
+pub const MAX_MISSING_VALUES: usize = 3;
+pub const MAX_NAME_BYTES: usize = 64;
+const RESERVED_NAMES: [&str; 13] = [
+    "ALL", "AND", "BY", "EQ", "GE", "GT", "LE", "LT", "NE", "NOT", "OR", "TO", "WITH",
+];
+
+/// Metadata for one CSV column, applied over what inference produced.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VariableSpec {
+    /// CSV header the entry applies to; defaults to `name`.
+    pub column: Option<String>,
+    /// SAV variable name instead of `V1`, `V2`, …
+    pub name: Option<String>,
+    /// Variable label instead of the CSV header.
+    pub label: Option<String>,
+    /// Value labels keyed by the value as it appears in the CSV.
+    pub value_labels: BTreeMap<String, String>,
+    /// User-missing codes, as they appear in the CSV.
+    pub missing: Vec<String>,
+    pub measure: Option<Measure>,
+}
+
+/// Value labels from a codebook file, by variable and then value.
+pub type Codebook = BTreeMap<String, BTreeMap<String, String>>;
+
+#[derive(Debug, Default, Deserialize)]
+struct RawDictionary {
+    variables: Vec<VariableSpec>,
+}
+
+/// A written variable as listed in an exported dictionary. The `column`, `name`,
+/// `label`, `measure`, `missing` and `value_labels` fields read back with [`load`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportedVariable {
+    pub column: String,
+    pub name: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub var_type: &'static str,
+    /// SPSS display format, e.g. `F8.2`, `A20` or `DATE11`.
+    pub format: String,
+    /// Bytes of a string variable.
+    pub width: Option<usize>,
+    pub measure: Measure,
+    pub missing: Vec<String>,
+    pub value_labels: BTreeMap<String, String>,
+}
+
+impl ExportedVariable {
+    pub fn new(column: &str, def: &ColDef) -> Self {
+        let (var_type, format, width, measure) = match &def.col_type {
+            ColType::Numeric { width, decimals } => {
+                ("numeric", format!("F{width}.{decimals}"), None, Measure::Scale)
+            }
+            ColType::String(w) => ("string", format!("A{w}"), Some(*w), Measure::Nominal),
+            ColType::Date(format) => ("date", format.to_string(), None, Measure::Scale),
+        };
+        let missing = def
+            .missing_numbers
+            .iter()
+            .map(f64::to_string)
+            .chain(def.missing_range.map(|(lo, hi)| format!("{lo} thru {hi}")))
+            .chain(def.missing_strings.iter().cloned())
+            .collect();
+        let value_labels = def
+            .value_labels
+            .iter()
+            .map(|(value, text)| {
+                let value = match value {
+                    LabelValue::Number(n) => n.to_string(),
+                    LabelValue::Str(s) => s.clone(),
+                };
+                (value, text.clone())
+            })
+            .collect();
+        Self {
+            column: column.to_string(),
+            name: def.name.clone(),
+            label: def.label.clone(),
+            var_type,
+            format,
+            width,
+            measure: def.measure.unwrap_or(measure),
+            missing,
+            value_labels,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedDictionary<'a> {
+    variables: &'a [ExportedVariable],
+}
+
+/// A data dictionary indexed by CSV header.
+#[derive(Debug, Default)]
+pub struct DataDictionary {
+    variables: HashMap<String, VariableSpec>,
+}
+
+impl DataDictionary {
+    pub fn get(&self, header: &str) -> Option<&VariableSpec> {
+        self.variables.get(header)
+    }
+
+    /// Warnings for entries whose column is not among `headers`.
+    pub fn unmatched(&self, headers: &[String]) -> Vec<String> {
+        let mut columns: Vec<&String> = self
+            .variables
+            .keys()
+            .filter(|column| !headers.contains(column))
+            .collect();
+        columns.sort();
+        columns
+            .into_iter()
+            .map(|column| format!("Data dictionary entry '{column}' matches no header"))
+            .collect()
+    }
+
+    /// Adds a codebook's value labels, replacing any the dictionary has for the same
+    /// value. Returns warnings for codebook variables that match no column.
+    pub fn add_value_labels(&mut self, codebook: Codebook, headers: &[String]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (variable, labels) in codebook {
+            let column = if headers.contains(&variable) {
+                Some(variable.clone())
+            } else {
+                self.variables
+                    .iter()
+                    .find(|(_, spec)| spec.name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(&variable)))
+                    .map(|(column, _)| column.clone())
+            };
+            match column {
+                Some(column) => self.variables.entry(column).or_default().value_labels.extend(labels),
+                None => warnings.push(format!("Value label file: variable '{variable}' matches no column")),
+            }
+        }
+        warnings
+    }
+}
+
+/// Loads a codebook: a CSV with `variable`, `value` and `label` columns, in any order.
+pub fn load_value_labels(path: &Path) -> Result<Codebook, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read value label file: {e}"))?;
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(&text);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid value label file: {e}"))?
+        .clone();
+    let index = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (Some(variable), Some(value), Some(label)) = (index("variable"), index("value"), index("label")) else {
+        return Err("Value label file needs variable, value and label columns".to_string());
+    };
+
+    let mut codebook = Codebook::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid value label file: {e}"))?;
+        let field = |i: usize| record.get(i).map_or("", str::trim);
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let (name, code) = (field(variable), field(value));
+        if name.is_empty() || code.is_empty() {
+            return Err(format!("Value label file row {}: needs a variable and a value", row + 1));
+        }
+        let labels = codebook.entry(name.to_string()).or_default();
+        if labels.insert(code.to_string(), field(label).to_string()).is_some() {
+            return Err(format!("Value label file row {}: '{name}' labels value '{code}' twice", row + 1));
+        }
+    }
+    Ok(codebook)
+}
+
+/// Loads a dictionary from JSON (`{"variables": [...]}`) or, for a `.csv` file, from
+/// a table with `column`, `name`, `label`, `measure`, `missing` and `value_labels`
+/// columns. In the CSV form, missing codes are separated by `;` and value labels are
+/// written `1=Male;2=Female`.
+pub fn load(path: &Path) -> Result<DataDictionary, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read data dictionary: {e}"))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let specs = if is_csv {
+        parse_csv(&text)?
+    } else {
+        serde_json::from_str::<RawDictionary>(&text)
+            .map_err(|e| format!("Invalid data dictionary: {e}"))?
+            .variables
+    };
+
+    let mut variables = HashMap::new();
+    for (i, spec) in specs.into_iter().enumerate() {
+        let Some(column) = spec.column.clone().or_else(|| spec.name.clone()) else {
+            return Err(format!("Data dictionary entry {}: needs a column or name", i + 1));
+        };
+        if let Some(name) = &spec.name {
+            validate_name(name).map_err(|e| format!("Data dictionary entry '{column}': {e}"))?;
+        }
+        if spec.missing.len() > MAX_MISSING_VALUES {
+            return Err(format!(
+                "Data dictionary entry '{column}': at most {MAX_MISSING_VALUES} missing values are allowed"
+            ));
+        }
+        if variables.insert(column.clone(), spec).is_some() {
+            return Err(format!("Data dictionary lists column '{column}' twice"));
+        }
+    }
+    Ok(DataDictionary { variables })
+}
+
+fn parse_csv(text: &str) -> Result<Vec<VariableSpec>, String> {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid data dictionary: {e}"))?
+        .clone();
+    let index = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (column, name, label, measure, missing, value_labels) = (
+        index("column"),
+        index("name"),
+        index("label"),
+        index("measure"),
+        index("missing"),
+        index("value_labels"),
+    );
+
+    let mut specs = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid data dictionary: {e}"))?;
+        let field = |i: Option<usize>| {
+            i.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+        };
+        let measure = match field(measure) {
+            Some(text) => Some(
+                serde_json::from_value(text.to_ascii_lowercase().into())
+                    .map_err(|_| format!("Data dictionary row {}: unknown measure '{text}'", row + 1))?,
+            ),
+            None => None,
+        };
+        let mut labels = BTreeMap::new();
+        for pair in field(value_labels).unwrap_or_default().split(';') {
+            if pair.trim().is_empty() {
+                continue;
+            }
+            let (value, text) = pair.split_once('=').ok_or_else(|| {
+                format!("Data dictionary row {}: value label '{pair}' is not value=label", row + 1)
+            })?;
+            labels.insert(value.trim().to_string(), text.trim().to_string());
+        }
+        specs.push(VariableSpec {
+            column: field(column).map(str::to_string),
+            name: field(name).map(str::to_string),
+            label: field(label).map(str::to_string),
+            value_labels: labels,
+            missing: field(missing)
+                .unwrap_or_default()
+                .split(';')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+            measure,
+        });
+    }
+    Ok(specs)
+}
+
+/// `data.zsav` → `data.zsav.dictionary.csv`.
+pub fn export_path(output: &Path, format: DictionaryFormat) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(match format {
+        DictionaryFormat::Csv => ".dictionary.csv",
+        DictionaryFormat::Json => ".dictionary.json",
+    });
+    output.with_file_name(name)
+}
+
+/// Writes the variables as JSON or as a CSV table in the layout [`load`] reads.
+pub fn export(path: &Path, format: DictionaryFormat, variables: &[ExportedVariable]) -> Result<(), String> {
+    let error = |e: String| format!("Failed to write data dictionary: {e}");
+    let bytes = match format {
+        DictionaryFormat::Json => serde_json::to_vec_pretty(&ExportedDictionary { variables })
+            .map_err(|e| error(e.to_string()))?,
+        DictionaryFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record([
+                    "column", "name", "label", "type", "format", "width", "measure", "missing",
+                    "value_labels",
+                ])
+                .map_err(|e| error(e.to_string()))?;
+            for v in variables {
+                let labels: Vec<String> = v
+                    .value_labels
+                    .iter()
+                    .map(|(value, text)| format!("{value}={text}"))
+                    .collect();
+                writer
+                    .write_record([
+                        v.column.as_str(),
+                        &v.name,
+                        &v.label,
+                        v.var_type,
+                        &v.format,
+                        &v.width.map(|w| w.to_string()).unwrap_or_default(),
+                        measure_name(v.measure),
+                        &v.missing.join(";"),
+                        &labels.join(";"),
+                    ])
+                    .map_err(|e| error(e.to_string()))?;
+            }
+            writer.into_inner().map_err(|e| error(e.to_string()))?
+        }
+    };
+    fs::write(path, bytes).map_err(|e| error(e.to_string()))
+}
+
+fn measure_name(measure: Measure) -> &'static str {
+    match measure {
+        Measure::Nominal => "nominal",
+        Measure::Ordinal => "ordinal",
+        Measure::Scale => "scale",
+    }
+}
+
+/// Checks SPSS variable naming rules: at most 64 bytes, starting with a letter, `@`,
+/// `#` or `$`, then letters, digits, `.`, `_`, `@`, `#` or `$`, and not a reserved word.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return Err("variable name is empty".to_string());
+    };
+    if name.len() > MAX_NAME_BYTES {
+        return Err(format!("variable name '{name}' is longer than {MAX_NAME_BYTES} bytes"));
+    }
+    if !(first.is_alphabetic() || matches!(first, '@' | '#' | '$')) {
+        return Err(format!("variable name '{name}' must start with a letter"));
+    }
+    if !chars.all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '@' | '#' | '$')) {
+        return Err(format!("variable name '{name}' contains characters SPSS does not allow"));
+    }
+    if name.ends_with('.') || name.ends_with('_') {
+        return Err(format!("variable name '{name}' must not end with '.' or '_'"));
+    }
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+        return Err(format!("variable name '{name}' is a reserved word"));
+    }
+    Ok(())
+}
+
+/// Turns a header like `Q3_1` or `Duration (in seconds)` into a valid SPSS variable
+/// name by replacing disallowed characters with `_`; None when nothing usable is left.
+pub fn sanitize_name(header: &str) -> Option<String> {
+    let mut name = String::with_capacity(header.len());
+    for c in header.trim().chars() {
+        let c = if c.is_alphanumeric() || matches!(c, '.' | '@' | '#' | '$') { c } else { '_' };
+        if !(c == '_' && name.ends_with('_')) {
+            name.push(c);
+        }
+    }
+    let mut name = crate::converter::truncate_utf8(&name, MAX_NAME_BYTES).to_string();
+    while name.ends_with(['_', '.']) {
+        name.pop();
+    }
+    validate_name(&name).ok().map(|_| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_csv_and_json_dictionaries() {
+        let dir = std::env::temp_dir().join("csv2sav_dictionary_test");
+        fs::create_dir_all(&dir).unwrap();
+        let csv_path = dir.join("dict.csv");
+        fs::write(
+            &csv_path,
+            "column,name,label,measure,missing,value_labels\nSex,sex,Respondent sex,Nominal,-99,1=Male;2=Female\nAge,age,,scale,-98;-99,\n",
+        )
+        .unwrap();
+        let dict = load(&csv_path).unwrap();
+        let sex = dict.get("Sex").unwrap();
+        assert_eq!(sex.name.as_deref(), Some("sex"));
+        assert_eq!(sex.label.as_deref(), Some("Respondent sex"));
+        assert_eq!(sex.measure, Some(Measure::Nominal));
+        assert_eq!(sex.missing, vec!["-99"]);
+        assert_eq!(sex.value_labels.get("2").map(String::as_str), Some("Female"));
+        assert_eq!(dict.get("Age").unwrap().missing, vec!["-98", "-99"]);
+        assert_eq!(
+            dict.unmatched(&["Sex".to_string()]),
+            vec!["Data dictionary entry 'Age' matches no header"]
+        );
+
+        let json_path = dir.join("dict.json");
+        fs::write(
+            &json_path,
+            r#"{"variables": [{"name": "score", "measure": "ordinal", "value_labels": {"1": "Low"}}]}"#,
+        )
+        .unwrap();
+        let dict = load(&json_path).unwrap();
+        assert_eq!(dict.get("score").unwrap().measure, Some(Measure::Ordinal));
+
+        fs::write(&json_path, r#"{"variables": [{"column": "x", "name": "1x"}]}"#).unwrap();
+        assert!(load(&json_path).unwrap_err().contains("must start with a letter"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_value_label_file() {
+        let dir = std::env::temp_dir().join("csv2sav_value_label_file_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("codebook.csv");
+        fs::write(&path, "Variable,Value,Label\nSex,1,Male\nSex,2,Female\nsat,5,Very satisfied\n,,\nnone,1,x\n").unwrap();
+        let codebook = load_value_labels(&path).unwrap();
+        assert_eq!(codebook["Sex"].len(), 2);
+
+        let mut dict = DataDictionary::default();
+        dict.variables.insert(
+            "Satisfaction".to_string(),
+            VariableSpec {
+                name: Some("SAT".to_string()),
+                value_labels: BTreeMap::from([("5".to_string(), "Old".to_string())]),
+                ..Default::default()
+            },
+        );
+        let headers = ["Sex".to_string(), "Satisfaction".to_string()];
+        assert_eq!(
+            dict.add_value_labels(codebook, &headers),
+            vec!["Value label file: variable 'none' matches no column"]
+        );
+        assert_eq!(dict.get("Sex").unwrap().value_labels["2"], "Female");
+        assert_eq!(dict.get("Satisfaction").unwrap().value_labels["5"], "Very satisfied");
+
+        fs::write(&path, "variable,value,label\nSex,1,Male\nSex,1,M\n").unwrap();
+        assert!(load_value_labels(&path).unwrap_err().contains("twice"));
+        fs::write(&path, "variable,label\nSex,Male\n").unwrap();
+        assert!(load_value_labels(&path).is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("age_2").is_ok());
+        assert!(validate_name("$weight").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("has space").is_err());
+        assert!(validate_name("with").is_err());
+        assert!(validate_name("trailing.").is_err());
+        assert!(validate_name(&"x".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("Q3_1").as_deref(), Some("Q3_1"));
+        assert_eq!(
+            sanitize_name("Duration (in seconds)").as_deref(),
+            Some("Duration_in_seconds")
+        );
+        assert_eq!(sanitize_name("1_Q"), None);
+        assert_eq!(sanitize_name("("), None);
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::{CancelToken, TaskError};
+
+/// Error sentinel for an output file held open by another program (e.g. SPSS).
+pub const IN_USE: &str = "FileInUse";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Whether another process holds `path` open in a way that prevents overwriting it.
+/// Only Windows enforces such locks; elsewhere this is always false.
+#[cfg(windows)]
+pub fn is_in_use(path: &Path) -> bool {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    match OpenOptions::new().write(true).share_mode(0).open(path) {
+        Ok(_) => false,
+        Err(e) => matches!(
+            e.raw_os_error(),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        ),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn is_in_use(_path: &Path) -> bool {
+    false
+}
+
+/// Fails with [`IN_USE`] if the output is locked, optionally polling for up to `wait`
+/// so the user can close the other program. `on_wait` runs once when waiting starts.
+pub fn ensure_writable(
+    path: &Path,
+    wait: Duration,
+    cancel: &CancelToken,
+    on_wait: &dyn Fn(),
+) -> Result<(), TaskError> {
+    if !is_in_use(path) {
+        return Ok(());
+    }
+    if wait.is_zero() {
+        return Err(IN_USE.into());
+    }
+
+    on_wait();
+    let deadline = Instant::now() + wait;
+    while Instant::now() < deadline {
+        cancel.check()?;
+        thread::sleep(POLL_INTERVAL);
+        if !is_in_use(path) {
+            return Ok(());
+        }
+    }
+    Err(IN_USE.into())
+}
@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+use crate::schema;
+
+#[derive(Serialize)]
+pub struct OutputFormat {
+    id: &'static str,
+    extension: &'static str,
+    compressions: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct SupportedFormats {
+    input_formats: Vec<&'static str>,
+    output_formats: Vec<OutputFormat>,
+    /// Labels of the encodings input can be read in; see [`InputEncoding`].
+    ///
+    /// [`InputEncoding`]: crate::options::InputEncoding
+    input_encodings: Vec<&'static str>,
+    output_encodings: Vec<&'static str>,
+    max_string_width: usize,
+}
+
+/// What the backend reads and writes, for clients to offer.
+pub fn supported_formats() -> SupportedFormats {
+    SupportedFormats {
+        input_formats: vec!["csv", "jsonl"],
+        output_formats: vec![
+            OutputFormat {
+                id: "zsav",
+                extension: "zsav",
+                compressions: vec!["zlib"],
+            },
+            OutputFormat {
+                id: "sav",
+                extension: "sav",
+                compressions: vec!["rows", "none"],
+            },
+            OutputFormat {
+                id: "xpt",
+                extension: "xpt",
+                compressions: Vec::new(),
+            },
+        ],
+        input_encodings: vec![
+            "utf-8",
+            "gbk",
+            "big5",
+            "shift_jis",
+            "latin1",
+            "utf-16le",
+            "utf-16be",
+        ],
+        output_encodings: vec!["utf-8"],
+        max_string_width: schema::MAX_STRING_WIDTH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_formats() {
+        let formats = supported_formats();
+        assert_eq!(formats.input_formats, ["csv", "jsonl"]);
+        let outputs: Vec<_> = formats.output_formats.iter().map(|f| f.id).collect();
+        assert_eq!(outputs, ["zsav", "sav", "xpt"]);
+        assert_eq!(
+            formats.input_encodings,
+            ["utf-8", "gbk", "big5", "shift_jis", "latin1", "utf-16le", "utf-16be"]
+        );
+        for label in &formats.input_encodings {
+            assert!(encoding_rs::Encoding::for_label(label.as_bytes()).is_some(), "{label}");
+        }
+        assert_eq!(formats.output_encodings, ["utf-8"]);
+    }
+}
@@ -0,0 +1,113 @@
+/// First header of a Google Forms response export, in the languages Forms is most
+/// often used in.
+const TIMESTAMP_HEADERS: [&str; 8] = [
+    "Timestamp",
+    "Marca temporal",
+    "Horodateur",
+    "Zeitstempel",
+    "Carimbo de data/hora",
+    "Informazioni cronologiche",
+    "时间戳记",
+    "タイムスタンプ",
+];
+/// Checkbox answers are joined with this in the CSV download.
+pub const SEPARATOR: char = ';';
+/// Columns with more distinct parts than this are free text, not a checkbox question.
+const MAX_OPTIONS: usize = 30;
+
+/// Whether the header row looks like a Google Forms response export.
+pub fn is_google_forms(headers: &[String]) -> bool {
+    headers
+        .first()
+        .is_some_and(|h| TIMESTAMP_HEADERS.iter().any(|t| t.eq_ignore_ascii_case(h.trim())))
+}
+
+/// Collects the options of checkbox questions, whose answers list every ticked option
+/// separated by [`SEPARATOR`].
+#[derive(Debug, Clone)]
+pub struct MultiSelect {
+    /// Distinct options per column, in order of appearance; None once a column has
+    /// too many to be a checkbox question.
+    options: Vec<Option<Vec<String>>>,
+    /// Some answer in the column held more than one option.
+    joined: Vec<bool>,
+}
+
+impl MultiSelect {
+    pub fn new(columns: usize) -> Self {
+        Self {
+            options: vec![Some(Vec::new()); columns],
+            joined: vec![false; columns],
+        }
+    }
+
+    pub fn observe(&mut self, column: usize, value: &str) {
+        let Some(Some(options)) = self.options.get_mut(column) else {
+            return;
+        };
+        let mut parts = 0;
+        for part in split(value) {
+            parts += 1;
+            if !options.iter().any(|o| o == part) {
+                options.push(part.to_string());
+            }
+        }
+        if parts > 1 {
+            self.joined[column] = true;
+        }
+        if options.len() > MAX_OPTIONS {
+            self.options[column] = None;
+        }
+    }
+
+    /// Columns to split and their options.
+    pub fn finish(self) -> Vec<(usize, Vec<String>)> {
+        self.options
+            .into_iter()
+            .zip(self.joined)
+            .enumerate()
+            .filter_map(|(i, (options, joined))| Some((i, options.filter(|_| joined)?)))
+            .collect()
+    }
+}
+
+/// Header of the dummy column for one option, like Forms' own grid columns.
+pub fn dummy_header(header: &str, option: &str) -> String {
+    format!("{header} [{option}]")
+}
+
+/// Whether a checkbox answer includes `option`.
+pub fn is_selected(answer: &str, option: &str) -> bool {
+    split(answer).any(|part| part == option)
+}
+
+fn split(answer: &str) -> impl Iterator<Item = &str> {
+    answer
+        .split(SEPARATOR)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_order_and_multi_select() {
+        assert!(is_google_forms(&["Timestamp".to_string(), "Name".to_string()]));
+        assert!(!is_google_forms(&["id".to_string()]));
+
+        let mut multi = MultiSelect::new(3);
+        for row in [["Apple;Banana", "x", "1"], ["Kiwi", "y", "2"], ["", "z", "3"]] {
+            for (i, value) in row.iter().enumerate() {
+                multi.observe(i, value);
+            }
+        }
+        assert_eq!(
+            multi.finish(),
+            vec![(0, vec!["Apple".to_string(), "Banana".to_string(), "Kiwi".to_string()])]
+        );
+        assert!(is_selected("Apple; Banana", "Banana"));
+        assert!(!is_selected("Apple", "App"));
+    }
+}
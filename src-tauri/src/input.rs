@@ -0,0 +1,522 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use csv::{ByteRecord, StringRecord, Utf8Error};
+use encoding_rs::{
+    Decoder, DecoderResult, Encoding, BIG5, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, WINDOWS_1252,
+};
+
+use crate::options::{ConvertOptions, InputEncoding, InvalidUtf8};
+
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Consumes a UTF-8 byte order mark at the start of `reader`, so it never ends up
+/// glued to the first header. Returns whether one was present.
+pub fn skip_utf8_bom<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    let has_bom = reader.fill_buf()?.starts_with(UTF8_BOM);
+    if has_bom {
+        reader.consume(UTF8_BOM.len());
+    }
+    Ok(has_bom)
+}
+
+/// Signatures of files commonly dropped in place of a CSV, with what they are.
+const BINARY_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"PK\x03\x04", "a ZIP archive, such as an Excel .xlsx workbook"),
+    (b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", "an Office file, such as an Excel .xls workbook"),
+    (b"%PDF-", "a PDF document"),
+    (b"$FL2", "an SPSS data file"),
+    (b"$FL3", "an SPSS data file"),
+    (b"SQLite format 3\0", "an SQLite database"),
+    (b"\x1F\x8B", "a gzip archive"),
+    (b"\x89PNG", "a PNG image"),
+    (b"\xFF\xD8\xFF", "a JPEG image"),
+    (b"GIF8", "a GIF image"),
+];
+/// Bytes at the start checked for NULs, and the share of them that means binary data.
+const SNIFF_BYTES: usize = 8192;
+const MAX_NUL_SHARE: f64 = 0.1;
+
+/// Fails fast when `head`, the start of the input, is not delimited text, rather than
+/// letting the parser report confusing errors or produce garbage.
+pub fn check_delimited_text(head: &[u8]) -> Result<(), String> {
+    let sample = &head[..head.len().min(SNIFF_BYTES)];
+    let nuls = sample.iter().filter(|&&b| b == 0).count();
+    let kind = BINARY_SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, kind)| *kind)
+        .or((nuls as f64 > sample.len() as f64 * MAX_NUL_SHARE).then_some("binary data"));
+    match kind {
+        Some(kind) => Err(format!("Not a delimited text file: the content looks like {kind}")),
+        None => Ok(()),
+    }
+}
+
+/// Lines consumed before the header by [`skip_preamble`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Preamble {
+    pub lines: usize,
+    pub bytes: u64,
+    /// Delimiter named by an Excel `sep=` hint on the first line.
+    pub separator: Option<u8>,
+}
+
+impl Preamble {
+    /// A reader for the input's CSV dialect, using the hinted delimiter if there was one.
+    pub fn csv_reader(&self, options: &ConvertOptions) -> Result<csv::ReaderBuilder, String> {
+        let mut builder = options.csv_reader()?;
+        if let Some(separator) = self.separator {
+            builder.delimiter(separator);
+        }
+        Ok(builder)
+    }
+}
+
+/// Reads past the `header_row` records before the header, making the header
+/// `reader`'s headers.
+pub fn seek_header<R: Read>(reader: &mut csv::Reader<R>, header_row: usize) -> Result<(), String> {
+    if header_row == 0 {
+        return Ok(());
+    }
+    // The first read takes record 0 as the headers and returns record 1.
+    let mut record = ByteRecord::new();
+    for _ in 0..header_row {
+        let read = reader
+            .read_byte_record(&mut record)
+            .map_err(|e| format!("Failed to read CSV headers: {e}"))?;
+        if !read {
+            return Err(format!("CSV ends before header row {header_row}"));
+        }
+    }
+    reader.set_byte_headers(record);
+    Ok(())
+}
+
+/// Consumes the lines before the header: the first `skip_lines`, an Excel `sep=`
+/// hint and any further leading lines matching `skip_pattern`. A line longer than
+/// the reader's buffer is always taken as the header.
+pub fn skip_preamble<R: BufRead>(reader: &mut R, options: &ConvertOptions) -> Result<Preamble, String> {
+    let pattern = options
+        .skip_pattern
+        .as_deref()
+        .map(regex::Regex::new)
+        .transpose()
+        .map_err(|e| format!("Invalid skip pattern: {e}"))?;
+    let mut preamble = Preamble::default();
+    loop {
+        let buf = reader.fill_buf().map_err(|e| format!("Failed to read CSV: {e}"))?;
+        let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+            break;
+        };
+        let line = buf[..end].strip_suffix(b"\r").unwrap_or(&buf[..end]);
+        let separator = if preamble.lines == 0 { excel_separator(line) } else { None };
+        let skip = preamble.lines < options.skip_lines
+            || separator.is_some()
+            || pattern
+                .as_ref()
+                .is_some_and(|p| p.is_match(&String::from_utf8_lossy(line)));
+        if !skip {
+            break;
+        }
+        preamble.separator = preamble.separator.or(separator);
+        preamble.lines += 1;
+        preamble.bytes += end as u64 + 1;
+        reader.consume(end + 1);
+    }
+    Ok(preamble)
+}
+
+/// The delimiter of an Excel `sep=;` line.
+fn excel_separator(line: &[u8]) -> Option<u8> {
+    match line {
+        [s, e, p, b'=', sep] if [*s, *e, *p].eq_ignore_ascii_case(b"sep") && sep.is_ascii() => {
+            Some(*sep)
+        }
+        _ => None,
+    }
+}
+
+/// Converts a raw record to text. In strict mode invalid UTF-8 is an error; in lossy
+/// mode it is replaced with U+FFFD and the indices of the affected fields are returned.
+pub fn decode_record(
+    record: ByteRecord,
+    mode: InvalidUtf8,
+) -> Result<(StringRecord, Vec<usize>), Utf8Error> {
+    let raw = match StringRecord::from_byte_record(record) {
+        Ok(record) => return Ok((record, Vec::new())),
+        Err(e) if mode == InvalidUtf8::Strict => return Err(e.utf8_error().clone()),
+        Err(e) => e.into_byte_record(),
+    };
+
+    let mut replaced = Vec::new();
+    let mut decoded = StringRecord::with_capacity(raw.as_slice().len(), raw.len());
+    for (i, field) in raw.iter().enumerate() {
+        match std::str::from_utf8(field) {
+            Ok(text) => decoded.push_field(text),
+            Err(_) => {
+                replaced.push(i);
+                decoded.push_field(&String::from_utf8_lossy(field));
+            }
+        }
+    }
+    decoded.set_position(raw.position().cloned());
+    Ok((decoded, replaced))
+}
+
+/// Checks the fields at `cols` for valid UTF-8 without copying. In lossy mode invalid
+/// fields are replaced with U+FFFD in place and their indices returned; in strict mode
+/// the index of the first invalid field is the error.
+pub fn repair_fields(
+    record: &mut ByteRecord,
+    cols: &[usize],
+    mode: InvalidUtf8,
+) -> Result<Vec<usize>, usize> {
+    let invalid: Vec<usize> = cols
+        .iter()
+        .copied()
+        .filter(|&i| record.get(i).is_some_and(|f| std::str::from_utf8(f).is_err()))
+        .collect();
+    match invalid.first() {
+        None => return Ok(invalid),
+        Some(&i) if mode == InvalidUtf8::Strict => return Err(i),
+        Some(_) => {}
+    }
+
+    let mut repaired = ByteRecord::with_capacity(record.as_slice().len(), record.len());
+    for (i, field) in record.iter().enumerate() {
+        if invalid.contains(&i) {
+            repaired.push_field(String::from_utf8_lossy(field).as_bytes());
+        } else {
+            repaired.push_field(field);
+        }
+    }
+    repaired.set_position(record.position().cloned());
+    *record = repaired;
+    Ok(invalid)
+}
+
+/// Builds the strict-mode error for a record starting at byte `start` of `path`,
+/// pinpointing the first invalid sequence. `location` names the row and column.
+pub fn invalid_utf8_error(path: &Path, start: u64, location: &str) -> String {
+    match first_invalid_utf8(path, start) {
+        Ok(Some(offset)) => format!("Invalid UTF-8 at byte {offset} ({location})"),
+        _ => format!("Invalid UTF-8 ({location})"),
+    }
+}
+
+/// Offset of the first byte at or after `start` that is not part of valid UTF-8.
+/// Only called on the error path, so re-reading the file is acceptable.
+fn first_invalid_utf8(path: &Path, start: u64) -> io::Result<Option<u64>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut offset = start;
+    let mut filled = 0;
+    loop {
+        let n = file.read(&mut buf[filled..])?;
+        let end = filled + n;
+        match std::str::from_utf8(&buf[..end]) {
+            Ok(_) if n == 0 => return Ok(None),
+            Ok(_) => {
+                offset += end as u64;
+                filled = 0;
+            }
+            Err(e) if e.error_len().is_some() || n == 0 => {
+                return Ok(Some(offset + e.valid_up_to() as u64));
+            }
+            // A multi-byte character split across reads; carry its start over.
+            Err(e) => {
+                let valid = e.valid_up_to();
+                buf.copy_within(valid..end, 0);
+                filled = end - valid;
+                offset += valid as u64;
+            }
+        }
+    }
+}
+
+/// Bytes read before deciding the encoding of an `auto` input.
+const DETECT_BYTES: usize = 64 * 1024;
+const TRANSCODE_BUF_SIZE: usize = 64 * 1024;
+
+/// UTF-16LE or UTF-16BE when `head` starts with its byte order mark.
+pub fn utf16_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    Encoding::for_bom(head)
+        .map(|(encoding, _)| encoding)
+        .filter(|&encoding| encoding == UTF_16LE || encoding == UTF_16BE)
+}
+
+fn legacy_encoding(encoding: InputEncoding) -> Option<&'static Encoding> {
+    match encoding {
+        InputEncoding::Utf8 | InputEncoding::Auto => None,
+        InputEncoding::Gbk => Some(GBK),
+        InputEncoding::Big5 => Some(BIG5),
+        InputEncoding::ShiftJis => Some(SHIFT_JIS),
+        InputEncoding::Latin1 => Some(WINDOWS_1252),
+    }
+}
+
+/// Whether `head` decodes in `encoding` without malformed sequences. A character
+/// cut off at the end counts as valid unless `head` is the complete input.
+fn decodes_cleanly(encoding: &'static Encoding, head: &[u8], complete: bool) -> bool {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let capacity = decoder.max_utf8_buffer_length_without_replacement(head.len());
+    let mut text = String::with_capacity(capacity.unwrap_or(head.len() * 3));
+    let (result, _) = decoder.decode_to_string_without_replacement(head, &mut text, complete);
+    !matches!(result, DecoderResult::Malformed(..))
+}
+
+/// Guesses the encoding of `head`, the start of the input (all of it when
+/// `complete`): UTF-8 when valid, Latin-1 when most non-ASCII bytes stand alone like
+/// accented letters, Shift_JIS when it decodes to kana, GBK when every double-byte
+/// character sits in the GB2312 rows, then Big5 or GBK if either decodes cleanly.
+pub fn guess_encoding(head: &[u8], complete: bool) -> InputEncoding {
+    // Left to `check_delimited_text`, which recognizes them in the raw bytes.
+    if check_delimited_text(head).is_err() {
+        return InputEncoding::Utf8;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => return InputEncoding::Utf8,
+        Err(e) if e.error_len().is_none() && !complete => return InputEncoding::Utf8,
+        Err(_) => {}
+    }
+    let high = |i: usize| head.get(i).is_some_and(|&b| b >= 0x80);
+    let non_ascii = (0..head.len()).filter(|&i| high(i)).count();
+    let alone = (0..head.len()).filter(|&i| high(i) && !high(i + 1) && !(i > 0 && high(i - 1)));
+    if alone.count() * 2 > non_ascii {
+        return InputEncoding::Latin1;
+    }
+    if decodes_cleanly(SHIFT_JIS, head, complete) {
+        let (text, _) = SHIFT_JIS.decode_without_bom_handling(head);
+        if text.chars().any(|c| ('\u{3041}'..='\u{30FE}').contains(&c)) {
+            return InputEncoding::ShiftJis;
+        }
+    }
+    let mut i = 0;
+    let mut gb2312 = true;
+    while i < head.len() {
+        if head[i] < 0x80 {
+            i += 1;
+            continue;
+        }
+        gb2312 &= (0xA1..=0xF7).contains(&head[i]) && head.get(i + 1).is_none_or(|&b| b >= 0xA1);
+        i += 2;
+    }
+    if gb2312 && decodes_cleanly(GBK, head, complete) {
+        InputEncoding::Gbk
+    } else if decodes_cleanly(BIG5, head, complete) {
+        InputEncoding::Big5
+    } else if decodes_cleanly(GBK, head, complete) {
+        InputEncoding::Gbk
+    } else {
+        InputEncoding::Latin1
+    }
+}
+
+/// Reads `source` as UTF-8, decoding UTF-16 or a legacy encoding on the fly; `auto`
+/// guesses the latter from the first [`DETECT_BYTES`]. UTF-8 input passes through
+/// untouched.
+pub struct Transcoder<R> {
+    source: R,
+    encoding: InputEncoding,
+    /// Whether the first read has looked at the input.
+    started: bool,
+    decoder: Option<Decoder>,
+    raw: Vec<u8>,
+    /// UTF-8 waiting to be read, from `pos` on.
+    decoded: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Transcoder<R> {
+    pub fn new(source: R, encoding: InputEncoding) -> Self {
+        Self {
+            source,
+            encoding,
+            started: false,
+            decoder: None,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Reads up to [`DETECT_BYTES`] and settles the encoding: UTF-16 when the input
+    /// starts with its byte order mark, which is dropped, otherwise the configured one.
+    fn start(&mut self) -> io::Result<()> {
+        self.started = true;
+        let mut head = vec![0u8; DETECT_BYTES];
+        let mut filled = 0;
+        while filled < head.len() {
+            match self.source.read(&mut head[filled..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        head.truncate(filled);
+        let encoding = match utf16_encoding(&head) {
+            Some(utf16) => Some(utf16),
+            None if self.encoding == InputEncoding::Auto => {
+                legacy_encoding(guess_encoding(&head, self.eof))
+            }
+            None => legacy_encoding(self.encoding),
+        };
+        match encoding {
+            Some(encoding) => {
+                self.decoder = Some(encoding.new_decoder_with_bom_removal());
+                let last = self.eof;
+                self.decode(&head, last);
+            }
+            None => self.decoded = head,
+        }
+        Ok(())
+    }
+
+    fn decode(&mut self, raw: &[u8], last: bool) {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return;
+        };
+        let capacity = decoder.max_utf8_buffer_length(raw.len()).unwrap_or(raw.len() * 3);
+        self.decoded.resize(capacity, 0);
+        let (_, read, written, _) = decoder.decode_to_utf8(raw, &mut self.decoded, last);
+        debug_assert_eq!(read, raw.len());
+        self.decoded.truncate(written);
+        self.pos = 0;
+    }
+}
+
+impl<R: Read> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.start()?;
+        }
+        loop {
+            if self.pos < self.decoded.len() {
+                let n = buf.len().min(self.decoded.len() - self.pos);
+                buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            if self.decoder.is_none() {
+                return self.source.read(buf);
+            }
+            if self.eof {
+                return Ok(0);
+            }
+            let mut raw = std::mem::take(&mut self.raw);
+            raw.resize(TRANSCODE_BUF_SIZE, 0);
+            let n = self.source.read(&mut raw)?;
+            self.eof = n == 0;
+            self.decode(&raw[..n], self.eof);
+            self.raw = raw;
+        }
+    }
+}
+
+/// Keeps what is read from a stream that cannot be reopened, so it can be read again:
+/// inference samples the start of the input, then conversion reads all of it.
+pub struct Replay<R> {
+    inner: R,
+    seen: Vec<u8>,
+}
+
+impl<R: Read> Replay<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, seen: Vec::new() }
+    }
+
+    /// The whole input again: the bytes read so far, then the rest of the stream.
+    pub fn replay(self) -> io::Chain<io::Cursor<Vec<u8>>, R> {
+        io::Cursor::new(self.seen).chain(self.inner)
+    }
+}
+
+impl<R: Read> Read for Replay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.seen.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_utf8_bom() {
+        let mut with_bom: &[u8] = b"\xEF\xBB\xBFid,name\n";
+        assert!(skip_utf8_bom(&mut with_bom).unwrap());
+        assert_eq!(with_bom, b"id,name\n");
+
+        let mut without: &[u8] = b"id,name\n";
+        assert!(!skip_utf8_bom(&mut without).unwrap());
+        assert_eq!(without, b"id,name\n");
+    }
+
+    #[test]
+    fn test_guess_encoding() {
+        let text = "名前,都市\n山田さん,東京\n";
+        for (encoding, sample, expected) in [
+            (encoding_rs::GBK, "城市,人口\n北京,2189\n上海,2487\n", InputEncoding::Gbk),
+            (encoding_rs::BIG5, "城市,人口\n臺北,2646\n高雄,2773\n", InputEncoding::Big5),
+            (encoding_rs::SHIFT_JIS, text, InputEncoding::ShiftJis),
+        ] {
+            let (bytes, _, _) = encoding.encode(sample);
+            assert_eq!(guess_encoding(&bytes, true), expected, "{}", encoding.name());
+        }
+        assert_eq!(guess_encoding(b"city\nZ\xFCrich\nK\xF8benhavn\n", true), InputEncoding::Latin1);
+        assert_eq!(guess_encoding("北京".as_bytes(), true), InputEncoding::Utf8);
+
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode(text);
+        let mut decoded = String::new();
+        Transcoder::new(&bytes[..], InputEncoding::Auto).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn test_binary_input_rejected() {
+        let error = check_delimited_text(b"PK\x03\x04\x14\x00\x06\x00").unwrap_err();
+        assert_eq!(
+            error,
+            "Not a delimited text file: the content looks like a ZIP archive, such as an Excel .xlsx workbook"
+        );
+        assert!(check_delimited_text(b"%PDF-1.7\n").unwrap_err().contains("a PDF document"));
+        assert!(check_delimited_text(b"\x00\x01\x00\x02\x00\x00\x07\x00").is_err());
+        assert!(check_delimited_text(b"id,name\n1,a\x00b\n").is_ok());
+        assert!(check_delimited_text(b"").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_utf8_modes() {
+        let record = ByteRecord::from(vec![&b"ok"[..], &b"bad\xFFbyte"[..]]);
+        let err = decode_record(record.clone(), InvalidUtf8::Strict).unwrap_err();
+        assert_eq!(err.field(), 1);
+
+        let (decoded, replaced) = decode_record(record.clone(), InvalidUtf8::Lossy).unwrap();
+        assert_eq!(&decoded[1], "bad\u{FFFD}byte");
+        assert_eq!(replaced, vec![1]);
+
+        let mut strict = record.clone();
+        assert_eq!(repair_fields(&mut strict, &[0], InvalidUtf8::Strict), Ok(vec![]));
+        assert_eq!(repair_fields(&mut strict, &[0, 1], InvalidUtf8::Strict), Err(1));
+        let mut lossy = record;
+        assert_eq!(repair_fields(&mut lossy, &[0, 1], InvalidUtf8::Lossy), Ok(vec![1]));
+        assert_eq!(&lossy[1], "bad\u{FFFD}byte".as_bytes());
+
+        let path = std::env::temp_dir().join("csv2sav_invalid_utf8_test.csv");
+        std::fs::write(&path, b"id,name\n1,caf\xC3\xA9\n2,bad\xFF\n").unwrap();
+        let message = invalid_utf8_error(&path, 16, "row 2, column 'name'");
+        assert_eq!(message, "Invalid UTF-8 at byte 21 (row 2, column 'name')");
+        std::fs::remove_file(&path).ok();
+    }
+}
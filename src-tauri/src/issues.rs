@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// What the converter did to a cell or row that did not fit the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Truncated,
+    SetMissing,
+    Clamped,
+    PaddedMissingFields,
+    DroppedExtraFields,
+    ReplacedInvalidUtf8,
+    ScrubbedNul,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Truncated => "truncated",
+            Action::SetMissing => "set_missing",
+            Action::Clamped => "clamped",
+            Action::PaddedMissingFields => "padded_missing_fields",
+            Action::DroppedExtraFields => "dropped_extra_fields",
+            Action::ReplacedInvalidUtf8 => "replaced_invalid_utf8",
+            Action::ScrubbedNul => "scrubbed_nul",
+        }
+    }
+}
+
+/// One altered cell, or a row when `column` is empty.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub row: usize,
+    pub column: String,
+    pub original_value: String,
+    pub action: Action,
+}
+
+/// Issues kept in memory, and how many there were in all.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IssueList {
+    pub issues: Vec<Issue>,
+    pub total: usize,
+}
+
+/// Optional audit trail of every cell the converter altered, written as
+/// `<output>.issues.csv` with columns row, column, original_value, action, or kept
+/// in memory when only checking what a conversion would alter.
+pub struct IssueLog {
+    writer: Option<(PathBuf, csv::Writer<BufWriter<File>>)>,
+    /// Issues kept in memory, up to `limit`.
+    kept: Option<Vec<Issue>>,
+    limit: usize,
+    count: usize,
+}
+
+impl IssueLog {
+    pub fn disabled() -> Self {
+        Self {
+            writer: None,
+            kept: None,
+            limit: 0,
+            count: 0,
+        }
+    }
+
+    /// Keeps the first `limit` issues in memory and counts the rest.
+    pub fn in_memory(limit: usize) -> Self {
+        Self {
+            kept: Some(Vec::new()),
+            limit,
+            ..Self::disabled()
+        }
+    }
+
+    pub fn create(output: &Path) -> Result<Self, String> {
+        let path = issues_path(output);
+        let file = File::create(&path).map_err(|e| format!("Failed to create issues file: {e}"))?;
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer
+            .write_record(["row", "column", "original_value", "action"])
+            .map_err(|e| format!("Failed to write issues file: {e}"))?;
+        Ok(Self {
+            writer: Some((path, writer)),
+            ..Self::disabled()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some() || self.kept.is_some()
+    }
+
+    pub fn record(&mut self, row: usize, column: &str, value: &str, action: Action) -> Result<(), String> {
+        if let Some(kept) = self.kept.as_mut() {
+            self.count += 1;
+            if kept.len() < self.limit {
+                kept.push(Issue {
+                    row,
+                    column: column.to_string(),
+                    original_value: value.to_string(),
+                    action,
+                });
+            }
+            return Ok(());
+        }
+        let Some((_, writer)) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        self.count += 1;
+        writer
+            .write_record([row.to_string().as_str(), column, value, action.as_str()])
+            .map_err(|e| format!("Failed to write issues file: {e}"))
+    }
+
+    /// Flushes the file. Returns a summary line when anything was recorded; an
+    /// empty issues file is removed.
+    pub fn finish(self) -> Result<Option<String>, String> {
+        let Some((path, mut writer)) = self.writer else {
+            return Ok(None);
+        };
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write issues file: {e}"))?;
+        drop(writer);
+        if self.count == 0 {
+            let _ = std::fs::remove_file(&path);
+            return Ok(None);
+        }
+        Ok(Some(format!(
+            "{} altered cell(s) or row(s) listed in {}",
+            self.count,
+            path.display()
+        )))
+    }
+
+    /// The issues kept in memory; empty when they went to a file.
+    pub fn take(&mut self) -> IssueList {
+        IssueList {
+            issues: self.kept.as_mut().map(std::mem::take).unwrap_or_default(),
+            total: if self.kept.is_some() { self.count } else { 0 },
+        }
+    }
+}
+
+pub fn issues_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".issues.csv");
+    output.with_file_name(name)
+}
@@ -0,0 +1,340 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use csv::ByteRecord;
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::input;
+use crate::options::{JoinKind, JoinOptions};
+
+const BUF_SIZE: usize = 256 * 1024;
+/// Record bytes sorted in memory at a time; larger files are sorted in runs on disk
+/// and merged, so neither file has to fit in memory.
+const RUN_BYTES: usize = 64 * 1024 * 1024;
+const CANCEL_CHECK_ROWS: usize = 10_000;
+
+/// What the join did, for the conversion warnings.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JoinSummary {
+    pub rows: usize,
+    /// First-file rows with no match; kept blank-filled by a left join.
+    pub unmatched_left: usize,
+    /// Second-file rows whose key is not in the first file.
+    pub unmatched_right: usize,
+}
+
+impl JoinSummary {
+    pub fn warnings(&self, kind: JoinKind) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.unmatched_left > 0 {
+            let outcome = match kind {
+                JoinKind::Left => "kept with blank values",
+                JoinKind::Inner => "left out",
+            };
+            warnings.push(format!(
+                "Join: {} row(s) of the first file have no match in the second and were {outcome}",
+                self.unmatched_left
+            ));
+        }
+        if self.unmatched_right > 0 {
+            warnings.push(format!(
+                "Join: {} row(s) of the second file match no key in the first and were left out",
+                self.unmatched_right
+            ));
+        }
+        warnings
+    }
+}
+
+/// Joins `right` onto `left` by key and writes the result to `dest` as CSV: the first
+/// file's columns, then the second's without its key. Both files are sorted by key
+/// with an external merge sort, so rows come out in key order; rows sharing a key in
+/// both files give every combination. A blank key never matches.
+pub fn join_csv(
+    left: &Path,
+    right: &Path,
+    options: &JoinOptions,
+    dest: &Path,
+    cancel: &CancelToken,
+) -> Result<JoinSummary, TaskError> {
+    join_with_run_bytes(left, right, options, dest, cancel, RUN_BYTES)
+}
+
+fn join_with_run_bytes(
+    left: &Path,
+    right: &Path,
+    options: &JoinOptions,
+    dest: &Path,
+    cancel: &CancelToken,
+    run_bytes: usize,
+) -> Result<JoinSummary, TaskError> {
+    let runs = RunFiles::new(dest);
+    let right_key = options.right_key.as_deref().unwrap_or(&options.key);
+    let mut left = SortedCsv::open(left, &options.key, &runs, run_bytes, cancel)?;
+    let mut right = SortedCsv::open(right, right_key, &runs, run_bytes, cancel)?;
+
+    let right_columns: Vec<usize> = (0..right.headers.len()).filter(|&i| i != right.key).collect();
+    let mut headers = left.headers.clone();
+    for &i in &right_columns {
+        let mut header = right.headers[i].clone();
+        while headers.contains(&header) {
+            header.push_str("_2");
+        }
+        headers.push(header);
+    }
+
+    let file = File::create(dest).map_err(|e| format!("Failed to create joined file: {e}"))?;
+    let mut writer = csv::Writer::from_writer(BufWriter::with_capacity(BUF_SIZE, file));
+    let write_error = |e: csv::Error| format!("Failed to write joined file: {e}");
+    writer.write_record(&headers).map_err(write_error)?;
+
+    let mut summary = JoinSummary::default();
+    let blanks = vec![b"" as &[u8]; right_columns.len()];
+    // Second-file rows of the key last looked up, reused while the first file repeats it.
+    let mut group: (Vec<u8>, Vec<ByteRecord>) = (Vec::new(), Vec::new());
+    let mut next_right = right.next()?;
+    let mut rows = 0usize;
+    while let Some((key, record)) = left.next()? {
+        rows += 1;
+        if rows.is_multiple_of(CANCEL_CHECK_ROWS) {
+            cancel.check()?;
+        }
+        if group.0 != key || group.1.is_empty() {
+            group = (key.clone(), Vec::new());
+            while let Some((right_key, right_record)) = next_right.take() {
+                if right_key > key {
+                    next_right = Some((right_key, right_record));
+                    break;
+                }
+                if right_key == key && !key.is_empty() {
+                    group.1.push(right_record);
+                } else {
+                    summary.unmatched_right += 1;
+                }
+                next_right = right.next()?;
+            }
+        }
+        let own = (0..left.headers.len()).map(|i| record.get(i).unwrap_or_default());
+        if group.1.is_empty() || key.is_empty() {
+            summary.unmatched_left += 1;
+            if options.kind == JoinKind::Left {
+                writer.write_record(own.chain(blanks.iter().copied())).map_err(write_error)?;
+                summary.rows += 1;
+            }
+            continue;
+        }
+        for matched in &group.1 {
+            let theirs = right_columns.iter().map(|&i| matched.get(i).unwrap_or_default());
+            writer.write_record(own.clone().chain(theirs)).map_err(write_error)?;
+            summary.rows += 1;
+        }
+    }
+    // What is left of the second file matched nothing.
+    while next_right.take().is_some() {
+        summary.unmatched_right += 1;
+        next_right = right.next()?;
+    }
+    writer.flush().map_err(|e| format!("Failed to write joined file: {e}"))?;
+    Ok(summary)
+}
+
+/// Temporary sorted runs next to the joined file, removed when dropped.
+struct RunFiles {
+    base: PathBuf,
+    paths: std::cell::RefCell<Vec<PathBuf>>,
+}
+
+impl RunFiles {
+    fn new(dest: &Path) -> Self {
+        Self {
+            base: dest.to_path_buf(),
+            paths: Default::default(),
+        }
+    }
+
+    fn create(&self) -> Result<(PathBuf, File), String> {
+        let mut paths = self.paths.borrow_mut();
+        let mut name = self.base.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".run{}", paths.len()));
+        let path = self.base.with_file_name(name);
+        let file = File::create(&path).map_err(|e| format!("Failed to create sort run: {e}"))?;
+        paths.push(path.clone());
+        Ok((path, file))
+    }
+}
+
+impl Drop for RunFiles {
+    fn drop(&mut self) {
+        for path in self.paths.borrow().iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A CSV's records in key order, merged from sorted runs.
+struct SortedCsv {
+    headers: Vec<String>,
+    key: usize,
+    runs: Vec<csv::Reader<BufReader<File>>>,
+    /// Smallest unread key of each run, ties going to the earlier run so rows sharing
+    /// a key keep their file order.
+    heads: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+    pending: Vec<Option<ByteRecord>>,
+}
+
+impl SortedCsv {
+    fn open(
+        path: &Path,
+        key_header: &str,
+        runs: &RunFiles,
+        run_bytes: usize,
+        cancel: &CancelToken,
+    ) -> Result<Self, TaskError> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let file = File::open(path).map_err(|e| format!("Failed to open {name}: {e}"))?;
+        let mut buf = BufReader::with_capacity(BUF_SIZE, file);
+        input::skip_utf8_bom(&mut buf).map_err(|e| format!("Failed to read {name}: {e}"))?;
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(buf);
+        let headers: Vec<String> = reader
+            .byte_headers()
+            .map_err(|e| format!("Failed to read CSV headers of {name}: {e}"))?
+            .iter()
+            .map(|h| String::from_utf8_lossy(h).into_owned())
+            .collect();
+        let key = headers
+            .iter()
+            .position(|h| h.trim() == key_header.trim())
+            .ok_or_else(|| format!("{name} has no key column '{key_header}'"))?;
+
+        let mut run_paths = Vec::new();
+        let mut chunk: Vec<(Vec<u8>, ByteRecord)> = Vec::new();
+        let mut chunk_bytes = 0usize;
+        let mut record = ByteRecord::new();
+        let mut rows = 0usize;
+        loop {
+            let more = reader
+                .read_byte_record(&mut record)
+                .map_err(|e| format!("CSV read error in {name} at row {}: {e}", rows + 1))?;
+            if more {
+                rows += 1;
+                if rows.is_multiple_of(CANCEL_CHECK_ROWS) {
+                    cancel.check()?;
+                }
+                chunk_bytes += record.as_slice().len();
+                chunk.push((key_of(&record, key), record.clone()));
+            }
+            if (!more && !chunk.is_empty()) || chunk_bytes >= run_bytes {
+                run_paths.push(write_run(&mut chunk, runs)?);
+                chunk_bytes = 0;
+            }
+            if !more {
+                break;
+            }
+        }
+
+        let mut sorted = Self {
+            headers,
+            key,
+            runs: Vec::with_capacity(run_paths.len()),
+            heads: BinaryHeap::new(),
+            pending: Vec::new(),
+        };
+        for path in run_paths {
+            let file = File::open(&path).map_err(|e| format!("Failed to read sort run: {e}"))?;
+            let reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(BufReader::with_capacity(BUF_SIZE, file));
+            sorted.runs.push(reader);
+            sorted.pending.push(None);
+            sorted.advance(sorted.runs.len() - 1)?;
+        }
+        Ok(sorted)
+    }
+
+    /// Reads the next record of a run into the heap.
+    fn advance(&mut self, run: usize) -> Result<(), String> {
+        let mut record = ByteRecord::new();
+        let more = self.runs[run]
+            .read_byte_record(&mut record)
+            .map_err(|e| format!("Failed to read sort run: {e}"))?;
+        if more {
+            self.heads.push(Reverse((key_of(&record, self.key), run)));
+            self.pending[run] = Some(record);
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<(Vec<u8>, ByteRecord)>, String> {
+        let Some(Reverse((key, run))) = self.heads.pop() else {
+            return Ok(None);
+        };
+        let record = self.pending[run].take().unwrap_or_default();
+        self.advance(run)?;
+        Ok(Some((key, record)))
+    }
+}
+
+fn key_of(record: &ByteRecord, key: usize) -> Vec<u8> {
+    record.get(key).unwrap_or_default().trim_ascii().to_vec()
+}
+
+/// Sorts the chunk by key, keeping file order within a key, and writes it as a run.
+fn write_run(chunk: &mut Vec<(Vec<u8>, ByteRecord)>, runs: &RunFiles) -> Result<PathBuf, String> {
+    chunk.sort_by(|a, b| a.0.cmp(&b.0));
+    let (path, file) = runs.create()?;
+    let mut writer = csv::Writer::from_writer(BufWriter::with_capacity(BUF_SIZE, file));
+    for (_, record) in chunk.drain(..) {
+        writer
+            .write_byte_record(&record)
+            .map_err(|e| format!("Failed to write sort run: {e}"))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write sort run: {e}"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_merge_join() {
+        let dir = std::env::temp_dir().join("csv2sav_join_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let left = dir.join("responses.csv");
+        let right = dir.join("people.csv");
+        let dest = dir.join("joined.csv");
+        std::fs::write(&left, "id,q1\n3,c\n1,a\n2,b\n1,a2\n,x\n").unwrap();
+        std::fs::write(&right, "pid,age,q1\n2,40,r2\n1,30,r1\n9,99,r9\n").unwrap();
+
+        let cancel = CancelToken::new();
+        let options = JoinOptions {
+            key: "id".to_string(),
+            right_key: Some("pid".to_string()),
+            ..Default::default()
+        };
+        // Tiny runs force the on-disk merge.
+        let summary = join_with_run_bytes(&left, &right, &options, &dest, &cancel, 8).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&dest).unwrap(),
+            "id,q1,age,q1_2\n,x,,\n1,a,30,r1\n1,a2,30,r1\n2,b,40,r2\n3,c,,\n"
+        );
+        assert_eq!(
+            summary,
+            JoinSummary { rows: 5, unmatched_left: 2, unmatched_right: 1 }
+        );
+        assert!(!dir.join("joined.csv.run0").exists());
+
+        let inner = JoinOptions { kind: JoinKind::Inner, ..options };
+        let summary = join_csv(&left, &right, &inner, &dest, &cancel).unwrap();
+        assert_eq!(summary.rows, 3);
+        assert_eq!(summary.warnings(JoinKind::Inner).len(), 2);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
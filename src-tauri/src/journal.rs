@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Tracks output and staged scratch files that are currently in use, persisted to disk
+/// so partial files left behind by a crash or forced quit can be found on the next launch.
+pub struct Journal {
+    path: PathBuf,
+    entries: Mutex<Vec<PathBuf>>,
+}
+
+impl Journal {
+    pub fn open(path: PathBuf) -> Self {
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Deletes every output still recorded as in progress and clears the journal.
+    /// Returns the paths that were actually removed.
+    pub fn remove_orphans(&self) -> Vec<PathBuf> {
+        let mut entries = self.entries.lock().unwrap();
+        let removed: Vec<PathBuf> = entries
+            .drain(..)
+            .filter(|p| p.is_file() && fs::remove_file(p).is_ok())
+            .collect();
+        self.persist(&entries);
+        removed
+    }
+
+    pub fn begin(&self, output: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.iter().any(|p| p == output) {
+            entries.push(output.to_path_buf());
+        }
+        self.persist(&entries);
+    }
+
+    pub fn end(&self, output: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|p| p != output);
+        self.persist(&entries);
+    }
+
+    /// Best-effort: a journal that cannot be written must never fail a conversion.
+    fn persist(&self, entries: &[PathBuf]) {
+        if entries.is_empty() {
+            let _ = fs::remove_file(&self.path);
+            return;
+        }
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(data) = serde_json::to_vec(entries) {
+            let _ = fs::write(&self.path, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphans_survive_reopen() {
+        let dir = std::env::temp_dir().join("csv2sav_journal_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let journal_path = dir.join("journal.json");
+        let done = dir.join("done.zsav");
+        let partial = dir.join("partial.zsav");
+        fs::write(&done, b"ok").unwrap();
+        fs::write(&partial, b"half").unwrap();
+
+        let journal = Journal::open(journal_path.clone());
+        journal.begin(&done);
+        journal.begin(&partial);
+        journal.end(&done);
+        drop(journal);
+
+        let reopened = Journal::open(journal_path.clone());
+        assert_eq!(reopened.remove_orphans(), vec![partial.clone()]);
+        assert!(done.exists());
+        assert!(!partial.exists());
+        assert!(!journal_path.exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
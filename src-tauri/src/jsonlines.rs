@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::cancel::{CancelToken, TaskError};
+
+const BUF_SIZE: usize = 256 * 1024;
+const CANCEL_CHECK_ROWS: usize = 10_000;
+
+/// Key and text of each cell of one record, in document order.
+type Cells = Vec<(String, String)>;
+
+/// Rewrites the JSON Lines file at `path` as CSV at `dest`, so it goes through the
+/// same inference and writer as any other input. Every key found in any record
+/// becomes a column, in order of first appearance. Nested objects become
+/// `parent.child` columns, arrays their JSON text, booleans 1 and 0, and null or a
+/// missing key an empty cell. Returns the number of records.
+pub fn jsonl_to_csv(path: &Path, dest: &Path, cancel: &CancelToken) -> Result<usize, TaskError> {
+    // The first pass collects the columns, the second writes the rows.
+    let mut headers: Vec<String> = Vec::new();
+    let mut columns: HashMap<String, usize> = HashMap::new();
+    for_each_record(path, cancel, |cells| {
+        for (key, _) in cells.drain(..) {
+            if !columns.contains_key(&key) {
+                columns.insert(key.clone(), headers.len());
+                headers.push(key);
+            }
+        }
+        Ok(())
+    })?;
+    if headers.is_empty() {
+        return Err("No keys found in the JSON Lines file".into());
+    }
+
+    let file = File::create(dest).map_err(|e| format!("Failed to create staged CSV: {e}"))?;
+    let mut writer = csv::Writer::from_writer(BufWriter::with_capacity(BUF_SIZE, file));
+    let write_error = |e: csv::Error| format!("Failed to write staged CSV: {e}");
+    writer.write_record(&headers).map_err(write_error)?;
+    let mut row = vec![String::new(); headers.len()];
+    let records = for_each_record(path, cancel, |cells| {
+        row.iter_mut().for_each(String::clear);
+        for (key, text) in cells.drain(..) {
+            row[columns[&key]] = text;
+        }
+        writer.write_record(&row).map_err(write_error)?;
+        Ok(())
+    })?;
+    writer.flush().map_err(|e| format!("Failed to write staged CSV: {e}"))?;
+    Ok(records)
+}
+
+/// Calls `each` with the cells of every record, skipping blank lines. Returns the
+/// number of records.
+fn for_each_record(
+    path: &Path,
+    cancel: &CancelToken,
+    mut each: impl FnMut(&mut Cells) -> Result<(), TaskError>,
+) -> Result<usize, TaskError> {
+    let file = File::open(path).map_err(|e| format!("Failed to open JSON Lines file: {e}"))?;
+    let mut reader = BufReader::with_capacity(BUF_SIZE, file);
+    let mut line = String::new();
+    let mut cells = Cells::new();
+    let (mut line_number, mut records) = (0usize, 0usize);
+    loop {
+        line.clear();
+        line_number += 1;
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read JSON Lines file at line {line_number}: {e}"))?;
+        if read == 0 {
+            return Ok(records);
+        }
+        let text = match line_number {
+            1 => line.strip_prefix('\u{FEFF}').unwrap_or(&line),
+            _ => &line,
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        Record(&mut cells)
+            .deserialize(&mut deserializer)
+            .and_then(|()| deserializer.end())
+            .map_err(|e| format!("Line {line_number}: {e}"))?;
+        each(&mut cells)?;
+        records += 1;
+        if records.is_multiple_of(CANCEL_CHECK_ROWS) {
+            cancel.check()?;
+        }
+    }
+}
+
+/// A whole line, which must be an object.
+struct Record<'a>(&'a mut Cells);
+
+impl<'de> DeserializeSeed<'de> for Record<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Record<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        entries(map, "", self.0)
+    }
+}
+
+/// Walks the entries of an object in document order, which `serde_json::Map` would
+/// not keep.
+fn entries<'de, A: MapAccess<'de>>(
+    mut map: A,
+    prefix: &str,
+    cells: &mut Cells,
+) -> Result<(), A::Error> {
+    while let Some(key) = map.next_key::<String>()? {
+        let key = if prefix.is_empty() { key } else { format!("{prefix}.{key}") };
+        map.next_value_seed(Cell { key, cells: &mut *cells })?;
+    }
+    Ok(())
+}
+
+/// One value, stored under `key`, or under keys below it for an object.
+struct Cell<'a> {
+    key: String,
+    cells: &'a mut Cells,
+}
+
+impl Cell<'_> {
+    fn push(self, text: String) {
+        self.cells.push((self.key, text));
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Cell<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Cell<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<(), E> {
+        self.push(if v { "1" } else { "0" }.to_string());
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<(), E> {
+        self.push(v.to_string());
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<(), E> {
+        self.push(v.to_string());
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<(), E> {
+        self.push(v.to_string());
+        Ok(())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<(), E> {
+        self.push(v.to_string());
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        entries(map, &self.key, self.cells)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+        let value = serde_json::Value::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+        self.push(value.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jsonl_to_csv() {
+        let dir = std::env::temp_dir().join("csv2sav_jsonlines_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("events.jsonl");
+        let dest = dir.join("events.csv");
+        let lines = [
+            r#"{"id": 1, "user": {"name": "Ann", "age": 31}, "ok": true}"#,
+            "",
+            r#"{"id": 2, "tags": ["a", "b"], "ok": false, "user": {"name": "Bo, Jr."}}"#,
+            r#"{"score": 2.5, "id": 3, "user": null}"#,
+        ];
+        std::fs::write(&input, lines.join("\n")).unwrap();
+
+        let cancel = CancelToken::new();
+        assert_eq!(jsonl_to_csv(&input, &dest, &cancel), Ok(3));
+        assert_eq!(
+            std::fs::read_to_string(&dest).unwrap(),
+            "id,user.name,user.age,ok,tags,score\n1,Ann,31,1,,\n2,\"Bo, Jr.\",,0,\"[\"\"a\"\",\"\"b\"\"]\",\n3,,,,,2.5\n"
+        );
+
+        std::fs::write(&input, "{\"id\": 1}\n[1, 2]\n").unwrap();
+        let Err(err) = jsonl_to_csv(&input, &dest, &cancel) else {
+            panic!("array record accepted");
+        };
+        assert!(err.to_string().starts_with("Line 2: invalid type: sequence, expected a JSON"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
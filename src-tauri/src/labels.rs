@@ -0,0 +1,93 @@
+use std::borrow::Cow;
+
+use crate::converter::truncate_utf8;
+use crate::options::LabelOverflow;
+
+/// SPSS variable labels are limited to 255 bytes.
+pub const MAX_LABEL_BYTES: usize = 255;
+/// Value labels are limited to 120 bytes.
+pub const MAX_VALUE_LABEL_BYTES: usize = 120;
+/// Document records are stored as fixed 80-byte lines.
+const DOC_LINE_BYTES: usize = 80;
+/// Joins the start and end of an abbreviated label.
+const ELLIPSIS: &str = "…";
+
+/// `text` made to fit in `max` bytes as `policy` says; unchanged when it fits.
+pub fn fit(text: &str, max: usize, policy: LabelOverflow) -> Cow<'_, str> {
+    if text.len() <= max {
+        return Cow::Borrowed(text);
+    }
+    match policy {
+        LabelOverflow::Truncate | LabelOverflow::Document => {
+            Cow::Borrowed(truncate_utf8(text, max))
+        }
+        LabelOverflow::Abbreviate => Cow::Owned(abbreviate(text, max)),
+    }
+}
+
+/// How an over-long label was handled, for the warning about its variable.
+pub fn overflow_note(policy: LabelOverflow) -> &'static str {
+    match policy {
+        LabelOverflow::Truncate => "truncated",
+        LabelOverflow::Abbreviate => "abbreviated",
+        LabelOverflow::Document => "truncated; full text kept in the document record",
+    }
+}
+
+/// Collapses whitespace, then keeps about two thirds of the room for the start and
+/// the rest for the end, where survey questions often put what sets them apart.
+fn abbreviate(text: &str, max: usize) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.len() <= max {
+        return collapsed;
+    }
+    let room = max.saturating_sub(ELLIPSIS.len());
+    let head = truncate_utf8(&collapsed, room - room / 3);
+    let mut start = collapsed.len() - (room - head.len());
+    while !collapsed.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("{}{ELLIPSIS}{}", head.trim_end(), collapsed[start..].trim_start())
+}
+
+/// Splits `"{var}: {text}"` into document lines of at most DOC_LINE_BYTES,
+/// breaking only at UTF-8 character boundaries.
+pub fn document_lines(var: &str, text: &str) -> Vec<String> {
+    let full = format!("{var}: {text}");
+    let mut rest = full.as_str();
+    let mut lines = Vec::new();
+    while !rest.is_empty() {
+        let line = truncate_utf8(rest, DOC_LINE_BYTES);
+        lines.push(line.to_string());
+        rest = &rest[line.len()..];
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abbreviate_keeps_start_and_end() {
+        let text = format!("How satisfied   are you {} with the new canteen?", "very ".repeat(60));
+        let label = fit(&text, MAX_LABEL_BYTES, LabelOverflow::Abbreviate);
+        assert!(label.len() <= MAX_LABEL_BYTES);
+        assert!(label.starts_with("How satisfied are you very"), "{label}");
+        assert!(label.ends_with("very with the new canteen?"), "{label}");
+        assert!(label.contains(ELLIPSIS));
+
+        let text = "问".repeat(50);
+        let label = fit(&text, MAX_VALUE_LABEL_BYTES, LabelOverflow::Abbreviate);
+        assert!(label.len() <= MAX_VALUE_LABEL_BYTES);
+        assert_eq!(fit("short", 10, LabelOverflow::Abbreviate), "short");
+    }
+
+    #[test]
+    fn test_document_lines_split_on_char_boundaries() {
+        let text = "问".repeat(40);
+        let lines = document_lines("V1", &text);
+        assert!(lines.iter().all(|l| l.len() <= DOC_LINE_BYTES));
+        assert_eq!(lines.concat(), format!("V1: {text}"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::converter::{self, ConvertOutcome};
+use crate::options::ConvertOptions;
+use crate::paths;
+
+/// Flag that runs one conversion without opening a window.
+const CONVERT_FLAG: &str = "--convert";
+/// Stands for stdin as the input and stdout as the output.
+const STDIO: &str = "-";
+
+/// Filters launch arguments ("Open with…", CLI) down to existing CSV files.
+/// Flags and anything that is not a readable `.csv` file are ignored.
+pub fn csv_paths<I>(args: I) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    args.into_iter()
+        .filter(|arg| !arg.as_encoded_bytes().starts_with(b"-"))
+        .map(PathBuf::from)
+        .filter(|path| is_csv_file(path))
+        .collect()
+}
+
+pub fn is_csv_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// A headless conversion: `--convert INPUT [OUTPUT] [--options FILE]`, where `-` or a
+/// missing OUTPUT means stdin or stdout, and FILE holds the options as JSON.
+#[derive(Debug, Default, PartialEq)]
+pub struct StreamArgs {
+    /// None reads stdin.
+    pub input: Option<PathBuf>,
+    /// None writes stdout. A path may be a named pipe, as the ZSAV is written front
+    /// to back and never seeked.
+    pub output: Option<PathBuf>,
+    pub options: Option<PathBuf>,
+}
+
+/// The headless conversion asked for by the launch arguments, or None to start the app.
+pub fn stream_args<I>(args: I) -> Option<Result<StreamArgs, String>>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter();
+    if args.next()? != CONVERT_FLAG {
+        return None;
+    }
+    let path = |arg: OsString| (arg != STDIO).then(|| PathBuf::from(arg));
+    let mut stream = StreamArgs::default();
+    let mut positional = 0;
+    while let Some(arg) = args.next() {
+        if arg == "--options" {
+            let Some(file) = args.next() else {
+                return Some(Err("--options needs a file".to_string()));
+            };
+            stream.options = Some(PathBuf::from(file));
+            continue;
+        }
+        match positional {
+            0 => stream.input = path(arg),
+            1 => stream.output = path(arg),
+            _ => return Some(Err(format!("Unexpected argument '{}'", arg.to_string_lossy()))),
+        }
+        positional += 1;
+    }
+    if positional == 0 {
+        return Some(Err("--convert needs an input CSV, or - for stdin".to_string()));
+    }
+    Some(Ok(stream))
+}
+
+/// Converts as `args` says. Input and output are streamed, so either may be a pipe
+/// of unknown size; sidecar files are not available.
+pub fn stream(args: &StreamArgs, cancel: &CancelToken) -> Result<ConvertOutcome, TaskError> {
+    let options: ConvertOptions = match &args.options {
+        Some(path) => {
+            let text = std::fs::read_to_string(paths::for_io(path))
+                .map_err(|e| format!("Failed to read options: {e}"))?;
+            serde_json::from_str(&text).map_err(|e| format!("Invalid options: {e}"))?
+        }
+        None => ConvertOptions::default(),
+    };
+    let input: Box<dyn Read> = match &args.input {
+        Some(path) => Box::new(
+            File::open(paths::for_io(path)).map_err(|e| format!("Failed to open CSV: {e}"))?,
+        ),
+        None => Box::new(io::stdin().lock()),
+    };
+    let Some(path) = &args.output else {
+        return converter::convert_csv_stream(input, io::stdout(), &options, cancel);
+    };
+    let path = paths::for_io(path);
+    let output = File::create(&path).map_err(|e| format!("Failed to create output: {e}"))?;
+    let result = converter::convert_csv_stream(input, output, &options, cancel);
+    if result.is_err() && path.is_file() {
+        std::fs::remove_file(&path).ok();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_args_and_conversion() {
+        let args = |list: &[&str]| stream_args(list.iter().map(OsString::from));
+        assert!(args(&["data.csv"]).is_none());
+        assert_eq!(args(&["--convert", "-"]).unwrap().unwrap(), StreamArgs::default());
+        assert!(args(&["--convert"]).unwrap().is_err());
+        assert!(args(&["--convert", "a.csv", "b.zsav", "c"]).unwrap().is_err());
+
+        let dir = std::env::temp_dir().join("csv2sav_stream_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let options = dir.join("options.json");
+        std::fs::write(&input, "id,name\n1,ann\n2,bob\n").unwrap();
+        std::fs::write(&options, r#"{"write_issues_file": true}"#).unwrap();
+        let list = ["--convert", "in.csv", "out.zsav", "--options", "options.json"];
+        let mut parsed = args(&list).unwrap().unwrap();
+        assert_eq!(parsed.output, Some(PathBuf::from("out.zsav")));
+        parsed.input = Some(input);
+        parsed.output = Some(output.clone());
+        parsed.options = Some(options.clone());
+
+        let cancel = CancelToken::new();
+        assert!(stream(&parsed, &cancel).is_err());
+        assert!(!output.exists());
+        std::fs::write(&options, "{}").unwrap();
+        assert_eq!(stream(&parsed, &cancel).unwrap().rows, 2);
+        assert_eq!(&std::fs::read(&output).unwrap()[..4], b"$FL3");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
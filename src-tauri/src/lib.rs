@@ -1,43 +1,271 @@
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "pspp")]
+pub mod pspp;
+#[cfg(feature = "async")]
+pub mod async_api;
+#[cfg(feature = "database")]
+mod database;
+mod anonymize;
+mod cancel;
+mod cleaned;
+mod compare;
 mod converter;
+mod dates;
+mod deeplink;
+mod diagnostics;
+mod dictionary;
+mod exporter;
+mod filelock;
+mod formats;
+mod googleforms;
+mod input;
+mod join;
+mod jsonlines;
+mod issues;
+mod journal;
+mod labels;
+mod launch;
+mod manifest;
+mod options;
+mod output;
+mod pairs;
+mod paths;
+mod pii;
+mod preview;
+mod probe;
+mod provenance;
+mod qualtrics;
 mod readstat_sys;
 mod readstat_writer;
+mod reshape;
+mod responsesets;
+mod retry;
 mod schema;
+mod script;
+mod settings;
+mod surveymonkey;
+mod validate;
+mod webhook;
+mod zip;
 
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+pub use cancel::{CancelToken, Cancelled, TaskError};
+pub use converter::{convert_csv_stream, ConvertOutcome};
+pub use options::ConvertOptions;
 
-use serde::Serialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
 
 #[derive(Clone, Serialize)]
 struct ConvertProgress {
-    file: String,
+    file: PathBuf,
+    /// Index of the `convert_batch` job, so interleaved events can be told apart.
+    job: Option<usize>,
     current_rows: usize,
     bytes_read: u64,
     file_size: u64,
 }
 
-#[derive(Serialize)]
+/// Machine-readable failure reason, so the UI can explain and offer remedies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    Cancelled,
+    /// The output file is held open by another program, typically SPSS on Windows.
+    FileInUse,
+    /// More columns than fit one SAV file; retry with `split_columns`.
+    TooManyColumns,
+    /// The conversion ran longer than `timeout_secs` and was cancelled.
+    Timeout,
+}
+
+#[derive(Clone, Serialize)]
+struct ExportProgress {
+    file: PathBuf,
+    current_rows: usize,
+    /// Row count from the SAV header; 0 when the file does not record it.
+    total_rows: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct ConvertWarning {
+    file: PathBuf,
+    job: Option<usize>,
+    message: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct ConvertResult {
-    input_path: String,
-    output_path: String,
+    input_path: PathBuf,
+    output_path: PathBuf,
     total_rows: usize,
     success: bool,
     error: Option<String>,
+    #[serde(default)]
+    error_code: Option<ErrorCode>,
+    truncated_cols: Vec<String>,
+    /// Hex-encoded SHA-256 of the output file, for verifying transfers.
+    sha256: Option<String>,
+    duration_ms: u64,
+    #[serde(default)]
+    warnings: Vec<String>,
+    /// Per-column count and examples of values cut to the declared width.
+    #[serde(default)]
+    truncations: Vec<converter::TruncationReport>,
+    /// Files written when the columns were split; empty for a single output.
+    #[serde(default)]
+    parts: Vec<converter::OutputPart>,
+}
+
+impl ConvertResult {
+    fn failed(
+        input_path: PathBuf,
+        output_path: PathBuf,
+        error: String,
+        error_code: Option<ErrorCode>,
+        duration_ms: u64,
+    ) -> Self {
+        Self {
+            input_path,
+            output_path,
+            total_rows: 0,
+            success: false,
+            error: Some(error),
+            error_code,
+            truncated_cols: vec![],
+            sha256: None,
+            duration_ms,
+            warnings: vec![],
+            truncations: vec![],
+            parts: vec![],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ColumnMapping {
+    index: usize,
+    header: String,
+    /// Variable name the column receives in the SAV file.
+    name: String,
+    col_type: &'static str,
+    width: Option<usize>,
+    /// SPSS display format, e.g. `F12.4` or `A3000`.
+    format: String,
+    samples: Vec<String>,
+    /// Personal data the column appears to hold, e.g. `email`.
+    pii: Option<pii::PiiKind>,
+    /// Header of a column naming this column's codes, offered as its value labels.
+    label_column: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SchemaReport {
+    columns: Vec<ColumnMapping>,
+    /// Columns whose longest value exceeds the widest SPSS string and will be cut.
     truncated_cols: Vec<String>,
+    warnings: Vec<String>,
+    /// Data rows, when inference read the whole file.
+    row_count: Option<usize>,
 }
 
-#[derive(Clone)]
-struct CancelFlag(Arc<AtomicBool>);
+#[derive(Serialize)]
+struct ProblemReport {
+    rows: usize,
+    /// The first cells and rows the conversion would alter.
+    issues: Vec<issues::Issue>,
+    /// All of them, including those not listed.
+    total: usize,
+    warnings: Vec<String>,
+}
 
-const SAMPLE_ROWS: usize = 10_000;
+#[derive(Clone, Serialize)]
+struct BatchProgress {
+    completed_files: usize,
+    total_files: usize,
+    current_file: Option<PathBuf>,
+    /// Index of the job just started, with `current_file`.
+    job: Option<usize>,
+    /// Id of the run, which with `job` names a job for `cancel_batch_job`.
+    batch: u64,
+}
 
-fn emit_progress(app: &AppHandle, file: &str, current_rows: usize, bytes_read: u64, file_size: u64) {
+/// A deep link conversion awaiting the user's answer through `confirm_deep_link`.
+#[derive(Clone, Serialize)]
+struct DeepLinkRequest {
+    id: u64,
+    input: PathBuf,
+    output: PathBuf,
+}
+
+/// One file of a `convert_batch` call.
+#[derive(Deserialize)]
+struct BatchJob {
+    input_path: PathBuf,
+    output_path: PathBuf,
+}
+
+/// Token of the latest run, which `cancel_conversion` cancels. Each run swaps in a
+/// fresh one, so starting a run neither revives nor cancels another still going.
+#[derive(Default)]
+struct CancelFlag(Mutex<CancelToken>);
+
+impl CancelFlag {
+    /// Starts a run and returns its token.
+    fn begin(&self) -> CancelToken {
+        let token = CancelToken::new();
+        *self.0.lock().unwrap() = token.clone();
+        token
+    }
+
+    fn cancel(&self) {
+        self.0.lock().unwrap().cancel();
+    }
+}
+
+/// Starts a run on the app's [`CancelFlag`].
+fn begin_run(app: &AppHandle) -> Result<CancelToken, String> {
+    Ok(app.try_state::<CancelFlag>().ok_or("CancelFlag not managed")?.begin())
+}
+
+/// Files opened with the app before the frontend was ready to receive `files-opened`.
+#[derive(Default)]
+struct LaunchFiles(Mutex<Vec<PathBuf>>);
+
+const JOURNAL_FILE: &str = "in_progress.json";
+const SETTINGS_FILE: &str = "settings.json";
+/// Altered cells and rows listed by `preview_problems`; the rest are only counted.
+const PROBLEM_LIMIT: usize = 1000;
+
+/// Files `convert_batch` converts at once unless told otherwise: one per physical
+/// core, or per logical one where the physical count is unknown.
+fn batch_workers() -> usize {
+    match num_cpus::get_physical() {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        cores => cores,
+    }
+}
+
+fn emit_progress(
+    app: &AppHandle,
+    file: &Path,
+    job: Option<usize>,
+    current_rows: usize,
+    bytes_read: u64,
+    file_size: u64,
+) {
     let _ = app.emit(
         "convert-progress",
         ConvertProgress {
-            file: file.to_string(),
+            file: file.to_path_buf(),
+            job,
             current_rows,
             bytes_read,
             file_size,
@@ -45,96 +273,1125 @@ fn emit_progress(app: &AppHandle, file: &str, current_rows: usize, bytes_read: u
     );
 }
 
+fn emit_warning(app: &AppHandle, file: &Path, job: Option<usize>, message: &str) {
+    if let Some(diagnostics) = app.try_state::<diagnostics::Diagnostics>() {
+        diagnostics.record("warning", &format!("{}: {message}", file.display()));
+    }
+    let _ = app.emit(
+        "convert-warning",
+        ConvertWarning {
+            file: file.to_path_buf(),
+            job,
+            message: message.to_string(),
+        },
+    );
+}
+
+/// Keeps a conversion's outcome for diagnostic bundles.
+fn log_report(app: &AppHandle, report: &ConvertResult) {
+    let Some(diagnostics) = app.try_state::<diagnostics::Diagnostics>() else {
+        return;
+    };
+    let (level, outcome) = match &report.error {
+        None => ("info", format!("{} rows", report.total_rows)),
+        Some(error) => ("error", error.clone()),
+    };
+    diagnostics.record(
+        level,
+        &format!("{} -> {}: {outcome}", report.input_path.display(), report.output_path.display()),
+    );
+    diagnostics.save_report(report);
+}
+
+fn queue_opened_files(app: &AppHandle, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    if let Some(state) = app.try_state::<LaunchFiles>() {
+        state.0.lock().unwrap().extend(paths.iter().cloned());
+    }
+    let _ = app.emit("files-opened", paths);
+}
+
+/// Holds a conversion requested through a `csv2sav://` link and asks the window to
+/// confirm it with `deep-link-request`; nothing runs until [`confirm_deep_link`].
+fn handle_deep_link(app: &AppHandle, url: &tauri::Url) {
+    let request = match deeplink::parse(url) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = app.emit("deep-link-error", e);
+            return;
+        }
+    };
+    let Some(pending) = app.try_state::<deeplink::PendingRequests>() else {
+        return;
+    };
+    let (input, output) = (request.input.clone(), request.output.clone());
+    let id = pending.add(request);
+    let _ = app.emit("deep-link-request", DeepLinkRequest { id, input, output });
+}
+
+/// Answers a `deep-link-request`: converts it if the user `approved`, otherwise drops
+/// it. The conversion runs on its own token, apart from whatever the window is running.
+#[tauri::command]
+async fn confirm_deep_link(
+    app: AppHandle,
+    id: u64,
+    approved: bool,
+) -> Result<Option<ConvertResult>, String> {
+    let request = app
+        .try_state::<deeplink::PendingRequests>()
+        .ok_or("PendingRequests not managed")?
+        .take(id)
+        .ok_or_else(|| format!("No pending deep link request {id}"))?;
+    if !approved {
+        return Ok(None);
+    }
+    if paths::for_io(&request.output).exists() {
+        return Err(format!("Output already exists: {}", request.output.display()));
+    }
+    let options = options::ConvertOptions::default();
+    let result =
+        convert_file(app, request.input, request.output, options, CancelToken::new(), None).await?;
+    Ok(Some(result))
+}
+
+#[tauri::command]
+fn take_launch_files(state: tauri::State<'_, LaunchFiles>) -> Vec<PathBuf> {
+    std::mem::take(&mut *state.0.lock().unwrap())
+}
+
+/// Returns headers, sample values and inferred types for a column-mapping step.
+/// The schema is cached, so converting the same unchanged file afterwards skips inference.
+#[tauri::command]
+async fn get_column_mapping(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<Vec<ColumnMapping>, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let never = CancelToken::new();
+        let csv_schema = app.state::<schema::SchemaCache>().get_or_infer(
+            &paths::for_io(&input_path),
+            &options,
+            &never,
+        )?;
+        column_mappings(csv_schema, &options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Infers the schema for a review step before converting: the columns as
+/// `get_column_mapping` describes them, with the inference warnings and the columns
+/// that will be truncated. Cached like `get_column_mapping`.
+#[tauri::command]
+async fn infer_schema(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<SchemaReport, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let never = CancelToken::new();
+        let mut csv_schema = app.state::<schema::SchemaCache>().get_or_infer(
+            &paths::for_io(&input_path),
+            &options,
+            &never,
+        )?;
+        let truncated_cols = std::mem::take(&mut csv_schema.truncated_cols);
+        let warnings = std::mem::take(&mut csv_schema.warnings);
+        let row_count = csv_schema.row_count;
+        Ok(SchemaReport {
+            columns: column_mappings(csv_schema, &options)?,
+            truncated_cols,
+            warnings,
+            row_count,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Describes each column of an inferred schema for the column-mapping step.
+fn column_mappings(
+    csv_schema: schema::CsvSchema,
+    options: &options::ConvertOptions,
+) -> Result<Vec<ColumnMapping>, String> {
+    let dictionary = options
+        .dictionary
+        .as_deref()
+        .map(dictionary::load)
+        .transpose()?;
+    let names = converter::variable_names(&csv_schema, options, dictionary.as_ref());
+    let label_column = |i: usize| {
+        csv_schema
+            .label_pairs
+            .iter()
+            .find(|p| p.code == i)
+            .map(|p| csv_schema.headers[p.name].clone())
+    };
+    let label_columns: Vec<Option<String>> = (0..csv_schema.headers.len()).map(label_column).collect();
+    let mappings = csv_schema
+        .headers
+        .into_iter()
+        .zip(csv_schema.col_types)
+        .zip(csv_schema.samples)
+        .zip(names)
+        .zip(csv_schema.pii)
+        .zip(label_columns)
+        .enumerate()
+        .map(|(i, (((((header, col_type), samples), name), pii), label_column))| {
+            let (col_type, width, format) = match col_type {
+                schema::ColType::Numeric { width, decimals } => {
+                    ("numeric", None, format!("F{width}.{decimals}"))
+                }
+                schema::ColType::String(w) => ("string", Some(w), format!("A{w}")),
+                schema::ColType::Date | schema::ColType::NumericDate { .. } => {
+                    ("date", None, dates::DATE_FORMAT.to_string())
+                }
+                schema::ColType::Checkbox => ("checkbox", None, "F1.0".to_string()),
+                schema::ColType::Dummy { .. } => ("dummy", None, "F1.0".to_string()),
+                schema::ColType::Selected { .. } => ("selected", None, "F1.0".to_string()),
+                schema::ColType::Timestamp { .. } => {
+                    ("datetime", None, dates::DATETIME_FORMAT.to_string())
+                }
+                schema::ColType::Period(format) => {
+                    ("period", None, dates::period_format_spec(format).to_string())
+                }
+            };
+            ColumnMapping {
+                index: i,
+                header,
+                name,
+                col_type,
+                width,
+                format,
+                samples,
+                pii,
+                label_column,
+            }
+        })
+        .collect();
+    Ok(mappings)
+}
+
+/// Like `get_column_mapping` after a change to options that only affect typing, such
+/// as missing markers or the decimal separator: the affected columns are retyped from
+/// the cached records of the previous inference instead of scanning the file again.
+#[tauri::command]
+async fn reinfer_column_mapping(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<Vec<ColumnMapping>, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let never = CancelToken::new();
+        let csv_schema = app.state::<schema::SchemaCache>().reinfer(
+            &paths::for_io(&input_path),
+            &options,
+            &never,
+        )?;
+        column_mappings(csv_schema, &options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Converts the first rows of a CSV in memory and returns them as SPSS would display
+/// them. Shares the schema cache with conversions, like the column mapping.
+#[tauri::command]
+async fn preview_output(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+    rows: Option<usize>,
+) -> Result<preview::OutputPreview, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let never = CancelToken::new();
+        let input = paths::for_io(&input_path);
+        let csv_schema = app.state::<schema::SchemaCache>().get_or_infer(&input, &options, &never)?;
+        let rows = rows.unwrap_or(preview::DEFAULT_ROWS);
+        Ok(preview::preview_output(&input, &csv_schema, &options, rows, &never)?)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Writes a zip for support: recent activity, the last conversion report, versions,
+/// settings without secrets and, when `sample_path` is given, an anonymized sample of
+/// that CSV's first rows.
+#[tauri::command]
+async fn export_diagnostics(
+    app: AppHandle,
+    dest_path: PathBuf,
+    sample_path: Option<PathBuf>,
+) -> Result<(), String> {
+    let versions = diagnostics::Versions::new(app.package_info().version.to_string(), tauri::VERSION);
+    let settings = app.state::<settings::SettingsStore>().get();
+    tauri::async_runtime::spawn_blocking(move || {
+        let diagnostics = app
+            .try_state::<diagnostics::Diagnostics>()
+            .ok_or("Diagnostics not managed")?;
+        let sample = sample_path.as_deref().map(paths::for_io);
+        diagnostics.write_bundle(&paths::for_io(&dest_path), &versions, &settings, sample.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Describes a CSV from its first megabyte, quickly enough to fill in defaults before
+/// the column mapping is inferred.
+#[tauri::command]
+async fn get_csv_info(
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<probe::CsvInfo, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        probe::probe_csv(&paths::for_io(&input_path), &options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+/// Checks a CSV for problems worth fixing before conversion, without writing anything.
+#[tauri::command]
+async fn validate_csv(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<validate::ValidationReport, String> {
+    let options = options.unwrap_or_default();
+    let cancel = begin_run(&app)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        validate::validate_csv(&paths::for_io(&input_path), &options, &cancel)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+    .map_err(String::from)
+}
+
+/// Scans the whole CSV as a conversion would, without writing anything, and lists the
+/// cells and rows the current options would truncate, set to missing, clamp or pad.
+#[tauri::command]
+async fn preview_problems(
+    app: AppHandle,
+    input_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<ProblemReport, String> {
+    let options = options.unwrap_or_default();
+    let cancel = begin_run(&app)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let input = paths::for_io(&input_path);
+        let cache = app.state::<schema::SchemaCache>();
+        let csv_schema = cache.get_or_infer(&input, &options, &cancel)?;
+        let outcome =
+            converter::find_problems(&input, &csv_schema, &options, PROBLEM_LIMIT, &cancel)?;
+        Ok(ProblemReport {
+            rows: outcome.rows,
+            issues: outcome.issues.issues,
+            total: outcome.issues.total,
+            warnings: csv_schema.warnings.into_iter().chain(outcome.warnings).collect(),
+        })
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+    .map_err(|e: TaskError| String::from(e))
+}
+
+/// Diffs the dictionaries and first rows of two SAV files, e.g. a re-conversion and
+/// an earlier output of the same CSV.
+#[tauri::command]
+async fn compare_sav(
+    left_path: PathBuf,
+    right_path: PathBuf,
+    options: Option<options::CompareOptions>,
+) -> Result<compare::SavComparison, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        compare::compare_sav(&paths::for_io(&left_path), &paths::for_io(&right_path), &options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?
+}
+
+#[tauri::command]
+fn get_supported_formats() -> formats::SupportedFormats {
+    formats::supported_formats()
+}
+
 #[tauri::command]
 async fn cancel_conversion(app: AppHandle) {
     if let Some(flag) = app.try_state::<CancelFlag>() {
-        flag.0.store(true, Ordering::Relaxed);
+        flag.cancel();
     }
 }
 
 #[tauri::command]
 async fn convert_csv_to_sav(
     app: AppHandle,
-    input_path: String,
-    output_path: String,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Option<options::ConvertOptions>,
 ) -> Result<ConvertResult, String> {
-    let cancel_flag = app
-        .try_state::<CancelFlag>()
-        .ok_or("CancelFlag not managed")?;
+    let cancel = begin_run(&app)?;
+    let options = options.unwrap_or_default();
+    convert_file(app, input_path, output_path, options, cancel, None).await
+}
+
+/// Converts a CSV to a SAS Transport file, version 8 unless `version` says otherwise,
+/// for agencies that require XPT submissions.
+#[tauri::command]
+async fn convert_csv_to_xpt(
+    app: AppHandle,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    version: Option<readstat_writer::XportVersion>,
+    options: Option<options::ConvertOptions>,
+) -> Result<ConvertResult, String> {
+    let cancel = begin_run(&app)?;
+    let options = options::ConvertOptions {
+        xport: Some(version.unwrap_or_default()),
+        ..options.unwrap_or_default()
+    };
+    convert_file(app, input_path, output_path, options, cancel, None).await
+}
+
+/// Converts one file under `cancel`, which the options' time limit is set on. Events
+/// carry the `convert_batch` index `job`.
+async fn convert_file(
+    app: AppHandle,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: options::ConvertOptions,
+    cancel: CancelToken,
+    job: Option<usize>,
+) -> Result<ConvertResult, String> {
+    let max_columns = options.max_columns;
+    let timeout_secs = options.timeout_secs;
+    cancel.set_timeout(options.timeout());
 
-    cancel_flag.0.store(false, Ordering::Relaxed);
-    let cancelled = cancel_flag.0.clone();
+    let journal = app
+        .try_state::<journal::Journal>()
+        .ok_or("Journal not managed")?;
+    journal.begin(&output_path);
 
-    let input = input_path.clone();
-    let output = output_path.clone();
+    let input = paths::for_io(&input_path);
+    let output = paths::for_io(&output_path);
+    let file_name = input_path.clone();
+    let handle = app.clone();
+    let started = Instant::now();
 
     let result = tauri::async_runtime::spawn_blocking(move || {
-        let input_p = Path::new(&input);
-        let output_p = Path::new(&output);
-        let file_name = input.clone();
+        let input_p = input.as_path();
+        let output_p = output.as_path();
 
-        let csv_schema = schema::infer_schema(input_p, SAMPLE_ROWS, &cancelled)?;
+        filelock::ensure_writable(
+            output_p,
+            Duration::from_secs(options.lock_wait_secs),
+            &cancel,
+            &|| {
+                let _ = handle.emit("output-locked", &file_name);
+            },
+        )?;
 
-        if cancelled.load(Ordering::Relaxed) {
-            return Err("Cancelled".to_string());
-        }
+        let cache = handle.state::<schema::SchemaCache>();
+        let csv_schema = cache.get_or_infer(input_p, &options, &cancel);
+        cache.forget(input_p);
+        let csv_schema = csv_schema?;
+
+        cancel.check()?;
 
         let file_size = csv_schema.file_size;
-        let truncated_cols = csv_schema.truncated_cols.clone();
-        emit_progress(&app, &file_name, 0, 0, file_size);
+        for warning in &csv_schema.warnings {
+            emit_warning(&handle, &file_name, job, warning);
+        }
+        emit_progress(&handle, &file_name, job, 0, 0, file_size);
 
-        let actual_rows = converter::convert_csv_to_zsav(
+        let mut outcome = converter::convert_csv_to_zsav(
             input_p,
             output_p,
             &csv_schema,
-            &cancelled,
+            &options,
+            &cancel,
             &|current_rows, bytes_read, file_size| {
-                emit_progress(&app, &file_name, current_rows, bytes_read, file_size);
+                emit_progress(&handle, &file_name, job, current_rows, bytes_read, file_size);
             },
+            &|message| emit_warning(&handle, &file_name, job, message),
         )?;
 
-        emit_progress(&app, &file_name, actual_rows, file_size, file_size);
+        emit_progress(&handle, &file_name, job, outcome.rows, file_size, file_size);
+
+        let mut warnings = csv_schema.warnings;
+        warnings.append(&mut outcome.warnings);
+        outcome.warnings = warnings;
+        Ok::<_, TaskError>((outcome, csv_schema.truncated_cols))
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"));
+    journal.end(&output_path);
+    let result = result?;
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let report = match result {
+        Ok((mut outcome, truncated_cols)) => {
+            let sha256 = match outcome.parts.as_slice() {
+                [single] => Some(single.sha256.clone()),
+                _ => None,
+            };
+            if outcome.parts.len() == 1 {
+                outcome.parts.clear();
+            }
+            ConvertResult {
+                input_path,
+                output_path,
+                total_rows: outcome.rows,
+                success: true,
+                error: None,
+                error_code: None,
+                truncated_cols,
+                sha256,
+                duration_ms,
+                warnings: outcome.warnings,
+                truncations: outcome.truncations,
+                parts: outcome.parts,
+            }
+        }
+        Err(e) if e.is_timeout() => ConvertResult::failed(
+            input_path,
+            output_path,
+            format!("转换超过时间上限（{timeout_secs} 秒），已取消"),
+            Some(ErrorCode::Timeout),
+            duration_ms,
+        ),
+        Err(TaskError::Cancelled(_)) => ConvertResult::failed(
+            input_path,
+            output_path,
+            "已取消".to_string(),
+            Some(ErrorCode::Cancelled),
+            duration_ms,
+        ),
+        Err(TaskError::Failed(e)) if e == filelock::IN_USE => ConvertResult::failed(
+            input_path,
+            output_path,
+            "输出文件正被其他程序占用（例如 SPSS），请关闭后重试".to_string(),
+            Some(ErrorCode::FileInUse),
+            duration_ms,
+        ),
+        Err(TaskError::Failed(e)) if e == schema::TOO_MANY_COLUMNS => ConvertResult::failed(
+            input_path,
+            output_path,
+            format!("列数超过 SPSS 单个文件上限（{max_columns} 列），可启用按列拆分输出为多个文件"),
+            Some(ErrorCode::TooManyColumns),
+            duration_ms,
+        ),
+        Err(e) => ConvertResult::failed(input_path, output_path, e.to_string(), None, duration_ms),
+    };
+    log_report(&app, &report);
+    Ok(report)
+}
+
+/// Runs an SQL query against Postgres or MySQL and converts the result set to SAV.
+/// The rows are staged as a temporary CSV and go through the same inference and
+/// writer as a file; the result names the connection, without its password, as input.
+#[cfg(feature = "database")]
+#[tauri::command]
+async fn convert_query_to_sav(
+    app: AppHandle,
+    connection: String,
+    query: String,
+    output_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<ConvertResult, String> {
+    let cancel = begin_run(&app)?;
+    let source = PathBuf::from(database::redact(&connection));
+    let staged = staging_path(&app, "query", options.as_ref())?;
+    let started = Instant::now();
 
-        Ok::<_, String>((actual_rows, truncated_cols))
+    let target = staged.clone();
+    let staging = cancel.clone();
+    let dumped = tauri::async_runtime::spawn_blocking(move || {
+        database::query_to_csv(&connection, &query, &target, &staging)
     })
     .await
     .map_err(|e| format!("Task failed: {e}"))?;
+    let query_ms = started.elapsed().as_millis() as u64;
+    if dumped.is_err() {
+        unstage(&app, &staged);
+    }
+    match dumped {
+        Ok(()) => {}
+        Err(TaskError::Cancelled(_)) => {
+            return Ok(ConvertResult::failed(
+                source,
+                output_path,
+                "已取消".to_string(),
+                Some(ErrorCode::Cancelled),
+                query_ms,
+            ));
+        }
+        Err(e) => return Ok(ConvertResult::failed(source, output_path, e.to_string(), None, query_ms)),
+    }
+
+    let options =
+        options.map(options::ConvertOptions::with_standard_dialect).unwrap_or_default();
+    let result =
+        convert_file(app.clone(), staged.clone(), output_path, options, cancel, None).await;
+    unstage(&app, &staged);
+    let mut result = result?;
+    result.input_path = source;
+    result.duration_ms += query_ms;
+    Ok(result)
+}
+
+#[cfg(not(feature = "database"))]
+#[tauri::command]
+async fn convert_query_to_sav(
+    _connection: String,
+    _query: String,
+    _output_path: PathBuf,
+    _options: Option<options::ConvertOptions>,
+) -> Result<ConvertResult, String> {
+    Err("This build has no database support".to_string())
+}
+
+/// Joins a second CSV onto the first by key and converts the merged rows to one SAV.
+/// The join is staged as a temporary CSV, like a query result.
+#[tauri::command]
+async fn convert_joined_to_sav(
+    app: AppHandle,
+    input_path: PathBuf,
+    right_path: PathBuf,
+    output_path: PathBuf,
+    join: options::JoinOptions,
+    options: Option<options::ConvertOptions>,
+) -> Result<ConvertResult, String> {
+    let cancel = begin_run(&app)?;
+    let staged = staging_path(&app, "join", options.as_ref())?;
+    let started = Instant::now();
+
+    let (left, right, target) = (paths::for_io(&input_path), paths::for_io(&right_path), staged.clone());
+    let kind = join.kind;
+    let staging = cancel.clone();
+    let joined = tauri::async_runtime::spawn_blocking(move || {
+        join::join_csv(&left, &right, &join, &target, &staging)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?;
+    let join_ms = started.elapsed().as_millis() as u64;
+    let summary = match joined {
+        Ok(summary) => summary,
+        Err(e) => {
+            unstage(&app, &staged);
+            let (message, code) = match e {
+                TaskError::Cancelled(_) => ("已取消".to_string(), Some(ErrorCode::Cancelled)),
+                TaskError::Failed(e) => (e, None),
+            };
+            return Ok(ConvertResult::failed(input_path, output_path, message, code, join_ms));
+        }
+    };
+
+    let options =
+        options.map(options::ConvertOptions::with_standard_dialect).unwrap_or_default();
+    let result =
+        convert_file(app.clone(), staged.clone(), output_path, options, cancel, None).await;
+    unstage(&app, &staged);
+    let mut result = result?;
+    result.input_path = input_path;
+    result.duration_ms += join_ms;
+    let mut warnings = summary.warnings(kind);
+    warnings.append(&mut result.warnings);
+    result.warnings = warnings;
+    Ok(result)
+}
+
+/// Flattens a JSON Lines file into a staged CSV with one column per key and converts
+/// it, for data pulled from APIs and logs.
+#[tauri::command]
+async fn convert_jsonl_to_sav(
+    app: AppHandle,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Option<options::ConvertOptions>,
+) -> Result<ConvertResult, String> {
+    let cancel = begin_run(&app)?;
+    let staged = staging_path(&app, "jsonl", options.as_ref())?;
+    let started = Instant::now();
+
+    let (source, target) = (paths::for_io(&input_path), staged.clone());
+    let staging = cancel.clone();
+    let flattened = tauri::async_runtime::spawn_blocking(move || {
+        jsonlines::jsonl_to_csv(&source, &target, &staging)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"))?;
+    let flatten_ms = started.elapsed().as_millis() as u64;
+    if let Err(e) = flattened {
+        unstage(&app, &staged);
+        let (message, code) = match e {
+            TaskError::Cancelled(_) => ("已取消".to_string(), Some(ErrorCode::Cancelled)),
+            TaskError::Failed(e) => (e, None),
+        };
+        return Ok(ConvertResult::failed(input_path, output_path, message, code, flatten_ms));
+    }
+
+    let options =
+        options.map(options::ConvertOptions::with_standard_dialect).unwrap_or_default();
+    let result =
+        convert_file(app.clone(), staged.clone(), output_path, options, cancel, None).await;
+    unstage(&app, &staged);
+    let mut result = result?;
+    result.input_path = input_path;
+    result.duration_ms += flatten_ms;
+    Ok(result)
+}
+
+/// A unique temporary CSV for input produced before conversion, in the scratch
+/// directory named by the options or settings. It is journaled until [`unstage`]
+/// removes it, so one left by a crash is deleted on the next launch.
+fn staging_path(
+    app: &AppHandle,
+    kind: &str,
+    options: Option<&options::ConvertOptions>,
+) -> Result<PathBuf, String> {
+    let preferred = options.and_then(|o| o.temp_dir.as_deref());
+    let dir = app.state::<settings::SettingsStore>().get().scratch_dir(preferred);
+    if !dir.is_dir() {
+        return Err(format!("Temp directory not found: {}", dir.display()));
+    }
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let path = dir.join(format!("csv2sav-{kind}-{}-{stamp}.csv", std::process::id()));
+    app.state::<journal::Journal>().begin(&path);
+    Ok(path)
+}
+
+fn unstage(app: &AppHandle, staged: &Path) {
+    let _ = std::fs::remove_file(staged);
+    app.state::<journal::Journal>().end(staged);
+}
+
+/// Exports a SAV or ZSAV file to CSV. Shares the cancel flag with conversions and
+/// reports through `export-progress`.
+#[tauri::command]
+async fn export_sav_to_csv(
+    app: AppHandle,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Option<options::ExportOptions>,
+) -> Result<ConvertResult, String> {
+    let options = options.unwrap_or_default();
+    let cancel = begin_run(&app)?;
+
+    let journal = app
+        .try_state::<journal::Journal>()
+        .ok_or("Journal not managed")?;
+    journal.begin(&output_path);
+
+    let input = paths::for_io(&input_path);
+    let output = paths::for_io(&output_path);
+    let file_name = input_path.clone();
+    let handle = app.clone();
+    let started = Instant::now();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        filelock::ensure_writable(
+            &output,
+            Duration::from_secs(options.lock_wait_secs),
+            &cancel,
+            &|| {
+                let _ = handle.emit("output-locked", &file_name);
+            },
+        )?;
+
+        let outcome = exporter::export_sav_to_csv(
+            &input,
+            &output,
+            &options,
+            &cancel,
+            &|current_rows, total_rows| {
+                let _ = handle.emit(
+                    "export-progress",
+                    ExportProgress {
+                        file: file_name.clone(),
+                        current_rows,
+                        total_rows,
+                    },
+                );
+            },
+        )?;
+        for warning in &outcome.warnings {
+            emit_warning(&handle, &file_name, None, warning);
+        }
+        Ok::<_, TaskError>(outcome)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {e}"));
+    journal.end(&output_path);
+    let result = result?;
+    let duration_ms = started.elapsed().as_millis() as u64;
 
     match result {
-        Ok((total_rows, truncated_cols)) => Ok(ConvertResult {
+        Ok(outcome) => Ok(ConvertResult {
             input_path,
             output_path,
-            total_rows,
+            total_rows: outcome.rows,
             success: true,
             error: None,
-            truncated_cols,
+            error_code: None,
+            truncated_cols: vec![],
+            sha256: Some(outcome.sha256),
+            duration_ms,
+            warnings: outcome.warnings,
+            truncations: vec![],
+            parts: vec![],
         }),
-        Err(e) if e == "Cancelled" => Ok(ConvertResult {
+        Err(TaskError::Cancelled(_)) => Ok(ConvertResult::failed(
             input_path,
             output_path,
-            total_rows: 0,
-            success: false,
-            error: Some("已取消".to_string()),
-            truncated_cols: vec![],
-        }),
-        Err(e) => Ok(ConvertResult {
+            "已取消".to_string(),
+            Some(ErrorCode::Cancelled),
+            duration_ms,
+        )),
+        Err(TaskError::Failed(e)) if e == filelock::IN_USE => Ok(ConvertResult::failed(
             input_path,
             output_path,
-            total_rows: 0,
-            success: false,
-            error: Some(e),
-            truncated_cols: vec![],
-        }),
+            "输出文件正被其他程序占用（例如 Excel），请关闭后重试".to_string(),
+            Some(ErrorCode::FileInUse),
+            duration_ms,
+        )),
+        Err(e) => Ok(ConvertResult::failed(input_path, output_path, e.to_string(), None, duration_ms)),
+    }
+}
+
+#[tauri::command]
+fn get_settings(store: tauri::State<'_, settings::SettingsStore>) -> settings::Settings {
+    store.get()
+}
+
+#[tauri::command]
+fn set_settings(
+    store: tauri::State<'_, settings::SettingsStore>,
+    settings: settings::Settings,
+) -> Result<(), String> {
+    store.set(settings)
+}
+
+/// Posts the batch summary to the configured webhook, if any.
+async fn post_batch_summary(
+    app: &AppHandle,
+    results: &[ConvertResult],
+    duration_ms: u64,
+) -> Result<(), String> {
+    let Some(url) = app.state::<settings::SettingsStore>().get().webhook_url else {
+        return Ok(());
+    };
+    let files = results
+        .iter()
+        .map(|r| webhook::FileSummary {
+            input_path: r.input_path.clone(),
+            output_path: r.output_path.clone(),
+            rows: r.total_rows,
+            success: r.success,
+            error: r.error.clone(),
+            duration_ms: r.duration_ms,
+        })
+        .collect();
+    let summary = webhook::BatchSummary::new(files, duration_ms);
+    tauri::async_runtime::spawn_blocking(move || webhook::post(&url, &summary))
+        .await
+        .map_err(|e| format!("Task failed: {e}"))?
+}
+
+#[tauri::command]
+async fn notify_batch_complete(
+    app: AppHandle,
+    results: Vec<ConvertResult>,
+    duration_ms: u64,
+) -> Result<(), String> {
+    post_batch_summary(&app, &results, duration_ms).await
+}
+
+/// Converts job `job` of `batch` on a child of `cancel` that `cancel_batch_job` can
+/// cancel alone.
+async fn convert_job(
+    app: AppHandle,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: options::ConvertOptions,
+    cancel: &CancelToken,
+    batch: u64,
+    job: usize,
+) -> Result<ConvertResult, String> {
+    let token = app.state::<cancel::JobTokens>().start(batch, job, cancel);
+    let result =
+        convert_file(app.clone(), input_path, output_path, options, token, Some(job)).await;
+    app.state::<cancel::JobTokens>().finish(batch, job);
+    result
+}
+
+/// Cancels one job of a `convert_batch` or `run_manifest` run, named by the `job` and
+/// `batch` of its `batch-progress` events; without `batch`, of the latest run. The
+/// other jobs carry on. Returns false when the job is not running.
+#[tauri::command]
+async fn cancel_batch_job(app: AppHandle, job: usize, batch: Option<u64>) -> bool {
+    app.try_state::<cancel::JobTokens>().is_some_and(|jobs| jobs.cancel(batch, job))
+}
+
+/// Runs every job of a JSON/YAML manifest in order as one batch. A job that fails,
+/// even before converting, is reported in its result and the rest still run.
+#[tauri::command]
+async fn run_manifest(app: AppHandle, manifest_path: PathBuf) -> Result<Vec<ConvertResult>, String> {
+    let jobs = manifest::load(&paths::for_io(&manifest_path))?;
+    let cancel = begin_run(&app)?;
+    let batch = app.state::<cancel::JobTokens>().begin_batch();
+
+    let started = Instant::now();
+    let total_files = jobs.len();
+    let mut results = Vec::with_capacity(total_files);
+    for (i, job) in jobs.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let _ = app.emit(
+            "batch-progress",
+            BatchProgress {
+                completed_files: i,
+                total_files,
+                current_file: Some(job.input.clone()),
+                job: Some(i),
+                batch,
+            },
+        );
+        let job_started = Instant::now();
+        let created = match job.output.parent() {
+            Some(dir) => std::fs::create_dir_all(paths::for_io(dir))
+                .map_err(|e| format!("Failed to create output directory: {e}")),
+            None => Ok(()),
+        };
+        let (input, output) = (job.input.clone(), job.output.clone());
+        let result = match created {
+            Ok(()) => {
+                let (input, output) = (job.input, job.output);
+                convert_job(app.clone(), input, output, job.options, &cancel, batch, i).await
+            }
+            Err(e) => Err(e),
+        };
+        // One job failing to start is its own result, not the end of the manifest.
+        results.push(result.unwrap_or_else(|e| {
+            let duration_ms = job_started.elapsed().as_millis() as u64;
+            ConvertResult::failed(input, output, e, None, duration_ms)
+        }));
+    }
+    let _ = app.emit(
+        "batch-progress",
+        BatchProgress {
+            completed_files: results.len(),
+            total_files,
+            current_file: None,
+            job: None,
+            batch,
+        },
+    );
+
+    let duration_ms = started.elapsed().as_millis() as u64;
+    if let Err(e) = post_batch_summary(&app, &results, duration_ms).await {
+        let _ = app.emit("webhook-error", e);
+    }
+    Ok(results)
+}
+
+/// Converts every input to its output, at most `concurrency` files at a time, and
+/// returns one result per job in the order given; jobs a cancellation kept from
+/// starting are reported as cancelled. Each file reports its own progress and
+/// `batch-progress` reports the files finished.
+#[tauri::command]
+async fn convert_batch(
+    app: AppHandle,
+    jobs: Vec<BatchJob>,
+    options: Option<options::ConvertOptions>,
+    concurrency: Option<usize>,
+) -> Result<Vec<ConvertResult>, String> {
+    let cancel = begin_run(&app)?;
+    let batch = app.state::<cancel::JobTokens>().begin_batch();
+
+    let started = Instant::now();
+    let total_files = jobs.len();
+    let options = options.unwrap_or_default();
+    let paths: Vec<(PathBuf, PathBuf)> =
+        jobs.iter().map(|job| (job.input_path.clone(), job.output_path.clone())).collect();
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+    let results = Arc::new(Mutex::new(vec![None; total_files]));
+    let completed = Arc::new(AtomicUsize::new(0));
+    let workers = concurrency.unwrap_or_else(batch_workers).clamp(1, total_files.max(1));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let (app, queue, results) = (app.clone(), queue.clone(), results.clone());
+            let (options, cancel, completed) =
+                (options.clone(), cancel.clone(), completed.clone());
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    let Some((i, job)) = next.filter(|_| !cancel.is_cancelled()) else {
+                        return Ok::<_, String>(());
+                    };
+                    let progress = |current_file: Option<PathBuf>| BatchProgress {
+                        completed_files: completed.load(Ordering::Relaxed),
+                        total_files,
+                        job: current_file.as_ref().map(|_| i),
+                        current_file,
+                        batch,
+                    };
+                    let _ = app.emit("batch-progress", progress(Some(job.input_path.clone())));
+                    let result = convert_job(
+                        app.clone(),
+                        job.input_path,
+                        job.output_path,
+                        options.clone(),
+                        &cancel,
+                        batch,
+                        i,
+                    )
+                    .await?;
+                    results.lock().unwrap()[i] = Some(result);
+                    completed.fetch_add(1, Ordering::Relaxed);
+                    let _ = app.emit("batch-progress", progress(None));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.map_err(|e| format!("Task failed: {e}"))??;
+    }
+
+    let results: Vec<ConvertResult> = std::mem::take(&mut *results.lock().unwrap())
+        .into_iter()
+        .zip(paths)
+        .map(|(result, (input, output))| {
+            result.unwrap_or_else(|| {
+                let code = Some(ErrorCode::Cancelled);
+                ConvertResult::failed(input, output, "已取消".to_string(), code, 0)
+            })
+        })
+        .collect();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    if let Err(e) = post_batch_summary(&app, &results, duration_ms).await {
+        let _ = app.emit("webhook-error", e);
+    }
+    Ok(results)
+}
+
+/// Runs the conversion asked for by `--convert` in the launch arguments, reporting
+/// warnings and errors on stderr. Returns the exit code, or None to start the app.
+pub fn run_stream<I>(args: I) -> Option<i32>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let args = match launch::stream_args(args)? {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("csv2sav: {e}");
+            return Some(2);
+        }
+    };
+    match launch::stream(&args, &CancelToken::new()) {
+        Ok(outcome) => {
+            for warning in &outcome.warnings {
+                eprintln!("csv2sav: warning: {warning}");
+            }
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("csv2sav: {}", String::from(e));
+            Some(1)
+        }
     }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            let args = argv.into_iter().skip(1).map(OsString::from);
+            queue_opened_files(app, launch::csv_paths(args));
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(CancelFlag(Arc::new(AtomicBool::new(false))))
-        .invoke_handler(tauri::generate_handler![convert_csv_to_sav, cancel_conversion])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .manage(CancelFlag::default())
+        .manage(LaunchFiles::default())
+        .manage(cancel::JobTokens::default())
+        .manage(deeplink::PendingRequests::default())
+        .manage(schema::SchemaCache::default())
+        .setup(|app| {
+            let journal = journal::Journal::open(app.path().app_data_dir()?.join(JOURNAL_FILE));
+            journal.remove_orphans();
+            app.manage(journal);
+            app.manage(settings::SettingsStore::open(
+                app.path().app_config_dir()?.join(SETTINGS_FILE),
+            ));
+            app.manage(diagnostics::Diagnostics::open(app.path().app_log_dir()?));
+            queue_opened_files(app.handle(), launch::csv_paths(std::env::args_os().skip(1)));
+
+            #[cfg(any(windows, target_os = "linux"))]
+            app.deep_link().register_all()?;
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    handle_deep_link(&handle, &url);
+                }
+            });
+            for url in app.deep_link().get_current()?.unwrap_or_default() {
+                handle_deep_link(app.handle(), &url);
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            convert_csv_to_sav,
+            convert_csv_to_xpt,
+            convert_query_to_sav,
+            convert_joined_to_sav,
+            convert_jsonl_to_sav,
+            export_sav_to_csv,
+            compare_sav,
+            cancel_conversion,
+            cancel_batch_job,
+            get_supported_formats,
+            take_launch_files,
+            confirm_deep_link,
+            get_settings,
+            set_settings,
+            notify_batch_complete,
+            run_manifest,
+            convert_batch,
+            get_csv_info,
+            get_column_mapping,
+            infer_schema,
+            reinfer_column_mapping,
+            preview_output,
+            validate_csv,
+            preview_problems,
+            export_diagnostics
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            if let tauri::RunEvent::Opened { urls } = event {
+                let paths = urls
+                    .into_iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .map(|path| path.into_os_string());
+                queue_opened_files(app, launch::csv_paths(paths));
+            }
+            #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+            let _ = (app, event);
+        });
 }
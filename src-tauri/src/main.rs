@@ -2,5 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if let Some(code) = csv2sav_app_lib::run_stream(std::env::args_os().skip(1)) {
+        std::process::exit(code);
+    }
     csv2sav_app_lib::run()
 }
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::launch;
+use crate::options::ConvertOptions;
+
+#[derive(Debug, Deserialize)]
+struct RawManifest {
+    #[serde(default)]
+    defaults: JsonValue,
+    jobs: Vec<RawJob>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawJob {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    #[serde(default)]
+    options: JsonValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub options: ConvertOptions,
+}
+
+/// Loads a JSON or YAML (by extension) manifest. Relative paths resolve against the
+/// manifest's directory and per-job options are layered over `defaults` key by key.
+pub fn load(path: &Path) -> Result<Vec<Job>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read manifest: {e}"))?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+    let raw: RawManifest = if is_yaml {
+        serde_yaml::from_str(&text).map_err(|e| format!("Invalid YAML manifest: {e}"))?
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON manifest: {e}"))?
+    };
+    let base = path.parent().unwrap_or(Path::new("."));
+    resolve(raw, base)
+}
+
+fn resolve(raw: RawManifest, base: &Path) -> Result<Vec<Job>, String> {
+    if raw.jobs.is_empty() {
+        return Err("Manifest contains no jobs".to_string());
+    }
+
+    let mut outputs = HashSet::new();
+    let mut jobs = Vec::with_capacity(raw.jobs.len());
+    for (i, job) in raw.jobs.into_iter().enumerate() {
+        let n = i + 1;
+        let input = base.join(&job.input);
+        if !launch::is_csv_file(&input) {
+            return Err(format!("Job {n}: input is not a CSV file: {}", input.display()));
+        }
+        let output = match job.output {
+            Some(p) => base.join(p),
+            None => input.with_extension("zsav"),
+        };
+        if output == input {
+            return Err(format!("Job {n}: output would overwrite the input"));
+        }
+        if !outputs.insert(output.clone()) {
+            return Err(format!("Job {n}: duplicate output {}", output.display()));
+        }
+
+        let mut merged = raw.defaults.clone();
+        merge(&mut merged, job.options);
+        let mut options: ConvertOptions = if merged.is_null() {
+            ConvertOptions::default()
+        } else {
+            serde_json::from_value(merged).map_err(|e| format!("Job {n}: invalid options: {e}"))?
+        };
+        if let Some(dictionary) = &options.dictionary {
+            options.dictionary = Some(base.join(dictionary));
+        }
+        if let Some(codebook) = &options.value_label_file {
+            options.value_label_file = Some(base.join(codebook));
+        }
+
+        jobs.push(Job {
+            input,
+            output,
+            options,
+        });
+    }
+    Ok(jobs)
+}
+
+fn merge(base: &mut JsonValue, overlay: JsonValue) {
+    match (base, overlay) {
+        (JsonValue::Object(base), JsonValue::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(JsonValue::Null), value);
+            }
+        }
+        (_, JsonValue::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_yaml_manifest() {
+        let dir = std::env::temp_dir().join("csv2sav_manifest_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.csv"), "x\n1\n").unwrap();
+        fs::write(dir.join("b.csv"), "y\n2\n").unwrap();
+        let manifest = dir.join("jobs.yaml");
+        fs::write(
+            &manifest,
+            "defaults:\n  sample_rows: 50\njobs:\n  - input: a.csv\n  - input: b.csv\n    output: out/b.zsav\n    options:\n      sample_rows: 7\n",
+        )
+        .unwrap();
+
+        let jobs = load(&manifest).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].output, dir.join("a.zsav"));
+        assert_eq!(jobs[0].options.sample_rows, 50);
+        assert_eq!(jobs[1].output, dir.join("out/b.zsav"));
+        assert_eq!(jobs[1].options.sample_rows, 7);
+
+        fs::write(&manifest, "jobs:\n  - input: a.csv\n  - input: a.csv\n").unwrap();
+        assert!(load(&manifest).unwrap_err().contains("duplicate output"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
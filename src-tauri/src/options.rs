@@ -0,0 +1,691 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::readstat_writer::{Alignment, Compression, XportVersion};
+use crate::retry::RetryPolicy;
+
+pub const DEFAULT_SAMPLE_ROWS: usize = 10_000;
+/// Variables per file beyond which SPSS becomes impractical to work with.
+pub const DEFAULT_MAX_COLUMNS: usize = 32_767;
+pub const DEFAULT_CACHE_RECORDS_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+/// What to do with variable and value labels longer than the SPSS limits of 255 and
+/// 120 bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelOverflow {
+    /// Cut at a character boundary.
+    #[default]
+    Truncate,
+    /// Collapse whitespace and keep the start and the end around an ellipsis.
+    Abbreviate,
+    /// Cut, and keep the full text in the file's document record.
+    Document,
+}
+
+/// Type forced on a column in place of the inferred one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForcedType {
+    /// Text, such as IDs that look numeric but keep their leading zeros.
+    String,
+    /// Numbers; values that are not become missing.
+    Numeric,
+}
+
+/// How to treat byte sequences in the CSV that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidUtf8 {
+    /// Fail with the byte offset, row and column of the first bad sequence.
+    #[default]
+    Strict,
+    /// Replace bad sequences with U+FFFD and report how many cells were affected.
+    Lossy,
+}
+
+/// Character encoding of the input CSV. Other encodings than UTF-8 are transcoded to
+/// UTF-8 as the file is read, before inference and writing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 if the start of the file is valid UTF-8, otherwise the legacy encoding
+    /// it reads best in.
+    Auto,
+    /// Simplified Chinese, decoded as its superset GB18030.
+    Gbk,
+    /// Traditional Chinese.
+    Big5,
+    ShiftJis,
+    /// Western European, decoded as Windows-1252 like browsers do.
+    Latin1,
+}
+
+/// What to do with NUL characters in text values, which SAV strings cannot hold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NulBytes {
+    /// Replace each with U+FFFD and report the affected cells.
+    #[default]
+    Replace,
+    /// Remove them and report the affected cells.
+    Strip,
+    /// Fail with the row and column of the first one.
+    Error,
+}
+
+/// What to do with numbers SPSS cannot display: infinities, NaN, subnormals and
+/// magnitudes too large for the widest numeric format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRange {
+    /// Write the value unchanged; only warn.
+    #[default]
+    Keep,
+    /// Clamp to the largest displayable magnitude (subnormals become 0, NaN missing).
+    Clamp,
+    SetMissing,
+}
+
+/// How string cells containing only whitespace are written. Numeric columns always
+/// treat them as system-missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhitespaceOnly {
+    /// Trimmed to a zero-length string.
+    #[default]
+    Empty,
+    /// Trimmed, and blank is declared a user-missing value of every string variable.
+    Missing,
+    /// Kept verbatim as a valid, non-missing value.
+    Blank,
+}
+
+/// SPSS period formats for columns like "2024Q1", "Mar 2024" or "2024W05".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodFormat {
+    Qyr,
+    Moyr,
+    Wkyr,
+}
+
+/// How a column holding personal data is written, so the original values never
+/// reach the SAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anonymize {
+    /// Left out of the SAV file.
+    Drop,
+    /// Replaced by a salted SHA-256, so equal values stay equal.
+    Hash,
+    /// All but the last four characters replaced with `*`.
+    Mask,
+    /// Dates reduced to their year.
+    Year,
+}
+
+/// File format of an exported data dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DictionaryFormat {
+    Csv,
+    Json,
+}
+
+/// Restructuring applied to rows before they are written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reshape {
+    /// Repeated-measure columns become rows: one row per original row and level.
+    ToLong(ToLong),
+    /// Rows sharing an id become one row with a column per variable and level.
+    ToWide(ToWide),
+}
+
+/// Melts `Q1_T1`, `Q1_T2`, … into a `Q1` column and an index column holding the level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToLong {
+    /// Stems of the repeated columns, e.g. `Q1`.
+    pub stubs: Vec<String>,
+    /// Column suffixes in order, e.g. `T1`, `T2`, `T3`; also the index values.
+    pub levels: Vec<String>,
+    /// Text between stem and level in the wide headers.
+    pub separator: String,
+    /// Header of the index column.
+    pub index: String,
+}
+
+impl Default for ToLong {
+    fn default() -> Self {
+        Self {
+            stubs: Vec::new(),
+            levels: Vec::new(),
+            separator: "_".to_string(),
+            index: "time".to_string(),
+        }
+    }
+}
+
+/// Spreads the rows of each id over `variable_level` columns. The rows of an id must
+/// be adjacent, as in most long-format exports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToWide {
+    /// Header of the column identifying a case.
+    pub id: String,
+    /// Header of the column holding the level.
+    pub index: String,
+    /// Index values in order; rows with other values are left out.
+    pub levels: Vec<String>,
+    /// Headers of the columns that vary by level; the others are taken from an id's
+    /// first row.
+    pub variables: Vec<String>,
+    pub separator: String,
+}
+
+impl Default for ToWide {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            index: String::new(),
+            levels: Vec::new(),
+            variables: Vec::new(),
+            separator: "_".to_string(),
+        }
+    }
+}
+
+/// Which rows a key join keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinKind {
+    /// Every row of the first file, with blanks where the second has no match.
+    #[default]
+    Left,
+    /// Only rows whose key is in both files.
+    Inner,
+}
+
+/// How two CSVs are joined before conversion, e.g. responses with demographics.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JoinOptions {
+    /// Key column of the first file.
+    pub key: String,
+    /// Key column of the second file when its header differs.
+    pub right_key: Option<String>,
+    pub kind: JoinKind,
+}
+
+/// Settings for a single column, keyed by its header in [`ConvertOptions::columns`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnOptions {
+    /// Convert the column to a period-formatted date instead of inferring its type.
+    pub period: Option<PeriodFormat>,
+    pub anonymize: Option<Anonymize>,
+    /// Decimal places of a numeric column, replacing the inferred ones (at most 16).
+    pub decimals: Option<usize>,
+    /// Data View alignment, replacing the type's default.
+    pub alignment: Option<Alignment>,
+    /// Data View column width in characters.
+    pub display_width: Option<usize>,
+    /// Code to label, such as `{"1": "Male"}`; replaces a dictionary's label for
+    /// the same code.
+    pub value_labels: BTreeMap<String, String>,
+    /// Type in place of the inferred one.
+    pub col_type: Option<ForcedType>,
+    /// Bytes of a string, or the display width of a number, in place of the inferred one.
+    pub width: Option<usize>,
+    /// Variable name in place of a dictionary's or the default.
+    pub name: Option<String>,
+    /// Variable label in place of a dictionary's or the header.
+    pub label: Option<String>,
+    /// User-missing codes such as `-99`, or one numeric range such as `90 thru 99`,
+    /// added to a dictionary's; at most three, a range counting as two.
+    pub missing: Vec<String>,
+}
+
+/// Per-conversion settings supplied by the frontend, a manifest, or a deep link.
+/// Every field has a default so callers only send what they change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvertOptions {
+    /// Rows sampled for type inference.
+    pub sample_rows: usize,
+    /// Character that separates fields. An Excel `sep=` line in the file takes
+    /// precedence.
+    pub delimiter: char,
+    /// Character that quotes fields.
+    pub quote: char,
+    /// Character that escapes a quote inside a quoted field, such as `\`; None for
+    /// the RFC 4180 doubled quote.
+    pub escape: Option<char>,
+    /// Whether the quote character is special; off reads quotes as ordinary text.
+    pub quoting: bool,
+    /// Lines starting with this character are skipped as comments.
+    pub comment: Option<char>,
+    /// Lines before the header skipped whatever they hold, such as a report title.
+    pub skip_lines: usize,
+    /// Regular expression; leading lines matching it are skipped before the header.
+    /// An Excel `sep=` hint on the first line is always skipped.
+    pub skip_pattern: Option<String>,
+    /// CSV record holding the header, counting from 0 after the skipped lines; the
+    /// records before it are skipped. Unlike `skip_lines`, a quoted title spanning
+    /// several lines counts once.
+    pub header_row: usize,
+    /// Character between the integer and fractional parts of numbers, such as `,`.
+    pub decimal_separator: char,
+    /// Character grouping thousands in numbers, such as `.` in `1.234,5`.
+    pub grouping_separator: Option<char>,
+    /// Share of a column's non-empty sampled values that must be numbers for it to
+    /// be numeric, such as 0.99; the other values become missing. 1 requires all.
+    pub numeric_threshold: f64,
+    /// Retries for transient read errors, e.g. on network drives.
+    pub read_retries: u32,
+    /// Delay before the first retry in milliseconds; doubled on each further retry.
+    pub retry_backoff_ms: u64,
+    /// How long to wait for a locked output file (e.g. open in SPSS) to be released; 0 fails immediately.
+    pub lock_wait_secs: u64,
+    /// Longest a conversion may run before it is cancelled and its partial output
+    /// removed; 0 means no limit.
+    pub timeout_secs: u64,
+    /// Keep integer columns with more than 15 significant digits (IDs) as strings.
+    pub preserve_long_integers: bool,
+    /// Cell values treated as missing during inference (case-insensitive, trimmed), so a
+    /// stray "NA" does not turn a numeric column into a string.
+    pub missing_markers: Vec<String>,
+    /// Write `<output>.issues.csv` listing every truncated, coerced or ragged-row fix.
+    pub write_issues_file: bool,
+    /// Handling of variable and value labels that exceed the SPSS limits.
+    pub label_overflow: LabelOverflow,
+    /// Strict failure or lossy replacement for input that is not valid UTF-8.
+    pub invalid_utf8: InvalidUtf8,
+    /// Encoding the CSV is decoded from. Sequences invalid in a legacy encoding
+    /// become U+FFFD.
+    pub encoding: InputEncoding,
+    pub nul_bytes: NulBytes,
+    /// Handling of numeric values outside the SPSS-representable range.
+    pub out_of_range: OutOfRange,
+    /// Most columns written to one SAV file.
+    pub max_columns: usize,
+    /// Split wider CSVs into several files by column range instead of failing.
+    pub split_columns: bool,
+    /// Treatment of whitespace-only string cells.
+    pub whitespace_only: WhitespaceOnly,
+    /// Infer date columns from values with month names such as "01-Mar-2024", and
+    /// date and datetime columns from values such as "2024-03-01" or "01/03/2024 10:15".
+    pub detect_dates: bool,
+    /// Extra month name sets for non-English dates, twelve names each, January first.
+    pub month_names: Vec<Vec<String>>,
+    /// Per-column settings by header. A BTreeMap keeps the serialized form, and with
+    /// it the schema cache key, stable.
+    pub columns: BTreeMap<String, ColumnOptions>,
+    /// Files up to this size are parsed once: inference keeps the records for the
+    /// writer. 0 disables.
+    pub cache_records_max_bytes: u64,
+    /// Larger files are read to the end during inference, only splitting records past
+    /// the sample, so the schema holds the exact row count. Conversion to files needs
+    /// no count, so this only helps callers that show it or convert into memory.
+    pub count_all_rows: bool,
+    /// Data dictionary (JSON, or CSV by extension) with variable names, labels,
+    /// value labels, missing codes and measure levels to apply.
+    pub dictionary: Option<PathBuf>,
+    /// Codebook CSV with `variable`, `value` and `label` columns, one row per label,
+    /// applied over the data dictionary's value labels. `variable` is a CSV header or
+    /// a variable name from the data dictionary.
+    pub value_label_file: Option<PathBuf>,
+    /// Where a column such as `region_name` names the codes of `region_code`, write
+    /// only the codes, with the names as their value labels.
+    pub merge_label_columns: bool,
+    /// Write column families such as `Q5_1`..`Q5_12` holding checkbox answers as
+    /// multiple response sets.
+    pub register_response_sets: bool,
+    /// In those families, turn columns holding one text when ticked into 1 or 0,
+    /// with the text as the label of 1.
+    pub collapse_selected: bool,
+    /// Recognize Qualtrics exports: question ids become variable names, the question
+    /// text row becomes labels, the import metadata row is skipped and "-99" (seen but
+    /// unanswered) is declared user-missing.
+    pub detect_qualtrics: bool,
+    /// Recognize SurveyMonkey exports: the two header rows are merged into question
+    /// names and labels, and checkbox questions become multiple response sets.
+    pub detect_surveymonkey: bool,
+    /// Recognize Google Forms exports and read their Timestamp column as a datetime.
+    pub detect_google_forms: bool,
+    /// In Google Forms exports, add a 0/1 column per option of checkbox questions
+    /// whose answers are joined with ";".
+    pub split_multi_select: bool,
+    /// Salt for columns anonymized by hashing; required when any column is hashed.
+    pub anonymize_salt: String,
+    /// Also write `<output>.dictionary.csv` or `.json` describing every written
+    /// variable, in the form [`ConvertOptions::dictionary`] accepts.
+    pub export_dictionary: Option<DictionaryFormat>,
+    /// Write `<output>.meta.json` recording the source file and its hash, the tool
+    /// version, the options, the variables, the row count and the warnings.
+    pub write_provenance: bool,
+    /// Also write `<output>.cleaned.csv` holding the values exactly as written.
+    pub write_cleaned_csv: bool,
+    /// Output compression; `rows` or `none` write a plain SAV, which SPSS before
+    /// version 21 can open.
+    pub compression: Compression,
+    /// Write a SAS Transport (XPT) file of this version instead of SAV; `compression`
+    /// then does not apply.
+    pub xport: Option<XportVersion>,
+    /// On cancellation, finalize the output with the rows converted so far instead of
+    /// deleting it.
+    pub keep_partial_output: bool,
+    /// Convert only this many rows, drawn at random from the whole file, to check the
+    /// schema and formats in SPSS before a long full conversion.
+    pub random_sample: Option<usize>,
+    /// Seed for `random_sample`; the same seed draws the same rows. Taken from the
+    /// clock when unset and reported in a warning.
+    pub random_seed: Option<u64>,
+    /// Rhai script run on every row before conversion, after any reshape, to clean,
+    /// derive or blank values; see [`crate::script::RowScript`].
+    pub row_script: Option<PathBuf>,
+    /// Wide-to-long or long-to-wide restructuring; column options and the data
+    /// dictionary refer to the reshaped headers.
+    pub reshape: Option<Reshape>,
+    /// Header of the column that weights the cases (SPSS WEIGHT BY); it must be
+    /// numeric and its values positive.
+    pub weight: Option<String>,
+    /// Header of a 0/1 column that selects cases (SPSS FILTER BY). ReadStat cannot
+    /// write the filter state, so the syntax turning it on goes in the document record.
+    pub filter: Option<String>,
+    /// SPSS variable sets by name, each listing the headers of its columns.
+    pub variable_sets: BTreeMap<String, Vec<String>>,
+    /// Further SAV files written in the same pass, by file name, each holding only
+    /// the listed headers' columns. Relative names are placed next to the output.
+    pub subset_outputs: BTreeMap<String, Vec<String>>,
+    /// Directory for scratch files, overriding the one in the settings.
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl ConvertOptions {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.read_retries,
+            backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    /// Whether the output can be written without knowing the row count upfront: ZSAV
+    /// holds its header back until the end, and XPT records no count.
+    pub fn streams_rows(&self) -> bool {
+        self.xport.is_some() || self.compression == Compression::Zlib
+    }
+
+    /// A reader for the input's CSV dialect. Records may have more or fewer fields
+    /// than the header.
+    pub fn csv_reader(&self) -> Result<csv::ReaderBuilder, String> {
+        let byte = |c: char, what: &str| {
+            u8::try_from(c)
+                .ok()
+                .filter(u8::is_ascii)
+                .ok_or(format!("{what} must be a single ASCII character"))
+        };
+        if self.grouping_separator == Some(self.decimal_separator) {
+            return Err("Decimal and grouping separators must differ".to_string());
+        }
+        if self.quoting && self.delimiter == self.quote {
+            return Err("Delimiter and quote character must differ".to_string());
+        }
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .flexible(true)
+            .delimiter(byte(self.delimiter, "Delimiter")?)
+            .quote(byte(self.quote, "Quote character")?)
+            .quoting(self.quoting)
+            .comment(self.comment.map(|c| byte(c, "Comment prefix")).transpose()?);
+        if let Some(escape) = self.escape {
+            builder.escape(Some(byte(escape, "Escape character")?)).double_quote(false);
+        }
+        Ok(builder)
+    }
+
+    /// These options for a CSV this app wrote itself, such as a staged query result,
+    /// which is RFC 4180 whatever the dialect options say.
+    pub fn with_standard_dialect(self) -> Self {
+        let standard = Self::default();
+        Self {
+            delimiter: standard.delimiter,
+            quote: standard.quote,
+            escape: standard.escape,
+            quoting: standard.quoting,
+            comment: standard.comment,
+            skip_lines: standard.skip_lines,
+            skip_pattern: standard.skip_pattern,
+            header_row: standard.header_row,
+            decimal_separator: standard.decimal_separator,
+            grouping_separator: standard.grouping_separator,
+            ..self
+        }
+    }
+
+    pub fn anonymize(&self, header: &str) -> Option<Anonymize> {
+        self.columns.get(header).and_then(|c| c.anonymize)
+    }
+
+    /// `text` rewritten with a plain `.` decimal point and no grouping, or None if it
+    /// does not follow the declared separators. Grouping may only split the integer
+    /// part into threes, so `1.5` is not a number when `.` groups thousands.
+    pub fn numeric_text<'a>(&self, text: &'a str) -> Option<Cow<'a, str>> {
+        if self.decimal_separator == '.' && self.grouping_separator.is_none() {
+            return Some(Cow::Borrowed(text));
+        }
+        let (int_part, frac_part) = match text.split_once(self.decimal_separator) {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (text, None),
+        };
+        let mut plain = String::with_capacity(text.len());
+        match self.grouping_separator {
+            Some(grouping) if int_part.contains(grouping) => {
+                let mut groups = int_part.split(grouping);
+                let lead = groups.next().unwrap_or_default();
+                if lead.trim_start_matches(['+', '-']).is_empty() {
+                    return None;
+                }
+                plain.push_str(lead);
+                for group in groups {
+                    if group.len() != 3 || !group.bytes().all(|b| b.is_ascii_digit()) {
+                        return None;
+                    }
+                    plain.push_str(group);
+                }
+            }
+            _ => plain.push_str(int_part),
+        }
+        if let Some(frac_part) = frac_part {
+            plain.push('.');
+            plain.push_str(frac_part);
+        }
+        // A stray `.` would otherwise parse as a decimal point.
+        let points = plain.matches('.').count();
+        (points <= usize::from(frac_part.is_some())).then_some(Cow::Owned(plain))
+    }
+
+    /// `text` as a number written with the declared separators.
+    pub fn parse_number(&self, text: &str) -> Option<f64> {
+        self.numeric_text(text)?.parse().ok()
+    }
+
+    pub fn is_missing_marker(&self, field: &str) -> bool {
+        let field = field.trim();
+        self.missing_markers
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(field))
+    }
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            sample_rows: DEFAULT_SAMPLE_ROWS,
+            delimiter: ',',
+            quote: '"',
+            escape: None,
+            quoting: true,
+            comment: None,
+            skip_lines: 0,
+            skip_pattern: None,
+            header_row: 0,
+            decimal_separator: '.',
+            grouping_separator: None,
+            numeric_threshold: 1.0,
+            read_retries: 3,
+            retry_backoff_ms: 200,
+            lock_wait_secs: 0,
+            timeout_secs: 0,
+            preserve_long_integers: true,
+            missing_markers: ["NA", "N/A", "#N/A", "NULL", "."]
+                .map(String::from)
+                .to_vec(),
+            write_issues_file: false,
+            label_overflow: LabelOverflow::default(),
+            invalid_utf8: InvalidUtf8::default(),
+            encoding: InputEncoding::default(),
+            nul_bytes: NulBytes::default(),
+            out_of_range: OutOfRange::default(),
+            max_columns: DEFAULT_MAX_COLUMNS,
+            split_columns: false,
+            whitespace_only: WhitespaceOnly::default(),
+            detect_dates: true,
+            month_names: Vec::new(),
+            columns: BTreeMap::new(),
+            cache_records_max_bytes: DEFAULT_CACHE_RECORDS_MAX_BYTES,
+            count_all_rows: false,
+            dictionary: None,
+            value_label_file: None,
+            merge_label_columns: false,
+            register_response_sets: false,
+            collapse_selected: false,
+            detect_qualtrics: true,
+            detect_surveymonkey: true,
+            detect_google_forms: true,
+            split_multi_select: false,
+            anonymize_salt: String::new(),
+            export_dictionary: None,
+            write_provenance: false,
+            write_cleaned_csv: false,
+            compression: Compression::Zlib,
+            xport: None,
+            keep_partial_output: false,
+            random_sample: None,
+            random_seed: None,
+            row_script: None,
+            reshape: None,
+            weight: None,
+            filter: None,
+            variable_sets: BTreeMap::new(),
+            subset_outputs: BTreeMap::new(),
+            temp_dir: None,
+        }
+    }
+}
+
+/// Whether exported cells hold the stored codes or their value labels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportValues {
+    #[default]
+    Coded,
+    /// The value label where one is defined, otherwise the code.
+    Labeled,
+}
+
+/// How variables with an SPSS date, time or datetime format are exported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportDates {
+    /// `2024-03-01`, `2024-03-01 13:45:00` or `13:45:00`, depending on the format.
+    #[default]
+    Iso,
+    /// The stored number of seconds since 1582-10-14.
+    Seconds,
+}
+
+/// Character encoding of the exported CSV.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 with a byte order mark, which Excel needs to detect the encoding.
+    Utf8Bom,
+    /// For Excel on Simplified Chinese Windows. Characters GBK cannot represent are
+    /// written as numeric character references and reported.
+    Gbk,
+}
+
+/// Treatment of values declared user-missing in the SAV dictionary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserMissing {
+    /// Written as [`ExportOptions::missing_value`], like system-missing values.
+    #[default]
+    Missing,
+    /// Written as the stored value (or its label).
+    Value,
+}
+
+/// Settings for exporting a SAV or ZSAV file back to CSV.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub values: ExportValues,
+    pub dates: ExportDates,
+    /// Field separator; must be a single ASCII character.
+    pub delimiter: char,
+    pub encoding: ExportEncoding,
+    /// Text written for missing values.
+    pub missing_value: String,
+    pub user_missing: UserMissing,
+    /// How long to wait for a locked output file (e.g. open in Excel) to be released; 0 fails immediately.
+    pub lock_wait_secs: u64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            values: ExportValues::default(),
+            dates: ExportDates::default(),
+            delimiter: ',',
+            encoding: ExportEncoding::default(),
+            missing_value: String::new(),
+            user_missing: UserMissing::default(),
+            lock_wait_secs: 0,
+        }
+    }
+}
+
+/// Settings for comparing two SAV or ZSAV files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompareOptions {
+    /// Leading rows whose values are compared; 0 compares the dictionaries only.
+    pub sample_rows: usize,
+    /// Differing cells listed in the result; all of them are counted.
+    pub max_differences: usize,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            sample_rows: 1_000,
+            max_differences: 100,
+        }
+    }
+}
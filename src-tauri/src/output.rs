@@ -0,0 +1,152 @@
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use sha2::{Digest, Sha256};
+
+const BUFFER_SIZE: usize = 512 * 1024;
+
+/// Output file owned by a dedicated I/O thread. Filled buffers go over a bounded
+/// channel, so compression on the conversion thread overlaps with disk writes and
+/// hashing; written buffers come back for reuse (double buffering).
+pub struct OutputThread {
+    buf: Vec<u8>,
+    full: Option<SyncSender<Vec<u8>>>,
+    empty: Receiver<Vec<u8>>,
+    error: Arc<Mutex<Option<String>>>,
+    handle: Option<JoinHandle<Option<String>>>,
+}
+
+impl OutputThread {
+    pub fn spawn(mut file: impl Write + Send + 'static) -> Self {
+        let (full_tx, full_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        let (empty_tx, empty_rx) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+
+        // Returns the hex SHA-256 of everything written, or None after an I/O error.
+        let handle = thread::spawn(move || {
+            let mut hasher = Sha256::new();
+            for mut buf in full_rx {
+                if let Err(e) = file.write_all(&buf) {
+                    *thread_error.lock().unwrap() = Some(e.to_string());
+                    return None;
+                }
+                hasher.update(&buf);
+                buf.clear();
+                let _ = empty_tx.send(buf);
+            }
+            if let Err(e) = file.flush() {
+                *thread_error.lock().unwrap() = Some(e.to_string());
+                return None;
+            }
+            Some(format!("{:x}", hasher.finalize()))
+        });
+
+        Self {
+            buf: Vec::with_capacity(BUFFER_SIZE),
+            full: Some(full_tx),
+            empty: empty_rx,
+            error,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= BUFFER_SIZE {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<(), String> {
+        let next = self
+            .empty
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(BUFFER_SIZE));
+        let full = std::mem::replace(&mut self.buf, next);
+        let sent = self.full.as_ref().is_some_and(|tx| tx.send(full).is_ok());
+        if sent {
+            Ok(())
+        } else {
+            Err(self.thread_error())
+        }
+    }
+
+    fn thread_error(&self) -> String {
+        self.error
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| "output thread stopped".to_string())
+    }
+
+    /// Writes what is left, closes the file and returns its hex SHA-256.
+    pub fn finish(&mut self) -> Result<String, String> {
+        if !self.buf.is_empty() {
+            self.send()?;
+        }
+        self.full = None;
+        let handle = self.handle.take().ok_or("Output already finished")?;
+        match handle.join() {
+            Ok(Some(sha256)) => Ok(sha256),
+            Ok(None) => Err(self.thread_error()),
+            Err(_) => Err("Output thread panicked".to_string()),
+        }
+    }
+}
+
+/// Growable byte buffer that can be handed to the output thread while the caller
+/// keeps a handle to read it back.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for OutputThread {
+    fn drop(&mut self) {
+        self.full = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_output_thread_writes_and_hashes_in_order() {
+        let path = std::env::temp_dir().join("csv2sav_output_thread_test.bin");
+        let mut output = OutputThread::spawn(File::create(&path).unwrap());
+        let chunk: Vec<u8> = (0..=255).collect();
+        for _ in 0..5_000 {
+            output.write(&chunk).unwrap();
+        }
+        let sha256 = output.finish().unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.len(), 256 * 5_000);
+        assert_eq!(sha256, format!("{:x}", Sha256::digest(&written)));
+        std::fs::remove_file(&path).ok();
+    }
+}
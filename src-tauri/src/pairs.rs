@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+/// Header endings of a column holding codes.
+const CODE_SUFFIXES: [&str; 7] = ["code", "cd", "id", "no", "num", "number", "key"];
+/// Header endings of a column holding the names those codes stand for.
+const NAME_SUFFIXES: [&str; 6] = ["name", "label", "desc", "description", "text", "title"];
+/// More distinct codes than this read as identifiers rather than categories.
+const MAX_CODES: usize = 200;
+
+/// A code column whose values each come with one name in another column, such as
+/// `region_code` and `region_name`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelPair {
+    pub code: usize,
+    pub name: usize,
+    /// Name of every code seen.
+    pub labels: BTreeMap<String, String>,
+    /// Every row was looked at, so no code can be missing from `labels`.
+    pub complete: bool,
+}
+
+impl LabelPair {
+    /// Warning offering to merge the pair.
+    pub fn offer(&self, headers: &[String]) -> String {
+        format!(
+            "Column '{}' names the codes in '{}'; set merge_label_columns to keep only the codes, with the names as value labels",
+            headers[self.name], headers[self.code]
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    code: usize,
+    name: usize,
+    labels: BTreeMap<String, String>,
+    consistent: bool,
+}
+
+/// Checks candidate pairs, found by header, against the rows.
+#[derive(Debug, Clone, Default)]
+pub struct PairTracker {
+    candidates: Vec<Candidate>,
+}
+
+/// Splits `region_code` or `Region Name` into its lowercased stem and ending.
+fn split_header(header: &str) -> Option<(String, String)> {
+    let header = header.trim().to_lowercase();
+    let at = header.rfind(['_', ' ', '-', '.'])?;
+    let (stem, suffix) = (&header[..at], &header[at + 1..]);
+    (!stem.is_empty()).then(|| (stem.to_string(), suffix.to_string()))
+}
+
+impl PairTracker {
+    /// Re-checks known pairs, e.g. over rows that inference did not sample.
+    pub fn for_pairs(pairs: &[LabelPair]) -> Self {
+        let candidates = pairs
+            .iter()
+            .map(|p| Candidate { code: p.code, name: p.name, labels: BTreeMap::new(), consistent: true })
+            .collect();
+        Self { candidates }
+    }
+
+    pub fn new(headers: &[String]) -> Self {
+        let mut candidates = Vec::new();
+        for (code, header) in headers.iter().enumerate() {
+            let Some((stem, suffix)) = split_header(header) else {
+                continue;
+            };
+            if !CODE_SUFFIXES.contains(&suffix.as_str()) {
+                continue;
+            }
+            // `region_name`, or just `region`.
+            let name = headers.iter().position(|other| {
+                split_header(other)
+                    .is_some_and(|(s, suffix)| s == stem && NAME_SUFFIXES.contains(&suffix.as_str()))
+            });
+            let name = name.or_else(|| headers.iter().position(|other| other.trim().to_lowercase() == stem));
+            if let Some(name) = name {
+                candidates.push(Candidate { code, name, labels: BTreeMap::new(), consistent: true });
+            }
+        }
+        Self { candidates }
+    }
+
+    /// Notes one row's values.
+    pub fn observe<'a>(&mut self, field: impl Fn(usize) -> Option<&'a str>) {
+        for candidate in self.candidates.iter_mut().filter(|c| c.consistent) {
+            let code = field(candidate.code).unwrap_or_default().trim();
+            let name = field(candidate.name).unwrap_or_default().trim();
+            candidate.consistent = match (code.is_empty(), name.is_empty()) {
+                // A name without a code would be lost.
+                (true, false) => false,
+                (_, true) => true,
+                (false, false) => match candidate.labels.get(code) {
+                    Some(known) => known == name,
+                    None => {
+                        candidate.labels.insert(code.to_string(), name.to_string());
+                        candidate.labels.len() <= MAX_CODES
+                    }
+                },
+            };
+        }
+    }
+
+    /// Pairs that held for every row observed.
+    pub fn finish(self, complete: bool) -> Vec<LabelPair> {
+        self.candidates
+            .into_iter()
+            .filter(|c| c.consistent && !c.labels.is_empty())
+            .map(|c| LabelPair { code: c.code, name: c.name, labels: c.labels, complete })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(headers: &[&str], rows: &[&[&str]]) -> Vec<(usize, usize, usize)> {
+        let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+        let mut tracker = PairTracker::new(&headers);
+        for row in rows {
+            tracker.observe(|i| row.get(i).copied());
+        }
+        tracker.finish(true).into_iter().map(|p| (p.code, p.name, p.labels.len())).collect()
+    }
+
+    #[test]
+    fn test_pairs_by_header_and_values() {
+        let rows: &[&[&str]] = &[&["1", "North", "x"], &["2", "South", "y"], &["1", "North", "z"], &["", "", "w"]];
+        assert_eq!(pairs(&["region_code", "Region Name", "other"], rows), [(0, 1, 2)]);
+        assert_eq!(pairs(&["Country ID", "country", "other"], rows), [(0, 1, 2)]);
+        assert!(pairs(&["region_code", "region_name"], &[&["1", "North"], &["1", "South"]]).is_empty());
+        assert!(pairs(&["region_code", "region_name"], &[&["", "North"]]).is_empty());
+        assert!(pairs(&["code", "name"], rows).is_empty());
+    }
+}
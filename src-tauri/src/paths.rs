@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+
+/// Returns a path suitable for file I/O. On Windows, paths at or beyond MAX_PATH are
+/// made absolute and given the `\\?\` (or `\\?\UNC\`) verbatim prefix; elsewhere the
+/// path is returned unchanged.
+pub fn for_io(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        windows::long_path(path)
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::path::{Component, Path, PathBuf, Prefix};
+
+    const MAX_PATH: usize = 260;
+
+    pub fn long_path(path: &Path) -> PathBuf {
+        if path.as_os_str().len() < MAX_PATH {
+            return path.to_path_buf();
+        }
+        // `absolute` resolves `..` and separators, which verbatim paths no longer do.
+        let Ok(abs) = std::path::absolute(path) else {
+            return path.to_path_buf();
+        };
+        let mut components = abs.components();
+        let Some(Component::Prefix(prefix)) = components.next() else {
+            return abs;
+        };
+        match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut out = OsString::from(r"\\?\");
+                out.push(abs.as_os_str());
+                PathBuf::from(out)
+            }
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                unc.push(r"\");
+                let mut out = PathBuf::from(unc);
+                out.extend(components.filter(|c| !matches!(c, Component::RootDir)));
+                out
+            }
+            // Already verbatim or a device path.
+            _ => abs,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use serde::Serialize;
+
+/// Share of a column's sampled values that must match for it to be flagged.
+const MIN_SHARE: f64 = 0.8;
+/// Headers of columns holding people's names.
+const NAME_HEADERS: [&str; 14] = [
+    "name",
+    "full name",
+    "fullname",
+    "first name",
+    "firstname",
+    "given name",
+    "last name",
+    "lastname",
+    "surname",
+    "family name",
+    "respondent name",
+    "contact name",
+    "姓名",
+    "名字",
+];
+/// Header words that make a run of digits read as a phone number.
+const PHONE_HEADERS: [&str; 6] = ["phone", "tel", "mobile", "cell", "电话", "手机"];
+/// Weights of the first 17 digits of a Chinese resident identity card number.
+const CN_ID_WEIGHTS: [u32; 17] = [7, 9, 10, 5, 8, 4, 2, 1, 6, 3, 7, 9, 10, 5, 8, 4, 2];
+const CN_ID_CHECK: &[u8; 11] = b"10X98765432";
+
+/// Kind of personal data a column appears to hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+    Email,
+    Phone,
+    NationalId,
+    Name,
+}
+
+impl PiiKind {
+    fn describe(self) -> &'static str {
+        match self {
+            PiiKind::Email => "email addresses",
+            PiiKind::Phone => "phone numbers",
+            PiiKind::NationalId => "national ID numbers",
+            PiiKind::Name => "people's names",
+        }
+    }
+}
+
+/// Counts sampled values of one column that look like personal data.
+#[derive(Debug, Clone, Default)]
+pub struct PiiTally {
+    values: usize,
+    emails: usize,
+    phones: usize,
+    /// Digit runs that are phone numbers only if the header says so.
+    bare_phones: usize,
+    national_ids: usize,
+    words: usize,
+}
+
+impl PiiTally {
+    /// Notes a trimmed, non-empty value.
+    pub fn observe(&mut self, value: &str) {
+        self.values += 1;
+        if is_email(value) {
+            self.emails += 1;
+        } else if is_national_id(value) {
+            self.national_ids += 1;
+        } else if let Some(formatted) = phone_shape(value) {
+            self.bare_phones += 1;
+            self.phones += formatted as usize;
+        } else if is_name_like(value) {
+            self.words += 1;
+        }
+    }
+
+    /// What the column appears to hold, judged from its values and header.
+    pub fn kind(&self, header: &str) -> Option<PiiKind> {
+        let share = |n: usize| self.values > 0 && n as f64 / self.values as f64 >= MIN_SHARE;
+        let header = header.trim().to_lowercase();
+        let phone_header = PHONE_HEADERS.iter().any(|h| header.contains(h));
+        if share(self.emails) {
+            Some(PiiKind::Email)
+        } else if share(self.national_ids) {
+            Some(PiiKind::NationalId)
+        } else if share(self.phones) || (phone_header && share(self.bare_phones)) {
+            Some(PiiKind::Phone)
+        } else if NAME_HEADERS.contains(&header.as_str()) && share(self.words) {
+            Some(PiiKind::Name)
+        } else {
+            None
+        }
+    }
+}
+
+/// Warning shown for a flagged column.
+pub fn warning(header: &str, kind: PiiKind) -> String {
+    format!(
+        "Column '{header}' looks like it holds {}; drop or anonymize it unless the analysis needs it",
+        kind.describe()
+    )
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !value.contains(char::is_whitespace)
+        && !domain.contains('@')
+        && domain
+            .split_once('.')
+            .is_some_and(|(host, tld)| !host.is_empty() && tld.len() >= 2)
+}
+
+/// Some(true) for a formatted phone number such as `+49 30 1234567` or
+/// `(555) 123-4567`, Some(false) for 7 to 15 bare digits, None otherwise.
+fn phone_shape(value: &str) -> Option<bool> {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | ' ' | '(' | ')' | '.'))
+    {
+        return None;
+    }
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    if !(7..=15).contains(&digits) {
+        return None;
+    }
+    let formatted = value.starts_with('+') || value.contains(['-', ' ', '(']);
+    // "12.5" style decimals are numbers, not phone numbers.
+    if value.contains('.') && !formatted {
+        return None;
+    }
+    Some(formatted || is_cn_mobile(value))
+}
+
+/// An 11-digit mainland China mobile number, which is unambiguous without formatting.
+fn is_cn_mobile(value: &str) -> bool {
+    let b = value.as_bytes();
+    b.len() == 11 && b.iter().all(u8::is_ascii_digit) && b[0] == b'1' && (b'3'..=b'9').contains(&b[1])
+}
+
+/// US social security, UK national insurance or Chinese resident identity numbers.
+fn is_national_id(value: &str) -> bool {
+    let b = value.as_bytes();
+    let digits = |range: std::ops::Range<usize>| b[range].iter().all(u8::is_ascii_digit);
+    let ssn = b.len() == 11 && b[3] == b'-' && b[6] == b'-' && digits(0..3) && digits(4..6) && digits(7..11);
+    let nino = b.len() == 9
+        && b[..2].iter().all(u8::is_ascii_uppercase)
+        && digits(2..8)
+        && matches!(b[8], b'A'..=b'D');
+    ssn || nino || is_cn_id(b)
+}
+
+fn is_cn_id(b: &[u8]) -> bool {
+    if b.len() != 18 || !b[..17].iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    let sum: u32 = b[..17]
+        .iter()
+        .zip(CN_ID_WEIGHTS)
+        .map(|(d, w)| u32::from(d - b'0') * w)
+        .sum();
+    CN_ID_CHECK[(sum % 11) as usize] == b[17].to_ascii_uppercase()
+}
+
+/// One to four words of letters, as names are written.
+fn is_name_like(value: &str) -> bool {
+    let words = value.split_whitespace().count();
+    (1..=4).contains(&words)
+        && value
+            .chars()
+            .all(|c| c.is_alphabetic() || c.is_whitespace() || matches!(c, '-' | '\'' | '.' | '·'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(header: &str, values: &[&str]) -> Option<PiiKind> {
+        let mut tally = PiiTally::default();
+        for value in values {
+            tally.observe(value);
+        }
+        tally.kind(header)
+    }
+
+    #[test]
+    fn test_pii_kinds() {
+        assert_eq!(kind("contact", &["a@example.com", "b.c@mail.org"]), Some(PiiKind::Email));
+        assert_eq!(kind("x", &["+49 30 1234567", "(555) 123-4567"]), Some(PiiKind::Phone));
+        assert_eq!(kind("x", &["13812345678"]), Some(PiiKind::Phone));
+        assert_eq!(kind("Mobile", &["0612345678"]), Some(PiiKind::Phone));
+        assert_eq!(kind("amount", &["0612345678", "1234567"]), None);
+        assert_eq!(kind("x", &["123-45-6789", "AB123456C"]), Some(PiiKind::NationalId));
+        assert_eq!(kind("x", &["11010519491231002X"]), Some(PiiKind::NationalId));
+        assert_eq!(kind("x", &["110105194912310021"]), None);
+        assert_eq!(kind("Full Name", &["Ada Lovelace", "Grace Hopper"]), Some(PiiKind::Name));
+        assert_eq!(kind("product name", &["Widget"]), None);
+        assert_eq!(kind("score", &["1.5", "2.25"]), None);
+    }
+}
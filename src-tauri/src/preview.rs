@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::compare::{self, Variable};
+use crate::converter;
+use crate::dates;
+use crate::options::ConvertOptions;
+use crate::schema::CsvSchema;
+
+/// Rows previewed when the caller does not ask for a number.
+pub const DEFAULT_ROWS: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewVariable {
+    pub name: String,
+    pub label: String,
+    /// SPSS display format such as `F8.2`, `A20` or `DATE11`.
+    pub format: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreviewCell {
+    /// The value as SPSS displays it; `.` when system-missing.
+    pub text: String,
+    /// System-missing, or one of the variable's user-missing values.
+    pub missing: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputPreview {
+    pub variables: Vec<PreviewVariable>,
+    pub rows: Vec<Vec<PreviewCell>>,
+    /// Warnings raised while converting the previewed rows.
+    pub warnings: Vec<String>,
+}
+
+/// Converts the first `rows` rows in memory and reads them back, so every value is
+/// shown as SPSS's Data View would show it: formatted, truncated and marked missing.
+pub fn preview_output(
+    input: &Path,
+    csv_schema: &CsvSchema,
+    options: &ConvertOptions,
+    rows: usize,
+    cancel: &CancelToken,
+) -> Result<OutputPreview, TaskError> {
+    let (data, outcome) = converter::convert_csv_head(input, csv_schema, options, rows, cancel)?;
+    let contents = compare::read_bytes(&data, rows)?;
+    let cells = contents
+        .data
+        .iter()
+        .map(|row| row.iter().zip(&contents.variables).map(|(text, v)| cell(text, v)).collect())
+        .collect();
+    let variables = contents
+        .variables
+        .into_iter()
+        .map(|v| PreviewVariable {
+            name: v.name,
+            label: v.label,
+            format: v.format,
+        })
+        .collect();
+    Ok(OutputPreview {
+        variables,
+        rows: cells,
+        warnings: outcome.warnings,
+    })
+}
+
+/// A value as read back from the SAV, where system-missing is blank.
+fn cell(text: &str, variable: &Variable) -> PreviewCell {
+    let missing = variable.missing.iter().any(|m| m == text);
+    if variable.is_string {
+        return PreviewCell {
+            text: text.trim_end().to_string(),
+            missing,
+        };
+    }
+    let Ok(n) = text.parse::<f64>() else {
+        return PreviewCell {
+            text: ".".to_string(),
+            missing: true,
+        };
+    };
+    let text = dates::format_display(n, &variable.format)
+        .or_else(|| fixed_format(&variable.format).map(|(w, d)| format_fixed(n, w, d)))
+        .unwrap_or_else(|| n.to_string());
+    PreviewCell { text, missing }
+}
+
+/// Width and decimals of an `Fw.d` format.
+fn fixed_format(format: &str) -> Option<(usize, usize)> {
+    let (width, decimals) = format.strip_prefix('F')?.split_once('.')?;
+    Some((width.parse().ok()?, decimals.parse().ok()?))
+}
+
+/// A number in `Fw.d`: rounded half away from zero to `d` decimals, with fewer
+/// decimals and then scientific notation when it does not fit, as SPSS does; all
+/// asterisks when nothing fits.
+fn format_fixed(n: f64, width: usize, decimals: usize) -> String {
+    for d in (0..=decimals).rev() {
+        let scale = 10f64.powi(d as i32);
+        let text = format!("{:.d$}", (n * scale).round() / scale);
+        if text.len() <= width {
+            return text;
+        }
+    }
+    for d in (0..width).rev() {
+        let text = format!("{n:.d$E}");
+        let (mantissa, exponent) = text.split_once('E').unwrap_or((&text, "0"));
+        let exponent: i32 = exponent.parse().unwrap_or(0);
+        let sign = if exponent < 0 { '-' } else { '+' };
+        let text = format!("{mantissa}E{sign}{:02}", exponent.abs());
+        if text.len() <= width {
+            return text;
+        }
+    }
+    "*".repeat(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_output() {
+        let input = std::env::temp_dir().join("csv2sav_preview_test.csv");
+        std::fs::write(
+            &input,
+            "id,score,name,when\n1,2.25,Ann,01-Mar-2024\n2,NA,Bob,\n3,1234567.5,Cy,02-Mar-2024\n4,0,Di,03-Mar-2024\n",
+        )
+        .unwrap();
+        let options = ConvertOptions::default();
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let preview = preview_output(&input, &schema, &options, 3, &cancel).unwrap();
+
+        let formats: Vec<&str> = preview.variables.iter().map(|v| v.format.as_str()).collect();
+        assert_eq!(formats[3], dates::DATE_FORMAT);
+        assert_eq!(preview.rows.len(), 3);
+        let texts: Vec<Vec<&str>> = preview
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|c| c.text.as_str()).collect())
+            .collect();
+        assert_eq!(texts[0][2..], ["Ann", "01-MAR-2024"]);
+        assert_eq!(texts[1][1], ".");
+        assert!(preview.rows[1][1].missing && preview.rows[1][3].missing);
+        assert!(!preview.rows[0][1].missing);
+
+        assert_eq!(format_fixed(2.5, 8, 0), "3");
+        assert_eq!(format_fixed(-2.125, 8, 2), "-2.13");
+        assert_eq!(format_fixed(1234567.5, 8, 2), "1234568");
+        assert_eq!(format_fixed(1e12, 8, 2), "1.00E+12");
+        assert_eq!(format_fixed(-1e12, 3, 0), "***");
+
+        std::fs::remove_file(&input).ok();
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::input;
+use crate::options::{ConvertOptions, InputEncoding};
+
+/// Bytes read from the start of the file.
+const PROBE_BYTES: usize = 1024 * 1024;
+/// Delimiters tried, in order of preference when they fit equally well.
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+/// Records compared when choosing a delimiter.
+const SNIFF_RECORDS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectedEncoding {
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Bom,
+    /// UTF-16LE or UTF-16BE starting with a byte order mark, decoded on the fly.
+    Utf16,
+    /// Not valid UTF-8, likely a legacy code page; converting it needs its encoding
+    /// set, or lossy decoding.
+    NotUtf8,
+}
+
+/// What the start of a CSV reveals, for filling in defaults before inference.
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvInfo {
+    pub file_size: u64,
+    /// Data rows, extrapolated from the average row length unless the whole file
+    /// was read.
+    pub estimated_rows: usize,
+    pub rows_exact: bool,
+    pub encoding: DetectedEncoding,
+    /// For `not_utf8`, the encoding `auto` would decode the file as.
+    pub likely_encoding: Option<InputEncoding>,
+    pub delimiter: char,
+    pub headers: Vec<String>,
+}
+
+/// Reads about the first megabyte of a CSV and describes it without inferring types.
+pub fn probe_csv(path: &Path, options: &ConvertOptions) -> Result<CsvInfo, String> {
+    probe(path, options, PROBE_BYTES)
+}
+
+fn probe(path: &Path, options: &ConvertOptions, limit: usize) -> Result<CsvInfo, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open CSV: {e}"))?;
+    let file_size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut data = Vec::with_capacity(limit.min(file_size as usize));
+    file.take(limit as u64)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let complete = data.len() as u64 >= file_size;
+
+    // UTF-16 is looked at as the UTF-8 it converts to, with the size scaled to match.
+    let utf16 = input::utf16_encoding(&data);
+    let mut text_size = file_size;
+    if let Some(utf16) = utf16 {
+        let (text, _) = utf16.decode_with_bom_removal(&data);
+        text_size = (file_size as f64 * text.len() as f64 / data.len() as f64) as u64;
+        data = text.into_owned().into_bytes();
+    }
+
+    input::check_delimited_text(&data)?;
+    let mut rest = data.as_slice();
+    let has_bom = input::skip_utf8_bom(&mut rest).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let encoding = match std::str::from_utf8(rest) {
+        _ if utf16.is_some() => DetectedEncoding::Utf16,
+        _ if has_bom => DetectedEncoding::Utf8Bom,
+        Ok(_) => DetectedEncoding::Utf8,
+        // A character cut off by the limit is not a sign of another encoding.
+        Err(e) if e.error_len().is_none() && !complete => DetectedEncoding::Utf8,
+        Err(_) => DetectedEncoding::NotUtf8,
+    };
+    let likely_encoding =
+        (encoding == DetectedEncoding::NotUtf8).then(|| input::guess_encoding(&data, complete));
+    let preamble = input::skip_preamble(&mut rest, options)?;
+    let offset = (data.len() - rest.len()) as u64;
+    let delimiter = match preamble.separator {
+        Some(separator) => separator,
+        None => sniff_delimiter(rest, options)?,
+    };
+
+    let mut builder = preamble.csv_reader(options)?;
+    builder.delimiter(delimiter);
+    let mut reader = builder.from_reader(rest);
+    input::seek_header(&mut reader, options.header_row)?;
+    let headers: Vec<String> = reader
+        .byte_headers()
+        .map_err(|e| format!("Failed to read CSV headers: {e}"))?
+        .iter()
+        .map(|h| String::from_utf8_lossy(h).trim().to_string())
+        .collect();
+    let data_start = reader.position().byte();
+
+    // Ends of the last two records, as the last one read may be cut off by the limit.
+    let mut rows = 0usize;
+    let (mut last_end, mut end) = (data_start, data_start);
+    let mut record = csv::ByteRecord::new();
+    while reader.read_byte_record(&mut record).unwrap_or(false) {
+        rows += 1;
+        last_end = end;
+        end = reader.position().byte();
+    }
+    let estimated_rows = if complete {
+        rows
+    } else {
+        let full_rows = rows.saturating_sub(1);
+        let remaining = text_size.saturating_sub(offset + data_start);
+        match last_end.checked_sub(data_start).filter(|&bytes| bytes > 0) {
+            Some(bytes) => (full_rows as f64 * remaining as f64 / bytes as f64).round() as usize,
+            None => rows,
+        }
+    };
+
+    Ok(CsvInfo {
+        file_size,
+        estimated_rows,
+        rows_exact: complete,
+        encoding,
+        likely_encoding,
+        delimiter: delimiter as char,
+        headers,
+    })
+}
+
+/// The delimiter that splits the first records into the most fields, consistently
+/// with the header; a comma when none splits the header at all.
+fn sniff_delimiter(data: &[u8], options: &ConvertOptions) -> Result<u8, String> {
+    let mut best = (b',', 0usize, 0usize);
+    for delimiter in DELIMITERS {
+        let mut builder = options.csv_reader()?;
+        builder.delimiter(delimiter).has_headers(false);
+        let mut records = builder.from_reader(data).into_byte_records().map_while(Result::ok);
+        let Some(header) = records.next() else {
+            break;
+        };
+        let fields = header.len();
+        if fields < 2 {
+            continue;
+        }
+        let consistent = records.take(SNIFF_RECORDS).filter(|r| r.len() == fields).count();
+        if (consistent, fields) > (best.1, best.2) {
+            best = (delimiter, consistent, fields);
+        }
+    }
+    Ok(best.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_detects_dialect_and_estimates_rows() {
+        let path = std::env::temp_dir().join("csv2sav_probe_test.csv");
+        let mut csv = b"\xEF\xBB\xBFid;name;note\n".to_vec();
+        for i in 0..1000 {
+            csv.extend_from_slice(format!("{i:04};name {i:04};\"a, b\"\n").as_bytes());
+        }
+        std::fs::write(&path, &csv).unwrap();
+        let options = ConvertOptions::default();
+
+        let info = probe_csv(&path, &options).unwrap();
+        assert_eq!(info.delimiter, ';');
+        assert_eq!(info.encoding, DetectedEncoding::Utf8Bom);
+        assert_eq!(info.headers, vec!["id", "name", "note"]);
+        assert_eq!((info.estimated_rows, info.rows_exact), (1000, true));
+
+        let info = probe(&path, &options, 4096).unwrap();
+        assert!(!info.rows_exact);
+        assert_eq!(info.estimated_rows, 1000);
+        assert_eq!(info.file_size, csv.len() as u64);
+
+        let text = std::str::from_utf8(&csv[3..]).unwrap();
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        std::fs::write(&path, &utf16).unwrap();
+        let info = probe(&path, &options, 8192).unwrap();
+        assert_eq!((info.encoding, info.delimiter), (DetectedEncoding::Utf16, ';'));
+        assert_eq!(info.headers, vec!["id", "name", "note"]);
+        assert_eq!(info.estimated_rows, 1000);
+
+        std::fs::write(&path, b"id,name\n1,Z\xFCrich\n").unwrap();
+        let info = probe_csv(&path, &options).unwrap();
+        assert_eq!((info.encoding, info.delimiter), (DetectedEncoding::NotUtf8, ','));
+        assert_eq!(info.likely_encoding, Some(InputEncoding::Latin1));
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::converter::OutputPart;
+use crate::dictionary::ExportedVariable;
+use crate::options::ConvertOptions;
+
+const HASH_BUF_SIZE: usize = 256 * 1024;
+
+/// The CSV a SAV file was made from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Source {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the file.
+    pub sha256: String,
+}
+
+/// Contents of `<output>.meta.json`: where a SAV file came from and how it was made.
+#[derive(Debug, Serialize)]
+pub struct Provenance<'a> {
+    pub source: Source,
+    pub tool: &'static str,
+    pub version: &'static str,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// The options used, without the anonymization salt.
+    pub options: ConvertOptions,
+    pub rows: usize,
+    pub outputs: &'a [OutputPart],
+    pub variables: &'a [ExportedVariable],
+    pub warnings: &'a [String],
+}
+
+/// `data.zsav` → `data.zsav.meta.json`.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".meta.json");
+    output.with_file_name(name)
+}
+
+impl<'a> Provenance<'a> {
+    /// Hashes `input` and gathers the rest. The salt is left out because it would
+    /// let anyone holding the file reverse hashed values by guessing.
+    pub fn new(
+        input: &Path,
+        options: &ConvertOptions,
+        rows: usize,
+        outputs: &'a [OutputPart],
+        variables: &'a [ExportedVariable],
+        warnings: &'a [String],
+        cancel: &CancelToken,
+    ) -> Result<Self, TaskError> {
+        Ok(Self {
+            source: hash_source(input, cancel)?,
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            options: ConvertOptions {
+                anonymize_salt: String::new(),
+                ..options.clone()
+            },
+            rows,
+            outputs,
+            variables,
+            warnings,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| format!("Failed to encode provenance: {e}"))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write provenance: {e}"))
+    }
+}
+
+fn hash_source(input: &Path, cancel: &CancelToken) -> Result<Source, TaskError> {
+    let file = File::open(input).map_err(|e| format!("Failed to open CSV: {e}"))?;
+    let mut reader = BufReader::with_capacity(HASH_BUF_SIZE, file);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; HASH_BUF_SIZE];
+    let mut size = 0u64;
+    loop {
+        cancel.check()?;
+        let n = reader.read(&mut buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok(Source {
+        path: std::path::absolute(input).unwrap_or_else(|_| input.to_path_buf()),
+        size,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_written_next_to_output() {
+        let dir = std::env::temp_dir().join("csv2sav_provenance_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.zsav");
+        let csv = "id,name\n1,ann\n2,bob\n";
+        std::fs::write(&input, csv).unwrap();
+        let options = ConvertOptions {
+            write_provenance: true,
+            anonymize_salt: "pepper".to_string(),
+            ..ConvertOptions::default()
+        };
+        let cancel = CancelToken::new();
+        let schema = crate::schema::infer_schema(&input, &options, &cancel).unwrap();
+        let outcome = crate::converter::convert_csv_to_zsav(
+            &input, &output, &schema, &options, &cancel, &|_, _, _| {}, &|_| {},
+        )
+        .unwrap();
+        assert!(outcome.warnings.iter().any(|w| w.starts_with("Provenance written to")));
+
+        let path = sidecar_path(&output);
+        assert_eq!(path, dir.join("out.zsav.meta.json"));
+        let meta: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(meta["source"]["sha256"], format!("{:x}", Sha256::digest(csv)));
+        assert_eq!(meta["source"]["size"], csv.len());
+        assert_eq!(meta["rows"], 2);
+        assert_eq!(meta["options"]["anonymize_salt"], "");
+        assert_eq!(meta["variables"][1]["column"], "name");
+        assert_eq!(meta["outputs"][0]["sha256"], outcome.parts[0].sha256);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
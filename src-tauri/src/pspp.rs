@@ -0,0 +1,187 @@
+//! Golden validation against GNU PSPP: converts a CSV, reads the output back with
+//! `pspp-convert` (or `pspp` itself) and compares what PSPP sees with the source.
+//! Only compiled with the `pspp` feature, for the tests in `tests/pspp_golden.rs`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cancel::CancelToken;
+use crate::converter;
+use crate::options::ConvertOptions;
+use crate::schema::{self, ColType};
+
+/// The PSPP program used to read files back.
+#[derive(Debug, Clone)]
+pub enum Tool {
+    /// `pspp-convert <input> <output.csv>`.
+    PsppConvert(PathBuf),
+    /// `pspp <syntax.sps>` running GET FILE and SAVE TRANSLATE.
+    Pspp(PathBuf),
+}
+
+/// A cell PSPP read differently from the source CSV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// 1-based data row.
+    pub row: usize,
+    pub column: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RoundTrip {
+    pub rows: usize,
+    /// Date-like columns, whose PSPP text depends on the display format.
+    pub skipped_columns: Vec<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Finds `pspp-convert`, then `pspp`, on PATH.
+pub fn find_tool() -> Option<Tool> {
+    let path = std::env::var_os("PATH")?;
+    let find = |name: &str| {
+        let file = format!("{name}{}", std::env::consts::EXE_SUFFIX);
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(&file))
+            .find(|candidate| candidate.is_file())
+    };
+    find("pspp-convert")
+        .map(Tool::PsppConvert)
+        .or_else(|| find("pspp").map(Tool::Pspp))
+}
+
+/// Has PSPP write the data of `sav` to `dest` as CSV with a header row of
+/// variable names.
+pub fn to_csv(tool: &Tool, sav: &Path, dest: &Path) -> Result<(), String> {
+    let output = match tool {
+        Tool::PsppConvert(program) => Command::new(program).arg(sav).arg(dest).output(),
+        Tool::Pspp(program) => {
+            let syntax = dest.with_extension("sps");
+            std::fs::write(
+                &syntax,
+                format!(
+                    "GET FILE={}.\nSAVE TRANSLATE /OUTFILE={} /TYPE=CSV /FIELDNAMES /REPLACE.\n",
+                    quote(sav),
+                    quote(dest)
+                ),
+            )
+            .map_err(|e| format!("Failed to write PSPP syntax: {e}"))?;
+            let output = Command::new(program).arg(&syntax).output();
+            std::fs::remove_file(&syntax).ok();
+            output
+        }
+    }
+    .map_err(|e| format!("Failed to run PSPP: {e}"))?;
+    if !output.status.success() || !dest.is_file() {
+        return Err(format!(
+            "PSPP could not read {}: {}",
+            sav.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// PSPP syntax string literal for a path.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', "''"))
+}
+
+/// Converts `input` with default options into `work_dir`, reads the result back
+/// through PSPP and compares every cell with the source.
+pub fn round_trip(tool: &Tool, input: &Path, work_dir: &Path) -> Result<RoundTrip, String> {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let output = work_dir.join(format!("{stem}.zsav"));
+    let exported = work_dir.join(format!("{stem}.pspp.csv"));
+
+    let options = ConvertOptions::default();
+    let cancel = CancelToken::new();
+    let csv_schema = schema::infer_schema(input, &options, &cancel)?;
+    converter::convert_csv_to_zsav(input, &output, &csv_schema, &options, &cancel, &|_, _, _| {}, &|_| {})?;
+    to_csv(tool, &output, &exported)?;
+
+    let read = |path: &Path| -> Result<Vec<csv::StringRecord>, String> {
+        csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(path)
+            .and_then(|mut reader| reader.records().collect())
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))
+    };
+    let source = read(input)?;
+    let actual = read(&exported)?;
+    compare(&csv_schema.headers, &csv_schema.col_types, &options, &source, &actual)
+}
+
+fn compare(
+    headers: &[String],
+    col_types: &[ColType],
+    options: &ConvertOptions,
+    source: &[csv::StringRecord],
+    actual: &[csv::StringRecord],
+) -> Result<RoundTrip, String> {
+    if source.len() != actual.len() {
+        return Err(format!("PSPP read {} rows, the CSV has {}", actual.len(), source.len()));
+    }
+    let mut result = RoundTrip { rows: source.len(), ..Default::default() };
+    for (column, (header, col_type)) in headers.iter().zip(col_types).enumerate() {
+        if !matches!(col_type, ColType::Numeric { .. } | ColType::String(_)) {
+            result.skipped_columns.push(header.clone());
+            continue;
+        }
+        for (row, (source_row, actual_row)) in source.iter().zip(actual).enumerate() {
+            let expected = source_row.get(column).unwrap_or_default();
+            let read_back = actual_row.get(column).unwrap_or_default();
+            let same = match col_type {
+                ColType::Numeric { decimals, .. } => {
+                    let present = |text: &str| !text.is_empty() && !options.is_missing_marker(text);
+                    let (expected, read_back) = (expected.trim(), read_back.trim());
+                    // The source uses the declared separators; PSPP prints a plain `.`.
+                    let expected = options.parse_number(expected).filter(|_| present(expected));
+                    let read_back = read_back.parse::<f64>().ok().filter(|_| present(read_back));
+                    // PSPP prints with the variable's display decimals.
+                    let tolerance = 0.5 * 10f64.powi(-(*decimals as i32)) + 1e-9;
+                    match (expected, read_back) {
+                        (Some(a), Some(b)) => (a - b).abs() <= tolerance,
+                        (a, b) => a.is_none() && b.is_none(),
+                    }
+                }
+                _ => expected.trim_end() == read_back.trim_end(),
+            };
+            if !same {
+                result.mismatches.push(Mismatch {
+                    row: row + 1,
+                    column: header.clone(),
+                    expected: expected.to_string(),
+                    actual: read_back.to_string(),
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[&[&str]]) -> Vec<csv::StringRecord> {
+        rows.iter().map(|r| csv::StringRecord::from(r.to_vec())).collect()
+    }
+
+    #[test]
+    fn test_compare_tolerates_pspp_formatting() {
+        let headers = ["score", "name", "when"].map(String::from);
+        let col_types = [ColType::Numeric { width: 8, decimals: 1 }, ColType::String(8), ColType::Date];
+        let source = records(&[&["2.5", "ann", "1 Jan 2024"], &["NA", "bob ", "2 Jan 2024"]]);
+        let actual = records(&[&["2.50", "ann", "01-JAN-2024"], &["", "bobby", "02-JAN-2024"]]);
+        let result = compare(&headers, &col_types, &ConvertOptions::default(), &source, &actual).unwrap();
+        assert_eq!(result.skipped_columns, ["when"]);
+        assert_eq!(
+            result.mismatches,
+            [Mismatch { row: 2, column: "name".to_string(), expected: "bob ".to_string(), actual: "bobby".to_string() }]
+        );
+        assert!(compare(&headers, &col_types, &ConvertOptions::default(), &source, &actual[..1]).is_err());
+        assert_eq!(quote(Path::new("/tmp/it's.zsav")), "'/tmp/it''s.zsav'");
+    }
+}
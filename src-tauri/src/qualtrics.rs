@@ -0,0 +1,91 @@
+use csv::ByteRecord;
+
+/// Qualtrics writes this for questions a respondent saw but did not answer.
+pub const SEEN_UNANSWERED: f64 = -99.0;
+/// Columns every Qualtrics response export starts with, in either header style.
+const EXPORT_COLUMNS: [&[&str]; 2] = [&["StartDate", "V8"], &["ResponseId", "ResponseID", "V1"]];
+
+/// Header layout of a Qualtrics export: a row of question ids (the CSV header), a row
+/// of question text, and in newer exports a row of `{"ImportId":…}` metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualtricsHeader {
+    /// Question text per column, used as variable labels.
+    pub labels: Vec<String>,
+    /// Rows after the CSV header that are not data: 1, or 2 with the metadata row.
+    pub skip_rows: usize,
+}
+
+/// Recognizes a Qualtrics export from its header and its first two records. The
+/// metadata row identifies one on its own; without it the standard `StartDate` and
+/// `ResponseId` columns must be present.
+pub fn detect(
+    headers: &[String],
+    first: Option<&ByteRecord>,
+    second: Option<&ByteRecord>,
+) -> Option<QualtricsHeader> {
+    let first = first?;
+    let skip_rows = if second.is_some_and(is_import_row) {
+        2
+    } else if has_export_columns(headers) && !is_import_row(first) {
+        1
+    } else {
+        return None;
+    };
+    let labels = (0..headers.len())
+        .map(|i| {
+            first
+                .get(i)
+                .map(|f| String::from_utf8_lossy(f).trim().to_string())
+                .unwrap_or_default()
+        })
+        .collect();
+    Some(QualtricsHeader { labels, skip_rows })
+}
+
+fn has_export_columns(headers: &[String]) -> bool {
+    EXPORT_COLUMNS
+        .iter()
+        .all(|names| headers.iter().any(|h| names.contains(&h.trim())))
+}
+
+/// Whether every non-empty field is a JSON object with an `ImportId` key.
+fn is_import_row(record: &ByteRecord) -> bool {
+    let mut fields = record.iter().filter(|f| !f.trim_ascii().is_empty()).peekable();
+    fields.peek().is_some()
+        && fields.all(|f| {
+            let f = f.trim_ascii();
+            f.starts_with(b"{") && f.ends_with(b"}") && f.windows(10).any(|w| w == b"\"ImportId\"")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: &[&str]) -> ByteRecord {
+        ByteRecord::from(fields.to_vec())
+    }
+
+    #[test]
+    fn test_detect_layouts() {
+        let headers: Vec<String> = ["StartDate", "ResponseId", "Q1"].map(String::from).to_vec();
+        let text = record(&["Start Date", "Response ID", "How satisfied are you?"]);
+        let import = record(&[
+            r#"{"ImportId":"startDate","timeZone":"America/Denver"}"#,
+            r#"{"ImportId":"_recordId"}"#,
+            r#"{"ImportId":"QID1"}"#,
+        ]);
+        let data = record(&["2024-03-01 10:00:00", "R_1", "4"]);
+
+        let layout = detect(&headers, Some(&text), Some(&import)).unwrap();
+        assert_eq!(layout.skip_rows, 2);
+        assert_eq!(layout.labels[2], "How satisfied are you?");
+        assert_eq!(detect(&headers, Some(&text), Some(&data)).unwrap().skip_rows, 1);
+
+        let plain: Vec<String> = ["id", "Q1"].map(String::from).to_vec();
+        assert!(detect(&plain, Some(&record(&["1", "4"])), Some(&record(&["2", "5"]))).is_none());
+        let short = record(&["id", "Question"]);
+        let import = record(&[r#"{"ImportId":"id"}"#, r#"{"ImportId":"QID1"}"#]);
+        assert_eq!(detect(&plain, Some(&short), Some(&import)).unwrap().skip_rows, 2);
+    }
+}
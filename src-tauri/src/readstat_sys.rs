@@ -42,6 +42,14 @@ pub enum readstat_alignment_t {
     READSTAT_ALIGNMENT_RIGHT = 3,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum readstat_io_flags_t {
+    READSTAT_SEEK_SET = 0,
+    READSTAT_SEEK_CUR = 1,
+    READSTAT_SEEK_END = 2,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum readstat_error_t {
@@ -92,11 +100,119 @@ pub struct readstat_writer_t {
     _opaque: [u8; 0],
 }
 
+/// A multiple response set to write; `counted_value` is null for a category set.
+#[repr(C)]
+pub struct readstat_mr_set_write_t {
+    pub name: *const c_char,
+    pub label: *const c_char,
+    pub counted_value: *const c_char,
+    pub var_indices: *const c_int,
+    pub var_count: c_int,
+}
+
 #[repr(C)]
 pub struct readstat_variable_t {
     _opaque: [u8; 0],
 }
 
+#[repr(C)]
+pub struct readstat_label_set_t {
+    _opaque: [u8; 0],
+}
+
+#[repr(C)]
+pub struct readstat_parser_t {
+    _opaque: [u8; 0],
+}
+
+#[repr(C)]
+pub struct readstat_metadata_t {
+    _opaque: [u8; 0],
+}
+
+pub const READSTAT_HANDLER_OK: c_int = 0;
+pub const READSTAT_HANDLER_ABORT: c_int = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union readstat_value_union_t {
+    pub float_value: f32,
+    pub double_value: f64,
+    pub i8_value: i8,
+    pub i16_value: i16,
+    pub i32_value: i32,
+    pub string_value: *const c_char,
+}
+
+/// Passed by value to the parser callbacks; read it only through the accessor functions.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct readstat_value_t {
+    pub v: readstat_value_union_t,
+    pub type_: readstat_type_t,
+    pub tag: c_char,
+    /// The `is_system_missing:1` and `is_tagged_missing:1` bit-fields. GCC and Clang
+    /// pack them into the bytes after `tag`; MSVC starts a new `unsigned int`.
+    #[cfg(not(target_env = "msvc"))]
+    _bits: [u8; 3],
+    #[cfg(target_env = "msvc")]
+    _bits: std::os::raw::c_uint,
+}
+
+pub type readstat_metadata_handler =
+    Option<unsafe extern "C" fn(metadata: *mut readstat_metadata_t, ctx: *mut c_void) -> c_int>;
+pub type readstat_variable_handler = Option<
+    unsafe extern "C" fn(
+        index: c_int,
+        variable: *mut readstat_variable_t,
+        val_labels: *const c_char,
+        ctx: *mut c_void,
+    ) -> c_int,
+>;
+pub type readstat_value_handler = Option<
+    unsafe extern "C" fn(
+        obs_index: c_int,
+        variable: *mut readstat_variable_t,
+        value: readstat_value_t,
+        ctx: *mut c_void,
+    ) -> c_int,
+>;
+pub type readstat_value_label_handler = Option<
+    unsafe extern "C" fn(
+        val_labels: *const c_char,
+        value: readstat_value_t,
+        label: *const c_char,
+        ctx: *mut c_void,
+    ) -> c_int,
+>;
+pub type readstat_fweight_handler =
+    Option<unsafe extern "C" fn(variable: *mut readstat_variable_t, ctx: *mut c_void) -> c_int>;
+pub type readstat_error_handler =
+    Option<unsafe extern "C" fn(error_message: *const c_char, ctx: *mut c_void)>;
+
+pub type readstat_open_handler =
+    Option<unsafe extern "C" fn(path: *const c_char, io_ctx: *mut c_void) -> c_int>;
+pub type readstat_close_handler = Option<unsafe extern "C" fn(io_ctx: *mut c_void) -> c_int>;
+pub type readstat_seek_handler = Option<
+    unsafe extern "C" fn(
+        offset: readstat_off_t,
+        whence: readstat_io_flags_t,
+        io_ctx: *mut c_void,
+    ) -> readstat_off_t,
+>;
+pub type readstat_read_handler =
+    Option<unsafe extern "C" fn(buf: *mut c_void, nbyte: usize, io_ctx: *mut c_void) -> isize>;
+pub type readstat_progress_handler =
+    Option<unsafe extern "C" fn(progress: f64, ctx: *mut c_void) -> c_int>;
+pub type readstat_update_handler = Option<
+    unsafe extern "C" fn(
+        file_size: std::os::raw::c_long,
+        progress_handler: readstat_progress_handler,
+        user_ctx: *mut c_void,
+        io_ctx: *mut c_void,
+    ) -> readstat_error_t,
+>;
+
 pub type readstat_data_writer =
     Option<unsafe extern "C" fn(data: *const c_void, len: usize, ctx: *mut c_void) -> isize>;
 
@@ -138,6 +254,44 @@ extern "C" {
         measure: readstat_measure_t,
     );
 
+    pub fn readstat_variable_add_missing_string_value(
+        variable: *mut readstat_variable_t,
+        value: *const c_char,
+    ) -> readstat_error_t;
+
+    pub fn readstat_variable_add_missing_double_value(
+        variable: *mut readstat_variable_t,
+        value: f64,
+    ) -> readstat_error_t;
+    pub fn readstat_variable_add_missing_double_range(
+        variable: *mut readstat_variable_t,
+        lo: f64,
+        hi: f64,
+    ) -> readstat_error_t;
+
+    pub fn readstat_add_label_set(
+        writer: *mut readstat_writer_t,
+        var_type: readstat_type_t,
+        name: *const c_char,
+    ) -> *mut readstat_label_set_t;
+
+    pub fn readstat_label_double_value(
+        label_set: *mut readstat_label_set_t,
+        value: f64,
+        label: *const c_char,
+    );
+
+    pub fn readstat_label_string_value(
+        label_set: *mut readstat_label_set_t,
+        value: *const c_char,
+        label: *const c_char,
+    );
+
+    pub fn readstat_variable_set_label_set(
+        variable: *mut readstat_variable_t,
+        label_set: *mut readstat_label_set_t,
+    );
+
     pub fn readstat_variable_set_alignment(
         variable: *mut readstat_variable_t,
         alignment: readstat_alignment_t,
@@ -153,6 +307,24 @@ extern "C" {
         compression: readstat_compress_t,
     ) -> readstat_error_t;
 
+    pub fn readstat_writer_set_fweight_variable(
+        writer: *mut readstat_writer_t,
+        variable: *const readstat_variable_t,
+    ) -> readstat_error_t;
+
+    pub fn readstat_writer_set_variable_sets(
+        writer: *mut readstat_writer_t,
+        variable_sets: *const c_char,
+    ) -> readstat_error_t;
+
+    pub fn readstat_writer_set_mr_sets(
+        writer: *mut readstat_writer_t,
+        mr_sets: *const readstat_mr_set_write_t,
+        count: std::os::raw::c_long,
+    ) -> readstat_error_t;
+
+    pub fn readstat_add_note(writer: *mut readstat_writer_t, note: *const c_char);
+
     pub fn readstat_writer_set_file_label(
         writer: *mut readstat_writer_t,
         file_label: *const c_char,
@@ -164,6 +336,14 @@ extern "C" {
         row_count: std::os::raw::c_long,
     ) -> readstat_error_t;
 
+    /// SAS Transport; the version (5 or 8, the default) is set with
+    /// [`readstat_writer_set_file_format_version`].
+    pub fn readstat_begin_writing_xport(
+        writer: *mut readstat_writer_t,
+        user_ctx: *mut c_void,
+        row_count: std::os::raw::c_long,
+    ) -> readstat_error_t;
+
     pub fn readstat_begin_row(writer: *mut readstat_writer_t) -> readstat_error_t;
 
     pub fn readstat_insert_double_value(
@@ -187,8 +367,116 @@ extern "C" {
 
     pub fn readstat_end_writing(writer: *mut readstat_writer_t) -> readstat_error_t;
 
+    pub fn readstat_end_writing_early(writer: *mut readstat_writer_t) -> readstat_error_t;
+
     pub fn readstat_get_variable(
         writer: *mut readstat_writer_t,
         index: c_int,
     ) -> *mut readstat_variable_t;
+
+    pub fn readstat_parser_init() -> *mut readstat_parser_t;
+    pub fn readstat_parser_free(parser: *mut readstat_parser_t);
+
+    pub fn readstat_set_metadata_handler(
+        parser: *mut readstat_parser_t,
+        metadata_handler: readstat_metadata_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_variable_handler(
+        parser: *mut readstat_parser_t,
+        variable_handler: readstat_variable_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_value_handler(
+        parser: *mut readstat_parser_t,
+        value_handler: readstat_value_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_value_label_handler(
+        parser: *mut readstat_parser_t,
+        value_label_handler: readstat_value_label_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_fweight_handler(
+        parser: *mut readstat_parser_t,
+        fweight_handler: readstat_fweight_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_error_handler(
+        parser: *mut readstat_parser_t,
+        error_handler: readstat_error_handler,
+    ) -> readstat_error_t;
+
+    pub fn readstat_set_open_handler(
+        parser: *mut readstat_parser_t,
+        open_handler: readstat_open_handler,
+    ) -> readstat_error_t;
+    pub fn readstat_set_close_handler(
+        parser: *mut readstat_parser_t,
+        close_handler: readstat_close_handler,
+    ) -> readstat_error_t;
+    pub fn readstat_set_seek_handler(
+        parser: *mut readstat_parser_t,
+        seek_handler: readstat_seek_handler,
+    ) -> readstat_error_t;
+    pub fn readstat_set_read_handler(
+        parser: *mut readstat_parser_t,
+        read_handler: readstat_read_handler,
+    ) -> readstat_error_t;
+    pub fn readstat_set_update_handler(
+        parser: *mut readstat_parser_t,
+        update_handler: readstat_update_handler,
+    ) -> readstat_error_t;
+    /// Replaces the context passed to the I/O handlers; ReadStat does not free it.
+    pub fn readstat_set_io_ctx(parser: *mut readstat_parser_t, io_ctx: *mut c_void) -> readstat_error_t;
+
+    /// Reads both SAV and ZSAV files.
+    pub fn readstat_parse_sav(
+        parser: *mut readstat_parser_t,
+        path: *const c_char,
+        user_ctx: *mut c_void,
+    ) -> readstat_error_t;
+
+    /// With a NULL encoding, strings reach the callbacks in the file's own encoding
+    /// and ReadStat needs no iconv.
+    pub fn readstat_set_handler_character_encoding(
+        parser: *mut readstat_parser_t,
+        encoding: *const c_char,
+    ) -> readstat_error_t;
+
+    pub fn readstat_get_row_count(metadata: *mut readstat_metadata_t) -> c_int;
+    pub fn readstat_get_file_encoding(metadata: *mut readstat_metadata_t) -> *const c_char;
+
+    pub fn readstat_value_type(value: readstat_value_t) -> readstat_type_t;
+    pub fn readstat_value_is_missing(
+        value: readstat_value_t,
+        variable: *mut readstat_variable_t,
+    ) -> c_int;
+    pub fn readstat_value_is_system_missing(value: readstat_value_t) -> c_int;
+    pub fn readstat_double_value(value: readstat_value_t) -> f64;
+    pub fn readstat_string_value(value: readstat_value_t) -> *const c_char;
+
+    pub fn readstat_variable_get_index(variable: *const readstat_variable_t) -> c_int;
+    pub fn readstat_variable_get_name(variable: *const readstat_variable_t) -> *const c_char;
+    pub fn readstat_variable_get_format(variable: *const readstat_variable_t) -> *const c_char;
+    pub fn readstat_variable_get_label(variable: *const readstat_variable_t) -> *const c_char;
+    pub fn readstat_variable_get_type(variable: *const readstat_variable_t) -> readstat_type_t;
+    pub fn readstat_variable_get_storage_width(variable: *const readstat_variable_t) -> usize;
+    pub fn readstat_variable_get_measure(variable: *const readstat_variable_t) -> readstat_measure_t;
+    pub fn readstat_variable_get_display_width(variable: *const readstat_variable_t) -> c_int;
+    pub fn readstat_variable_get_missing_ranges_count(variable: *const readstat_variable_t) -> c_int;
+    pub fn readstat_variable_get_missing_range_lo(
+        variable: *const readstat_variable_t,
+        i: c_int,
+    ) -> readstat_value_t;
+    pub fn readstat_variable_get_missing_range_hi(
+        variable: *const readstat_variable_t,
+        i: c_int,
+    ) -> readstat_value_t;
+
+    /// Stops the parse after this many rows; 0 reads them all.
+    pub fn readstat_set_row_limit(
+        parser: *mut readstat_parser_t,
+        row_limit: std::os::raw::c_long,
+    ) -> readstat_error_t;
 }
@@ -1,14 +1,133 @@
+use std::collections::HashSet;
 use std::ffi::CString;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::os::raw::{c_long, c_void};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_int, c_long, c_void};
+use std::path::Path;
 
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::dates::{self, DateKind};
+use crate::output::OutputThread;
 use crate::readstat_sys::*;
 
 #[derive(Debug, Clone)]
 pub enum ColType {
-    Numeric,
+    /// Display format F`width`.`decimals`.
+    Numeric { width: usize, decimals: usize },
     String(usize),
+    /// Numeric date value shown with an SPSS date format such as `DATE11`.
+    Date(&'static str),
+}
+
+/// SPSS measurement level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Measure {
+    Nominal,
+    Ordinal,
+    Scale,
+}
+
+/// Alignment of a variable's values in Data View.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// Compression of the data records. SPSS before version 21 cannot open `Zlib`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// ZSAV, compressed in zlib blocks; the smallest files.
+    #[default]
+    Zlib,
+    /// SAV with bytecode-compressed rows.
+    Rows,
+    /// SAV with uncompressed rows.
+    None,
+}
+
+/// SAS Transport version. V5 limits names to 8 characters, labels to 40 and strings
+/// to 200 bytes; V8 allows 32 characters, 256 and 32767.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XportVersion {
+    V5,
+    #[default]
+    V8,
+}
+
+impl XportVersion {
+    /// Widest string variable the version holds, in bytes.
+    pub fn max_string_width(self) -> usize {
+        match self {
+            XportVersion::V5 => 200,
+            XportVersion::V8 => 32767,
+        }
+    }
+
+    /// Longest variable name the version holds, in bytes.
+    pub fn max_name_len(self) -> usize {
+        match self {
+            XportVersion::V5 => 8,
+            XportVersion::V8 => 32,
+        }
+    }
+}
+
+/// `names` made legal for SAS Transport `version`: ASCII letters, digits and `_` only,
+/// not starting with a digit, at most [`XportVersion::max_name_len`] bytes, and unique
+/// ignoring case. Names shortened into a clash get a number at the end.
+pub fn sas_names(names: &[String], version: XportVersion) -> Vec<String> {
+    let max = version.max_name_len();
+    let mut taken = HashSet::new();
+    names
+        .iter()
+        .map(|name| {
+            let mut base = String::with_capacity(name.len());
+            for c in name.chars() {
+                let c = if c.is_ascii_alphanumeric() { c } else { '_' };
+                if !(c == '_' && base.ends_with('_')) {
+                    base.push(c);
+                }
+            }
+            while base.len() > 1 && base.ends_with('_') {
+                base.pop();
+            }
+            if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                base.insert(0, '_');
+            }
+            base.truncate(max);
+            let mut candidate = base.clone();
+            for n in 2.. {
+                if taken.insert(candidate.to_uppercase()) {
+                    break;
+                }
+                let suffix = n.to_string();
+                candidate = format!("{}{suffix}", &base[..base.len().min(max - suffix.len())]);
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// File format ReadStat writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Sav(readstat_compress_t),
+    Xport(XportVersion),
+}
+
+/// Key of a value label; must match the variable's type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelValue {
+    Number(f64),
+    Str(String),
 }
 
 #[derive(Debug, Clone)]
@@ -16,16 +135,57 @@ pub struct ColDef {
     pub name: String,
     pub label: String,
     pub col_type: ColType,
+    /// User-missing values of a string variable; SPSS allows at most three.
+    pub missing_strings: Vec<String>,
+    /// User-missing values of a numeric variable; at most three.
+    pub missing_numbers: Vec<f64>,
+    /// Inclusive user-missing range of a numeric variable; it takes the place of two
+    /// missing values.
+    pub missing_range: Option<(f64, f64)>,
+    pub value_labels: Vec<(LabelValue, String)>,
+    /// Overrides the level implied by the type (scale for numbers, nominal for strings).
+    pub measure: Option<Measure>,
+    /// Overrides the alignment implied by the type (right for numbers, left for strings).
+    pub alignment: Option<Alignment>,
+    /// Data View column width in characters; 8 when unset.
+    pub display_width: Option<usize>,
+}
+
+/// File-level dictionary entries that are not tied to a single variable.
+#[derive(Debug, Clone, Default)]
+pub struct FileMeta {
+    /// Document record lines, at most 80 bytes each.
+    pub notes: Vec<String>,
+    /// Index of the numeric variable that weights the cases.
+    pub weight: Option<usize>,
+    /// Named variable sets, each with the indices of its variables.
+    pub variable_sets: Vec<(String, Vec<usize>)>,
+    pub mr_sets: Vec<MrSet>,
+}
+
+/// A multiple response set over variables by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MrSet {
+    /// Starts with `$`.
+    pub name: String,
+    pub label: String,
+    /// The value counted in a multiple dichotomy set; None for a multiple category set.
+    pub counted_value: Option<String>,
+    pub vars: Vec<usize>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Value<'a> {
     Number(Option<f64>),
     Str(&'a str),
 }
 
 struct WriterCtx {
-    output: BufWriter<File>,
+    /// Writes and hashes on its own thread, so no re-read is needed for the checksum.
+    output: OutputThread,
+    /// ZSAV header and dictionary, kept back until the row count is final. ReadStat
+    /// holds a ZSAV's data until the end anyway, so nothing else waits behind them.
+    held: Option<Vec<u8>>,
     error: Option<String>,
 }
 
@@ -34,18 +194,26 @@ unsafe extern "C" fn data_writer_callback(
     len: usize,
     ctx: *mut c_void,
 ) -> isize {
+    // Empty writes may come with a null pointer.
+    if len == 0 {
+        return 0;
+    }
     let wctx = unsafe { &mut *(ctx as *mut WriterCtx) };
     let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
-    match wctx.output.write_all(slice) {
+    if let Some(held) = &mut wctx.held {
+        held.extend_from_slice(slice);
+        return len as isize;
+    }
+    match wctx.output.write(slice) {
         Ok(()) => len as isize,
         Err(e) => {
-            wctx.error = Some(e.to_string());
+            wctx.error = Some(e);
             -1
         }
     }
 }
 
-fn check(err: readstat_error_t) -> Result<(), String> {
+pub fn check(err: readstat_error_t) -> Result<(), String> {
     if err == readstat_error_t::READSTAT_OK {
         return Ok(());
     }
@@ -66,58 +234,144 @@ pub struct Writer {
     writer: *mut readstat_writer_t,
     ctx: *mut WriterCtx,
     var_count: usize,
+    /// Rows announced to ReadStat, and rows written so far.
+    announced: usize,
+    rows: usize,
+    /// XPORT records no row count, so it may end after any row.
+    xport: bool,
+    /// Kinds of an XPORT's date variables, whose values move to SAS's units; empty
+    /// for SAV.
+    sas_dates: Vec<Option<DateKind>>,
     finished: bool,
+    c_buf: Vec<u8>,
+    /// ReadStat keeps pointers to string missing values and writes them with the
+    /// header, at the first row or at the end.
+    _missing_strings: Vec<CString>,
+    /// Likewise kept for ReadStat until the header is written.
+    _variable_sets: CString,
+    _mr_sets: MrSetsC,
+}
+
+/// C views of the multiple response sets and the strings and indices they point to.
+struct MrSetsC {
+    _strings: Vec<CString>,
+    _indices: Vec<Vec<c_int>>,
+    _sets: Vec<readstat_mr_set_write_t>,
+}
+
+/// Frees a ReadStat writer on the early returns of [`init_writer`]; forgotten
+/// once the [`Writer`] takes ownership.
+struct WriterGuard(*mut readstat_writer_t);
+
+impl Drop for WriterGuard {
+    fn drop(&mut self) {
+        unsafe { readstat_writer_free(self.0) };
+    }
 }
 
 fn init_writer(
-    output_file: File,
+    output_file: impl Write + Send + 'static,
     cols: &[ColDef],
-    compression: readstat_compress_t,
+    meta: &FileMeta,
+    format: Format,
     row_count: c_long,
 ) -> Result<Writer, String> {
-    let ctx = Box::into_raw(Box::new(WriterCtx {
-        output: BufWriter::with_capacity(512 * 1024, output_file),
-        error: None,
-    }));
-
+    let binary = format == Format::Sav(readstat_compress_t::READSTAT_COMPRESS_BINARY);
+    let xport = matches!(format, Format::Xport(_));
+    if row_count == UNKNOWN_ROW_COUNT as c_long && !binary && !xport {
+        return Err("An unknown row count needs ZSAV compression".to_string());
+    }
     let writer = unsafe { readstat_writer_init() };
     if writer.is_null() {
-        unsafe { drop(Box::from_raw(ctx)) };
         return Err("Failed to initialize ReadStat writer".to_string());
     }
+    let guard = WriterGuard(writer);
 
     unsafe {
         check(readstat_set_data_writer(writer, Some(data_writer_callback)))?;
-        check(readstat_writer_set_compression(writer, compression))?;
-        if compression == readstat_compress_t::READSTAT_COMPRESS_BINARY {
-            check(readstat_writer_set_file_format_version(writer, 3))?;
+        match format {
+            Format::Sav(compression) => {
+                check(readstat_writer_set_compression(writer, compression))?;
+                if binary {
+                    check(readstat_writer_set_file_format_version(writer, 3))?;
+                }
+            }
+            Format::Xport(XportVersion::V5) => {
+                check(readstat_writer_set_file_format_version(writer, 5))?;
+            }
+            Format::Xport(XportVersion::V8) => {
+                check(readstat_writer_set_file_format_version(writer, 8))?;
+            }
         }
     }
 
-    for col in cols {
+    let mut missing_strings = Vec::new();
+    for (index, col) in cols.iter().enumerate() {
         let c_name = CString::new(col.name.as_str())
             .map_err(|_| format!("Invalid variable name: {}", col.name))?;
 
         let (var_type, width) = match &col.col_type {
-            ColType::Numeric => (readstat_type_t::READSTAT_TYPE_DOUBLE, 0),
+            ColType::Numeric { .. } | ColType::Date(_) => {
+                (readstat_type_t::READSTAT_TYPE_DOUBLE, 0)
+            }
             ColType::String(w) => (readstat_type_t::READSTAT_TYPE_STRING, *w),
         };
 
         let var = unsafe { readstat_add_variable(writer, c_name.as_ptr(), var_type, width) };
         if var.is_null() {
-            unsafe {
-                readstat_writer_free(writer);
-                drop(Box::from_raw(ctx));
-            }
             return Err(format!("Failed to add variable: {}", col.name));
         }
 
         let c_label = CString::new(col.label.as_str()).unwrap_or_default();
         unsafe { readstat_variable_set_label(var, c_label.as_ptr()) };
 
+        for value in &col.missing_strings {
+            let c_value = CString::new(value.as_str()).unwrap_or_default();
+            unsafe { check(readstat_variable_add_missing_string_value(var, c_value.as_ptr()))? };
+            missing_strings.push(c_value);
+        }
+        if let Some((lo, hi)) = col.missing_range {
+            unsafe { check(readstat_variable_add_missing_double_range(var, lo, hi))? };
+        }
+        for &value in &col.missing_numbers {
+            unsafe { check(readstat_variable_add_missing_double_value(var, value))? };
+        }
+
+        if !col.value_labels.is_empty() {
+            let c_set = CString::new(format!("labels{}", index)).unwrap();
+            let label_set = unsafe { readstat_add_label_set(writer, var_type, c_set.as_ptr()) };
+            for (value, label) in &col.value_labels {
+                let c_label = CString::new(label.as_str()).unwrap_or_default();
+                match value {
+                    LabelValue::Number(n) => unsafe {
+                        readstat_label_double_value(label_set, *n, c_label.as_ptr())
+                    },
+                    LabelValue::Str(s) => {
+                        let c_value = CString::new(s.as_str()).unwrap_or_default();
+                        unsafe {
+                            readstat_label_string_value(label_set, c_value.as_ptr(), c_label.as_ptr())
+                        }
+                    }
+                }
+            }
+            unsafe { readstat_variable_set_label_set(var, label_set) };
+        }
+
         match &col.col_type {
-            ColType::Numeric => {
-                let c_fmt = CString::new("F8.2").unwrap();
+            ColType::Numeric { width, decimals } => {
+                let c_fmt = CString::new(format!("F{}.{}", width, decimals)).unwrap();
+                unsafe {
+                    readstat_variable_set_format(var, c_fmt.as_ptr());
+                    readstat_variable_set_measure(var, readstat_measure_t::READSTAT_MEASURE_SCALE);
+                    readstat_variable_set_alignment(
+                        var,
+                        readstat_alignment_t::READSTAT_ALIGNMENT_RIGHT,
+                    );
+                }
+            }
+            ColType::Date(format) => {
+                let format = if xport { dates::sas_format(format) } else { format.to_string() };
+                let c_fmt = CString::new(format).unwrap();
                 unsafe {
                     readstat_variable_set_format(var, c_fmt.as_ptr());
                     readstat_variable_set_measure(var, readstat_measure_t::READSTAT_MEASURE_SCALE);
@@ -128,7 +382,7 @@ fn init_writer(
                 }
             }
             ColType::String(w) => {
-                let fmt = format!("A{}", w);
+                let fmt = if xport { format!("${}", w) } else { format!("A{}", w) };
                 let c_fmt = CString::new(fmt).unwrap();
                 unsafe {
                     readstat_variable_set_format(var, c_fmt.as_ptr());
@@ -143,69 +397,197 @@ fn init_writer(
                 }
             }
         }
+        if let Some(measure) = col.measure {
+            let measure = match measure {
+                Measure::Nominal => readstat_measure_t::READSTAT_MEASURE_NOMINAL,
+                Measure::Ordinal => readstat_measure_t::READSTAT_MEASURE_ORDINAL,
+                Measure::Scale => readstat_measure_t::READSTAT_MEASURE_SCALE,
+            };
+            unsafe { readstat_variable_set_measure(var, measure) };
+        }
+        if let Some(alignment) = col.alignment {
+            let alignment = match alignment {
+                Alignment::Left => readstat_alignment_t::READSTAT_ALIGNMENT_LEFT,
+                Alignment::Center => readstat_alignment_t::READSTAT_ALIGNMENT_CENTER,
+                Alignment::Right => readstat_alignment_t::READSTAT_ALIGNMENT_RIGHT,
+            };
+            unsafe { readstat_variable_set_alignment(var, alignment) };
+        }
+        if let Some(display_width) = col.display_width {
+            let display_width = display_width.min(i32::MAX as usize) as std::os::raw::c_int;
+            unsafe { readstat_variable_set_display_width(var, display_width) };
+        }
     }
 
-    unsafe {
-        check(readstat_begin_writing_sav(writer, ctx as *mut c_void, row_count))?;
+    if let Some(index) = meta.weight {
+        let var = unsafe { readstat_get_variable(writer, index as std::os::raw::c_int) };
+        if var.is_null() {
+            return Err(format!("Weight variable index {index} out of range"));
+        }
+        unsafe { check(readstat_writer_set_fweight_variable(writer, var))? };
+    }
+
+    let mut sets = String::new();
+    for (name, vars) in &meta.variable_sets {
+        let mut names = Vec::with_capacity(vars.len());
+        for &index in vars {
+            let Some(col) = cols.get(index) else {
+                return Err(format!("Variable set '{name}': variable index {index} out of range"));
+            };
+            names.push(col.name.as_str());
+        }
+        sets.push_str(&format!("{name}= {}\n", names.join(" ")));
+    }
+    let variable_sets = CString::new(sets).map_err(|_| "Invalid variable set name".to_string())?;
+    unsafe { check(readstat_writer_set_variable_sets(writer, variable_sets.as_ptr()))? };
+
+    let mut strings = Vec::new();
+    let mut indices = Vec::new();
+    let mut sets = Vec::with_capacity(meta.mr_sets.len());
+    for set in &meta.mr_sets {
+        let invalid = |_| format!("Invalid multiple response set '{}'", set.name);
+        let name = CString::new(set.name.as_str()).map_err(invalid)?;
+        let label = CString::new(set.label.as_str()).map_err(invalid)?;
+        let counted_value =
+            set.counted_value.as_deref().map(CString::new).transpose().map_err(invalid)?;
+        let vars: Vec<c_int> = set.vars.iter().map(|&i| i as c_int).collect();
+        sets.push(readstat_mr_set_write_t {
+            name: name.as_ptr(),
+            label: label.as_ptr(),
+            counted_value: counted_value.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+            var_indices: vars.as_ptr(),
+            var_count: vars.len() as c_int,
+        });
+        strings.extend([name, label].into_iter().chain(counted_value));
+        indices.push(vars);
+    }
+    unsafe { check(readstat_writer_set_mr_sets(writer, sets.as_ptr(), sets.len() as c_long))? };
+    let mr_sets = MrSetsC { _strings: strings, _indices: indices, _sets: sets };
+
+    for note in &meta.notes {
+        let c_note = CString::new(note.as_str()).unwrap_or_default();
+        unsafe { readstat_add_note(writer, c_note.as_ptr()) };
+    }
+
+    let begin_writing = match format {
+        Format::Sav(_) => readstat_begin_writing_sav,
+        Format::Xport(_) => readstat_begin_writing_xport,
+    };
+    // The context owns the output file, so it only exists once nothing but
+    // begin_writing can fail.
+    let ctx = Box::into_raw(Box::new(WriterCtx {
+        output: OutputThread::spawn(output_file),
+        held: binary.then(Vec::new),
+        error: None,
+    }));
+    if let Err(e) = unsafe { check(begin_writing(writer, ctx as *mut c_void, row_count)) } {
+        unsafe { drop(Box::from_raw(ctx)) };
+        return Err(e);
     }
+    std::mem::forget(guard);
+    let sas_dates = if xport {
+        let kind = |col: &ColDef| match &col.col_type {
+            ColType::Date(format) => dates::date_kind(format),
+            _ => None,
+        };
+        cols.iter().map(kind).collect()
+    } else {
+        Vec::new()
+    };
 
     Ok(Writer {
         writer,
         ctx,
         var_count: cols.len(),
+        announced: row_count as usize,
+        rows: 0,
+        xport,
+        sas_dates,
         finished: false,
+        c_buf: Vec::new(),
+        _missing_strings: missing_strings,
+        _variable_sets: variable_sets,
+        _mr_sets: mr_sets,
     })
 }
 
 impl Writer {
-    /// ZSAV with zlib compression. Requires exact row_count upfront.
-    pub fn new_zsav(output_file: File, cols: &[ColDef], row_count: usize) -> Result<Self, String> {
-        init_writer(
-            output_file,
-            cols,
-            readstat_compress_t::READSTAT_COMPRESS_BINARY,
-            row_count as c_long,
-        )
+    /// SAV or ZSAV, as `compression` decides. `row_count` is exact, or for ZSAV
+    /// [`UNKNOWN_ROW_COUNT`].
+    pub fn new(
+        output_file: impl Write + Send + 'static,
+        cols: &[ColDef],
+        meta: &FileMeta,
+        row_count: usize,
+        compression: Compression,
+    ) -> Result<Self, String> {
+        let compression = match compression {
+            Compression::Zlib => readstat_compress_t::READSTAT_COMPRESS_BINARY,
+            Compression::Rows => readstat_compress_t::READSTAT_COMPRESS_ROWS,
+            Compression::None => readstat_compress_t::READSTAT_COMPRESS_NONE,
+        };
+        init_writer(output_file, cols, meta, Format::Sav(compression), row_count as c_long)
     }
 
-    pub fn write_row(&mut self, values: &[Value<'_>]) -> Result<(), String> {
-        if values.len() != self.var_count {
+    /// SAS Transport, for submissions that require XPT. Only names, labels and
+    /// display formats carry over from the dictionary; dates are moved to SAS's
+    /// days or seconds since 1960. The row count need not be known.
+    pub fn xport(
+        output_file: impl Write + Send + 'static,
+        cols: &[ColDef],
+        meta: &FileMeta,
+        version: XportVersion,
+    ) -> Result<Self, String> {
+        let row_count = UNKNOWN_ROW_COUNT as c_long;
+        init_writer(output_file, cols, meta, Format::Xport(version), row_count)
+    }
+
+    /// Starts a row. Call [`Writer::insert`] for every variable, then [`Writer::end_row`];
+    /// values go straight to ReadStat, so no per-row buffer is needed.
+    pub fn begin_row(&mut self) -> Result<(), String> {
+        unsafe { check(readstat_begin_row(self.writer)) }
+    }
+
+    pub fn insert(&mut self, index: usize, value: Value<'_>) -> Result<(), String> {
+        if index >= self.var_count {
             return Err(format!(
-                "Expected {} values, got {}",
-                self.var_count,
-                values.len()
+                "Variable index {} out of range ({} variables)",
+                index, self.var_count
             ));
         }
-
-        unsafe { check(readstat_begin_row(self.writer))? };
-
-        for (i, val) in values.iter().enumerate() {
-            let var = unsafe { readstat_get_variable(self.writer, i as std::os::raw::c_int) };
-            match val {
-                Value::Number(None) => {
-                    unsafe { check(readstat_insert_missing_value(self.writer, var))? };
-                }
-                Value::Number(Some(n)) => {
-                    unsafe { check(readstat_insert_double_value(self.writer, var, *n))? };
+        let var = unsafe { readstat_get_variable(self.writer, index as std::os::raw::c_int) };
+        match value {
+            Value::Number(None) | Value::Str("") => unsafe {
+                check(readstat_insert_missing_value(self.writer, var))
+            },
+            Value::Number(Some(n)) => {
+                let n = match self.sas_dates.get(index) {
+                    Some(&Some(kind)) => dates::sas_value(n, kind),
+                    _ => n,
+                };
+                unsafe { check(readstat_insert_double_value(self.writer, var, n)) }
+            }
+            Value::Str(s) => {
+                // Reused NUL-terminated copy; a value with an interior NUL is written empty.
+                self.c_buf.clear();
+                if !s.as_bytes().contains(&0) {
+                    self.c_buf.extend_from_slice(s.as_bytes());
                 }
-                Value::Str(s) => {
-                    if s.is_empty() {
-                        unsafe { check(readstat_insert_missing_value(self.writer, var))? };
-                    } else {
-                        let c_str = CString::new(*s).unwrap_or_default();
-                        unsafe {
-                            check(readstat_insert_string_value(
-                                self.writer,
-                                var,
-                                c_str.as_ptr(),
-                            ))?
-                        };
-                    }
+                self.c_buf.push(0);
+                unsafe {
+                    check(readstat_insert_string_value(
+                        self.writer,
+                        var,
+                        self.c_buf.as_ptr() as *const std::os::raw::c_char,
+                    ))
                 }
             }
         }
+    }
 
+    pub fn end_row(&mut self) -> Result<(), String> {
         unsafe { check(readstat_end_row(self.writer))? };
+        self.rows += 1;
 
         let wctx = unsafe { &*self.ctx };
         if let Some(ref e) = wctx.error {
@@ -215,19 +597,45 @@ impl Writer {
         Ok(())
     }
 
-    pub fn finish(mut self) -> Result<(), String> {
+    /// Finalizes the file and returns the hex-encoded SHA-256 of everything written.
+    /// A ZSAV may hold fewer rows than announced: its counts are corrected before the
+    /// header reaches the output.
+    pub fn finish(self) -> Result<String, String> {
+        if self.xport {
+            return self.end(readstat_end_writing_early);
+        }
+        let wctx = unsafe { &mut *self.ctx };
+        let Some(mut prefix) = wctx.held.take() else {
+            return self.end(readstat_end_writing);
+        };
+        // Empty without rows: ReadStat then writes the header when ending.
+        if !prefix.is_empty() && self.rows < self.announced {
+            patch_case_count(&mut prefix, self.announced, self.rows)?;
+        }
+        wctx.output.write(&prefix)?;
+        self.end(readstat_end_writing_early)
+    }
+
+    /// Finalizes a SAV after fewer rows than announced. The header still holds the
+    /// announced count; [`correct_case_count`] fixes it once the file is closed.
+    pub fn finish_early(self) -> Result<String, String> {
+        self.end(readstat_end_writing_early)
+    }
+
+    fn end(
+        mut self,
+        end_writing: unsafe extern "C" fn(*mut readstat_writer_t) -> readstat_error_t,
+    ) -> Result<String, String> {
         self.finished = true;
-        unsafe { check(readstat_end_writing(self.writer))? };
+        unsafe { check(end_writing(self.writer))? };
 
         let wctx = unsafe { &mut *self.ctx };
-        wctx.output
-            .flush()
-            .map_err(|e| format!("Failed to flush output: {e}"))?;
-
         if let Some(ref e) = wctx.error {
             return Err(format!("I/O error: {}", e));
         }
-        Ok(())
+        wctx.output
+            .finish()
+            .map_err(|e| format!("Failed to flush output: {e}"))
     }
 }
 
@@ -246,3 +654,499 @@ impl Drop for Writer {
 }
 
 unsafe impl Send for Writer {}
+
+/// ZSAV row count announced when it is not known upfront: the most ReadStat's `int`
+/// count holds, so writing never reaches it. [`Writer::finish`] corrects it.
+pub const UNKNOWN_ROW_COUNT: usize = i32::MAX as usize;
+
+/// Offset of the 32-bit case count in the SAV file header.
+const HEADER_CASES_OFFSET: u64 = 80;
+const CHUNK: usize = 1 << 20;
+
+/// Record type 7, subtype 16, announcing `rows` cases. The 64-bit count is its last
+/// field, and it comes before any data.
+fn case_count_record(rows: usize) -> Vec<u8> {
+    let mut record = Vec::with_capacity(32);
+    for field in [7i32, 16, 8, 2] {
+        record.extend_from_slice(&field.to_ne_bytes());
+    }
+    record.extend_from_slice(&1u64.to_ne_bytes());
+    record.extend_from_slice(&(rows as u64).to_ne_bytes());
+    record
+}
+
+/// The 32-bit case count of the file header; -1, unknown, when `rows` does not fit.
+fn header_case_count(rows: usize) -> [u8; 4] {
+    i32::try_from(rows).unwrap_or(-1).to_ne_bytes()
+}
+
+/// Rewrites the case counts in a held header and dictionary from `announced` to `rows`.
+fn patch_case_count(prefix: &mut [u8], announced: usize, rows: usize) -> Result<(), String> {
+    let record = case_count_record(announced);
+    let position = prefix
+        .windows(record.len())
+        .position(|w| w == record)
+        .ok_or("Number of cases record not found")?
+        + 24;
+    let header = HEADER_CASES_OFFSET as usize;
+    prefix[header..header + 4].copy_from_slice(&header_case_count(rows));
+    prefix[position..position + 8].copy_from_slice(&(rows as u64).to_ne_bytes());
+    Ok(())
+}
+
+/// Rewrites the case counts of a SAV file finished early, from the `announced` rows
+/// to the `rows` actually written, and returns the SHA-256 of the corrected file.
+/// A SAV streams its rows behind the header, so unlike a ZSAV it has to be patched
+/// and hashed again once closed.
+pub fn correct_case_count(path: &Path, announced: usize, rows: usize) -> Result<String, String> {
+    let io_error = |e: std::io::Error| format!("Failed to correct the case count: {e}");
+    let mut file = OpenOptions::new().read(true).write(true).open(path).map_err(io_error)?;
+    file.seek(SeekFrom::Start(HEADER_CASES_OFFSET)).map_err(io_error)?;
+    file.write_all(&header_case_count(rows)).map_err(io_error)?;
+
+    let record = case_count_record(announced);
+    file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    let mut window: Vec<u8> = Vec::with_capacity(CHUNK + record.len());
+    let mut offset = 0u64;
+    let mut buf = vec![0u8; CHUNK];
+    let position = loop {
+        let n = file.read(&mut buf).map_err(io_error)?;
+        if n == 0 {
+            return Err("Number of cases record not found".into());
+        }
+        window.extend_from_slice(&buf[..n]);
+        if let Some(pos) = window.windows(record.len()).position(|w| w == record) {
+            break offset + pos as u64 + 24;
+        }
+        let keep = window.len().min(record.len() - 1);
+        offset += (window.len() - keep) as u64;
+        window.drain(..window.len() - keep);
+    };
+    file.seek(SeekFrom::Start(position)).map_err(io_error)?;
+    file.write_all(&(rows as u64).to_ne_bytes()).map_err(io_error)?;
+
+    file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    loop {
+        let n = file.read(&mut buf).map_err(io_error)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use proptest::prelude::*;
+
+    /// Text without NULs or trailing spaces, which SPSS padding would not preserve.
+    fn text(max_chars: usize) -> impl Strategy<Value = String> {
+        proptest::string::string_regex(&format!("[a-zA-Z0-9 ,\"'é中文]{{0,{max_chars}}}"))
+            .unwrap()
+            .prop_map(|s| s.trim_end().to_string())
+    }
+
+    /// Longest prefix of `s` that fits in `width` bytes.
+    fn fit(s: &str, width: usize) -> &str {
+        let mut end = width.min(s.len());
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s[..end].trim_end()
+    }
+
+    fn measure() -> impl Strategy<Value = Option<Measure>> {
+        prop_oneof![Just(None), Just(Some(Measure::Nominal)), Just(Some(Measure::Ordinal)), Just(Some(Measure::Scale))]
+    }
+
+    fn number() -> impl Strategy<Value = f64> {
+        prop_oneof![(-100_000i64..100_000).prop_map(|n| n as f64), -1e12..1e12f64]
+    }
+
+    fn column() -> impl Strategy<Value = ColDef> {
+        let numeric = (
+            (1usize..=20, 0usize..=6),
+            proptest::collection::vec(number(), 0..=3),
+            proptest::collection::vec((number(), text(20)), 0..4),
+        )
+            .prop_map(|((width, decimals), missing_numbers, labels)| ColDef {
+                name: String::new(),
+                label: String::new(),
+                col_type: ColType::Numeric { width, decimals },
+                missing_strings: Vec::new(),
+                missing_numbers,
+                missing_range: None,
+                value_labels: labels.into_iter().map(|(v, l)| (LabelValue::Number(v), l)).collect(),
+                measure: None,
+                alignment: None,
+                display_width: None,
+            });
+        let date = Just(ColDef {
+            name: String::new(),
+            label: String::new(),
+            col_type: ColType::Date("DATE11"),
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        });
+        let string = (
+            1usize..=300,
+            proptest::collection::vec("[a-z]{1,8}", 0..=3),
+            proptest::collection::vec(("[a-z]{1,8}", text(20)), 0..4),
+        )
+            .prop_map(|(width, missing, labels)| ColDef {
+                name: String::new(),
+                label: String::new(),
+                col_type: ColType::String(width),
+                missing_strings: missing.iter().map(|m| fit(m, width).to_string()).filter(|m| !m.is_empty()).collect(),
+                missing_numbers: Vec::new(),
+                missing_range: None,
+                value_labels: labels
+                    .into_iter()
+                    .map(|(v, l)| (LabelValue::Str(fit(&v, width).to_string()), l))
+                    .filter(|(v, _)| v != &LabelValue::Str(String::new()))
+                    .collect(),
+                measure: None,
+                alignment: None,
+                display_width: None,
+            });
+        (prop_oneof![3 => numeric, 1 => date, 3 => string], "[a-z][a-z0-9_]{0,6}", text(40), measure()).prop_map(
+            |(mut col, name, label, measure)| {
+                col.name = name;
+                col.label = label;
+                col.measure = measure;
+                col
+            },
+        )
+    }
+
+    /// Columns with unique names and up to 20 rows of values for them.
+    fn table() -> impl Strategy<Value = (Vec<ColDef>, Vec<Vec<Option<String>>>)> {
+        proptest::collection::vec(column(), 1..6).prop_flat_map(|mut cols| {
+            for (i, col) in cols.iter_mut().enumerate() {
+                col.name = format!("{}{i}", col.name);
+                // One label per value, so the label read back is unambiguous.
+                let mut seen = Vec::new();
+                col.value_labels.retain(|(value, _)| {
+                    let new = !seen.contains(value);
+                    seen.push(value.clone());
+                    new
+                });
+            }
+            let cell = |col: &ColDef| -> BoxedStrategy<Option<String>> {
+                match col.col_type {
+                    ColType::String(width) => text(60).prop_map(move |s| Some(fit(&s, width).to_string())).boxed(),
+                    _ => proptest::option::of(number().prop_map(|n| n.to_string())).boxed(),
+                }
+            };
+            let row: Vec<_> = cols.iter().map(cell).collect();
+            (Just(cols), proptest::collection::vec(row, 0..20))
+        })
+    }
+
+    fn write(path: &std::path::Path, cols: &[ColDef], rows: &[Vec<Option<String>>]) -> Result<String, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let meta = FileMeta::default();
+        let mut writer = Writer::new(file, cols, &meta, rows.len(), Compression::Zlib)?;
+        for row in rows {
+            writer.begin_row()?;
+            for (i, (cell, col)) in row.iter().zip(cols).enumerate() {
+                let value = match col.col_type {
+                    ColType::String(_) => Value::Str(cell.as_deref().unwrap_or_default()),
+                    _ => Value::Number(cell.as_ref().map(|n| n.parse().unwrap())),
+                };
+                writer.insert(i, value)?;
+            }
+            writer.end_row()?;
+        }
+        writer.finish()
+    }
+
+    static CASE: AtomicUsize = AtomicUsize::new(0);
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_round_trip_through_readstat((cols, rows) in table()) {
+            let path = std::env::temp_dir().join(format!(
+                "csv2sav_writer_roundtrip_{}_{}.zsav",
+                std::process::id(),
+                CASE.fetch_add(1, Ordering::Relaxed)
+            ));
+            write(&path, &cols, &rows).unwrap();
+            let read = crate::compare::read(&path, usize::MAX);
+            std::fs::remove_file(&path).ok();
+            let read = read.unwrap();
+
+            prop_assert_eq!(read.rows, rows.len());
+            prop_assert_eq!(read.variables.len(), cols.len());
+            for (variable, col) in read.variables.iter().zip(&cols) {
+                let (is_string, format, default_measure) = match &col.col_type {
+                    ColType::Numeric { width, decimals } => (false, format!("F{width}.{decimals}"), "scale"),
+                    ColType::Date(format) => (false, format.to_string(), "scale"),
+                    ColType::String(width) => (true, format!("A{width}"), "nominal"),
+                };
+                let measure = match col.measure {
+                    Some(Measure::Nominal) => "nominal",
+                    Some(Measure::Ordinal) => "ordinal",
+                    Some(Measure::Scale) => "scale",
+                    None => default_measure,
+                };
+                let missing: Vec<String> = if is_string {
+                    col.missing_strings.clone()
+                } else {
+                    col.missing_numbers.iter().map(f64::to_string).collect()
+                };
+                let mut labels: Vec<(String, String)> = col
+                    .value_labels
+                    .iter()
+                    .map(|(value, label)| match value {
+                        LabelValue::Number(n) => (n.to_string(), label.clone()),
+                        LabelValue::Str(s) => (s.clone(), label.clone()),
+                    })
+                    .collect();
+                labels.sort();
+                prop_assert_eq!(&variable.name, &col.name);
+                prop_assert_eq!(&variable.label, &col.label);
+                prop_assert_eq!(variable.is_string, is_string);
+                prop_assert_eq!(&variable.format, &format);
+                prop_assert_eq!(variable.measure, measure);
+                prop_assert_eq!(&variable.missing, &missing);
+                prop_assert_eq!(&variable.value_labels, &labels);
+            }
+            let expected: Vec<Vec<String>> =
+                rows.iter().map(|row| row.iter().map(|cell| cell.clone().unwrap_or_default()).collect()).collect();
+            prop_assert_eq!(read.data, expected);
+        }
+    }
+
+    #[test]
+    fn test_encoding_records() {
+        let path = std::env::temp_dir().join("csv2sav_writer_encoding.sav");
+        let col = ColDef {
+            name: "city".to_string(),
+            label: String::new(),
+            col_type: ColType::String(8),
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        };
+        write(&path, &[col], &[vec![Some("Zürich".to_string())]]).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let record = |subtype: i32, size: i32, count: i32| {
+            [7, subtype, size, count].iter().flat_map(|n| n.to_le_bytes()).collect::<Vec<u8>>()
+        };
+        let mut encoding = record(20, 1, 5);
+        encoding.extend_from_slice(b"UTF-8");
+        assert!(data.windows(encoding.len()).any(|w| w == encoding));
+        let integer_info = record(3, 4, 8);
+        let at = data.windows(16).position(|w| w == integer_info).unwrap() + 16;
+        assert_eq!(data[at + 28..at + 32], 65001i32.to_le_bytes());
+        std::fs::remove_file(&path).ok();
+    }
+
+    /// Variables (name, label, format, width) and rows of an XPT file, numbers as
+    /// decoded IBM doubles and strings without their padding.
+    #[allow(clippy::type_complexity)]
+    fn read_xport(data: &[u8]) -> (Vec<(String, String, String, usize)>, Vec<Vec<Value<'_>>>) {
+        let find = |name: &str| {
+            let header = format!("HEADER RECORD*******{name:<8}HEADER RECORD!!!!!!!");
+            data.windows(48).position(|w| w == header.as_bytes()).unwrap()
+        };
+        let text = |bytes: &[u8]| std::str::from_utf8(bytes).unwrap().trim_end().to_string();
+        let short = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        let v8 = data.starts_with(b"HEADER RECORD*******LIBV8 ");
+        let at = find(if v8 { "NAMSTV8" } else { "NAMESTR" });
+        let count: usize = text(&data[at + 53..at + 58]).parse().unwrap();
+        let mut vars = Vec::new();
+        for namestr in data[at + 80..].chunks(140).take(count) {
+            let name = text(if v8 { &namestr[88..120] } else { &namestr[8..16] });
+            let format = format!("{}{}", text(&namestr[56..64]), short(&namestr[64..]));
+            vars.push((name, text(&namestr[16..56]), format, short(&namestr[4..])));
+        }
+        let strings: Vec<bool> =
+            (0..count).map(|i| short(&data[at + 80 + 140 * i..]) == 2).collect();
+        let obs = find(if v8 { "OBSV8" } else { "OBS" }) + 80;
+        let obs_len: usize = vars.iter().map(|v| v.3).sum();
+        let body = &data[obs..];
+        let rows = body.chunks_exact(obs_len).take_while(|r| r.iter().any(|&b| b != b' '));
+        let rows = rows
+            .map(|row| {
+                let mut at = 0;
+                (vars.iter().zip(&strings))
+                    .map(|(var, &string)| {
+                        let bytes = &row[at..at + var.3];
+                        at += var.3;
+                        if string {
+                            return Value::Str(std::str::from_utf8(bytes).unwrap().trim_end());
+                        }
+                        if bytes[0] == b'.' && bytes[1..].iter().all(|&b| b == 0) {
+                            return Value::Number(None);
+                        }
+                        let mut ibm = [0u8; 8];
+                        ibm[..bytes.len()].copy_from_slice(bytes);
+                        let bits = u64::from_be_bytes(ibm);
+                        let fraction = (bits & 0x00FF_FFFF_FFFF_FFFF) as f64 / 2f64.powi(56);
+                        let exponent = ((bits >> 56) & 0x7F) as i32 - 64;
+                        let sign = if bits >> 63 == 1 { -1.0 } else { 1.0 };
+                        Value::Number(Some(sign * fraction * 16f64.powi(exponent)))
+                    })
+                    .collect()
+            })
+            .collect();
+        (vars, rows)
+    }
+
+    #[test]
+    fn test_xport_round_trip() {
+        let col = |name: &str, label: &str, col_type| ColDef {
+            name: name.to_string(),
+            label: label.to_string(),
+            col_type,
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        };
+        let long_label = "Score on the final assessment, out of one hundred points";
+        let cols = [
+            col("score", long_label, ColType::Numeric { width: 8, decimals: 2 }),
+            col("name", "Name", ColType::String(10)),
+            col("visit", "", ColType::Date("ADATE10")),
+        ];
+        let visit = dates::spss_date(2024, 3, 1);
+        let rows = [
+            [Value::Number(Some(1.0)), Value::Str("Ann"), Value::Number(Some(visit))],
+            [Value::Number(Some(-2.5)), Value::Str(""), Value::Number(None)],
+            [Value::Number(Some(123_456.789)), Value::Str("Bob Smith"), Value::Number(Some(0.0))],
+        ];
+
+        for version in [XportVersion::V5, XportVersion::V8] {
+            let path = std::env::temp_dir().join(format!("csv2sav_writer_{version:?}.xpt"));
+            let file = File::create(&path).unwrap();
+            let mut writer = Writer::xport(file, &cols, &FileMeta::default(), version).unwrap();
+            for row in &rows {
+                writer.begin_row().unwrap();
+                for (i, value) in row.iter().enumerate() {
+                    writer.insert(i, *value).unwrap();
+                }
+                writer.end_row().unwrap();
+            }
+            writer.finish().unwrap();
+
+            let data = std::fs::read(&path).unwrap();
+            assert_eq!(data.len() % 80, 0, "{version:?}");
+            let (vars, read) = read_xport(&data);
+            let names: Vec<_> = vars.iter().map(|v| (v.0.as_str(), v.2.as_str(), v.3)).collect();
+            assert_eq!(names, [("score", "F8", 8), ("name", "$10", 10), ("visit", "MMDDYY10", 8)]);
+            assert_eq!(vars[0].1, long_label[..40]);
+            // The long label follows the namestrs in V8 only.
+            let has_long_label = data.windows(long_label.len()).any(|w| w == long_label.as_bytes());
+            assert_eq!(has_long_label, version == XportVersion::V8);
+
+            // 1.0 in IBM floating point.
+            let obs = data.windows(23).position(|w| w == b"HEADER RECORD*******OBS").unwrap();
+            assert_eq!(data[obs + 80..obs + 88], [0x41, 0x10, 0, 0, 0, 0, 0, 0]);
+
+            assert_eq!(read.len(), 3);
+            for (got, want) in read.iter().zip(&rows) {
+                let mut want = *want;
+                // Dates are read back as days since 1960.
+                if let Value::Number(Some(n)) = want[2] {
+                    want[2] = Value::Number(Some(dates::sas_value(n, DateKind::Date)));
+                }
+                assert_eq!(format!("{got:?}"), format!("{want:?}"), "{version:?}");
+            }
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    #[test]
+    fn test_sas_names() {
+        let names: Vec<String> = ["Q1_1", "Duration (in seconds)", "Q2.a", "2nd", "Größe", "q1_1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            sas_names(&names, XportVersion::V8),
+            ["Q1_1", "Duration_in_seconds", "Q2_a", "_2nd", "Gr_e", "q1_12"]
+        );
+        let names = ["participant_id", "participant_name", "age"].map(String::from);
+        assert_eq!(sas_names(&names, XportVersion::V5), ["particip", "partici2", "age"]);
+    }
+
+    #[test]
+    fn test_xport_v5_limits() {
+        let col = ColDef {
+            name: "participant_id".to_string(),
+            label: String::new(),
+            col_type: ColType::Numeric { width: 8, decimals: 0 },
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        };
+        let meta = FileMeta::default();
+        let v5 = Writer::xport(Vec::new(), std::slice::from_ref(&col), &meta, XportVersion::V5);
+        assert!(v5.is_err());
+        assert!(Writer::xport(Vec::new(), &[col], &meta, XportVersion::V8).is_ok());
+    }
+
+    #[test]
+    fn test_failed_init_releases_output() {
+        struct Output(Arc<AtomicBool>);
+        impl Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl Drop for Output {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let col = ColDef {
+            name: "age".to_string(),
+            label: String::new(),
+            col_type: ColType::Numeric { width: 8, decimals: 0 },
+            missing_strings: Vec::new(),
+            missing_numbers: Vec::new(),
+            missing_range: None,
+            value_labels: Vec::new(),
+            measure: None,
+            alignment: None,
+            display_width: None,
+        };
+        let meta = FileMeta { weight: Some(3), ..FileMeta::default() };
+        let closed = Arc::new(AtomicBool::new(false));
+        let output = Output(closed.clone());
+        assert!(Writer::new(output, &[col], &meta, 1, Compression::Zlib).is_err());
+        assert!(closed.load(Ordering::SeqCst));
+    }
+}
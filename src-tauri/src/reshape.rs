@@ -0,0 +1,320 @@
+use std::collections::{HashSet, VecDeque};
+
+use csv::{ByteRecord, Position};
+
+use crate::options::{Reshape, ToLong, ToWide};
+
+/// Turns input records into reshaped ones. Built once from the headers; conversion
+/// works on a clone, since long-to-wide keeps the current id's row between records.
+#[derive(Debug, Clone)]
+pub struct Reshaper {
+    headers: Vec<String>,
+    /// Input column of every output column; None for the long index column.
+    sources: Vec<Option<usize>>,
+    plan: Plan,
+    /// Rows left out because their index value is not a level.
+    unknown_levels: usize,
+    /// Rows repeating a level already seen for their id; the later one wins.
+    duplicates: usize,
+    /// Ids whose rows were not adjacent and so became more than one row.
+    split_ids: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Plan {
+    Long {
+        levels: Vec<String>,
+        /// Columns copied into every row.
+        keep: Vec<usize>,
+        /// Input column per stub and level.
+        stubs: Vec<Vec<usize>>,
+    },
+    Wide {
+        id: usize,
+        index: usize,
+        levels: Vec<String>,
+        /// Columns taken from an id's first row, the id among them.
+        constant: Vec<usize>,
+        varying: Vec<usize>,
+        current: Option<WideRow>,
+        finished: HashSet<Vec<u8>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct WideRow {
+    id: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+    filled: Vec<bool>,
+    position: Option<Position>,
+}
+
+impl Reshaper {
+    pub fn new(spec: &Reshape, headers: &[String]) -> Result<Self, String> {
+        let find = |header: &str| {
+            headers
+                .iter()
+                .position(|h| h == header)
+                .ok_or_else(|| format!("Reshape: column '{header}' not found"))
+        };
+        let (out, sources, plan) = match spec {
+            Reshape::ToLong(ToLong { stubs, levels, separator, index }) => {
+                if stubs.is_empty() || levels.is_empty() {
+                    return Err("Reshape: to_long needs stubs and levels".to_string());
+                }
+                let stubs = stubs
+                    .iter()
+                    .map(|stub| {
+                        let columns = levels
+                            .iter()
+                            .map(|level| find(&format!("{stub}{separator}{level}")))
+                            .collect::<Result<Vec<usize>, String>>()?;
+                        Ok((stub.clone(), columns))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                let melted: HashSet<usize> = stubs.iter().flat_map(|(_, c)| c.iter().copied()).collect();
+                let keep: Vec<usize> = (0..headers.len()).filter(|i| !melted.contains(i)).collect();
+                let mut out: Vec<String> = keep.iter().map(|&i| headers[i].clone()).collect();
+                let mut sources: Vec<Option<usize>> = keep.iter().map(|&i| Some(i)).collect();
+                out.push(index.clone());
+                sources.push(None);
+                for (stub, columns) in &stubs {
+                    out.push(stub.clone());
+                    sources.push(Some(columns[0]));
+                }
+                let plan = Plan::Long {
+                    levels: levels.clone(),
+                    keep,
+                    stubs: stubs.into_iter().map(|(_, c)| c).collect(),
+                };
+                (out, sources, plan)
+            }
+            Reshape::ToWide(ToWide { id, index, levels, variables, separator }) => {
+                if levels.is_empty() || variables.is_empty() {
+                    return Err("Reshape: to_wide needs levels and variables".to_string());
+                }
+                let (id, index) = (find(id)?, find(index)?);
+                let varying = variables
+                    .iter()
+                    .map(|v| find(v))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                let constant: Vec<usize> = (0..headers.len())
+                    .filter(|i| *i != index && !varying.contains(i))
+                    .collect();
+                let mut out: Vec<String> = constant.iter().map(|&i| headers[i].clone()).collect();
+                let mut sources: Vec<Option<usize>> = constant.iter().map(|&i| Some(i)).collect();
+                for &v in &varying {
+                    for level in levels {
+                        out.push(format!("{}{separator}{level}", headers[v]));
+                        sources.push(Some(v));
+                    }
+                }
+                let plan = Plan::Wide {
+                    id,
+                    index,
+                    levels: levels.clone(),
+                    constant,
+                    varying,
+                    current: None,
+                    finished: HashSet::new(),
+                };
+                (out, sources, plan)
+            }
+        };
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = out.iter().find(|h| !seen.insert(h.as_str())) {
+            return Err(format!("Reshape: column '{duplicate}' would appear twice"));
+        }
+        Ok(Self {
+            headers: out,
+            sources,
+            plan,
+            unknown_levels: 0,
+            duplicates: 0,
+            split_ids: 0,
+        })
+    }
+
+    /// Headers of the reshaped rows.
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Per-column metadata such as Qualtrics labels, moved to the reshaped columns.
+    pub fn remap<T: Clone + Default>(&self, values: &[T]) -> Vec<T> {
+        self.sources
+            .iter()
+            .map(|s| s.and_then(|i| values.get(i).cloned()).unwrap_or_default())
+            .collect()
+    }
+
+    /// Reshaped row count for `input_rows` records, when it follows from that alone.
+    pub fn rows(&self, input_rows: usize) -> Option<usize> {
+        match &self.plan {
+            Plan::Long { levels, .. } => Some(input_rows * levels.len()),
+            Plan::Wide { .. } => None,
+        }
+    }
+
+    /// Feeds one input record; the rows it completes are appended to `out`.
+    pub fn push(&mut self, record: &ByteRecord, out: &mut VecDeque<ByteRecord>) {
+        let field = |i: usize| record.get(i).unwrap_or_default();
+        match &mut self.plan {
+            Plan::Long { levels, keep, stubs } => {
+                for (l, level) in levels.iter().enumerate() {
+                    let mut row = ByteRecord::with_capacity(record.as_slice().len(), self.headers.len());
+                    for &i in keep.iter() {
+                        row.push_field(field(i));
+                    }
+                    row.push_field(level.as_bytes());
+                    for columns in stubs.iter() {
+                        row.push_field(field(columns[l]));
+                    }
+                    row.set_position(record.position().cloned());
+                    out.push_back(row);
+                }
+            }
+            Plan::Wide { id, index, levels, constant, varying, current, finished } => {
+                let key = field(*id).trim_ascii();
+                if current.as_ref().is_none_or(|row| row.id != key) {
+                    if let Some(row) = current.take() {
+                        finished.insert(row.id.clone());
+                        out.push_back(row.into_record());
+                    }
+                    if finished.contains(key) {
+                        self.split_ids += 1;
+                    }
+                    let mut fields: Vec<Vec<u8>> = constant.iter().map(|&i| field(i).to_vec()).collect();
+                    fields.resize(self.headers.len(), Vec::new());
+                    *current = Some(WideRow {
+                        id: key.to_vec(),
+                        fields,
+                        filled: vec![false; levels.len()],
+                        position: record.position().cloned(),
+                    });
+                }
+                let Some(row) = current.as_mut() else {
+                    return;
+                };
+                let level = String::from_utf8_lossy(field(*index).trim_ascii());
+                let Some(l) = levels.iter().position(|v| *v == level) else {
+                    self.unknown_levels += 1;
+                    return;
+                };
+                if std::mem::replace(&mut row.filled[l], true) {
+                    self.duplicates += 1;
+                }
+                for (v, &column) in varying.iter().enumerate() {
+                    row.fields[constant.len() + v * levels.len() + l] = field(column).to_vec();
+                }
+            }
+        }
+    }
+
+    /// Emits the row still being collected at the end of the input.
+    pub fn finish(&mut self, out: &mut VecDeque<ByteRecord>) {
+        if let Plan::Wide { current, finished, .. } = &mut self.plan {
+            if let Some(row) = current.take() {
+                finished.insert(row.id.clone());
+                out.push_back(row.into_record());
+            }
+        }
+    }
+
+    /// Problems met while reshaping, for the conversion result.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.unknown_levels > 0 {
+            warnings.push(format!(
+                "Reshape: {} row(s) left out because their index value is not a listed level",
+                self.unknown_levels
+            ));
+        }
+        if self.duplicates > 0 {
+            warnings.push(format!(
+                "Reshape: {} row(s) repeat a level of their id; the last one was kept",
+                self.duplicates
+            ));
+        }
+        if self.split_ids > 0 {
+            warnings.push(format!(
+                "Reshape: the rows of {} id(s) are not adjacent, so each became more than one row",
+                self.split_ids
+            ));
+        }
+        warnings
+    }
+}
+
+impl WideRow {
+    fn into_record(self) -> ByteRecord {
+        let mut record = ByteRecord::from(self.fields);
+        record.set_position(self.position);
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(reshaper: &mut Reshaper, rows: &[&[&str]]) -> Vec<ByteRecord> {
+        let mut out = VecDeque::new();
+        for row in rows {
+            reshaper.push(&ByteRecord::from(row.to_vec()), &mut out);
+        }
+        reshaper.finish(&mut out);
+        out.into()
+    }
+
+    fn headers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|h| h.to_string()).collect()
+    }
+
+    #[test]
+    fn test_to_long_and_to_wide() {
+        let spec = Reshape::ToLong(ToLong {
+            stubs: vec!["Q1".to_string()],
+            levels: vec!["T1".to_string(), "T2".to_string()],
+            ..Default::default()
+        });
+        let mut long = Reshaper::new(&spec, &headers(&["id", "Q1_T1", "Q1_T2", "sex"])).unwrap();
+        assert_eq!(long.headers(), ["id", "sex", "time", "Q1"]);
+        assert_eq!(long.remap(&["a", "b", "c", "d"]), ["a", "d", "", "b"]);
+        assert_eq!(long.rows(3), Some(6));
+        assert_eq!(
+            run(&mut long, &[&["1", "4", "5", "m"]]),
+            [ByteRecord::from(vec!["1", "m", "T1", "4"]), ByteRecord::from(vec!["1", "m", "T2", "5"])]
+        );
+
+        let spec = Reshape::ToWide(ToWide {
+            id: "id".to_string(),
+            index: "wave".to_string(),
+            levels: vec!["1".to_string(), "2".to_string()],
+            variables: vec!["score".to_string()],
+            ..Default::default()
+        });
+        let mut wide = Reshaper::new(&spec, &headers(&["id", "wave", "score", "sex"])).unwrap();
+        assert_eq!(wide.headers(), ["id", "sex", "score_1", "score_2"]);
+        let rows = run(
+            &mut wide,
+            &[&["1", "1", "10", "m"], &["1", "2", "12", "m"], &["2", "2", "7", "f"], &["2", "3", "8", "f"], &["1", "1", "11", "m"]],
+        );
+        assert_eq!(
+            rows,
+            [
+                ByteRecord::from(vec!["1", "m", "10", "12"]),
+                ByteRecord::from(vec!["2", "f", "", "7"]),
+                ByteRecord::from(vec!["1", "m", "11", ""]),
+            ]
+        );
+        assert_eq!(wide.warnings().len(), 2);
+
+        let missing = Reshape::ToLong(ToLong {
+            stubs: vec!["Q2".to_string()],
+            levels: vec!["T1".to_string()],
+            ..Default::default()
+        });
+        assert!(Reshaper::new(&missing, &headers(&["Q1_T1"])).unwrap_err().contains("Q2_T1"));
+    }
+}
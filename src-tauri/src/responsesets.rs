@@ -0,0 +1,178 @@
+use crate::schema::ColType;
+
+/// Fewest columns a family needs to read as one checkbox question.
+const MIN_COLUMNS: usize = 2;
+
+/// What a ticked cell of a family holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Counted {
+    /// 1, next to 0 or blank for unticked.
+    One,
+    /// The same text in every column, such as "Selected".
+    Text(String),
+    /// A different text per column, usually the option's name; the texts are counted
+    /// as categories.
+    Categories,
+}
+
+/// Columns such as `Q5_1`..`Q5_12` whose cells are each blank or a tick, so they
+/// read as the options of one checkbox question.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSet {
+    /// Header part before `_1`, `_2`, …
+    pub stem: String,
+    pub columns: Vec<usize>,
+    pub counted: Counted,
+}
+
+impl ResponseSet {
+    /// Warning offering to register the set.
+    pub fn offer(&self, headers: &[String]) -> String {
+        format!(
+            "Columns '{}' to '{}' look like the options of one checkbox question; set register_response_sets to write them as a multiple response set",
+            headers[self.columns[0]],
+            headers[self.columns[self.columns.len() - 1]]
+        )
+    }
+
+    /// Value counted by a multiple dichotomy set; None for a multiple category set.
+    pub fn counted_value(&self) -> Option<&str> {
+        match &self.counted {
+            Counted::One => Some("1"),
+            Counted::Text(text) => Some(text),
+            Counted::Categories => None,
+        }
+    }
+}
+
+/// What one column's cells say about it being a checkbox option.
+#[derive(Debug, Clone, PartialEq)]
+enum Tick {
+    /// Never filled in the sample; fits a numeric family.
+    Blank,
+    Number,
+    Text(String),
+}
+
+fn tick(col_type: &ColType, samples: &[String]) -> Option<Tick> {
+    let is_tick = |s: &String| s.parse::<f64>().is_ok_and(|n| n == 0.0 || n == 1.0);
+    match col_type {
+        ColType::Numeric { .. } if samples.is_empty() => Some(Tick::Blank),
+        ColType::Numeric { .. } if samples.iter().all(is_tick) => Some(Tick::Number),
+        ColType::String(_) if samples.len() == 1 => Some(Tick::Text(samples[0].clone())),
+        ColType::Checkbox | ColType::Selected { .. } => Some(Tick::Number),
+        _ => None,
+    }
+}
+
+/// `Q5_12` split into `Q5` and 12.
+fn split_header(header: &str) -> Option<(&str, u32)> {
+    let (stem, number) = header.trim().rsplit_once(['_', '.'])?;
+    let number = number.parse().ok()?;
+    (!stem.is_empty()).then_some((stem, number))
+}
+
+/// Families of at least two numbered columns whose cells are all ticks of one kind,
+/// in the order of their first column.
+pub fn detect(
+    headers: &[String],
+    col_types: &[ColType],
+    samples: &[Vec<String>],
+) -> Vec<ResponseSet> {
+    let mut families: Vec<(&str, Vec<(u32, usize)>)> = Vec::new();
+    for (i, header) in headers.iter().enumerate() {
+        let Some((stem, number)) = split_header(header) else {
+            continue;
+        };
+        match families.iter_mut().find(|(s, _)| *s == stem) {
+            Some((_, members)) => members.push((number, i)),
+            None => families.push((stem, vec![(number, i)])),
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (stem, mut members) in families {
+        members.sort_unstable();
+        members.dedup_by_key(|(number, _)| *number);
+        let ticks: Option<Vec<(usize, Tick)>> = members
+            .iter()
+            .map(|&(_, i)| tick(&col_types[i], &samples[i]).map(|t| (i, t)))
+            .collect();
+        let Some(mut ticks) = ticks else {
+            continue;
+        };
+        let texts: Vec<&String> = ticks
+            .iter()
+            .filter_map(|(_, t)| match t {
+                Tick::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect();
+        let counted = if texts.is_empty() {
+            Counted::One
+        } else if ticks.iter().any(|(_, t)| *t == Tick::Number) {
+            continue;
+        } else if texts.iter().all(|t| *t == texts[0]) {
+            Counted::Text(texts[0].clone())
+        } else {
+            Counted::Categories
+        };
+        // Text options never ticked in the sample are numeric, so they stay out.
+        if counted != Counted::One {
+            ticks.retain(|(_, t)| *t != Tick::Blank);
+        }
+        if ticks.len() < MIN_COLUMNS || ticks.iter().all(|(_, t)| *t == Tick::Blank) {
+            continue;
+        }
+        let columns = ticks.into_iter().map(|(i, _)| i).collect();
+        sets.push(ResponseSet { stem: stem.to_string(), columns, counted });
+    }
+    sets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_checkbox_families() {
+        let headers: Vec<String> =
+            ["id", "Q5_2", "Q5_1", "Q5_3", "Q6_1", "Q6_2", "Q7_1", "Q7_2", "Q8_1"]
+                .map(String::from)
+                .to_vec();
+        let number = ColType::Numeric { width: 8, decimals: 0 };
+        let text = ColType::String(10);
+        let col_types = [
+            number.clone(),
+            number.clone(),
+            number.clone(),
+            number.clone(),
+            text.clone(),
+            text.clone(),
+            text.clone(),
+            number.clone(),
+            number,
+        ];
+        let samples: Vec<Vec<String>> = [
+            vec!["1", "2", "3"],
+            vec!["1"],
+            vec!["0", "1"],
+            vec![],
+            vec!["Apples"],
+            vec!["Pears"],
+            vec!["Selected"],
+            vec![],
+            vec!["1"],
+        ]
+        .into_iter()
+        .map(|s| s.into_iter().map(String::from).collect())
+        .collect();
+
+        let sets = detect(&headers, &col_types, &samples);
+        assert_eq!(sets.len(), 2);
+        assert_eq!((sets[0].stem.as_str(), &sets[0].columns), ("Q5", &vec![2, 1, 3]));
+        assert_eq!(sets[0].counted_value(), Some("1"));
+        assert_eq!((sets[1].columns.clone(), sets[1].counted_value()), (vec![4, 5], None));
+        assert!(sets[0].offer(&headers).starts_with("Columns 'Q5_1' to 'Q5_3'"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::cell::Cell;
+use std::io::{self, ErrorKind, Read};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// Delay before the first retry; doubled after each further attempt.
+    pub backoff: Duration,
+}
+
+/// Errors worth retrying: interruptions, timeouts, and dropped network shares.
+fn is_transient(e: &io::Error) -> bool {
+    if matches!(
+        e.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+    ) {
+        return true;
+    }
+    // Windows: ERROR_UNEXP_NET_ERR, ERROR_NETNAME_DELETED, ERROR_SEM_TIMEOUT.
+    cfg!(windows) && matches!(e.raw_os_error(), Some(59 | 64 | 121))
+}
+
+/// Retries transient read failures with exponential backoff and counts
+/// the reads that only succeeded after retrying.
+pub struct RetryReader<R> {
+    inner: R,
+    policy: RetryPolicy,
+    recovered: Rc<Cell<usize>>,
+}
+
+impl<R: Read> RetryReader<R> {
+    pub fn new(inner: R, policy: RetryPolicy) -> (Self, Rc<Cell<usize>>) {
+        let recovered = Rc::new(Cell::new(0usize));
+        let reader = Self {
+            inner,
+            policy,
+            recovered: recovered.clone(),
+        };
+        (reader, recovered)
+    }
+}
+
+impl<R: Read> Read for RetryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempt = 0u32;
+        loop {
+            match self.inner.read(buf) {
+                Ok(n) => {
+                    if attempt > 0 {
+                        self.recovered.set(self.recovered.get() + 1);
+                    }
+                    return Ok(n);
+                }
+                Err(e) if attempt < self.policy.retries && is_transient(&e) => {
+                    thread::sleep(self.policy.backoff * 2u32.saturating_pow(attempt));
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Warning text for a non-zero recovered count.
+pub fn recovered_warning(recovered: usize) -> Option<String> {
+    (recovered > 0).then(|| format!("Recovered from {recovered} transient read error(s) by retrying"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Flaky {
+        failures: u32,
+        data: &'static [u8],
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::new(ErrorKind::TimedOut, "share went away"));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_retries_until_success_or_budget() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let (mut reader, recovered) = RetryReader::new(Flaky { failures: 2, data: b"ok" }, policy);
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "ok");
+        assert_eq!(recovered.get(), 1);
+
+        let (mut reader, _) = RetryReader::new(Flaky { failures: 3, data: b"ok" }, policy);
+        assert!(reader.read_to_string(&mut out).is_err());
+    }
+}
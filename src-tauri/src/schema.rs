@@ -1,25 +1,156 @@
+use std::collections::{HashMap, VecDeque};
 use std::fs::{self, File};
-use std::io::BufReader;
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::dates::{MonthNames, TimestampColumn};
+use crate::googleforms::{self, MultiSelect};
+use crate::input;
+use crate::options::{ColumnOptions, ConvertOptions, ForcedType, InvalidUtf8, PeriodFormat};
+use crate::pairs::{LabelPair, PairTracker};
+use crate::pii::{self, PiiKind, PiiTally};
+use crate::qualtrics::{self, QualtricsHeader};
+use crate::reshape::Reshaper;
+use crate::responsesets::{self, Counted, ResponseSet};
+use crate::script::RowScript;
+use crate::retry::{self, RetryReader};
+use crate::surveymonkey::SurveyMonkeyHeader;
 
 const BUF_SIZE: usize = 256 * 1024;
+/// Error sentinel for a CSV wider than `max_columns` when splitting is off.
+pub const TOO_MANY_COLUMNS: &str = "TooManyColumns";
 /// SPSS Very Long String max: 32767 bytes per logical variable.
 pub const MAX_STRING_WIDTH: usize = 32767;
 /// Fixed declared width for all non-numeric string columns.
 const STRING_DECLARED_WIDTH: usize = 3000;
+/// Distinct non-empty values kept per column for previews.
+const SAMPLE_VALUES: usize = 5;
+/// SPSS limits for F formats: total width 1..=40, at most 16 decimals.
+const MAX_NUMERIC_WIDTH: usize = 40;
+const MAX_NUMERIC_DECIMALS: usize = 16;
+/// Integers with more significant digits than this do not survive a round trip through f64.
+const MAX_EXACT_INT_DIGITS: usize = 15;
+/// Format for numeric columns without any observed value.
+const DEFAULT_NUMERIC_FORMAT: (usize, usize) = (8, 2);
+/// Values that are not numbers kept per column, as examples for the warning when a
+/// mostly numeric column stays numeric.
+const NON_NUMBER_EXAMPLES: usize = 3;
 
 #[derive(Debug, Clone)]
 pub enum ColType {
-    Numeric,
+    /// Display format F`width`.`decimals`.
+    Numeric { width: usize, decimals: usize },
     /// Width in bytes (1..=32767).
     String(usize),
+    /// Month-name dates, stored as SPSS date values.
+    Date,
+    /// Dates written in digits, such as `2024-03-01` or `01/03/2024`, read day first
+    /// or month first; stored as SPSS date values.
+    NumericDate { day_first: bool },
+    /// Quarter, month or week of a year, chosen per column.
+    Period(PeriodFormat),
+    /// A SurveyMonkey checkbox option: 1 when ticked, missing otherwise.
+    Checkbox,
+    /// A date with a time of day, such as `2024-03-01T10:15:30Z` or a Google Forms
+    /// timestamp, read day first or month first.
+    Timestamp { day_first: bool },
+    /// 1 when the checkbox answer in column `source` includes `option`, 0 when it
+    /// does not, missing when it is blank. Not a CSV field: added after all of them.
+    Dummy { source: usize, option: String },
+    /// A checkbox option collapsed from text: 1 when the cell holds any text, 0 when
+    /// it is blank; `option` is the text seen.
+    Selected { option: String },
 }
 
 #[derive(Debug, Clone)]
 pub struct ColInfo {
     is_numeric: bool,
     max_byte_len: usize,
+    /// Widest integer part and longest fraction seen among numeric values.
+    int_digits: usize,
+    decimals: usize,
+    has_negative: bool,
+    has_number: bool,
+    /// Non-empty values that parsed as numbers and that did not, and a few of the latter.
+    numbers: usize,
+    non_numbers: usize,
+    non_number_examples: Vec<String>,
+    /// An integer value was seen with more than MAX_EXACT_INT_DIGITS significant digits.
+    has_long_integer: bool,
+    /// Every non-empty value so far parsed as a month-name date, and at least one did.
+    is_date: bool,
+    has_date: bool,
+    /// Orders in which every value parses as a date written in digits.
+    digit_dates: TimestampColumn,
+    samples: Vec<String>,
+    pii: PiiTally,
+}
+
+/// `col_type` shown with `decimals` places if it is numeric; the integer part keeps
+/// its width.
+fn pin_decimals(col_type: ColType, decimals: usize) -> ColType {
+    match col_type {
+        ColType::Numeric { width, decimals: inferred } => {
+            let int_width = width.saturating_sub(inferred + usize::from(inferred > 0)).max(1);
+            let decimals = decimals.min(MAX_NUMERIC_DECIMALS);
+            let width = int_width + decimals + usize::from(decimals > 0);
+            ColType::Numeric { width: width.min(MAX_NUMERIC_WIDTH), decimals }
+        }
+        other => other,
+    }
+}
+
+/// The type of a column from everything observed in it, after the numeric share
+/// threshold, long-integer preservation and pinned decimals.
+fn settle_type(
+    info: &mut ColInfo,
+    header: &str,
+    options: &ConvertOptions,
+    warnings: &mut Vec<String>,
+) -> ColType {
+    let column = options.columns.get(header);
+    let forced = column.and_then(|c| c.col_type);
+    if forced.is_none() {
+        warnings.extend(info.accept_mostly_numeric(header, options.numeric_threshold));
+        if options.preserve_long_integers && info.preserve_long_integers() {
+            warnings.push(format!(
+                "Column '{header}' has integers longer than {MAX_EXACT_INT_DIGITS} digits; kept as string to avoid precision loss"
+            ));
+        }
+    }
+    let col_type = match forced {
+        Some(ForcedType::String) => info.string_type(),
+        Some(ForcedType::Numeric) => {
+            let (width, decimals) = info.numeric_format();
+            ColType::Numeric { width, decimals }
+        }
+        None => info.col_type(),
+    };
+    let col_type = match column.and_then(|c| c.decimals) {
+        Some(decimals) if matches!(col_type, ColType::Numeric { .. }) => {
+            pin_decimals(col_type, decimals)
+        }
+        Some(_) => {
+            warnings.push(format!("Decimals for '{header}' ignored: the column is not numeric"));
+            col_type
+        }
+        None => col_type,
+    };
+    match (col_type, column.and_then(|c| c.width)) {
+        (ColType::String(_), Some(width)) => ColType::String(width.clamp(1, MAX_STRING_WIDTH)),
+        (ColType::Numeric { decimals, .. }, Some(width)) => {
+            let least = decimals + usize::from(decimals > 0) + 1;
+            ColType::Numeric { width: width.clamp(least, MAX_NUMERIC_WIDTH), decimals }
+        }
+        (col_type, Some(_)) => {
+            warnings.push(format!("Width for '{header}' ignored: the column is not a number or string"));
+            col_type
+        }
+        (col_type, None) => col_type,
+    }
 }
 
 impl ColInfo {
@@ -27,35 +158,187 @@ impl ColInfo {
         Self {
             is_numeric: true,
             max_byte_len: 0,
+            int_digits: 0,
+            decimals: 0,
+            has_negative: false,
+            has_number: false,
+            numbers: 0,
+            non_numbers: 0,
+            non_number_examples: Vec::new(),
+            has_long_integer: false,
+            is_date: true,
+            has_date: false,
+            digit_dates: TimestampColumn::default(),
+            samples: Vec::new(),
+            pii: PiiTally::default(),
         }
     }
 
-    pub fn observe(&mut self, value: &str) {
+    pub fn observe(&mut self, value: &str, options: &ConvertOptions) {
         let trimmed = value.trim();
         if trimmed.is_empty() {
             return;
         }
-        if self.is_numeric && trimmed.parse::<f64>().is_err() {
-            self.is_numeric = false;
+        // Below a threshold of 1 a column may still turn out mostly numeric, so the
+        // numbers after the first non-number are tracked too.
+        if self.is_numeric || options.numeric_threshold < 1.0 {
+            let number = options
+                .numeric_text(trimmed)
+                .and_then(|text| text.parse::<f64>().ok().map(|n| (text, n)));
+            match number {
+                Some((text, n)) => {
+                    self.numbers += 1;
+                    self.observe_number(&text, n);
+                }
+                None => {
+                    self.is_numeric = false;
+                    self.non_numbers += 1;
+                    if self.non_number_examples.len() < NON_NUMBER_EXAMPLES {
+                        self.non_number_examples.push(trimmed.to_string());
+                    }
+                }
+            }
+        }
+        self.pii.observe(trimmed);
+        self.observe_text(trimmed);
+    }
+
+    /// Tracks whether the column holds only month-name dates, or only dates and
+    /// timestamps written in digits.
+    pub fn observe_date(&mut self, value: &str, months: &MonthNames) {
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            return;
         }
+        if self.is_date {
+            if months.parse(trimmed).is_some() {
+                self.has_date = true;
+            } else {
+                self.is_date = false;
+            }
+        }
+        self.digit_dates.observe(trimmed);
+    }
+
+    fn is_date_column(&self) -> bool {
+        !self.is_numeric && self.is_date && self.has_date
+    }
+
+    /// Records a recognized missing marker ("NA", "NULL", …): it counts toward a string
+    /// column's width but never turns a numeric column into a string.
+    pub fn observe_missing(&mut self, value: &str) {
+        self.observe_text(value.trim());
+    }
+
+    fn observe_text(&mut self, trimmed: &str) {
         let byte_len = trimmed.len();
         if byte_len > self.max_byte_len {
             self.max_byte_len = byte_len;
         }
+        if self.samples.len() < SAMPLE_VALUES && !self.samples.iter().any(|s| s == trimmed) {
+            self.samples.push(trimmed.to_string());
+        }
+    }
+
+    fn observe_number(&mut self, text: &str, value: f64) {
+        self.has_number = true;
+        if value.is_sign_negative() && value != 0.0 {
+            self.has_negative = true;
+        }
+        // Plain decimal text keeps its written precision ("1.50" → 2 decimals);
+        // exponent notation and the like fall back to the value's shortest form.
+        let digits = text.trim_start_matches(['+', '-']);
+        if digits.bytes().all(|b| b.is_ascii_digit())
+            && digits.trim_start_matches('0').len() > MAX_EXACT_INT_DIGITS
+        {
+            self.has_long_integer = true;
+        }
+        let plain = if digits.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+            digits.to_string()
+        } else if value.is_finite() {
+            format!("{}", value.abs())
+        } else {
+            return;
+        };
+        let (int_part, frac_part) = plain.split_once('.').unwrap_or((&plain, ""));
+        self.int_digits = self.int_digits.max(int_part.len().max(1));
+        self.decimals = self.decimals.max(frac_part.len());
+    }
+
+    fn numeric_format(&self) -> (usize, usize) {
+        if !self.has_number {
+            return DEFAULT_NUMERIC_FORMAT;
+        }
+        let decimals = self.decimals.min(MAX_NUMERIC_DECIMALS);
+        let point = if decimals > 0 { 1 } else { 0 };
+        let width = self.has_negative as usize + self.int_digits + point + decimals;
+        (width.min(MAX_NUMERIC_WIDTH), decimals)
+    }
+
+    /// Makes a column numeric if at least `threshold` of its non-empty values are
+    /// numbers. Returns a warning naming the values conversion will set to missing.
+    pub fn accept_mostly_numeric(&mut self, header: &str, threshold: f64) -> Option<String> {
+        if self.is_numeric || self.numbers == 0 || threshold >= 1.0 {
+            return None;
+        }
+        let share = self.numbers as f64 / (self.numbers + self.non_numbers) as f64;
+        if share < threshold {
+            return None;
+        }
+        self.is_numeric = true;
+        Some(format!(
+            "Column '{header}' is {:.1}% numeric; kept numeric and its {} other sampled value(s) become missing, e.g. '{}'",
+            share * 100.0,
+            self.non_numbers,
+            self.non_number_examples.join("', '")
+        ))
+    }
+
+    /// Demotes a numeric column holding integers too long for f64 (e.g. 18-digit IDs)
+    /// to a string column. Returns whether the column was changed.
+    pub fn preserve_long_integers(&mut self) -> bool {
+        if self.is_numeric && self.has_long_integer {
+            self.is_numeric = false;
+            return true;
+        }
+        false
     }
 
     pub fn col_type(&self) -> ColType {
         if self.is_numeric {
-            ColType::Numeric
-        } else {
-            let width = if self.max_byte_len <= STRING_DECLARED_WIDTH {
-                STRING_DECLARED_WIDTH
+            let (width, decimals) = self.numeric_format();
+            ColType::Numeric { width, decimals }
+        } else if self.is_date_column() {
+            ColType::Date
+        } else if let Some(day_first) = self.digit_dates.day_first() {
+            if self.digit_dates.has_time() {
+                ColType::Timestamp { day_first }
             } else {
-                self.max_byte_len.min(MAX_STRING_WIDTH)
-            };
-            ColType::String(width)
+                ColType::NumericDate { day_first }
+            }
+        } else {
+            self.string_type()
         }
     }
+
+    fn string_type(&self) -> ColType {
+        let width = if self.max_byte_len <= STRING_DECLARED_WIDTH {
+            STRING_DECLARED_WIDTH
+        } else {
+            self.max_byte_len.min(MAX_STRING_WIDTH)
+        };
+        ColType::String(width)
+    }
+}
+
+/// Raw data records of a small file, kept from inference so conversion need not
+/// parse it again.
+#[derive(Debug)]
+pub struct CachedRecords {
+    /// Bytes skipped before the first record (a BOM and preamble lines); record
+    /// positions exclude them.
+    pub skipped: u64,
+    pub records: Vec<csv::ByteRecord>,
 }
 
 #[derive(Debug, Clone)]
@@ -65,22 +348,69 @@ pub struct CsvSchema {
     pub file_size: u64,
     /// Column names whose observed values exceed MAX_STRING_WIDTH and will be truncated.
     pub truncated_cols: Vec<String>,
+    /// Up to SAMPLE_VALUES distinct non-empty values per column, in order of appearance.
+    pub samples: Vec<Vec<String>>,
+    /// Non-fatal issues noticed while inferring, surfaced in the conversion result.
+    pub warnings: Vec<String>,
+    /// Total data rows, known when sampling reached the end of the file.
+    pub row_count: Option<usize>,
+    pub records: Option<Arc<CachedRecords>>,
+    /// Question text and header rows to skip when the file is a Qualtrics export.
+    pub qualtrics: Option<QualtricsHeader>,
+    /// Merged header rows and checkbox groups when the file is a SurveyMonkey export.
+    pub surveymonkey: Option<SurveyMonkeyHeader>,
+    /// Personal data each column appears to hold, None for most columns.
+    pub pii: Vec<Option<PiiKind>>,
+    /// Restructures input records into the rows these headers describe.
+    pub reshape: Option<Reshaper>,
+    /// Code columns with a column naming each code, such as `region_code` and
+    /// `region_name`.
+    pub label_pairs: Vec<LabelPair>,
+    /// Numbered column families holding checkbox answers.
+    pub response_sets: Vec<ResponseSet>,
+}
+
+impl CsvSchema {
+    /// Number of CSV fields per row; dummy columns come after them.
+    pub fn fields(&self) -> usize {
+        self.col_types
+            .iter()
+            .filter(|t| !matches!(t, ColType::Dummy { .. }))
+            .count()
+    }
+
+    /// Rows after the CSV header that are not data.
+    pub fn skip_rows(&self) -> usize {
+        match (&self.qualtrics, &self.surveymonkey) {
+            (Some(q), _) => q.skip_rows,
+            (None, Some(_)) => SurveyMonkeyHeader::SKIP_ROWS,
+            (None, None) => 0,
+        }
+    }
 }
 
 /// Counts data rows using the CSV parser so quoted multi-line fields are handled correctly.
-pub fn count_rows(path: &Path, cancelled: &AtomicBool) -> Result<usize, String> {
+pub fn count_rows(
+    path: &Path,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<usize, TaskError> {
     let file = File::open(path).map_err(|e| format!("Failed to open CSV: {e}"))?;
-    let buf = BufReader::with_capacity(BUF_SIZE, file);
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(buf);
+    let (file, _) = RetryReader::new(file, options.retry_policy());
+    let file = input::Transcoder::new(file, options.encoding);
+    let mut buf = BufReader::with_capacity(BUF_SIZE, file);
+    input::skip_utf8_bom(&mut buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let preamble = input::skip_preamble(&mut buf, options)?;
+    let mut reader = preamble.csv_reader(options)?.from_reader(buf);
+    input::seek_header(&mut reader, options.header_row)?;
 
+    // Encoding is checked by inference and conversion; counting only needs record boundaries.
     let mut count = 0usize;
-    for result in reader.records() {
+    for result in reader.byte_records() {
         result.map_err(|e| format!("CSV read error at row {}: {e}", count + 1))?;
         count += 1;
-        if count % 100_000 == 0 && cancelled.load(Ordering::Relaxed) {
-            return Err("Cancelled".to_string());
+        if count.is_multiple_of(100_000) {
+            cancel.check()?;
         }
     }
     Ok(count)
@@ -88,62 +418,1007 @@ pub fn count_rows(path: &Path, cancelled: &AtomicBool) -> Result<usize, String>
 
 pub fn infer_schema(
     path: &Path,
-    sample_rows: usize,
-    cancelled: &AtomicBool,
-) -> Result<CsvSchema, String> {
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<CsvSchema, TaskError> {
     let file_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
-
     let file = File::open(path).map_err(|e| format!("Failed to open CSV: {e}"))?;
-    let buf = BufReader::with_capacity(BUF_SIZE, file);
-    let mut reader = csv::Reader::from_reader(buf);
+    let keep_records = file_size <= options.cache_records_max_bytes;
+    infer_schema_from(file, file_size, path, keep_records, options, cancel)
+}
+
+/// Infers the schema of CSV read from `source`. `path` is only used to locate invalid
+/// UTF-8 in error messages. With `keep_records` every record is kept in the schema,
+/// so conversion never reads the source again.
+pub fn infer_schema_from(
+    source: impl Read,
+    file_size: u64,
+    path: &Path,
+    keep_records: bool,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<CsvSchema, TaskError> {
+    let sample_rows = options.sample_rows;
+
+    let (file, recovered) = RetryReader::new(source, options.retry_policy());
+    let file = input::Transcoder::new(file, options.encoding);
+    let mut buf = BufReader::with_capacity(BUF_SIZE, file);
+    let head = buf.fill_buf().map_err(|e| format!("Failed to read CSV: {e}"))?;
+    input::check_delimited_text(head)?;
+    let has_bom =
+        input::skip_utf8_bom(&mut buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let preamble = input::skip_preamble(&mut buf, options)?;
+    let bom = if has_bom { input::UTF8_BOM.len() as u64 } else { 0 };
+    let skipped = bom + preamble.bytes;
+    let mut reader = preamble.csv_reader(options)?.from_reader(buf);
+    input::seek_header(&mut reader, options.header_row)?;
 
-    let headers: Vec<String> = reader
-        .headers()
+    let raw_headers = reader
+        .byte_headers()
         .map_err(|e| format!("Failed to read CSV headers: {e}"))?
-        .iter()
-        .map(|h| h.to_string())
-        .collect();
+        .clone();
+    let header_start = skipped + raw_headers.position().map_or(0, |p| p.byte());
+    let (header_record, replaced_headers) =
+        input::decode_record(raw_headers, options.invalid_utf8).map_err(|e| {
+            let location = format!("header, column {}", e.field() + 1);
+            input::invalid_utf8_error(path, header_start, &location)
+        })?;
+    let headers: Vec<String> = header_record.iter().map(|h| h.to_string()).collect();
 
     if headers.is_empty() {
-        return Err("CSV has no columns".to_string());
+        return Err("CSV has no columns".into());
+    }
+    // Checked before sampling so very wide files fail fast.
+    if headers.len() > options.max_columns && !options.split_columns {
+        return Err(TOO_MANY_COLUMNS.into());
     }
 
-    let mut col_infos: Vec<ColInfo> = vec![ColInfo::new(); headers.len()];
+    let months = if options.detect_dates {
+        Some(MonthNames::new(&options.month_names)?)
+    } else {
+        None
+    };
+    // Reshaped rows no longer have the Google Forms layout.
+    let google_forms = options.reshape.is_none()
+        && options.detect_google_forms
+        && googleforms::is_google_forms(&headers);
+    let mut timestamp = google_forms.then(TimestampColumn::default);
+    let mut multi_select =
+        (google_forms && options.split_multi_select).then(|| MultiSelect::new(headers.len()));
+    let script = options.row_script.as_deref().map(RowScript::load).transpose()?;
     let mut sampled_rows = 0usize;
+    let mut reached_end = true;
+    let mut kept = Vec::new();
 
-    for result in reader.records() {
-        if cancelled.load(Ordering::Relaxed) {
-            return Err("Cancelled".to_string());
+    let mut records = reader.byte_records();
+    let mut head: Vec<csv::Result<csv::ByteRecord>> = Vec::new();
+    let mut layout = None;
+    let mut survey = None;
+    if options.detect_qualtrics || options.detect_surveymonkey {
+        head.extend(records.by_ref().take(2));
+        let mut peeked = head.iter().map(|r| r.as_ref().ok());
+        let (first, second) = (peeked.next().flatten(), peeked.next().flatten());
+        if options.detect_qualtrics {
+            layout = qualtrics::detect(&headers, first, second);
         }
+        if layout.is_none() && options.detect_surveymonkey {
+            survey = SurveyMonkeyHeader::detect(&headers, first);
+        }
+        let skip = match (&layout, &survey) {
+            (Some(layout), _) => layout.skip_rows,
+            (None, Some(_)) => SurveyMonkeyHeader::SKIP_ROWS,
+            (None, None) => 0,
+        };
+        head.drain(..skip.min(head.len()));
+    }
 
-        let record =
-            result.map_err(|e| format!("CSV read error at row {}: {e}", sampled_rows + 1))?;
-        sampled_rows += 1;
+    // Inference sees the reshaped rows, since those are what gets written.
+    let reshaper = options
+        .reshape
+        .as_ref()
+        .map(|spec| Reshaper::new(spec, &headers))
+        .transpose()?;
+    if let Some(reshaper) = &reshaper {
+        if survey.is_some() {
+            return Err("Reshaping SurveyMonkey exports is not supported".into());
+        }
+        if let Some(layout) = &mut layout {
+            layout.labels = reshaper.remap(&layout.labels);
+        }
+    }
+    let headers: Vec<String> = reshaper.as_ref().map_or(headers, |r| r.headers().to_vec());
+    let mut reshaping = reshaper.clone();
+    let mut reshaped = VecDeque::new();
+    let mut col_infos: Vec<ColInfo> = vec![ColInfo::new(); headers.len()];
+    let mut pair_tracker = PairTracker::new(&headers);
+    let mut observed_rows = 0usize;
+    let mut observe = |raw: csv::ByteRecord, input_row: usize| -> Result<(), String> {
+        observed_rows += 1;
+        let start = skipped + raw.position().map_or(0, |p| p.byte());
+        let (record, _) = input::decode_record(raw, options.invalid_utf8).map_err(|e| {
+            let column = headers.get(e.field()).map_or("", String::as_str);
+            input::invalid_utf8_error(path, start, &format!("row {input_row}, column '{column}'"))
+        })?;
+        // Types are inferred from the values the script leaves, as those are written.
+        let record = match &script {
+            Some(script) => {
+                let fields: Vec<_> = record.iter().map(std::borrow::Cow::Borrowed).collect();
+                csv::StringRecord::from(script.run(&headers, &fields, observed_rows)?)
+            }
+            None => record,
+        };
 
+        pair_tracker.observe(|i| record.get(i));
         for (i, field) in record.iter().enumerate() {
             if i < col_infos.len() {
-                col_infos[i].observe(field);
+                if let Some(survey) = &mut survey {
+                    survey.observe(i, field);
+                }
+                if let (0, Some(timestamp)) = (i, &mut timestamp) {
+                    timestamp.observe(field);
+                }
+                if let Some(multi_select) = &mut multi_select {
+                    multi_select.observe(i, field);
+                }
+                if options.is_missing_marker(field) {
+                    col_infos[i].observe_missing(field);
+                } else {
+                    col_infos[i].observe(field, options);
+                    if let Some(months) = &months {
+                        col_infos[i].observe_date(field, months);
+                    }
+                }
             }
         }
+        Ok(())
+    };
+
+    for result in head.into_iter().chain(records) {
+        cancel.check()?;
 
-        if sampled_rows >= sample_rows {
+        let raw =
+            result.map_err(|e| format!("CSV read error at row {}: {e}", sampled_rows + 1))?;
+        sampled_rows += 1;
+        if keep_records {
+            kept.push(raw.clone());
+            // Past the sample only the records are collected; conversion decodes them.
+            if sampled_rows > sample_rows {
+                continue;
+            }
+        }
+        if sampled_rows > sample_rows && options.count_all_rows {
+            continue;
+        }
+        match &mut reshaping {
+            Some(reshaping) => {
+                reshaping.push(&raw, &mut reshaped);
+                for row in reshaped.drain(..) {
+                    observe(row, sampled_rows)?;
+                }
+            }
+            None => observe(raw, sampled_rows)?,
+        }
+
+        if sampled_rows >= sample_rows && !keep_records && !options.count_all_rows {
+            reached_end = false;
             break;
         }
     }
+    if let Some(reshaping) = &mut reshaping {
+        reshaping.finish(&mut reshaped);
+        for row in reshaped.drain(..) {
+            observe(row, sampled_rows)?;
+        }
+    }
+
+    let mut warnings: Vec<String> = Vec::new();
+    if has_bom {
+        warnings.push("Removed the UTF-8 byte order mark (BOM) at the start of the file".to_string());
+    }
+    for i in replaced_headers {
+        warnings.push(format!(
+            "Header of column {} contains invalid UTF-8; replaced with U+FFFD",
+            i + 1
+        ));
+    }
+    if let Some(survey) = &mut survey {
+        survey.group_checkboxes();
+    }
+    let mut col_types: Vec<ColType> = Vec::with_capacity(headers.len());
+    for (i, (header, info)) in headers.iter().zip(col_infos.iter_mut()).enumerate() {
+        col_types.push(match options.columns.get(header).and_then(|c| c.period) {
+            Some(period) => ColType::Period(period),
+            None if survey.as_ref().is_some_and(|s| s.is_checkbox(i)) => ColType::Checkbox,
+            None => settle_type(info, header, options, &mut warnings),
+        });
+    }
+    let mut pii: Vec<Option<PiiKind>> = headers
+        .iter()
+        .zip(&col_infos)
+        .zip(&col_types)
+        .map(|((header, info), col_type)| match col_type {
+            ColType::String(_) | ColType::Numeric { .. } => info.pii.kind(header),
+            _ => None,
+        })
+        .collect();
+    for (header, kind) in headers.iter().zip(&pii) {
+        if let Some(kind) = kind.filter(|_| options.anonymize(header).is_none()) {
+            warnings.push(pii::warning(header, kind));
+        }
+    }
+    let mut headers = headers;
+    let mut samples: Vec<Vec<String>> = Vec::new();
+    if google_forms {
+        let day_first = timestamp.and_then(|t| t.day_first());
+        if let (Some(day_first), Some(first)) = (day_first, col_types.first_mut()) {
+            if !matches!(first, ColType::Period(_)) {
+                *first = ColType::Timestamp { day_first };
+            }
+        }
+        let split = multi_select.map(MultiSelect::finish).unwrap_or_default();
+        let mut dummies = 0;
+        for (source, choices) in &split {
+            if !matches!(col_types[*source], ColType::String(_)) {
+                continue;
+            }
+            for option in choices {
+                headers.push(googleforms::dummy_header(&headers[*source], option));
+                col_types.push(ColType::Dummy {
+                    source: *source,
+                    option: option.clone(),
+                });
+                samples.push(Vec::new());
+                pii.push(None);
+                dummies += 1;
+            }
+        }
+        let mut message = "Google Forms export detected".to_string();
+        if day_first.is_some() {
+            message.push_str(&format!("; '{}' read as date and time", headers[0]));
+        }
+        if dummies > 0 {
+            message.push_str(&format!("; {dummies} option column(s) added for checkbox answers"));
+        }
+        warnings.push(message);
+    }
+    let label_pairs: Vec<LabelPair> = pair_tracker
+        .finish(reached_end && sampled_rows <= sample_rows)
+        .into_iter()
+        .filter(|pair| label_pair_fits(pair, &headers, &col_types, options))
+        .collect();
+    if !options.merge_label_columns {
+        warnings.extend(label_pairs.iter().map(|pair| pair.offer(&headers)));
+    }
+    let fields = col_infos.len();
+    let field_samples: Vec<Vec<String>> = col_infos.iter().map(|c| c.samples.clone()).collect();
+    let response_sets = response_sets(
+        &headers[..fields],
+        &mut col_types[..fields],
+        &field_samples,
+        options,
+        &mut warnings,
+    );
+    for name in options.columns.keys() {
+        if !headers.contains(name) {
+            warnings.push(format!("Column options for '{name}' match no header"));
+        }
+    }
 
     let truncated_cols: Vec<String> = headers
         .iter()
         .zip(&col_infos)
-        .filter(|(_, info)| !info.is_numeric && info.max_byte_len > MAX_STRING_WIDTH)
-        .map(|(h, _)| h.clone())
+        .zip(&col_types)
+        .filter(|((_, info), col_type)| {
+            matches!(col_type, ColType::String(_)) && info.max_byte_len > MAX_STRING_WIDTH
+        })
+        .map(|((h, _), _)| h.clone())
         .collect();
 
-    let col_types: Vec<ColType> = col_infos.iter().map(|c| c.col_type()).collect();
+    if preamble.lines > 0 {
+        warnings.push(format!("{} line(s) before the header skipped", preamble.lines));
+    }
+    if let Some(layout) = &layout {
+        warnings.push(format!(
+            "Qualtrics export detected: question ids used as variable names, question text as labels; {} extra header row(s) skipped",
+            layout.skip_rows
+        ));
+    }
+    if let Some(survey) = &survey {
+        warnings.push(format!(
+            "SurveyMonkey export detected: header rows merged into question names and labels; {} checkbox question(s) grouped as multiple response sets",
+            survey.groups.len()
+        ));
+    }
+
+    let samples = col_infos
+        .into_iter()
+        .map(|c| c.samples)
+        .chain(samples)
+        .collect();
+    warnings.extend(retry::recovered_warning(recovered.get()));
 
     Ok(CsvSchema {
         headers,
         col_types,
         file_size,
         truncated_cols,
+        samples,
+        warnings,
+        // Long-to-wide row counts depend on the ids, known only if every row was reshaped.
+        row_count: match &reshaper {
+            Some(reshaper) => reached_end
+                .then(|| reshaper.rows(sampled_rows).or((sampled_rows <= sample_rows).then_some(observed_rows)))
+                .flatten(),
+            None => reached_end.then_some(sampled_rows),
+        },
+        records: keep_records.then(|| Arc::new(CachedRecords { skipped, records: kept })),
+        qualtrics: layout,
+        surveymonkey: survey,
+        pii,
+        reshape: reshaper,
+        label_pairs,
+        response_sets,
     })
 }
+
+/// Finds checkbox column families, offering them as warnings unless they are to be
+/// registered, and collapses their text columns to 1 or 0 when asked to.
+fn response_sets(
+    headers: &[String],
+    col_types: &mut [ColType],
+    samples: &[Vec<String>],
+    options: &ConvertOptions,
+    warnings: &mut Vec<String>,
+) -> Vec<ResponseSet> {
+    let mut sets = responsesets::detect(headers, col_types, samples);
+    if !options.register_response_sets {
+        warnings.extend(sets.iter().map(|set| set.offer(headers)));
+        return sets;
+    }
+    if options.collapse_selected {
+        for set in sets.iter_mut().filter(|set| set.counted != Counted::One) {
+            for &i in &set.columns {
+                let option = samples[i][0].clone();
+                col_types[i] = ColType::Selected { option };
+            }
+            set.counted = Counted::One;
+        }
+    }
+    sets
+}
+
+/// Whether a code column and its name column can be merged into value labels.
+fn label_pair_fits(
+    pair: &LabelPair,
+    headers: &[String],
+    col_types: &[ColType],
+    options: &ConvertOptions,
+) -> bool {
+    matches!(col_types[pair.code], ColType::Numeric { .. } | ColType::String(_))
+        && matches!(col_types[pair.name], ColType::String(_))
+        && options.anonymize(&headers[pair.code]).is_none()
+        && options.anonymize(&headers[pair.name]).is_none()
+}
+
+/// Options that decide how every value is typed, rather than which values are read.
+fn typing_options(options: &ConvertOptions) -> impl PartialEq + '_ {
+    (
+        &options.missing_markers,
+        options.decimal_separator,
+        options.grouping_separator,
+        options.numeric_threshold,
+        options.preserve_long_integers,
+        options.invalid_utf8,
+    )
+}
+
+/// The options with everything that only affects typing reset, serialized so two
+/// sets of options can be compared.
+fn without_typing_options(options: &ConvertOptions) -> Option<String> {
+    let default = ConvertOptions::default();
+    let mut options = options.clone();
+    options.missing_markers = default.missing_markers;
+    options.decimal_separator = default.decimal_separator;
+    options.grouping_separator = default.grouping_separator;
+    options.numeric_threshold = default.numeric_threshold;
+    options.preserve_long_integers = default.preserve_long_integers;
+    options.invalid_utf8 = default.invalid_utf8;
+    let unset = serde_json::to_value(ColumnOptions::default()).ok()?;
+    options.columns.retain(|_, column| {
+        column.decimals = None;
+        serde_json::to_value(&*column).ok().as_ref() != Some(&unset)
+    });
+    serde_json::to_string(&options).ok()
+}
+
+/// Observes column `i` of `records` the way inference does. None when a value is
+/// not valid UTF-8 and the options do not allow replacing it.
+fn observe_column(
+    records: &[csv::ByteRecord],
+    i: usize,
+    options: &ConvertOptions,
+    months: Option<&MonthNames>,
+) -> Option<ColInfo> {
+    let mut info = ColInfo::new();
+    for record in records {
+        let Some(field) = record.get(i) else { continue };
+        let field = match std::str::from_utf8(field) {
+            Ok(text) => std::borrow::Cow::Borrowed(text),
+            Err(_) if options.invalid_utf8 == InvalidUtf8::Lossy => {
+                String::from_utf8_lossy(field)
+            }
+            Err(_) => return None,
+        };
+        if options.is_missing_marker(&field) {
+            info.observe_missing(&field);
+        } else {
+            info.observe(&field, options);
+            if let Some(months) = months {
+                info.observe_date(&field, months);
+            }
+        }
+    }
+    Some(info)
+}
+
+/// Retypes the columns of `schema`, inferred under `old`, that `new` types differently,
+/// from the cached records of the file. None when `new` changes more than typing or the
+/// schema cannot be retyped without reading the file again.
+fn retype(
+    mut schema: CsvSchema,
+    old: &ConvertOptions,
+    new: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<Option<CsvSchema>, TaskError> {
+    let Some(cached) = schema.records.clone() else {
+        return Ok(None);
+    };
+    // Headers replaced under lossy decoding fail in strict mode, which needs a full read.
+    let stricter =
+        old.invalid_utf8 == InvalidUtf8::Lossy && new.invalid_utf8 == InvalidUtf8::Strict;
+    let derived = schema.reshape.is_some()
+        || schema.surveymonkey.is_some()
+        || new.row_script.is_some()
+        || schema
+            .col_types
+            .iter()
+            .any(|t| {
+                matches!(
+                    t,
+                    ColType::Timestamp { .. } | ColType::Dummy { .. } | ColType::Selected { .. }
+                )
+            });
+    if stricter || derived || without_typing_options(old) != without_typing_options(new) {
+        return Ok(None);
+    }
+    let decimals = |options: &ConvertOptions, header: &str| {
+        options.columns.get(header).and_then(|c| c.decimals)
+    };
+    // Pinned decimals for unknown headers are reported by a full inference.
+    let mut pinned = old.columns.iter().chain(&new.columns).filter(|(_, c)| c.decimals.is_some());
+    if pinned.any(|(name, _)| !schema.headers.contains(name)) {
+        return Ok(None);
+    }
+
+    let retype_all = typing_options(old) != typing_options(new);
+    let affected: Vec<usize> = (0..schema.fields())
+        .filter(|&i| {
+            let header = &schema.headers[i];
+            let typed = matches!(
+                schema.col_types[i],
+                ColType::Numeric { .. }
+                    | ColType::String(_)
+                    | ColType::Date
+                    | ColType::NumericDate { .. }
+            );
+            typed && (retype_all || decimals(old, header) != decimals(new, header))
+        })
+        .collect();
+    if affected.is_empty() {
+        return Ok(Some(schema));
+    }
+
+    let months = new.detect_dates.then(|| MonthNames::new(&new.month_names)).transpose()?;
+    let sample = &cached.records[..cached.records.len().min(new.sample_rows)];
+    let mut changed = false;
+    for i in affected {
+        cancel.check()?;
+        let header = schema.headers[i].clone();
+        let (Some(mut before), Some(mut after)) = (
+            observe_column(sample, i, old, months.as_ref()),
+            observe_column(sample, i, new, months.as_ref()),
+        ) else {
+            return Ok(None);
+        };
+        let (mut stale, mut fresh) = (Vec::new(), Vec::new());
+        let old_type = settle_type(&mut before, &header, old, &mut stale);
+        let new_type = settle_type(&mut after, &header, new, &mut fresh);
+        let pii_of = |info: &ColInfo, col_type: &ColType| match col_type {
+            ColType::String(_) | ColType::Numeric { .. } => info.pii.kind(&header),
+            _ => None,
+        };
+        let pii = pii_of(&after, &new_type);
+        if new.anonymize(&header).is_none() {
+            stale.extend(pii_of(&before, &old_type).map(|kind| pii::warning(&header, kind)));
+            fresh.extend(pii.map(|kind| pii::warning(&header, kind)));
+        }
+        for warning in stale {
+            if let Some(pos) = schema.warnings.iter().position(|w| *w == warning) {
+                schema.warnings.remove(pos);
+            }
+        }
+        schema.warnings.extend(fresh);
+
+        schema.truncated_cols.retain(|h| *h != header);
+        if matches!(new_type, ColType::String(_)) && after.max_byte_len > MAX_STRING_WIDTH {
+            schema.truncated_cols.push(header);
+        }
+        changed |= std::mem::discriminant(&new_type) != std::mem::discriminant(&old_type);
+        schema.col_types[i] = new_type;
+        schema.samples[i] = after.samples;
+        schema.pii[i] = pii;
+    }
+
+    // Which code and name columns pair up depends on their types.
+    if changed {
+        let mut tracker = PairTracker::new(&schema.headers);
+        for raw in sample {
+            let Ok((record, _)) = input::decode_record(raw.clone(), new.invalid_utf8) else {
+                return Ok(None);
+            };
+            tracker.observe(|i| record.get(i));
+        }
+        let label_pairs: Vec<LabelPair> = tracker
+            .finish(cached.records.len() <= new.sample_rows)
+            .into_iter()
+            .filter(|pair| label_pair_fits(pair, &schema.headers, &schema.col_types, new))
+            .collect();
+        if !new.merge_label_columns {
+            let offers: Vec<String> =
+                schema.label_pairs.iter().map(|pair| pair.offer(&schema.headers)).collect();
+            schema.warnings.retain(|w| !offers.contains(w));
+            schema
+                .warnings
+                .extend(label_pairs.iter().map(|pair| pair.offer(&schema.headers)));
+        }
+        schema.label_pairs = label_pairs;
+
+        let offers: Vec<String> =
+            schema.response_sets.iter().map(|set| set.offer(&schema.headers)).collect();
+        schema.warnings.retain(|w| !offers.contains(w));
+        schema.response_sets = response_sets(
+            &schema.headers,
+            &mut schema.col_types,
+            &schema.samples,
+            new,
+            &mut schema.warnings,
+        );
+    }
+    Ok(Some(schema))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CacheKey {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Serialized options, since most of them influence inference.
+    options: String,
+}
+
+impl CacheKey {
+    fn for_file(path: &Path, options: &ConvertOptions) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            options: serde_json::to_string(options).ok()?,
+        })
+    }
+}
+
+/// Files whose schemas [`SchemaCache`] keeps; the least recently used goes first.
+const CACHE_ENTRIES: usize = 16;
+
+/// Remembers inferred schemas so a preview followed by a conversion of the same,
+/// unchanged file only scans it once. Only the schema stored last keeps its records,
+/// which can run to `cache_records_max_bytes`.
+#[derive(Default)]
+pub struct SchemaCache {
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    /// Key, schema and when the entry was last used.
+    map: HashMap<PathBuf, (CacheKey, CsvSchema, u64)>,
+    clock: u64,
+}
+
+impl CacheEntries {
+    fn get(&mut self, path: &Path) -> Option<(CacheKey, CsvSchema)> {
+        self.clock += 1;
+        let (key, schema, used) = self.map.get_mut(path)?;
+        *used = self.clock;
+        Some((key.clone(), schema.clone()))
+    }
+
+    fn insert(&mut self, path: &Path, key: CacheKey, schema: CsvSchema) {
+        for (_, older, _) in self.map.values_mut() {
+            older.records = None;
+        }
+        self.clock += 1;
+        self.map.insert(path.to_path_buf(), (key, schema, self.clock));
+        if self.map.len() > CACHE_ENTRIES {
+            let oldest = self.map.iter().min_by_key(|(_, (_, _, used))| *used);
+            if let Some(oldest) = oldest.map(|(path, _)| path.clone()) {
+                self.map.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl SchemaCache {
+    pub fn get_or_infer(
+        &self,
+        path: &Path,
+        options: &ConvertOptions,
+        cancel: &CancelToken,
+    ) -> Result<CsvSchema, TaskError> {
+        let key = CacheKey::for_file(path, options);
+        if let Some(ref key) = key {
+            if let Some((cached_key, schema)) = self.entries.lock().unwrap().get(path) {
+                if &cached_key == key {
+                    return Ok(schema);
+                }
+            }
+        }
+
+        let schema = infer_schema(path, options, cancel)?;
+        if let Some(key) = key {
+            self.entries.lock().unwrap().insert(path, key, schema.clone());
+        }
+        Ok(schema)
+    }
+
+    /// Like `get_or_infer`, but when the cached schema of the unchanged file was inferred
+    /// under options that differ only in typing, retypes the affected columns from its
+    /// cached records instead of scanning the file again.
+    pub fn reinfer(
+        &self,
+        path: &Path,
+        options: &ConvertOptions,
+        cancel: &CancelToken,
+    ) -> Result<CsvSchema, TaskError> {
+        let key = CacheKey::for_file(path, options);
+        let cached = self.entries.lock().unwrap().get(path);
+        if let (Some(key), Some((cached_key, schema))) = (key, cached) {
+            let old = serde_json::from_str::<ConvertOptions>(&cached_key.options).ok();
+            let unchanged = cached_key.len == key.len && cached_key.modified == key.modified;
+            if let Some(old) = old.filter(|_| unchanged) {
+                if let Some(schema) = retype(schema, &old, options, cancel)? {
+                    self.entries.lock().unwrap().insert(path, key, schema.clone());
+                    return Ok(schema);
+                }
+            }
+        }
+        self.get_or_infer(path, options, cancel)
+    }
+
+    pub fn forget(&self, path: &Path) {
+        self.entries.lock().unwrap().map.remove(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(values: &[&str]) -> ColType {
+        let mut info = ColInfo::new();
+        for v in values {
+            info.observe(v, &ConvertOptions::default());
+        }
+        info.col_type()
+    }
+
+    #[test]
+    fn test_numeric_format_from_observed_digits() {
+        assert!(matches!(
+            infer(&["1234567.8912", "3.5", ""]),
+            ColType::Numeric { width: 12, decimals: 4 }
+        ));
+        assert!(matches!(
+            infer(&["-12", "7", "1.50"]),
+            ColType::Numeric { width: 6, decimals: 2 }
+        ));
+        assert!(matches!(
+            infer(&["1e3"]),
+            ColType::Numeric { width: 4, decimals: 0 }
+        ));
+        assert!(matches!(infer(&[]), ColType::Numeric { width: 8, decimals: 2 }));
+    }
+
+    #[test]
+    fn test_digit_dates_and_timestamps() {
+        let options = ConvertOptions::default();
+        let months = MonthNames::new(&[]).unwrap();
+        let infer_dates = |values: &[&str]| {
+            let mut info = ColInfo::new();
+            for v in values {
+                info.observe(v, &options);
+                info.observe_date(v, &months);
+            }
+            info.col_type()
+        };
+        assert!(matches!(
+            infer_dates(&["2024-03-01", "2024/12/31", ""]),
+            ColType::NumericDate { day_first: false }
+        ));
+        assert!(matches!(
+            infer_dates(&["01-03-2024", "25-12-2024"]),
+            ColType::NumericDate { day_first: true }
+        ));
+        assert!(matches!(
+            infer_dates(&["2024-03-01T10:15:30Z", "2024-03-02 08:00"]),
+            ColType::Timestamp { day_first: false }
+        ));
+        assert!(matches!(infer_dates(&["01-Mar-2024"]), ColType::Date));
+        assert!(matches!(infer_dates(&["2024-03-01", "soon"]), ColType::String(_)));
+        assert!(matches!(infer_dates(&["2024", "2025"]), ColType::Numeric { .. }));
+    }
+
+    #[test]
+    fn test_missing_markers_keep_column_numeric() {
+        let options = ConvertOptions::default();
+        let mut info = ColInfo::new();
+        for v in ["1.5", "NA", " n/a ", "", "2"] {
+            if options.is_missing_marker(v) {
+                info.observe_missing(v);
+            } else {
+                info.observe(v, &options);
+            }
+        }
+        assert!(matches!(info.col_type(), ColType::Numeric { decimals: 1, .. }));
+    }
+
+    #[test]
+    fn test_declared_numeric_locale() {
+        let options = ConvertOptions {
+            decimal_separator: ',',
+            grouping_separator: Some('.'),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(options.parse_number("-1.234.567,5"), Some(-1234567.5));
+        assert_eq!(options.parse_number("12,25"), Some(12.25));
+        assert_eq!(options.parse_number("1.5"), None);
+        assert_eq!(options.parse_number(".500"), None);
+
+        let mut info = ColInfo::new();
+        for v in ["1.234,56", "7", "-0,5"] {
+            info.observe(v, &options);
+        }
+        assert!(matches!(info.col_type(), ColType::Numeric { width: 8, decimals: 2 }));
+        info.observe("3.25", &options);
+        assert!(matches!(info.col_type(), ColType::String(_)));
+
+        let same = ConvertOptions { grouping_separator: Some('.'), ..ConvertOptions::default() };
+        assert!(same.csv_reader().is_err());
+    }
+
+    #[test]
+    fn test_pinned_decimals() {
+        let path = std::env::temp_dir().join("csv2sav_schema_decimals.csv");
+        fs::write(&path, "price,rate,name\n-1.5,0.12345,a\n12,0.5,b\n").unwrap();
+        let pin = |decimals| crate::options::ColumnOptions {
+            decimals: Some(decimals),
+            ..Default::default()
+        };
+        let options = ConvertOptions {
+            columns: [("price", pin(2)), ("rate", pin(4)), ("name", pin(2))]
+                .into_iter()
+                .map(|(header, column)| (header.to_string(), column))
+                .collect(),
+            ..ConvertOptions::default()
+        };
+        let schema = infer_schema(&path, &options, &CancelToken::new()).unwrap();
+        assert!(matches!(schema.col_types[0], ColType::Numeric { width: 6, decimals: 2 }));
+        assert!(matches!(schema.col_types[1], ColType::Numeric { width: 6, decimals: 4 }));
+        assert!(matches!(schema.col_types[2], ColType::String(_)));
+        assert!(schema.warnings.iter().any(|w| w.starts_with("Decimals for 'name' ignored")));
+        assert!(matches!(
+            pin_decimals(ColType::Numeric { width: 3, decimals: 0 }, 20),
+            ColType::Numeric { width: 20, decimals: 16 }
+        ));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_reinfer_retypes_from_cached_records() {
+        let path = std::env::temp_dir().join("csv2sav_schema_reinfer.csv");
+        fs::write(&path, "amount,code\n\"1,5\",7\n-,8\n\"2,25\",9\n").unwrap();
+        let cache = SchemaCache::default();
+        let cancel = CancelToken::new();
+        let defaults = ConvertOptions::default();
+        let schema = cache.get_or_infer(&path, &defaults, &cancel).unwrap();
+        assert!(matches!(schema.col_types[0], ColType::String(_)));
+
+        let mut options = ConvertOptions { decimal_separator: ',', ..ConvertOptions::default() };
+        options.missing_markers.push("-".to_string());
+        let retyped = cache.reinfer(&path, &options, &cancel).unwrap();
+        let full = infer_schema(&path, &options, &cancel).unwrap();
+        assert!(matches!(retyped.col_types[0], ColType::Numeric { width: 4, decimals: 2 }));
+        assert!(matches!(retyped.col_types[1], ColType::Numeric { width: 1, decimals: 0 }));
+        assert_eq!(retyped.warnings, full.warnings);
+
+        let resampled = ConvertOptions { sample_rows: 1, ..options.clone() };
+        assert!(retype(retyped, &options, &resampled, &cancel).unwrap().is_none());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let dir = std::env::temp_dir().join("csv2sav_schema_cache_test");
+        fs::create_dir_all(&dir).unwrap();
+        let cache = SchemaCache::default();
+        let cancel = CancelToken::new();
+        let options =
+            ConvertOptions { cache_records_max_bytes: u64::MAX, ..ConvertOptions::default() };
+        let paths: Vec<PathBuf> =
+            (0..=CACHE_ENTRIES).map(|i| dir.join(format!("{i}.csv"))).collect();
+        for path in &paths {
+            fs::write(path, "a\n1\n").unwrap();
+            assert!(cache.get_or_infer(path, &options, &cancel).unwrap().records.is_some());
+            if path == &paths[1] {
+                // The first file is used again, so the second is the least recently used.
+                cache.get_or_infer(&paths[0], &options, &cancel).unwrap();
+            }
+        }
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.map.len(), CACHE_ENTRIES);
+        assert!(entries.map.contains_key(&paths[0]) && !entries.map.contains_key(&paths[1]));
+        let with_records: Vec<_> =
+            entries.map.iter().filter(|(_, (_, schema, _))| schema.records.is_some()).collect();
+        assert_eq!(with_records.len(), 1);
+        assert_eq!(with_records[0].0, &paths[CACHE_ENTRIES]);
+        drop(entries);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_mostly_numeric_threshold() {
+        let observe = |threshold| {
+            let options = ConvertOptions { numeric_threshold: threshold, ..ConvertOptions::default() };
+            let mut info = ColInfo::new();
+            for v in ["1.5", "oops", "12", "3"] {
+                info.observe(v, &options);
+            }
+            let warning = info.accept_mostly_numeric("score", threshold);
+            (info.col_type(), warning)
+        };
+        let (col_type, warning) = observe(0.75);
+        assert!(matches!(col_type, ColType::Numeric { width: 4, decimals: 1 }));
+        assert_eq!(
+            warning.as_deref(),
+            Some("Column 'score' is 75.0% numeric; kept numeric and its 1 other sampled value(s) become missing, e.g. 'oops'")
+        );
+        assert!(matches!(observe(0.8), (ColType::String(_), None)));
+        assert!(matches!(observe(1.0), (ColType::String(_), None)));
+    }
+
+    #[test]
+    fn test_long_integer_ids_become_strings() {
+        let mut info = ColInfo::new();
+        info.observe("123456789012345678", &ConvertOptions::default());
+        assert!(info.preserve_long_integers());
+        assert!(matches!(info.col_type(), ColType::String(_)));
+
+        let mut info = ColInfo::new();
+        info.observe("000000000000000123", &ConvertOptions::default());
+        info.observe("123456789012345", &ConvertOptions::default());
+        assert!(!info.preserve_long_integers());
+    }
+
+    #[test]
+    fn test_row_count_known_after_full_sample() {
+        let path = std::env::temp_dir().join("csv2sav_schema_row_count.csv");
+        fs::write(&path, "a,b\n1,x\n2,\"multi\nline\"\n3,z\n").unwrap();
+        let cancel = CancelToken::new();
+
+        let options = ConvertOptions {
+            sample_rows: 2,
+            ..ConvertOptions::default()
+        };
+        // Small enough to cache: read to the end despite the sample limit.
+        let schema = infer_schema(&path, &options, &cancel).unwrap();
+        assert_eq!(schema.row_count, Some(3));
+        assert_eq!(schema.records.unwrap().records.len(), 3);
+
+        let options = ConvertOptions {
+            cache_records_max_bytes: 0,
+            ..options
+        };
+        let schema = infer_schema(&path, &options, &cancel).unwrap();
+        assert_eq!(schema.row_count, None);
+        assert!(schema.records.is_none());
+
+        let options = ConvertOptions { count_all_rows: true, ..options };
+        let schema = infer_schema(&path, &options, &cancel).unwrap();
+        assert_eq!(schema.row_count, Some(3));
+        assert!(schema.records.is_none());
+        assert_eq!(schema.samples[0], vec!["1", "2"]);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_csv_dialect_options() {
+        let path = std::env::temp_dir().join("csv2sav_schema_dialect.csv");
+        fs::write(&path, "id,note\n# exported 2024-03-01\n1,'it\\'s, fine'\n2,'plain'\n").unwrap();
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            quote: '\'',
+            escape: Some('\\'),
+            comment: Some('#'),
+            ..ConvertOptions::default()
+        };
+        let schema = infer_schema(&path, &options, &cancel).unwrap();
+        assert_eq!(schema.row_count, Some(2));
+        assert_eq!(schema.samples[1], vec!["it's, fine", "plain"]);
+        assert_eq!(count_rows(&path, &options, &cancel), Ok(2));
+
+        let unquoted = ConvertOptions { quoting: false, ..ConvertOptions::default() };
+        let schema = infer_schema(&path, &unquoted, &cancel).unwrap();
+        assert_eq!(schema.samples[0][0], "# exported 2024-03-01");
+
+        let invalid = ConvertOptions { quote: '“', ..ConvertOptions::default() };
+        assert!(infer_schema(&path, &invalid, &cancel).is_err());
+
+        fs::write(&path, "id|note\n1|a, b\n2|'c|d'\n").unwrap();
+        let piped = ConvertOptions { delimiter: '|', quote: '\'', ..ConvertOptions::default() };
+        let schema = infer_schema(&path, &piped, &cancel).unwrap();
+        assert_eq!(schema.headers, vec!["id", "note"]);
+        assert_eq!(schema.samples[1], vec!["a, b", "c|d"]);
+        let clash = ConvertOptions { delimiter: '"', ..ConvertOptions::default() };
+        let Err(err) = infer_schema(&path, &clash, &cancel) else {
+            panic!("delimiter equal to the quote accepted");
+        };
+        assert_eq!(err.to_string(), "Delimiter and quote character must differ");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_preamble_lines() {
+        let path = std::env::temp_dir().join("csv2sav_schema_preamble.csv");
+        fs::write(&path, "sep=;\r\nQuarterly report\r\n\r\nid;name\r\n1;a\r\n2;b\r\n").unwrap();
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            skip_pattern: Some(r"^(Quarterly|\s*$)".to_string()),
+            ..ConvertOptions::default()
+        };
+        let schema = infer_schema(&path, &options, &cancel).unwrap();
+        assert_eq!(schema.headers, vec!["id", "name"]);
+        assert_eq!(schema.row_count, Some(2));
+        assert!(schema.warnings.iter().any(|w| w == "3 line(s) before the header skipped"));
+        assert_eq!(count_rows(&path, &options, &cancel), Ok(2));
+
+        let by_count = ConvertOptions { skip_lines: 3, ..ConvertOptions::default() };
+        assert_eq!(infer_schema(&path, &by_count, &cancel).unwrap().headers, vec!["id", "name"]);
+
+        let invalid = ConvertOptions { skip_pattern: Some("(".to_string()), ..ConvertOptions::default() };
+        assert!(infer_schema(&path, &invalid, &cancel).is_err());
+
+        let titled = "\"Survey export\nMarch 2024\"\nsource,panel\nid,name\n1,a\n2,b\n";
+        fs::write(&path, titled).unwrap();
+        let by_row = ConvertOptions { header_row: 2, ..ConvertOptions::default() };
+        let schema = infer_schema(&path, &by_row, &cancel).unwrap();
+        assert_eq!(schema.headers, vec!["id", "name"]);
+        assert_eq!(schema.row_count, Some(2));
+        assert_eq!(count_rows(&path, &by_row, &cancel), Ok(2));
+        let past_end = ConvertOptions { header_row: 9, ..ConvertOptions::default() };
+        let Err(err) = infer_schema(&path, &past_end, &cancel) else {
+            panic!("header row past the end accepted");
+        };
+        assert_eq!(err.to_string(), "CSV ends before header row 9");
+        fs::remove_file(&path).ok();
+    }
+}
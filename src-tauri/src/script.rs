@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use csv::ByteRecord;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+/// Operations one row may take before the script is stopped, so a runaway loop
+/// fails the conversion instead of hanging it.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A Rhai script run on every row before its cells are converted. The row is in scope
+/// as `row`, a map from header to the cell's CSV text (`()` when blank), along with its
+/// 1-based `row_number`. Whatever the script leaves in `row` is written: assigning to a
+/// column changes or derives its value and assigning `()` blanks it.
+///
+/// ```rhai
+/// row.age = if row.age == "999" { () } else { row.age };
+/// row.bmi = parse_float(row.weight) / (parse_float(row.height) / 100.0) ** 2;
+/// ```
+pub struct RowScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl RowScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read row script: {e}"))?;
+        Self::compile(&source)
+    }
+
+    pub fn compile(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("Row script error: {e}"))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Runs the script on one row of fields under `headers` and returns the new
+    /// fields. Fields past the headers pass through untouched.
+    pub fn run(&self, headers: &[String], fields: &[Cow<'_, str>], row: usize) -> Result<Vec<String>, String> {
+        let map: Map = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                let value = match fields.get(i) {
+                    Some(field) if !field.is_empty() => Dynamic::from(field.to_string()),
+                    _ => Dynamic::UNIT,
+                };
+                (header.as_str().into(), value)
+            })
+            .collect();
+        let mut scope = Scope::new();
+        scope.push("row", map);
+        scope.push_constant("row_number", row as i64);
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| format!("Row script failed at row {row}: {e}"))?;
+
+        let mut map = scope
+            .get_value::<Map>("row")
+            .ok_or_else(|| format!("Row script failed at row {row}: `row` is no longer a map"))?;
+        let mut out: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        for (i, header) in headers.iter().enumerate() {
+            let Some(value) = map.remove(header.as_str()) else {
+                continue;
+            };
+            let text = to_text(value)
+                .map_err(|kind| format!("Row script set column '{header}' to {kind} at row {row}"))?;
+            if i < out.len() {
+                out[i] = text;
+            } else if !text.is_empty() {
+                // A derived value for a column the row was too short to reach.
+                out.resize(i, String::new());
+                out.push(text);
+            }
+        }
+        if let Some(column) = map.keys().next() {
+            return Err(format!("Row script set column '{column}', which the CSV does not have"));
+        }
+        Ok(out)
+    }
+
+    /// Runs the script on a raw record in place, keeping its position for error messages.
+    pub fn apply(&self, headers: &[String], record: &mut ByteRecord, row: usize) -> Result<(), String> {
+        let fields: Vec<Cow<'_, str>> = record.iter().map(String::from_utf8_lossy).collect();
+        let out = self.run(headers, &fields, row)?;
+        let position = record.position().cloned();
+        *record = ByteRecord::from(out);
+        record.set_position(position);
+        Ok(())
+    }
+}
+
+/// CSV text of a value the script left in `row`, or the name of its unsupported type.
+fn to_text(value: Dynamic) -> Result<String, &'static str> {
+    if value.is_unit() {
+        Ok(String::new())
+    } else if let Some(n) = value.clone().try_cast::<f64>() {
+        Ok(n.to_string())
+    } else if value.is_int() || value.is_bool() || value.is_char() || value.is_string() {
+        Ok(value.to_string())
+    } else {
+        Err(value.type_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_script() {
+        let script = RowScript::compile(
+            r#"
+            if row.age == "999" { row.age = (); }
+            row.total = parse_float(row.a) + parse_float(row.b);
+            row.id = `R${row_number}`;
+            "#,
+        )
+        .unwrap();
+        let headers: Vec<String> = ["id", "age", "a", "b", "total"].map(String::from).to_vec();
+        let fields: Vec<Cow<'_, str>> = ["x", "999", "1.5", "2"].map(Cow::Borrowed).to_vec();
+        assert_eq!(script.run(&headers, &fields, 7).unwrap(), ["R7", "", "1.5", "2", "3.5"]);
+
+        let mut record = ByteRecord::from(vec!["x", "30", "1", "2", "", "extra"]);
+        script.apply(&headers, &mut record, 1).unwrap();
+        assert_eq!(record, ByteRecord::from(vec!["R1", "30", "1", "2", "3", "extra"]));
+
+        let unknown = RowScript::compile("row.nope = 1;").unwrap();
+        assert!(unknown.run(&headers, &fields, 1).unwrap_err().contains("'nope'"));
+        let runaway = RowScript::compile("loop {}").unwrap();
+        assert!(runaway.run(&headers, &fields, 1).is_err());
+    }
+}
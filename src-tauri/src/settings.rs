@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// User preferences persisted across launches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Receives a JSON POST with the batch summary after every batch completes.
+    pub webhook_url: Option<String>,
+    /// Directory for scratch files such as staged query and join results, e.g. on a
+    /// fast SSD; the system temp directory when unset.
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref raw) = self.webhook_url {
+            let url = url::Url::parse(raw).map_err(|e| format!("Invalid webhook URL: {e}"))?;
+            if url.scheme() != "http" && url.scheme() != "https" {
+                return Err(format!("Webhook URL must be http or https: {raw}"));
+            }
+        }
+        if let Some(ref dir) = self.temp_dir {
+            if !dir.is_dir() {
+                return Err(format!("Temp directory not found: {}", dir.display()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where scratch files go: `preferred` from the conversion options, else the
+    /// configured directory, else the system temp directory.
+    pub fn scratch_dir(&self, preferred: Option<&Path>) -> PathBuf {
+        preferred
+            .or(self.temp_dir.as_deref())
+            .map_or_else(std::env::temp_dir, Path::to_path_buf)
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads settings from `path`, falling back to defaults if the file is missing or unreadable.
+    pub fn open(path: PathBuf) -> Self {
+        let settings = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: Settings) -> Result<(), String> {
+        settings.validate()?;
+        write_json(&self.path, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+}
+
+fn write_json(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create settings dir: {e}"))?;
+    }
+    let data = serde_json::to_vec_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {e}"))?;
+    fs::write(path, data).map_err(|e| format!("Failed to save settings: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_dir_precedence() {
+        let missing = std::env::temp_dir().join("csv2sav_no_such_scratch_dir");
+        let mut settings = Settings::default();
+        assert_eq!(settings.scratch_dir(None), std::env::temp_dir());
+        settings.temp_dir = Some(missing.clone());
+        assert!(settings.validate().is_err());
+        assert_eq!(settings.scratch_dir(None), missing);
+        assert_eq!(settings.scratch_dir(Some(Path::new("/fast"))), Path::new("/fast"));
+    }
+}
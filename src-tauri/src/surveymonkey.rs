@@ -0,0 +1,282 @@
+use csv::ByteRecord;
+
+use crate::dictionary;
+
+/// Row 2 text of single-column questions, as opposed to an answer option.
+const RESPONSE_CELLS: [&str; 2] = ["Response", "Open-Ended Response"];
+/// Document record lines are 80 bytes.
+const SYNTAX_LINE_BYTES: usize = 80;
+/// Quoted strings cannot span lines; longer labels are split and joined with `+`.
+/// Small enough to fit a line even when every character is an escaped quote.
+const LABEL_CHUNK_BYTES: usize = 36;
+
+/// Checkbox columns of one question, written as a multiple dichotomy set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseGroup {
+    /// Set name, e.g. `$Q2`.
+    pub name: String,
+    /// The question text.
+    pub label: String,
+    pub columns: Vec<usize>,
+}
+
+/// Header layout of a SurveyMonkey export. Row 1 holds each question once, above its
+/// first column, and row 2 the answer option of every column ("Response" for
+/// single-column questions); metadata columns such as `Respondent ID` have no row 2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurveyMonkeyHeader {
+    /// `Q1`, `Q2_1`, … for question columns, the cleaned header for metadata columns.
+    pub names: Vec<Option<String>>,
+    /// The question, followed by the option for multi-column questions.
+    pub labels: Vec<String>,
+    pub groups: Vec<ResponseGroup>,
+    /// Question number per column, None for metadata columns.
+    questions: Vec<Option<usize>>,
+    /// Row 2 text per column.
+    options: Vec<String>,
+    /// Every value seen so far is blank or the column's option text.
+    checkbox: Vec<bool>,
+}
+
+impl SurveyMonkeyHeader {
+    /// Rows after the CSV header that are not data.
+    pub const SKIP_ROWS: usize = 1;
+
+    /// Recognizes a SurveyMonkey export from its header and the option row below it.
+    pub fn detect(headers: &[String], first: Option<&ByteRecord>) -> Option<Self> {
+        let first = first?;
+        let options: Vec<String> = (0..headers.len())
+            .map(|i| {
+                first
+                    .get(i)
+                    .map(|f| String::from_utf8_lossy(f).trim().to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+        let has_respondent = headers
+            .iter()
+            .any(|h| h.trim().eq_ignore_ascii_case("Respondent ID"));
+        if !has_respondent || !options.iter().any(|o| RESPONSE_CELLS.contains(&o.as_str())) {
+            return None;
+        }
+
+        // A question starts at a header with an option below it and runs on over the
+        // following columns with a blank header.
+        let mut questions = Vec::with_capacity(headers.len());
+        let mut question_text = Vec::with_capacity(headers.len());
+        let mut current: Option<(usize, &str)> = None;
+        let mut count = 0;
+        for (header, option) in headers.iter().map(|h| h.trim()).zip(&options) {
+            if !header.is_empty() {
+                current = if option.is_empty() {
+                    None
+                } else {
+                    count += 1;
+                    Some((count, header))
+                };
+            }
+            questions.push(current.map(|(n, _)| n));
+            question_text.push(current.map_or(header, |(_, text)| text));
+        }
+
+        let mut names = Vec::with_capacity(headers.len());
+        let mut labels = Vec::with_capacity(headers.len());
+        for (i, header) in headers.iter().enumerate() {
+            let Some(n) = questions[i] else {
+                names.push(dictionary::sanitize_name(header));
+                labels.push(header.trim().to_string());
+                continue;
+            };
+            let span: Vec<usize> = (0..headers.len()).filter(|&j| questions[j] == Some(n)).collect();
+            let option = options[i].as_str();
+            if span.len() == 1 {
+                names.push(Some(format!("Q{n}")));
+            } else {
+                let k = span.iter().position(|&j| j == i).unwrap_or_default() + 1;
+                names.push(Some(format!("Q{n}_{k}")));
+            }
+            labels.push(if span.len() == 1 && RESPONSE_CELLS.contains(&option) {
+                question_text[i].to_string()
+            } else {
+                format!("{} - {option}", question_text[i])
+            });
+        }
+
+        let checkbox = (0..headers.len())
+            .map(|i| {
+                questions[i].is_some() && !RESPONSE_CELLS.contains(&options[i].as_str())
+            })
+            .collect();
+        Some(Self {
+            names,
+            labels,
+            groups: Vec::new(),
+            questions,
+            options,
+            checkbox,
+        })
+    }
+
+    /// Notes a sampled value; a column holding anything but its option text is not a
+    /// checkbox.
+    pub fn observe(&mut self, column: usize, value: &str) {
+        let value = value.trim();
+        if self.checkbox.get(column) == Some(&true) && !value.is_empty() && value != self.options[column] {
+            self.checkbox[column] = false;
+        }
+    }
+
+    /// Groups the checkbox columns of each question once sampling is done. A question
+    /// needs at least two of them to form a set.
+    pub fn group_checkboxes(&mut self) {
+        let last = self.questions.iter().flatten().max().copied().unwrap_or(0);
+        self.groups = (1..=last)
+            .filter_map(|n| {
+                let columns: Vec<usize> = (0..self.questions.len())
+                    .filter(|&i| self.questions[i] == Some(n) && self.checkbox[i])
+                    .collect();
+                let first = *columns.first()?;
+                let suffix = format!(" - {}", self.options[first]);
+                let label = self.labels[first]
+                    .strip_suffix(&suffix)
+                    .unwrap_or(&self.labels[first])
+                    .to_string();
+                (columns.len() >= 2).then(|| ResponseGroup {
+                    name: format!("$Q{n}"),
+                    label,
+                    columns,
+                })
+            })
+            .collect();
+    }
+
+    /// Whether the column is part of a multiple response set.
+    pub fn is_checkbox(&self, column: usize) -> bool {
+        self.groups.iter().any(|g| g.columns.contains(&column))
+    }
+
+    /// Option text counted when the checkbox column is ticked.
+    pub fn option(&self, column: usize) -> &str {
+        self.options.get(column).map_or("", String::as_str)
+    }
+}
+
+/// SPSS syntax defining the sets over the given variable names, wrapped into document
+/// record lines. The sets are also written as the multiple response set record; the
+/// syntax is kept where users can copy and adapt it.
+pub fn mrsets_syntax(groups: &[ResponseGroup], names: &[String]) -> Vec<String> {
+    if groups.is_empty() {
+        return Vec::new();
+    }
+    // Words are never split across lines.
+    let mut words = vec!["MRSETS".to_string()];
+    for group in groups {
+        words.push("/MDGROUP".to_string());
+        words.push(format!("NAME={}", group.name));
+        let mut rest = group.label.as_str();
+        let mut prefix = "LABEL=";
+        loop {
+            let chunk = crate::converter::truncate_utf8(rest, LABEL_CHUNK_BYTES);
+            words.push(format!("{prefix}'{}'", chunk.replace('\'', "''")));
+            rest = &rest[chunk.len()..];
+            if rest.is_empty() || chunk.is_empty() {
+                break;
+            }
+            prefix = "+ ";
+        }
+        words.push("CATEGORYLABELS=VARLABELS".to_string());
+        for (k, &i) in group.columns.iter().enumerate() {
+            let prefix = if k == 0 { "VARIABLES=" } else { "" };
+            words.push(format!("{prefix}{}", names[i]));
+        }
+        words.push("VALUE=1".to_string());
+    }
+    words.push(".".to_string());
+
+    let mut lines = vec!["Multiple response sets (SPSS syntax):".to_string()];
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > SYNTAX_LINE_BYTES {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    lines.push(line);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconstruct_headers_and_group_checkboxes() {
+        let headers: Vec<String> = [
+            "Respondent ID",
+            "Which fruits do you like?",
+            "",
+            "",
+            "How old are you?",
+            "Rate each",
+            "",
+        ]
+        .map(String::from)
+        .to_vec();
+        let row2 = ByteRecord::from(vec![
+            "",
+            "Apple",
+            "Banana",
+            "Other (please specify)",
+            "Response",
+            "Price",
+            "Taste",
+        ]);
+        let mut layout = SurveyMonkeyHeader::detect(&headers, Some(&row2)).unwrap();
+        assert_eq!(
+            layout.names,
+            ["Respondent_ID", "Q1_1", "Q1_2", "Q1_3", "Q2", "Q3_1", "Q3_2"].map(|n| Some(n.to_string()))
+        );
+        assert_eq!(layout.labels[2], "Which fruits do you like? - Banana");
+        assert_eq!(layout.labels[4], "How old are you?");
+
+        for (i, value) in ["1", "Apple", "", "Kiwi", "30", "Good", "Bad"].iter().enumerate() {
+            layout.observe(i, value);
+        }
+        layout.observe(2, "Banana");
+        layout.group_checkboxes();
+        assert_eq!(
+            layout.groups,
+            vec![ResponseGroup {
+                name: "$Q1".to_string(),
+                label: "Which fruits do you like?".to_string(),
+                columns: vec![1, 2],
+            }]
+        );
+        assert!(layout.is_checkbox(1) && !layout.is_checkbox(3) && !layout.is_checkbox(5));
+
+        let names: Vec<String> = layout.names.iter().flatten().cloned().collect();
+        let syntax = mrsets_syntax(&layout.groups, &names);
+        assert!(syntax.iter().all(|l| l.len() <= SYNTAX_LINE_BYTES));
+        assert_eq!(
+            syntax[1..].join(" "),
+            "MRSETS /MDGROUP NAME=$Q1 LABEL='Which fruits do you like?' CATEGORYLABELS=VARLABELS VARIABLES=Q1_1 Q1_2 VALUE=1 ."
+        );
+
+        let long = ResponseGroup {
+            name: "$Q9".to_string(),
+            label: "It's ".repeat(30),
+            columns: vec![0, 1],
+        };
+        let syntax = mrsets_syntax(&[long], &names);
+        assert!(syntax.iter().all(|l| l.len() <= SYNTAX_LINE_BYTES));
+        // Quoted strings never run across lines.
+        assert!(syntax[1..].iter().all(|l| l.matches('\'').count() % 2 == 0));
+        assert!(syntax[2].starts_with("+ '"));
+
+        let plain = ByteRecord::from(vec!["1", "2"]);
+        assert!(SurveyMonkeyHeader::detect(&headers[..2], Some(&plain)).is_none());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cancel::{CancelToken, TaskError};
+use crate::input;
+use crate::options::ConvertOptions;
+use crate::qualtrics;
+use crate::retry::RetryReader;
+use crate::surveymonkey::SurveyMonkeyHeader;
+
+const BUF_SIZE: usize = 512 * 1024;
+/// Row numbers or values listed per issue.
+const EXAMPLES: usize = 5;
+/// A column is flagged as mixed when at least this share of its values are numbers
+/// but some are not: a few stray words usually mean a typo, not a text column.
+const MIXED_NUMERIC_SHARE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    RaggedRows,
+    InvalidUtf8,
+    DuplicateHeaders,
+    EmptyHeaders,
+    MixedTypes,
+    EmbeddedNewlines,
+}
+
+/// One kind of problem, with how often it occurs and where to look.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// Header of the affected column; None for whole-row or file-level issues.
+    pub column: Option<String>,
+    pub count: usize,
+    /// First affected data rows, 1-based.
+    pub rows: Vec<usize>,
+    /// Offending values, where that helps.
+    pub examples: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub rows: usize,
+    pub columns: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+/// Occurrences of one issue while the file is read.
+#[derive(Default)]
+struct Tally {
+    count: usize,
+    rows: Vec<usize>,
+    examples: Vec<String>,
+}
+
+impl Tally {
+    fn add(&mut self, row: usize, example: Option<&[u8]>) {
+        self.count += 1;
+        if self.rows.len() < EXAMPLES && self.rows.last() != Some(&row) {
+            self.rows.push(row);
+        }
+        if let Some(value) = example {
+            if self.examples.len() < EXAMPLES {
+                self.examples
+                    .push(String::from_utf8_lossy(value).into_owned());
+            }
+        }
+    }
+
+    fn into_issue(self, kind: IssueKind, column: Option<&str>, message: String) -> ValidationIssue {
+        ValidationIssue {
+            kind,
+            column: column.map(str::to_string),
+            count: self.count,
+            rows: self.rows,
+            examples: self.examples,
+            message,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ColumnTally {
+    numbers: usize,
+    /// Non-empty values that are neither numbers nor missing markers.
+    text: Tally,
+    invalid_utf8: Tally,
+    newlines: Tally,
+}
+
+/// Reads the whole CSV once and reports structural problems that would make a
+/// conversion fail or silently alter data. Does not stop at the first problem.
+pub fn validate_csv(
+    path: &Path,
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+) -> Result<ValidationReport, TaskError> {
+    let file = File::open(path).map_err(|e| format!("Failed to open CSV: {e}"))?;
+    let (file, _) = RetryReader::new(file, options.retry_policy());
+    let file = input::Transcoder::new(file, options.encoding);
+    let mut buf = BufReader::with_capacity(BUF_SIZE, file);
+    let head = buf.fill_buf().map_err(|e| format!("Failed to read CSV: {e}"))?;
+    input::check_delimited_text(head)?;
+    input::skip_utf8_bom(&mut buf).map_err(|e| format!("Failed to read CSV: {e}"))?;
+    let preamble = input::skip_preamble(&mut buf, options)?;
+    let mut reader = preamble.csv_reader(options)?.from_reader(buf);
+    input::seek_header(&mut reader, options.header_row)?;
+
+    let raw_headers = reader
+        .byte_headers()
+        .map_err(|e| format!("Failed to read CSV headers: {e}"))?
+        .clone();
+    let headers: Vec<String> = raw_headers
+        .iter()
+        .map(|h| String::from_utf8_lossy(h).trim().to_string())
+        .collect();
+    let mut issues = header_issues(&raw_headers, &headers);
+
+    // The extra header rows of a Qualtrics or SurveyMonkey export are not data.
+    let mut head = VecDeque::new();
+    if options.detect_qualtrics || options.detect_surveymonkey {
+        for _ in 0..2 {
+            let mut record = csv::ByteRecord::new();
+            if reader.read_byte_record(&mut record).unwrap_or(false) {
+                head.push_back(record);
+            }
+        }
+        let layout = options
+            .detect_qualtrics
+            .then(|| qualtrics::detect(&headers, head.front(), head.get(1)))
+            .flatten();
+        let skip = match layout {
+            Some(layout) => layout.skip_rows,
+            None if options.detect_surveymonkey
+                && SurveyMonkeyHeader::detect(&headers, head.front()).is_some() =>
+            {
+                SurveyMonkeyHeader::SKIP_ROWS
+            }
+            None => 0,
+        };
+        head.drain(..skip.min(head.len()));
+    }
+
+    let mut columns: Vec<ColumnTally> = headers.iter().map(|_| ColumnTally::default()).collect();
+    let mut ragged = Tally::default();
+    let mut rows = 0usize;
+    let mut record = csv::ByteRecord::new();
+    loop {
+        let more = match head.pop_front() {
+            Some(next) => {
+                record = next;
+                true
+            }
+            None => reader
+                .read_byte_record(&mut record)
+                .map_err(|e| format!("CSV read error at row {}: {e}", rows + 1))?,
+        };
+        if !more {
+            break;
+        }
+        rows += 1;
+        if rows.is_multiple_of(100_000) {
+            cancel.check()?;
+        }
+
+        if record.len() != headers.len() {
+            ragged.add(rows, None);
+        }
+        for (field, column) in record.iter().zip(columns.iter_mut()) {
+            if field.contains(&b'\n') || field.contains(&b'\r') {
+                column.newlines.add(rows, None);
+            }
+            let Ok(text) = std::str::from_utf8(field) else {
+                column.invalid_utf8.add(rows, Some(field));
+                continue;
+            };
+            let text = text.trim();
+            if text.is_empty() || options.is_missing_marker(text) {
+                continue;
+            }
+            if options.parse_number(text).is_some() {
+                column.numbers += 1;
+            } else {
+                column.text.add(rows, Some(text.as_bytes()));
+            }
+        }
+    }
+
+    if ragged.count > 0 {
+        let message = format!(
+            "{} row(s) do not have {} fields; missing fields become missing values and extra fields are dropped",
+            ragged.count,
+            headers.len()
+        );
+        issues.push(ragged.into_issue(IssueKind::RaggedRows, None, message));
+    }
+    for (header, column) in headers.iter().zip(columns) {
+        if column.invalid_utf8.count > 0 {
+            let message = format!(
+                "Column '{header}': {} cell(s) are not valid UTF-8",
+                column.invalid_utf8.count
+            );
+            issues.push(column.invalid_utf8.into_issue(
+                IssueKind::InvalidUtf8,
+                Some(header),
+                message,
+            ));
+        }
+        let values = column.numbers + column.text.count;
+        if column.text.count > 0
+            && values > 0
+            && column.numbers as f64 / values as f64 >= MIXED_NUMERIC_SHARE
+        {
+            let message = format!(
+                "Column '{header}': {} of {values} values are not numbers, so the column becomes a string",
+                column.text.count
+            );
+            issues.push(
+                column
+                    .text
+                    .into_issue(IssueKind::MixedTypes, Some(header), message),
+            );
+        }
+        if column.newlines.count > 0 {
+            let message = format!(
+                "Column '{header}': {} cell(s) contain line breaks",
+                column.newlines.count
+            );
+            issues.push(column.newlines.into_issue(
+                IssueKind::EmbeddedNewlines,
+                Some(header),
+                message,
+            ));
+        }
+    }
+
+    Ok(ValidationReport {
+        rows,
+        columns: headers.len(),
+        issues,
+    })
+}
+
+fn header_issues(raw: &csv::ByteRecord, headers: &[String]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    let invalid: Vec<String> = raw
+        .iter()
+        .filter(|h| std::str::from_utf8(h).is_err())
+        .map(|h| String::from_utf8_lossy(h).into_owned())
+        .collect();
+    if !invalid.is_empty() {
+        issues.push(ValidationIssue {
+            kind: IssueKind::InvalidUtf8,
+            column: None,
+            count: invalid.len(),
+            rows: vec![],
+            message: format!("{} header(s) are not valid UTF-8", invalid.len()),
+            examples: invalid,
+        });
+    }
+
+    let empty: Vec<String> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| h.is_empty())
+        .map(|(i, _)| format!("column {}", i + 1))
+        .collect();
+    if !empty.is_empty() {
+        issues.push(ValidationIssue {
+            kind: IssueKind::EmptyHeaders,
+            column: None,
+            count: empty.len(),
+            rows: vec![],
+            message: format!("{} column(s) have no header", empty.len()),
+            examples: empty,
+        });
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for header in headers.iter().filter(|h| !h.is_empty()) {
+        *seen.entry(header).or_default() += 1;
+    }
+    let mut duplicates: Vec<&str> = seen
+        .into_iter()
+        .filter(|&(_, n)| n > 1)
+        .map(|(h, _)| h)
+        .collect();
+    duplicates.sort_unstable();
+    for header in duplicates {
+        let count = headers.iter().filter(|h| *h == header).count();
+        issues.push(ValidationIssue {
+            kind: IssueKind::DuplicateHeaders,
+            column: Some(header.to_string()),
+            count,
+            rows: vec![],
+            examples: vec![],
+            message: format!("Header '{header}' appears {count} times"),
+        });
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_each_issue_kind() {
+        let path = std::env::temp_dir().join("csv2sav_validate_test.csv");
+        let mut csv = b"id,score,id,,note\n".to_vec();
+        csv.extend_from_slice(b"1,2.5,a,b,ok\n");
+        csv.extend_from_slice(b"2,oops,a,b,\"two\nlines\"\n");
+        csv.extend_from_slice(b"3,4,a,b\n");
+        csv.extend_from_slice(b"4,NA,a,b,bad\xFF\n");
+        std::fs::write(&path, csv).unwrap();
+
+        let report =
+            validate_csv(&path, &ConvertOptions::default(), &CancelToken::new()).unwrap();
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.columns, 5);
+        let kinds: Vec<(IssueKind, Option<&str>, usize)> = report
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.column.as_deref(), i.count))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (IssueKind::EmptyHeaders, None, 1),
+                (IssueKind::DuplicateHeaders, Some("id"), 2),
+                (IssueKind::RaggedRows, None, 1),
+                (IssueKind::MixedTypes, Some("score"), 1),
+                (IssueKind::InvalidUtf8, Some("note"), 1),
+                (IssueKind::EmbeddedNewlines, Some("note"), 1),
+            ]
+        );
+        let mixed = &report.issues[3];
+        assert_eq!(mixed.rows, vec![2]);
+        assert_eq!(mixed.examples, vec!["oops"]);
+        assert_eq!(report.issues[2].rows, vec![3]);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
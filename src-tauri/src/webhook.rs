@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Serialize;
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSummary {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    pub rows: usize,
+    pub success: bool,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchSummary {
+    pub files: Vec<FileSummary>,
+    pub total_rows: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub duration_ms: u64,
+}
+
+impl BatchSummary {
+    pub fn new(files: Vec<FileSummary>, duration_ms: u64) -> Self {
+        let succeeded = files.iter().filter(|f| f.success).count();
+        Self {
+            total_rows: files.iter().map(|f| f.rows).sum(),
+            failed: files.len() - succeeded,
+            succeeded,
+            files,
+            duration_ms,
+        }
+    }
+}
+
+/// POSTs the summary as JSON. Blocking; call from a worker thread.
+pub fn post(url: &str, summary: &BatchSummary) -> Result<(), String> {
+    let body =
+        serde_json::to_string(summary).map_err(|e| format!("Failed to encode summary: {e}"))?;
+    ureq::post(url)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| format!("Webhook request failed: {e}"))?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+/// DOS date of 1980-01-01, the earliest a zip entry can carry; entries are not dated.
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// Names are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_DEFLATE: u16 = 8;
+const VERSION: u16 = 20;
+
+/// Writes a zip archive of deflated entries, each given whole. No zip64: the archive
+/// must stay under 4 GiB, which is plenty for diagnostic bundles.
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u32,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+fn too_large() -> io::Error {
+    io::Error::other("zip archive too large")
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            central: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let crc = crc32fast::hash(data);
+        let compressed_size = u32::try_from(compressed.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+
+        // Fields shared by the local header and the central directory entry, from
+        // the version needed to extract through the extra field length.
+        let mut common = Vec::with_capacity(26);
+        for field in [VERSION, FLAG_UTF8, METHOD_DEFLATE, 0, DOS_DATE] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, compressed_size, size] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&name_len.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        local.extend_from_slice(&common);
+        local.extend_from_slice(name.as_bytes());
+        self.out.write_all(&local)?;
+        self.out.write_all(&compressed)?;
+
+        self.central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        self.central.extend_from_slice(&VERSION.to_le_bytes());
+        self.central.extend_from_slice(&common);
+        // Comment length, disk number, internal and external attributes.
+        self.central.extend_from_slice(&[0; 10]);
+        self.central.extend_from_slice(&self.offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+
+        self.offset = u32::try_from(local.len() + compressed.len())
+            .ok()
+            .and_then(|len| self.offset.checked_add(len))
+            .ok_or_else(too_large)?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer, flushed.
+    pub fn finish(mut self) -> io::Result<W> {
+        let central_size = u32::try_from(self.central.len()).map_err(|_| too_large())?;
+        self.out.write_all(&self.central)?;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&central_size.to_le_bytes());
+        end.extend_from_slice(&self.offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.out.write_all(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_zip_entries_read_back() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("a.txt", b"hello hello hello").unwrap();
+        zip.add("dir/b.json", b"{}").unwrap();
+        let data = zip.finish().unwrap();
+
+        let end = &data[data.len() - 22..];
+        assert_eq!(end[..4], 0x0605_4b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let central = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(data[central..central + 4], 0x0201_4b50u32.to_le_bytes());
+
+        // The first entry starts the archive.
+        let compressed_size = u32::from_le_bytes(data[18..22].try_into().unwrap()) as usize;
+        let name_len = u16::from_le_bytes([data[26], data[27]]) as usize;
+        assert_eq!(&data[30..30 + name_len], b"a.txt");
+        let start = 30 + name_len;
+        let mut text = String::new();
+        flate2::read::DeflateDecoder::new(&data[start..start + compressed_size])
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello hello hello");
+        assert_eq!(data[14..18], crc32fast::hash(text.as_bytes()).to_le_bytes());
+    }
+}
@@ -0,0 +1,43 @@
+//! Round trips the fixtures in `tests/fixtures/pspp` through GNU PSPP. Ignored by
+//! default; with `pspp-convert` or `pspp` on PATH, run them with
+//! `cargo test --features pspp --test pspp_golden -- --ignored`.
+
+use std::path::{Path, PathBuf};
+
+use csv2sav_app_lib::pspp;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pspp");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("fixture directory missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+#[ignore = "needs GNU PSPP"]
+fn pspp_reads_back_every_fixture() {
+    let Some(tool) = pspp::find_tool() else {
+        eprintln!("pspp-convert and pspp not found on PATH; skipping");
+        return;
+    };
+    let work_dir = std::env::temp_dir().join("csv2sav_pspp_golden");
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let mut failures = Vec::new();
+    for fixture in fixtures() {
+        let name = fixture.file_name().unwrap().to_string_lossy().into_owned();
+        match pspp::round_trip(&tool, &fixture, &work_dir) {
+            Ok(result) if result.rows == 0 => failures.push(format!("{name}: no rows read back")),
+            Ok(result) => failures.extend(result.mismatches.iter().map(|m| {
+                format!("{name}: row {} {}: expected {:?}, PSPP read {:?}", m.row, m.column, m.expected, m.actual)
+            })),
+            Err(e) => failures.push(format!("{name}: {e}")),
+        }
+    }
+    std::fs::remove_dir_all(&work_dir).ok();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
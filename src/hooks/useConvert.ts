@@ -56,6 +56,8 @@ export function useConvert() {
 
     setConverting(true);
     cancelledRef.current = false;
+    const batchStart = Date.now();
+    const results: ConvertResult[] = [];
 
     unlistenRef.current = await listen<ConvertProgress>(
       "convert-progress",
@@ -120,6 +122,7 @@ export function useConvert() {
           inputPath: file.inputPath,
           outputPath,
         });
+        results.push(result);
 
         setFiles((prev) =>
           prev.map((f) =>
@@ -165,6 +168,13 @@ export function useConvert() {
     unlistenRef.current?.();
     unlistenRef.current = null;
     setConverting(false);
+
+    if (results.length > 0) {
+      invoke("notify_batch_complete", {
+        results,
+        durationMs: Date.now() - batchStart,
+      }).catch((err) => console.warn("Webhook notification failed:", err));
+    }
   }
 
   return {
@@ -15,6 +15,7 @@ export interface ConvertFile {
 
 export interface ConvertProgress {
   file: string;
+  job?: number;
   current_rows: number;
   bytes_read: number;
   file_size: number;
@@ -26,4 +27,163 @@ export interface ConvertResult {
   total_rows: number;
   success: boolean;
   error?: string;
+  error_code?: ErrorCode;
+  truncated_cols: string[];
+  sha256?: string;
+  duration_ms: number;
+  warnings: string[];
+  truncations: TruncationReport[];
+  parts: OutputPart[];
 }
+
+export interface BatchJob {
+  input_path: string;
+  output_path: string;
+}
+
+export interface BatchProgress {
+  completed_files: number;
+  total_files: number;
+  current_file?: string;
+  job?: number;
+  batch: number;
+}
+
+export interface OutputPart {
+  path: string;
+  first_column: number;
+  last_column: number;
+  sha256: string;
+}
+
+export interface TruncationReport {
+  column: string;
+  width: number;
+  count: number;
+  examples: string[];
+}
+
+export interface ExportProgress {
+  file: string;
+  current_rows: number;
+  total_rows: number;
+}
+
+export interface ExportOptions {
+  values?: "coded" | "labeled";
+  dates?: "iso" | "seconds";
+  delimiter?: string;
+  encoding?: "utf8" | "utf8_bom" | "gbk";
+  missing_value?: string;
+  user_missing?: "missing" | "value";
+  lock_wait_secs?: number;
+}
+
+export type ValidationIssueKind =
+  | "ragged_rows"
+  | "invalid_utf8"
+  | "duplicate_headers"
+  | "empty_headers"
+  | "mixed_types"
+  | "embedded_newlines";
+
+export interface ValidationIssue {
+  kind: ValidationIssueKind;
+  column?: string;
+  count: number;
+  rows: number[];
+  examples: string[];
+  message: string;
+}
+
+export interface ValidationReport {
+  rows: number;
+  columns: number;
+  issues: ValidationIssue[];
+}
+
+export type IssueAction =
+  | "truncated"
+  | "set_missing"
+  | "clamped"
+  | "padded_missing_fields"
+  | "dropped_extra_fields"
+  | "replaced_invalid_utf8"
+  | "scrubbed_nul";
+
+export interface Issue {
+  row: number;
+  /** Empty when the whole row was altered. */
+  column: string;
+  original_value: string;
+  action: IssueAction;
+}
+
+export type PiiKind = "email" | "phone" | "national_id" | "name";
+
+export interface ColumnMapping {
+  index: number;
+  header: string;
+  /** Variable name the column receives in the SAV file. */
+  name: string;
+  col_type: string;
+  width?: number;
+  /** SPSS display format, e.g. `F12.4` or `A3000`. */
+  format: string;
+  samples: string[];
+  pii?: PiiKind;
+  label_column?: string;
+}
+
+export interface SchemaReport {
+  columns: ColumnMapping[];
+  truncated_cols: string[];
+  warnings: string[];
+  row_count?: number;
+}
+
+export interface ProblemReport {
+  rows: number;
+  issues: Issue[];
+  total: number;
+  warnings: string[];
+}
+
+export type DetectedEncoding = "utf8" | "utf8_bom" | "utf16" | "not_utf8";
+
+export type InputEncoding = "utf8" | "auto" | "gbk" | "big5" | "shift_jis" | "latin1";
+
+export interface CsvInfo {
+  file_size: number;
+  estimated_rows: number;
+  rows_exact: boolean;
+  encoding: DetectedEncoding;
+  likely_encoding?: InputEncoding;
+  delimiter: string;
+  headers: string[];
+}
+
+export interface PreviewVariable {
+  name: string;
+  label: string;
+  format: string;
+}
+
+export interface PreviewCell {
+  text: string;
+  missing: boolean;
+}
+
+export interface OutputPreview {
+  variables: PreviewVariable[];
+  rows: PreviewCell[][];
+  warnings: string[];
+}
+
+export interface ConvertWarning {
+  file: string;
+  job?: number;
+  message: string;
+}
+
+export type ErrorCode = "cancelled" | "file_in_use" | "too_many_columns" | "timeout";